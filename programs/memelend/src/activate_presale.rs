@@ -1,6 +1,7 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{TokenAccount, Mint};
 use crate::{VaultConfig, TokenConfig, UserYieldPosition, ErrorCode, YieldVault};
+use crate::emissions::{checkpoint_position, rebase_position, pending_wsol_rewards};
 
 #[derive(Accounts)]
 pub struct ActivatePresale<'info> {
@@ -9,21 +10,42 @@ pub struct ActivatePresale<'info> {
 
     /// CHECK: This is the user's wallet that owns the token.
     /// We don't deserialize it because we need to transfer tokens over.
-    #[account(mut)]
+    #[account(mut, address = token_config.creator @ ErrorCode::Unauthorized)]
     pub owner: AccountInfo<'info>,
 
-    #[account(mut)]
+    #[account(address = token_config.mint @ ErrorCode::InvalidMint)]
     pub mint: Box<Account<'info, Mint>>,
 
+    #[account(
+        seeds = [b"config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub token_config: Box<Account<'info, TokenConfig>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"vault_config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub vault_config: Box<Account<'info, VaultConfig>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"yield_vault", mint.key().as_ref()],
+        bump = yield_vault.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub yield_vault: Box<Account<'info, YieldVault>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"user_yield", mint.key().as_ref(), owner.key().as_ref()],
+        bump = user_yield_position.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+        constraint = user_yield_position.owner == owner.key() @ ErrorCode::Unauthorized,
+    )]
     pub user_yield_position: Box<Account<'info, UserYieldPosition>>,
 
 }
@@ -31,11 +53,16 @@ pub struct ActivatePresale<'info> {
 pub fn activate_presale(ctx: Context<ActivatePresale>) -> Result<()> {
     
     let vault_config = &mut ctx.accounts.vault_config;
-    let token_config = &mut ctx.accounts.token_config;
+    let token_config = &ctx.accounts.token_config;
     let user_yield_position = &mut ctx.accounts.user_yield_position;
     let yield_vault = &mut ctx.accounts.yield_vault;
 
     let presale_tokens = token_config.pre_sale_token_allocation;
+    let now = Clock::get()?.unix_timestamp;
+
+    // Book token emissions and slot rewards before the stake changes
+    checkpoint_position(yield_vault, user_yield_position, now)?;
+    let pending_wsol = pending_wsol_rewards(yield_vault, user_yield_position)?;
     
     yield_vault.total_staked = yield_vault.total_staked
             .checked_add(presale_tokens)
@@ -48,6 +75,10 @@ pub fn activate_presale(ctx: Context<ActivatePresale>) -> Result<()> {
     user_yield_position.deposited = user_yield_position.deposited
             .checked_add(presale_tokens)
             .ok_or(ErrorCode::Overflow)?; 
+
+    rebase_position(yield_vault, user_yield_position, now)?;
+    // WSOL rewards earned before activation stay claimable
+    user_yield_position.reward_debt = user_yield_position.reward_debt.saturating_sub(pending_wsol);
     
     emit!(ActivatePresaleEvent {
         mint: ctx.accounts.mint.key(),
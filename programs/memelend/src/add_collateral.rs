@@ -0,0 +1,151 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Token, TokenAccount, Mint, Transfer, CloseAccount};
+use anchor_spl::token::spl_token::native_mint::ID as NATIVE_MINT_ID;
+use crate::{TokenConfig, ProtocolConfig, Position, ErrorCode};
+use crate::get_liquidation_price;
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct AddCollateralArgs {
+    pub position_id: u64,
+    pub amount: u64,
+}
+
+#[derive(Accounts)]
+#[instruction(args: AddCollateralArgs)]
+pub struct AddCollateral<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
+    pub wsol_mint: Account<'info, Mint>,
+
+    #[account(address = token_config.mint @ ErrorCode::InvalidMint)]
+    pub mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [b"position", user.key().as_ref(), mint.key().as_ref(), &args.position_id.to_le_bytes()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+        constraint = position.owner == user.key() @ ErrorCode::Unauthorized,
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        seeds = [b"config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub token_config: Box<Account<'info, TokenConfig>>,
+
+    #[account(
+        mut,
+        constraint = temp_wsol_account.mint == NATIVE_MINT_ID @ ErrorCode::InvalidWsolMint,
+    )]
+    pub temp_wsol_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(address = user.key())]
+    pub temp_wsol_authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_wsol", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+    )]
+    pub liquidity_sol_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Box<Account<'info, ProtocolConfig>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn add_collateral(ctx: Context<AddCollateral>, args: AddCollateralArgs) -> Result<()> {
+    let position = &ctx.accounts.position;
+
+    // Check to see if its the correct position ID
+    require!(
+        args.position_id == position.position_id,
+        ErrorCode::InvalidPositionId
+    );
+    require!(position.open, ErrorCode::PositionAlreadyClosed);
+    require!(args.amount > 0, ErrorCode::ZeroInput);
+
+    msg!("amount: {}", args.amount);
+    msg!("collateral before: {}", position.collateral);
+
+    // Top-up goes straight into the WSOL vault that already holds the position's collateral
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.temp_wsol_account.to_account_info(),
+                to: ctx.accounts.liquidity_sol_vault.to_account_info(),
+                authority: ctx.accounts.temp_wsol_authority.to_account_info(),
+            },
+        ),
+        args.amount,
+    )?;
+
+    let maintenance_margin_bps = ctx.accounts.protocol_config.maintenance_margin_bps as u64;
+    let decimals = ctx.accounts.mint.decimals;
+    let position = &mut ctx.accounts.position;
+
+    position.collateral = position.collateral
+        .checked_add(args.amount)
+        .ok_or(ErrorCode::Overflow)?;
+
+    // More collateral pushes the liquidation price further above entry
+    position.liquidate = get_liquidation_price(
+        position.entry_price,
+        position.amount,
+        position.collateral,
+        maintenance_margin_bps,
+        decimals,
+    )?;
+
+    msg!("collateral after: {}", position.collateral);
+    msg!("liquidation_price: {}", position.liquidate);
+
+    //--- Close accounts ---
+    token::close_account(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.temp_wsol_account.to_account_info(),
+                destination: ctx.accounts.user.to_account_info(),
+                authority: ctx.accounts.temp_wsol_authority.to_account_info(),
+            },
+        )
+    )?;
+
+    let position = &ctx.accounts.position;
+
+    emit!(AddCollateralEvent {
+        user: ctx.accounts.user.key(),
+        mint: ctx.accounts.mint.key(),
+        position_id: position.position_id,
+        amount_added: args.amount,
+        collateral: position.collateral,
+        borrowed_tokens: position.amount,
+        entry_price: position.entry_price,
+        liquidation_price: position.liquidate,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct AddCollateralEvent {
+    pub user: Pubkey,
+    pub mint: Pubkey,
+    pub position_id: u64,
+    pub amount_added: u64,
+    pub collateral: u64,
+    pub borrowed_tokens: u64,
+    pub entry_price: u64,
+    pub liquidation_price: u64,
+    pub timestamp: i64,
+}
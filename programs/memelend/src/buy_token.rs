@@ -1,8 +1,8 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{self, Token, TokenAccount, Mint, Transfer, CloseAccount};
 use anchor_spl::token::spl_token::native_mint::ID as NATIVE_MINT_ID;
-use crate::{VaultConfig, TokenConfig, ReferralVault, ErrorCode, YieldVault};
-use crate::get_price_from_vault;
+use crate::{VaultConfig, TokenConfig, ProtocolConfig, ReferralVault, ErrorCode, YieldVault, UserTradeState, get_gross_for_net};
+use crate::curve::load_curve;
 
 pub const PRECISION: u128 = 1_000_000_000_000; // 1e12 for reward per share math
 
@@ -13,6 +13,13 @@ pub struct BuyTokenArgs {
     pub position_id: u64,
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct BuyTokenExactOutArgs {
+    pub tokens_out: u64,
+    pub max_sol_in: u64,
+    pub position_id: u64,
+}
+
 #[derive(Accounts)]
 pub struct BuyToken<'info> {
     #[account(mut)]
@@ -37,10 +44,17 @@ pub struct BuyToken<'info> {
     #[account(address = user.key())]
     pub temp_wsol_authority: Signer<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = user_token_account.mint == mint.key() @ ErrorCode::InvalidMint,
+    )]
     pub user_token_account: Box<Account<'info, TokenAccount>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"vault_liquidity", mint.key().as_ref()],
+        bump,
+    )]
     pub liquidity_token_vault: Box<Account<'info, TokenAccount>>,
 
     /// CHECK: PDA
@@ -58,10 +72,20 @@ pub struct BuyToken<'info> {
     )]
     pub liquidity_sol_vault: Box<Account<'info, TokenAccount>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"vault_config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub vault_config: Box<Account<'info, VaultConfig>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub token_config: Box<Account<'info, TokenConfig>>,
 
     #[account(
@@ -85,7 +109,7 @@ pub struct BuyToken<'info> {
     /// CHECK: This is a PDA derived from [b"vault", mint] and only used as a signer in token transfer CPI. No data access occurs.
     #[account(
         mut,
-        seeds = [b"vault", vault_config.mint.as_ref()],
+        seeds = [b"vault", mint.key().as_ref()],
         bump,
     )]
     pub vault_authority: UncheckedAccount<'info>,
@@ -119,15 +143,67 @@ pub struct BuyToken<'info> {
     /// CHECK: Only used for seeds/validation
     pub referrer: UncheckedAccount<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"yield_vault", mint.key().as_ref()],
+        bump = yield_vault.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub yield_vault: Box<Account<'info, YieldVault>>,
 
+    #[account(
+        init_if_needed,
+        payer = user,
+        seeds = [b"user_trade", mint.key().as_ref(), user.key().as_ref()],
+        bump,
+        space = 8 + UserTradeState::LEN,
+    )]
+    pub user_trade_state: Box<Account<'info, UserTradeState>>,
+
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Box<Account<'info, ProtocolConfig>>,
+
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
 }
 
 pub fn buy_token(ctx: Context<BuyToken>, args: BuyTokenArgs) -> Result<()> {
+    process_buy(ctx, args, None)
+}
+
+// Buy exactly `tokens_out`, paying at most `max_sol_in` (fees included).
+// The temp account should hold max_sol_in; whatever isn't spent is refunded when it is closed.
+pub fn buy_token_exact_out(ctx: Context<BuyToken>, args: BuyTokenExactOutArgs) -> Result<()> {
+    require!(args.tokens_out > 0, ErrorCode::ZeroInput);
+
+    // Invert the pipeline: curve first (net SOL the pool needs), then fees on top
+    let net_sol_needed = load_curve(&ctx.accounts.vault_config)?.quote_buy_exact_out(args.tokens_out)?;
+    let sol_amount = get_gross_for_net(
+        net_sol_needed,
+        ctx.accounts.token_config.creator_fee_bps,
+        ctx.accounts.protocol_config.platform_fee_bps,
+    )?;
+
+    msg!("net_sol_needed: {}", net_sol_needed);
+    msg!("sol_amount: {}", sol_amount);
+
+    require!(sol_amount <= args.max_sol_in, ErrorCode::SlippageExceeded);
+
+    process_buy(
+        ctx,
+        BuyTokenArgs {
+            sol_amount,
+            min_tokens_out: args.tokens_out,
+            position_id: args.position_id,
+        },
+        Some(args.tokens_out),
+    )
+}
+
+// Shared fee-then-curve pipeline. `tokens_out_cap` pins the payout for exact-out buys,
+// any rounding surplus stays in the pool.
+fn process_buy(ctx: Context<BuyToken>, args: BuyTokenArgs, tokens_out_cap: Option<u64>) -> Result<()> {
     let sol_amount = args.sol_amount;
     let min_tokens_out = args.min_tokens_out;
     let _position_id = args.position_id;
@@ -164,30 +240,18 @@ pub fn buy_token(ctx: Context<BuyToken>, args: BuyTokenArgs) -> Result<()> {
     );
 
     // === FLASH LOAN PROTECTION: TRADE COOLDOWN CHECK ===
-    let clock = Clock::get()?;
-    let last_trade_timestamp = vault_config.last_trade_timestamp;
-
-    // Prevent rapid successive trades (1 second cooldown)
-    // Allow first trade (timestamp == 0) or trades after cooldown period
-    if last_trade_timestamp > 0 {
-        require!(
-            clock.unix_timestamp - last_trade_timestamp >= 1,
-            ErrorCode::TradeCooldown
-        );
-    }
-
-    // Update timestamp for the next trade
-    vault_config.last_trade_timestamp = clock.unix_timestamp;
+    // Cooldown is per wallet, so one trader can't sandwich within a second
+    // without locking everyone else on this mint out
+    check_trade_cooldown(
+        &mut ctx.accounts.user_trade_state,
+        ctx.bumps.user_trade_state,
+        ctx.accounts.user.key(),
+        ctx.accounts.mint.key(),
+        now,
+    )?;
     // === END FLASH LOAN PROTECTION ===
 
-    let price = get_price_from_vault(
-        vault_config.sol_reserve,
-        vault_config.accumulated_c,
-        vault_config.token_reserve,
-        vault_config.virtual_sol,
-        vault_config.virtual_tokens,
-        ctx.accounts.mint.decimals,
-    );
+    let price = load_curve(vault_config)?.spot_price(ctx.accounts.mint.decimals);
 
     msg!("price: {}", price);
 
@@ -197,13 +261,216 @@ pub fn buy_token(ctx: Context<BuyToken>, args: BuyTokenArgs) -> Result<()> {
         ErrorCode::InvalidPrice
     );
 
-    if ctx.accounts.referral_tracking.to_account_info().data_is_empty() {
+    // init_if_needed has already created the account, a zero referrer means it is new
+    if ctx.accounts.referral_tracking.referrer == Pubkey::default() {
         ctx.accounts.referral_tracking.referrer = ctx.accounts.referrer.key();
-        ctx.accounts.referral_tracking.pending_rewards = 0;
-        ctx.accounts.referral_tracking.total_earned = 0;
     }
 
-    // Calculate fees
+    let is_referral = ctx.accounts.referrer.key() != ctx.accounts.platform_vault.key();
+    let protocol_config = &ctx.accounts.protocol_config;
+    let fees = split_buy_fees(sol_amount, token_config, vault_config, protocol_config, is_referral, now)?;
+    let BuyFees {
+        platform_share_sol,
+        referral_share_sol,
+        project_fees_sol,
+        net_sol_to_liquidity,
+        ..
+    } = fees;
+
+    // Track referral rewards
+    if is_referral {
+        ctx.accounts.referral_tracking.pending_rewards = ctx
+            .accounts.referral_tracking
+            .pending_rewards
+            .checked_add(referral_share_sol)
+            .ok_or(ErrorCode::Overflow)?; // divide after to prevent precision loss
+    }
+    msg!("updated referral pending reward: {}", ctx.accounts.referral_tracking.pending_rewards);
+
+    // Price the buy on this mint's curve
+    let mut token_out = load_curve(vault_config)?.quote_buy(net_sol_to_liquidity)?;
+    if let Some(cap) = tokens_out_cap {
+        token_out = token_out.min(cap);
+    }
+
+    msg!("token_out: {}", token_out);
+
+
+    // Slippage check
+    require!(
+        token_out >= min_tokens_out,
+        ErrorCode::SlippageExceeded
+    );
+    // Cap maximum purchase at max_buy_bps of pool (20% by default)
+    let max_allowed = ((vault_config.token_reserve as u128)
+        .checked_mul(protocol_config.max_buy_bps as u128)
+        .ok_or(ErrorCode::Overflow)? / 10_000) as u64;
+
+    require!(
+        token_out <= max_allowed,
+        ErrorCode::AmountTooLarge
+    );
+
+    // Transfer project fees to project vault
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.temp_wsol_account.to_account_info(),
+                to: ctx.accounts.project_vault.to_account_info(),
+                authority: ctx.accounts.temp_wsol_authority.to_account_info(),
+            },
+        ),
+        project_fees_sol,
+    )?;
+
+    // Transfer Platform fees to Platform vault
+     token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.temp_wsol_account.to_account_info(),
+                to: ctx.accounts.platform_vault.to_account_info(),
+                authority: ctx.accounts.temp_wsol_authority.to_account_info(),
+            },
+        ),
+        platform_share_sol,
+    )?;
+
+    // If Referral exist then it transfers referral fees to referral vault
+    if referral_share_sol > 0 {
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.temp_wsol_account.to_account_info(),
+                    to: ctx.accounts.referral_token_vault.to_account_info(),
+                    authority: ctx.accounts.temp_wsol_authority.to_account_info(),
+                },
+            ),
+            referral_share_sol,
+        )?;
+
+        // If true it updates total earned
+        ctx.accounts.referral_tracking.total_earned = ctx
+        .accounts
+        .referral_tracking
+        .total_earned
+        .checked_add(referral_share_sol)
+        .ok_or(ErrorCode::Overflow)?;
+    }
+
+    msg!("updated total earnings referral: {}", ctx.accounts.referral_tracking.total_earned);
+
+    // Transfers the remaining SOL amount after deductions to the Liquidity vault
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.temp_wsol_account.to_account_info(),
+                to: ctx.accounts.liquidity_sol_vault.to_account_info(),
+                authority: ctx.accounts.temp_wsol_authority.to_account_info(),
+            },
+        ),
+        net_sol_to_liquidity,
+    )?;
+
+    let bump = ctx.bumps.liquidity_vault_authority; 
+    let mint_key = ctx.accounts.mint.key();
+    let signer_seeds: &[&[u8]] = &[
+        b"vault_liquidity_authority",
+        mint_key.as_ref(), 
+        &[bump],
+    ];
+    let signer_seeds = &[signer_seeds];
+
+    //Transfers the tokens to the users wallet
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.liquidity_token_vault.to_account_info(),
+                to: ctx.accounts.user_token_account.to_account_info(),
+                authority: ctx.accounts.liquidity_vault_authority.to_account_info(),
+            },
+            signer_seeds,
+        ),
+        token_out,
+    )?;
+
+    let yield_vault = &mut ctx.accounts.yield_vault;
+    record_buy(vault_config, yield_vault, &fees, token_out, now)?;
+
+     // --- Calculate the entry price after everything has gone through ---
+    let entry_price = load_curve(vault_config)?.spot_price(ctx.accounts.mint.decimals);
+    msg!("entry_price: {}", entry_price);
+
+    //--- Close Temp accounts (also refunds any WSOL left over) ---
+    token::close_account(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.temp_wsol_account.to_account_info(),
+                destination: ctx.accounts.user.to_account_info(),
+                authority: ctx.accounts.temp_wsol_authority.to_account_info(),
+            },
+        )
+    )?;
+
+
+    emit!(BuyEvent {
+        user: ctx.accounts.user.key(),
+        mint: ctx.accounts.mint.key(),
+        position_id: args.position_id,
+        entry_price: entry_price as u64,
+        sol_in: net_sol_to_liquidity,
+        tokens_out: token_out,
+        pending_rewards: ctx.accounts.referral_tracking.pending_rewards, 
+        total_earned: ctx.accounts.referral_tracking.total_earned,
+        total_fees_earnings: yield_vault.total_earned,
+        creator_vault: vault_config.creator_vault,
+        platform_vault: vault_config.platform_vault,
+        referral_share_sol: referral_share_sol,
+        token_reserve: vault_config.token_reserve,
+        sol_reserve: vault_config.sol_reserve,
+        accumulated_c: vault_config.accumulated_c,
+        virtual_sol: vault_config.virtual_sol,
+        virtual_tokens: vault_config.virtual_tokens,
+        acc_reward_per_share: yield_vault.acc_reward_per_share as u64,
+        pre_sale_acc_fee_per_share: token_config.pre_sale_acc_fee_per_share as u64,
+        interest: vault_config.yield_vault,
+        pre_sale_fee_sol: vault_config.pre_sale_vault,
+        vault_bump: bump,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+
+// Fee split for a buy of `sol_amount` gross SOL
+#[derive(Clone, Copy)]
+pub struct BuyFees {
+    pub creator_fee_sol: u64,
+    pub pre_sale_fee_sol: u64,
+    pub platform_share_sol: u64,
+    pub referral_share_sol: u64,
+    pub yield_share_sol: u64,
+    pub total_fees_sol: u64,
+    pub project_fees_sol: u64, // creator + yield + presale, all held in the project vault
+    pub net_sol_to_liquidity: u64,
+}
+
+// Splits a buy into fees and the net SOL that reaches the curve. Presale fees are booked
+// into the presale accumulator here, everything else is left to the caller and record_buy.
+pub fn split_buy_fees(
+    sol_amount: u64,
+    token_config: &mut TokenConfig,
+    vault_config: &mut VaultConfig,
+    protocol_config: &ProtocolConfig,
+    is_referral: bool,
+    now: i64,
+) -> Result<BuyFees> {
     let pre_sale_participants = token_config.pre_sale_participants;
     msg!("pre_sale_participants: {}", pre_sale_participants);
     let mut pre_sale_fee_sol = 0u64;
@@ -253,20 +520,18 @@ pub fn buy_token(ctx: Context<BuyToken>, args: BuyTokenArgs) -> Result<()> {
     msg!("pre_sale_fee_sol: {}", pre_sale_fee_sol);
     msg!("creator_fee_sol: {}", creator_fee_sol);
 
-    let total_platform_fee_bps = token_config.platform_fee_bps as u64; // 100 = 1%
-    let referral_cut_bps = 40; // 0.4%
-    let mut yield_cut_bps = 10; // 0.1%
+    let total_platform_fee_bps = protocol_config.platform_fee_bps as u64; // default 100 = 1%
+    let referral_cut_bps = protocol_config.referral_cut_bps as u64; // default 0.4%
+    let mut yield_cut_bps = protocol_config.yield_cut_bps as u64; // default 0.1%
 
     let mut platform_share_sol = ((sol_amount as u64)
         .checked_mul(total_platform_fee_bps as u64)
         .ok_or(ErrorCode::Overflow)? / 10_000) as u64;
 
 
-    let is_referral = ctx.accounts.referrer.key() != ctx.accounts.platform_vault.key();
-
     let referral_share_sol = if is_referral {
         let referral_fee = ((sol_amount as u64)
-            .checked_mul(referral_cut_bps as u64)
+            .checked_mul(referral_cut_bps)
             .ok_or(ErrorCode::Overflow)? / 10_000) as u64;
 
         let platform_fee = platform_share_sol
@@ -276,17 +541,10 @@ pub fn buy_token(ctx: Context<BuyToken>, args: BuyTokenArgs) -> Result<()> {
         // Overwrite platform_share_sol with reduced value
         platform_share_sol = platform_fee;
 
-        // Track referral rewards
-        ctx.accounts.referral_tracking.pending_rewards = ctx
-            .accounts.referral_tracking
-            .pending_rewards
-            .checked_add(referral_fee)
-            .ok_or(ErrorCode::Overflow)?; // divide after to prevent precision loss
-
         referral_fee
     } else {
         // If no referrer, increase yield share from 0.1% → 0.2%
-        yield_cut_bps = 20; // 0.2%
+        yield_cut_bps = protocol_config.yield_cut_no_referral_bps as u64; // default 0.2%
 
         0
     };
@@ -303,7 +561,6 @@ pub fn buy_token(ctx: Context<BuyToken>, args: BuyTokenArgs) -> Result<()> {
     msg!("platform_share_sol: {}", platform_share_sol);
     msg!("yield_share_sol: {}", yield_share_sol);
     msg!("referral_share_sol: {}", referral_share_sol);
-    msg!("updated referral pending reward: {}", ctx.accounts.referral_tracking.pending_rewards);
 
     // --- Sum all fee components safely ---
     let total_fees_sol = creator_fee_sol
@@ -330,144 +587,35 @@ pub fn buy_token(ctx: Context<BuyToken>, args: BuyTokenArgs) -> Result<()> {
         .ok_or(ErrorCode::Underflow)?;
     msg!("net_sol_to_liquidity: {}", net_sol_to_liquidity);
 
-    let denominator = (vault_config.sol_reserve as i128)
-        .checked_add(vault_config.virtual_sol as i128)
-        .and_then(|sum| sum.checked_add(vault_config.accumulated_c as i128))
-        .and_then(|sum| sum.checked_add(net_sol_to_liquidity as i128))
-        .and_then(|sum| u64::try_from(sum).ok())
-        .ok_or(ErrorCode::Overflow)?;
-
-
-    let effective_token_reserve = vault_config.virtual_tokens as u128
-    + vault_config.token_reserve as u128;
-    
-
-    let numerator = (net_sol_to_liquidity as u128)
-        .checked_mul(effective_token_reserve)
-        .ok_or(ErrorCode::Overflow)?;
-  
-
-    let token_out = numerator
-        .checked_div(denominator as u128)
-        .ok_or(ErrorCode::Overflow)? as u64;
-
-    msg!("token_out: {}", token_out);
-
-
-    // Slippage check
-    require!(
-        token_out >= min_tokens_out,
-        ErrorCode::SlippageExceeded
-    );
-    // Cap maximum purchase at 20% of pool
-    let max_allowed = vault_config.token_reserve.checked_div(5)
-        .ok_or(ErrorCode::Overflow)?;
-
-    require!(
-        token_out <= max_allowed,
-        ErrorCode::AmountTooLarge
-    );
-
-    // Transfer project fees to project vault
-    token::transfer(
-        CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.temp_wsol_account.to_account_info(),
-                to: ctx.accounts.project_vault.to_account_info(),
-                authority: ctx.accounts.temp_wsol_authority.to_account_info(),
-            },
-        ),
-        project_fees_sol,
-    )?;
-
-    // Transfer Platform fees to Platform vault
-     token::transfer(
-        CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.temp_wsol_account.to_account_info(),
-                to: ctx.accounts.platform_vault.to_account_info(),
-                authority: ctx.accounts.temp_wsol_authority.to_account_info(),
-            },
-        ),
+    Ok(BuyFees {
+        creator_fee_sol,
+        pre_sale_fee_sol,
         platform_share_sol,
-    )?;
-
-    // If Referral exist then it transfers referral fees to referral vault
-    if referral_share_sol > 0 {
-        token::transfer(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.temp_wsol_account.to_account_info(),
-                    to: ctx.accounts.referral_token_vault.to_account_info(),
-                    authority: ctx.accounts.temp_wsol_authority.to_account_info(),
-                },
-            ),
-            referral_share_sol,
-        )?;
-
-        // If true it updates total earned
-        ctx.accounts.referral_tracking.total_earned = ctx
-        .accounts
-        .referral_tracking
-        .total_earned
-        .checked_add(referral_share_sol)
-        .ok_or(ErrorCode::Overflow)?;
-    }
-
-    msg!("updated total earnings referral: {}", ctx.accounts.referral_tracking.total_earned);
-
-    // Transfers the remaining SOL amount after deductions to the Liquidity vault
-    token::transfer(
-        CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.temp_wsol_account.to_account_info(),
-                to: ctx.accounts.liquidity_sol_vault.to_account_info(),
-                authority: ctx.accounts.temp_wsol_authority.to_account_info(),
-            },
-        ),
+        referral_share_sol,
+        yield_share_sol,
+        total_fees_sol,
+        project_fees_sol,
         net_sol_to_liquidity,
-    )?;
-
-    let bump = ctx.bumps.liquidity_vault_authority; 
-    let mint_key = ctx.accounts.mint.key();
-    let signer_seeds: &[&[u8]] = &[
-        b"vault_liquidity_authority",
-        mint_key.as_ref(), 
-        &[bump],
-    ];
-    let signer_seeds = &[signer_seeds];
-
-    //Transfers the tokens to the users wallet
-    token::transfer(
-        CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.liquidity_token_vault.to_account_info(),
-                to: ctx.accounts.user_token_account.to_account_info(),
-                authority: ctx.accounts.liquidity_vault_authority.to_account_info(),
-            },
-            signer_seeds,
-        ),
-        token_out,
-    )?;
-
-    // Get vault mutable
-    let yield_vault = &mut ctx.accounts.yield_vault;
-   
+    })
+}
 
+// Books a settled buy: yield rewards, curve reserves and fee stats
+pub fn record_buy(
+    vault_config: &mut VaultConfig,
+    yield_vault: &mut YieldVault,
+    fees: &BuyFees,
+    token_out: u64,
+    now: i64,
+) -> Result<()> {
     // Compute acc_reward_per_share delta
-    if yield_vault.total_staked > 0 {
+    if yield_vault.total_weighted_stake > 0 {
         // new rewards = interest added (WSOL)
-        let new_rewards = yield_share_sol as u128;
+        let new_rewards = fees.yield_share_sol as u128;
 
         // Update cumulative reward per share
         yield_vault.acc_reward_per_share = yield_vault
             .acc_reward_per_share
-            .checked_add(new_rewards * PRECISION / yield_vault.total_staked as u128)
+            .checked_add(new_rewards * PRECISION / yield_vault.total_weighted_stake as u128)
             .ok_or(ErrorCode::Overflow)?;
 
         yield_vault.last_accrual_ts = now;
@@ -478,7 +626,7 @@ pub fn buy_token(ctx: Context<BuyToken>, args: BuyTokenArgs) -> Result<()> {
 
     // --- Update SOL reserves ---
     vault_config.sol_reserve = vault_config.sol_reserve
-        .checked_add(net_sol_to_liquidity)
+        .checked_add(fees.net_sol_to_liquidity)
         .ok_or(ErrorCode::Overflow)?;
 
     msg!("updated sol reserve: {}", vault_config.sol_reserve);
@@ -493,84 +641,59 @@ pub fn buy_token(ctx: Context<BuyToken>, args: BuyTokenArgs) -> Result<()> {
     // --- Update Project fees calculation ---
     vault_config.creator_vault = vault_config
             .creator_vault
-            .checked_add(creator_fee_sol)
+            .checked_add(fees.creator_fee_sol)
             .ok_or(ErrorCode::Overflow)?; 
     msg!("updated Creator vault share: {}", vault_config.creator_vault);
 
     // --- Update Creators Total Earnings ---
     yield_vault.total_earned = yield_vault
             .total_earned
-            .checked_add(creator_fee_sol)
+            .checked_add(fees.creator_fee_sol)
             .ok_or(ErrorCode::Overflow)?; 
     msg!("updated creator total earned vault share: {}", yield_vault.total_earned);
 
      // --- Update Platform fees Stats ---
     vault_config.platform_vault = vault_config
             .platform_vault
-            .checked_add(platform_share_sol)
+            .checked_add(fees.platform_share_sol)
             .ok_or(ErrorCode::Overflow)?;
     msg!("updated platform vault share: {}", vault_config.platform_vault);
 
     vault_config.yield_vault = vault_config
             .yield_vault
-            .checked_add(yield_share_sol as u64)
+            .checked_add(fees.yield_share_sol)
             .ok_or(ErrorCode::Overflow)?;
     msg!("updated yield vault share: {}", vault_config.yield_vault);
 
-     // --- Calculate the entry price after everything has gone through ---
-    let entry_price = get_price_from_vault(
-        vault_config.sol_reserve,
-        vault_config.accumulated_c,
-        vault_config.token_reserve,
-        vault_config.virtual_sol,
-        vault_config.virtual_tokens,
-        ctx.accounts.mint.decimals,
-    );
-    msg!("entry_price: {}", entry_price);
-
-    //--- Close Temp accounts ---
-    token::close_account(
-        CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            CloseAccount {
-                account: ctx.accounts.temp_wsol_account.to_account_info(),
-                destination: ctx.accounts.user.to_account_info(),
-                authority: ctx.accounts.temp_wsol_authority.to_account_info(),
-            },
-        )
-    )?;
+    Ok(())
+}
 
+// Shared by buy and sell: a wallet may trade a given mint at most once per second.
+// The first trade (timestamp == 0) always passes.
+pub fn check_trade_cooldown(
+    trade_state: &mut UserTradeState,
+    bump: u8,
+    user: Pubkey,
+    mint: Pubkey,
+    now: i64,
+) -> Result<()> {
+    if trade_state.owner == Pubkey::default() {
+        trade_state.owner = user;
+        trade_state.mint = mint;
+        trade_state.bump = bump;
+    }
 
-    emit!(BuyEvent {
-        user: ctx.accounts.user.key(),
-        mint: ctx.accounts.mint.key(),
-        position_id: args.position_id,
-        entry_price: entry_price as u64,
-        sol_in: net_sol_to_liquidity,
-        tokens_out: token_out,
-        pending_rewards: ctx.accounts.referral_tracking.pending_rewards, 
-        total_earned: ctx.accounts.referral_tracking.total_earned,
-        total_fees_earnings: yield_vault.total_earned,
-        creator_vault: vault_config.creator_vault,
-        platform_vault: vault_config.platform_vault,
-        referral_share_sol: referral_share_sol,
-        token_reserve: vault_config.token_reserve,
-        sol_reserve: vault_config.sol_reserve,
-        accumulated_c: vault_config.accumulated_c,
-        virtual_sol: vault_config.virtual_sol,
-        virtual_tokens: vault_config.virtual_tokens,
-        acc_reward_per_share: yield_vault.acc_reward_per_share as u64,
-        pre_sale_acc_fee_per_share: token_config.pre_sale_acc_fee_per_share as u64,
-        interest: vault_config.yield_vault,
-        pre_sale_fee_sol: vault_config.pre_sale_vault,
-        vault_bump: bump,
-        timestamp: Clock::get()?.unix_timestamp,
-    });
+    if trade_state.last_trade_timestamp > 0 {
+        require!(
+            now - trade_state.last_trade_timestamp >= 1,
+            ErrorCode::TradeCooldown
+        );
+    }
 
+    trade_state.last_trade_timestamp = now;
     Ok(())
 }
 
-
 #[event]
 pub struct BuyEvent {
     pub user: Pubkey,
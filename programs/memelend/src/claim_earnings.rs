@@ -12,10 +12,20 @@ pub struct ClaimEarnings<'info> {
 
     pub mint: Account<'info, Mint>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"yield_vault", mint.key().as_ref()],
+        bump = yield_vault.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub yield_vault: Box<Account<'info, YieldVault>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"vault_config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub vault_config: Box<Account<'info, VaultConfig>>,
 
     #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
@@ -30,8 +40,13 @@ pub struct ClaimEarnings<'info> {
     #[account(address = user.key())]
     pub temp_wsol_authority: Signer<'info>,
     
-    #[account(mut)]
-    pub project_vault: Account<'info, TokenAccount>, 
+    #[account(
+        mut,
+        seeds = [b"vault_project", mint.key().as_ref()],
+        bump,
+        token::authority = vault_authority,
+    )]
+    pub project_vault: Account<'info, TokenAccount>,
     
     /// CHECK: PDA
     #[account(
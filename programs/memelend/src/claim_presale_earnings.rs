@@ -13,13 +13,29 @@ pub struct ClaimPresaleRewards<'info> {
 
     pub mint: Account<'info, Mint>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"yield_vault", mint.key().as_ref()],
+        bump = yield_vault.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub yield_vault: Account<'info, YieldVault>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub token_config: Box<Account<'info, TokenConfig>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"user_presale_position", user.key().as_ref(), mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+        constraint = user_presale_position.user == user.key() @ ErrorCode::Unauthorized,
+    )]
     pub user_presale_position: Box<Account<'info, UserPreSalePosition>>,
 
     #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
@@ -34,8 +50,13 @@ pub struct ClaimPresaleRewards<'info> {
     #[account(address = user.key())]
     pub temp_wsol_authority: Signer<'info>,
     
-    #[account(mut)]
-    pub project_vault: Account<'info, TokenAccount>, 
+    #[account(
+        mut,
+        seeds = [b"vault_project", mint.key().as_ref()],
+        bump,
+        token::authority = vault_authority,
+    )]
+    pub project_vault: Account<'info, TokenAccount>,
     
     /// CHECK: PDA
     #[account(
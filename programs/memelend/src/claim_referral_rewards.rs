@@ -8,10 +8,13 @@ pub struct ClaimReferralRewards<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
 
-    #[account(mut)]
+    #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
     pub wsol_mint: Account<'info, Mint>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        token::mint = wsol_mint,
+    )]
     pub temp_wsol_account: Box<Account<'info, TokenAccount>>,
 
     #[account(address = user.key())]
@@ -24,13 +27,25 @@ pub struct ClaimReferralRewards<'info> {
     )]
     pub referral_vault_authority: AccountInfo<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"vault_referral", referrer.key().as_ref()],
+        bump,
+        has_one = referrer @ ErrorCode::Unauthorized,
+    )]
     pub referral_tracking: Account<'info, ReferralVault>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"vault_referral_token", referrer.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+        token::authority = referral_vault_authority,
+    )]
     pub referral_token_vault: Box<Account<'info, TokenAccount>>,
 
-    /// CHECK: Only used for seeds/validation
+    /// CHECK: Only used for seeds/validation, must be the claiming wallet
+    #[account(address = user.key() @ ErrorCode::Unauthorized)]
     pub referrer: UncheckedAccount<'info>,
 
     pub token_program: Program<'info, Token>,
@@ -2,6 +2,7 @@ use anchor_lang::prelude::*;
 use anchor_spl::token::{self, Transfer, Token, TokenAccount, Mint};
 use crate::{ YieldVault, ErrorCode, UserYieldPosition};
 use anchor_spl::token::spl_token::native_mint::ID as NATIVE_MINT_ID;
+use crate::emissions::{checkpoint_position, rebase_position};
 
 
 pub const PRECISION: u128 = 1_000_000_000_000; // 1e12 for reward per share math
@@ -15,10 +16,21 @@ pub struct ClaimRewards<'info> {
 
     pub mint: Account<'info, Mint>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"yield_vault", mint.key().as_ref()],
+        bump = yield_vault.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub yield_vault: Account<'info, YieldVault>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"user_yield", mint.key().as_ref(), user.key().as_ref()],
+        bump = user_yield_position.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+        constraint = user_yield_position.owner == user.key() @ ErrorCode::Unauthorized,
+    )]
     pub user_yield_position: Account<'info, UserYieldPosition>,
 
     #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
@@ -33,8 +45,13 @@ pub struct ClaimRewards<'info> {
     #[account(address = user.key())]
     pub temp_wsol_authority: Signer<'info>,
     
-    #[account(mut)]
-    pub project_vault: Account<'info, TokenAccount>, 
+    #[account(
+        mut,
+        seeds = [b"vault_project", mint.key().as_ref()],
+        bump,
+        token::authority = vault_authority,
+    )]
+    pub project_vault: Account<'info, TokenAccount>,
     
     /// CHECK: PDA
     #[account(
@@ -43,6 +60,23 @@ pub struct ClaimRewards<'info> {
     )]
     pub vault_authority: UncheckedAccount<'info>,
 
+    // Only needed once the position has emissions to claim
+    #[account(
+        mut,
+        seeds = [b"vault_emission", mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = vault_authority,
+    )]
+    pub emission_vault: Option<Box<Account<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        constraint = user_token_account.mint == mint.key() @ ErrorCode::InvalidMint,
+        constraint = user_token_account.owner == user.key() @ ErrorCode::Unauthorized,
+    )]
+    pub user_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
@@ -66,9 +100,12 @@ pub fn claim_rewards(ctx: Context<ClaimRewards>) -> Result<()> {
     );
 
 
+    // Book token emissions and slot rewards up to now
+    checkpoint_position(vault, user_position, now_ts)?;
+    let emission = user_position.emission_owed;
   
     // Calculate user pending rewards
-    let pending = (user_position.deposited as u128)
+    let pending = (user_position.weighted_stake as u128)
         .checked_mul(vault.acc_reward_per_share)
         .unwrap()
         / PRECISION
@@ -76,15 +113,7 @@ pub fn claim_rewards(ctx: Context<ClaimRewards>) -> Result<()> {
 
    
     // Checks to see if the User has rewards available
-    require!(pending > 0, ErrorCode::NoRewardsAvailable);
-
-    // Transfer pending rewards from Interest Vault -> User
-    let cpi_accounts = Transfer {
-        from: ctx.accounts.project_vault.to_account_info(),
-        to: ctx.accounts.temp_wsol_account.to_account_info(),
-        authority: ctx.accounts.vault_authority.to_account_info(), // PDA authority
-    };
-    let cpi_program = ctx.accounts.token_program.to_account_info();
+    require!(pending > 0 || emission > 0, ErrorCode::NoRewardsAvailable);
 
     let mint_key = ctx.accounts.mint.key();
         let signer_seeds = &[
@@ -93,17 +122,51 @@ pub fn claim_rewards(ctx: Context<ClaimRewards>) -> Result<()> {
             &[ctx.bumps.vault_authority],
         ];
 
-    token::transfer(
-        CpiContext::new_with_signer(cpi_program, cpi_accounts, &[signer_seeds]),
-        pending as u64,
-    )?;
-
- 
-    //  Update user reward debt and claimed_total
-    user_position.reward_debt = (user_position.deposited as u128)
-        .checked_mul(vault.acc_reward_per_share)
-        .unwrap()
-        / PRECISION;
+    if pending > 0 {
+        // Transfer pending rewards from Interest Vault -> User
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.project_vault.to_account_info(),
+            to: ctx.accounts.temp_wsol_account.to_account_info(),
+            authority: ctx.accounts.vault_authority.to_account_info(), // PDA authority
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+
+        token::transfer(
+            CpiContext::new_with_signer(cpi_program, cpi_accounts, &[signer_seeds]),
+            pending as u64,
+        )?;
+    }
+
+    // Emissions are paid in the project token
+    if emission > 0 {
+        let (Some(emission_vault), Some(user_token_account)) =
+            (&ctx.accounts.emission_vault, &ctx.accounts.user_token_account)
+        else {
+            return err!(ErrorCode::EmissionAccountsMissing);
+        };
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: emission_vault.to_account_info(),
+                    to: user_token_account.to_account_info(),
+                    authority: ctx.accounts.vault_authority.to_account_info(),
+                },
+                &[signer_seeds],
+            ),
+            emission,
+        )?;
+
+        user_position.emission_owed = 0;
+        user_position.emission_claimed = user_position
+            .emission_claimed
+            .checked_add(emission)
+            .ok_or(ErrorCode::Overflow)?;
+    }
+
+    // An expired lock stops boosting from here on
+    rebase_position(vault, user_position, now_ts)?;
     user_position.claimed_total = user_position.claimed_total.checked_add(pending as u64).unwrap();
     user_position.last_action_ts = now_ts;
 
@@ -113,6 +176,7 @@ pub fn claim_rewards(ctx: Context<ClaimRewards>) -> Result<()> {
         owner: ctx.accounts.user.key(),
         mint: ctx.accounts.mint.key(),
         claimed_amount: pending as u64,
+        emission_claimed: emission,
         new_reward_debt: user_position.reward_debt,
         total_claimed: user_position.claimed_total,
         last_accrual_ts: now_ts,
@@ -128,6 +192,7 @@ pub struct ClaimRewardsEvent {
     pub owner: Pubkey,
     pub mint: Pubkey,
     pub claimed_amount: u64,
+    pub emission_claimed: u64,
     pub new_reward_debt: u128,
     pub total_claimed: u64,
     pub last_accrual_ts: i64,
@@ -1,6 +1,10 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{self, Transfer, Token, TokenAccount, Mint};
-use crate::{ YieldVault, ErrorCode, UserYieldPosition};
+use crate::{ YieldVault, ErrorCode, UserYieldPosition, WithdrawQueue, UserAccount};
+use crate::withdraw_queue::{init_withdraw_queue, available_liquidity};
+use crate::user_account::remove_position;
+use crate::emissions::{checkpoint_position, rebase_position};
+use crate::yield_lock::require_unlocked;
 
 
 
@@ -12,13 +16,35 @@ pub struct WithdrawYield<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"yield_vault", mint.key().as_ref()],
+        bump = yield_vault.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub yield_vault: Account<'info, YieldVault>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"user_yield", mint.key().as_ref(), user.key().as_ref()],
+        bump = user_yield_position.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+        constraint = user_yield_position.owner == user.key() @ ErrorCode::Unauthorized,
+    )]
     pub user_yield_position: Account<'info, UserYieldPosition>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"user_account", user.key().as_ref()],
+        bump = user_account.bump,
+    )]
+    pub user_account: Box<Account<'info, UserAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_lending", mint.key().as_ref()],
+        bump,
+    )]
     pub lending_vault: Box<Account<'info, TokenAccount>>,
 
     #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
@@ -33,8 +59,13 @@ pub struct WithdrawYield<'info> {
     )]
     pub vault_authority: AccountInfo<'info>,
 
-    #[account(mut)]
-    pub project_vault: Account<'info, TokenAccount>, 
+    #[account(
+        mut,
+        seeds = [b"vault_project", mint.key().as_ref()],
+        bump,
+        token::authority = vault_authority,
+    )]
+    pub project_vault: Account<'info, TokenAccount>,
 
     /// CHECK: PDA authority for lending vault
     #[account(
@@ -43,15 +74,31 @@ pub struct WithdrawYield<'info> {
     )]
     pub lending_vault_authority: AccountInfo<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = temp_wsol_account.mint == anchor_spl::token::spl_token::native_mint::ID @ ErrorCode::InvalidWsolMint,
+    )]
     pub temp_wsol_account: Box<Account<'info, TokenAccount>>,
 
     #[account(address = user.key())]
     pub temp_wsol_authority: Signer<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = user_token_account.mint == mint.key() @ ErrorCode::InvalidMint,
+        constraint = user_token_account.owner == user.key() @ ErrorCode::Unauthorized,
+    )]
     pub user_token_account: Box<Account<'info, TokenAccount>>,
 
+    #[account(
+        init_if_needed,
+        payer = user,
+        seeds = [b"withdraw_queue", mint.key().as_ref()],
+        bump,
+        space = 8 + WithdrawQueue::LEN,
+    )]
+    pub withdraw_queue: Box<Account<'info, WithdrawQueue>>,
+
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
@@ -71,8 +118,14 @@ pub fn withdraw_yield(ctx: Context<WithdrawYield>, amount: u64, position_id: u64
     // Check deposit value vs amount
     require!(amount <= user_position.deposited, ErrorCode::InsufficientBalance);
 
+    // Locked principal stays put until the lock expires
+    require_unlocked(user_position, now_ts)?;
+
+    // Book token emissions and slot rewards before the stake changes
+    checkpoint_position(vault, user_position, now_ts)?;
+
     // Calculate user pending rewards
-    let pending = (user_position.deposited as u128)
+    let pending = (user_position.weighted_stake as u128)
         .checked_mul(vault.acc_reward_per_share)
         .unwrap()
         / PRECISION
@@ -106,7 +159,13 @@ pub fn withdraw_yield(ctx: Context<WithdrawYield>, amount: u64, position_id: u64
         // (Optionally implement CPI transfer from Interest Vault here)
     }
 
-   
+    // Queued requests are paid first, the rest of the vault may be lent out
+    init_withdraw_queue(&mut ctx.accounts.withdraw_queue, ctx.bumps.withdraw_queue, ctx.accounts.mint.key());
+    require!(
+        amount <= available_liquidity(ctx.accounts.lending_vault.amount, &ctx.accounts.withdraw_queue),
+        ErrorCode::InsufficientLendingLiquidity
+    );
+
     // Transfer principal tokens back to user
     let cpi_accounts = Transfer {
         from: ctx.accounts.lending_vault.to_account_info(),
@@ -133,17 +192,16 @@ pub fn withdraw_yield(ctx: Context<WithdrawYield>, amount: u64, position_id: u64
     // Update user deposited and vault total_staked
     user_position.deposited = user_position.deposited.checked_sub(amount).unwrap();
     vault.total_staked = vault.total_staked.checked_sub(amount).unwrap();
-
- 
-    // Update user reward debt
-    user_position.reward_debt = (user_position.deposited as u128)
-        .checked_mul(vault.acc_reward_per_share)
-        .unwrap()
-        / PRECISION;
+    rebase_position(vault, user_position, now_ts)?;
     user_position.last_action_ts = now_ts;
 
     vault.last_accrual_ts = now_ts;
 
+    // Fully withdrawn positions drop off the open list until the next deposit
+    if user_position.deposited == 0 {
+        remove_position(&mut ctx.accounts.user_account, &user_position.key());
+    }
+
     emit!(WithdrawYieldEvent {
         owner: user_position.owner,
         mint: ctx.accounts.mint.key(),
@@ -1,6 +1,10 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{self, Transfer, CloseAccount, TokenAccount, Token, Mint};
-use crate::{VaultConfig, TokenConfig, Position, ErrorCode, ReferralVault, YieldVault};
+use crate::{VaultConfig, TokenConfig, ProtocolConfig, Position, ErrorCode, ReferralVault, YieldVault, UserAccount};
+use crate::curve::{load_curve, effective_sol};
+use crate::interest::{accrue_borrow_index, borrow_interest};
+use crate::insurance_fund::{split_shortfall, draw_insurance};
+use crate::user_account::remove_position;
 
 
 pub const PRECISION: u128 = 1_000_000_000_000; // 1e12 for reward per share math
@@ -20,10 +24,13 @@ pub struct ClosePosition<'info> {
     #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
     pub wsol_mint: Account<'info, Mint>,
 
-    #[account(mut)]
+    #[account(address = token_config.mint @ ErrorCode::InvalidMint)]
     pub mint: Box<Account<'info, Mint>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = temp_wsol_account.mint == anchor_spl::token::spl_token::native_mint::ID @ ErrorCode::InvalidWsolMint,
+    )]
     pub temp_wsol_account: Box<Account<'info, TokenAccount>>,
 
     /// CHECK: signer PDA
@@ -33,15 +40,33 @@ pub struct ClosePosition<'info> {
     #[account(
         mut,
         // close = user
+        seeds = [b"position", user.key().as_ref(), mint.key().as_ref(), &args.position_id.to_le_bytes()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+        constraint = position.owner == user.key() @ ErrorCode::Unauthorized,
     )]
     pub position: Box<Account<'info, Position>>,
 
+    #[account(
+        mut,
+        seeds = [b"user_account", user.key().as_ref()],
+        bump = user_account.bump,
+    )]
+    pub user_account: Box<Account<'info, UserAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub token_config: Box<Account<'info, TokenConfig>>,
 
     #[account(
         mut,
         seeds = [b"vault_config", mint.key().as_ref()],
-        bump
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
     )]
     pub vault_config: Account<'info, VaultConfig>,
 
@@ -54,11 +79,17 @@ pub struct ClosePosition<'info> {
 
     #[account(
         mut,
-        constraint = token_liquidity_vault.mint == token_config.mint,
+        seeds = [b"vault_liquidity", mint.key().as_ref()],
+        bump,
+        constraint = token_liquidity_vault.mint == token_config.mint @ ErrorCode::InvalidMint,
     )]
     pub token_liquidity_vault: Box<Account<'info, TokenAccount>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"vault_lending", mint.key().as_ref()],
+        bump,
+    )]
     pub lending_vault: Account<'info, TokenAccount>,
 
     #[account(
@@ -87,6 +118,15 @@ pub struct ClosePosition<'info> {
     )]
     pub platform_vault: Box<Account<'info, TokenAccount>>,
 
+    #[account(
+        mut,
+        seeds = [b"vault_insurance", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+        token::authority = vault_authority,
+    )]
+    pub insurance_vault: Box<Account<'info, TokenAccount>>,
+
     /// CHECK: PDA authority for referral
     #[account(
         seeds = [b"vault_referral_authority", referrer.key().as_ref()],
@@ -124,20 +164,26 @@ pub struct ClosePosition<'info> {
     )]
     pub wsol_vault_authority: UncheckedAccount<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"yield_vault", mint.key().as_ref()],
+        bump = yield_vault.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub yield_vault: Account<'info, YieldVault>,
 
     /// CHECK: Only used for seeds/validation
     pub referrer: UncheckedAccount<'info>,
 
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Box<Account<'info, ProtocolConfig>>,
+
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
 }
 
 pub fn close_position(ctx: Context<ClosePosition>, args: ClosePositionArgs) -> Result<()> {
-    let _position_id = args.position_id;
-
     let position = &ctx.accounts.position;
 
     // Check to see if its the correct position ID
@@ -145,37 +191,184 @@ pub fn close_position(ctx: Context<ClosePosition>, args: ClosePositionArgs) -> R
         args.position_id == position.position_id,
         ErrorCode::InvalidPositionId
     );
+    require!(position.open, ErrorCode::PositionAlreadyClosed);
+
+    let borrowed_tokens = position.amount;
+    let original_collateral = position.collateral;
+
+    // Slippage check
+    require!(
+        borrowed_tokens >= args.min_token_repay,
+        ErrorCode::SlippageExceeded
+    );
 
+    let settlement = settle_short(
+        ctx.accounts.settle_accounts(&ctx.bumps),
+        borrowed_tokens,
+        original_collateral,
+    )?;
+
+    let position = &mut ctx.accounts.position;
+    position.open = false;
+
+    remove_position(&mut ctx.accounts.user_account, &position.key());
+
+    token::close_account(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.temp_wsol_account.to_account_info(),
+                destination: ctx.accounts.user.to_account_info(),
+                authority: ctx.accounts.temp_wsol_authority.to_account_info(),
+            },
+        )
+    )?;
+
+    let vault_config = &ctx.accounts.vault_config;
+    let token_config = &ctx.accounts.token_config;
+    let yield_vault = &ctx.accounts.yield_vault;
+    let pnl_difference = settlement.pnl.unsigned_abs();
+
+    emit!(ClosePositionEvent {
+        user: ctx.accounts.user.key(),
+        mint: ctx.accounts.mint.key(),
+        repaid_tokens: borrowed_tokens,
+        collateral_returned: settlement.collateral_returned,
+        total_fees: settlement.total_fees_sol,
+        interest: vault_config.yield_vault,
+        accumulated_c_after: vault_config.accumulated_c,
+        acc_reward_per_share: yield_vault.acc_reward_per_share as u64,
+        pending_rewards: ctx.accounts.referral_tracking.pending_rewards, 
+        total_earned: ctx.accounts.referral_tracking.total_earned,
+        total_fees_earnings: yield_vault.total_earned,
+        creator_vault: vault_config.creator_vault,
+        platform_vault: vault_config.platform_vault,
+        referral_share_sol: settlement.referral_share_sol,
+        borrow_interest: settlement.interest_sol,
+        insurance_fee: settlement.insurance_sol,
+        bad_debt: settlement.bad_debt_sol,
+        token_reserve: vault_config.token_reserve,
+        sol_reserve: vault_config.sol_reserve,
+        position_id: args.position_id,
+        pnl: if settlement.pnl > 0 { pnl_difference as i64 } else { -(pnl_difference as i64) },
+        exit_price: settlement.value_now,
+        virtual_sol: vault_config.virtual_sol,
+        virtual_tokens: vault_config.virtual_tokens,
+        pre_sale_acc_fee_per_share: token_config.pre_sale_acc_fee_per_share as u64,
+        pre_sale_fee_sol: vault_config.pre_sale_vault,
+    });
+
+    Ok(())
+}
+
+
+// Result of settling (part of) a short
+pub struct ShortSettlement {
+    pub value_now: u64,
+    pub pnl: i128,
+    pub total_fees_sol: u64,
+    pub referral_share_sol: u64,
+    pub interest_sol: u64,
+    pub insurance_sol: u64,
+    pub bad_debt_sol: u64,
+    pub keeper_fee_sol: u64,
+    pub collateral_returned: u64,
+}
+
+// Accounts settle_short works on, borrowed out of whichever context is settling the short
+pub struct ShortSettleAccounts<'a, 'info> {
+    pub mint: &'a Account<'info, Mint>,
+    pub position: &'a Account<'info, Position>,
+    pub token_config: &'a mut Account<'info, TokenConfig>,
+    pub vault_config: &'a mut Account<'info, VaultConfig>,
+    pub yield_vault: &'a mut Account<'info, YieldVault>,
+    pub protocol_config: &'a Account<'info, ProtocolConfig>,
+    pub vault_authority: &'a AccountInfo<'info>,
+    pub token_liquidity_vault: &'a Account<'info, TokenAccount>,
+    pub liquidity_vault_authority: &'a AccountInfo<'info>,
+    pub lending_vault: &'a Account<'info, TokenAccount>,
+    pub liquidity_sol_vault: &'a Account<'info, TokenAccount>,
+    pub wsol_vault_authority: &'a AccountInfo<'info>,
+    pub project_vault: &'a Account<'info, TokenAccount>,
+    pub platform_vault: &'a Account<'info, TokenAccount>,
+    pub insurance_vault: &'a Account<'info, TokenAccount>,
+    // Referral tracking and token vault, None when the trade has no referrer
+    pub referral: Option<(&'a mut Account<'info, ReferralVault>, &'a Account<'info, TokenAccount>)>,
+    // wSOL account the remaining collateral is paid into
+    pub payout: &'a Account<'info, TokenAccount>,
+    // Keeper account and fee bps, taken out of the payout
+    pub keeper: Option<(&'a Account<'info, TokenAccount>, u16)>,
+    pub token_program: &'a Program<'info, Token>,
+    pub vault_authority_bump: u8,
+    pub liquidity_vault_authority_bump: u8,
+    pub wsol_vault_authority_bump: u8,
+}
+
+impl<'info> ClosePosition<'info> {
+    pub fn settle_accounts<'a>(&'a mut self, bumps: &ClosePositionBumps) -> ShortSettleAccounts<'a, 'info> {
+        let is_referral = self.referrer.key() != self.platform_vault.key();
+
+        ShortSettleAccounts {
+            mint: &self.mint,
+            position: &self.position,
+            token_config: &mut self.token_config,
+            vault_config: &mut self.vault_config,
+            yield_vault: &mut self.yield_vault,
+            protocol_config: &self.protocol_config,
+            vault_authority: &self.vault_authority,
+            token_liquidity_vault: &self.token_liquidity_vault,
+            liquidity_vault_authority: &self.liquidity_vault_authority,
+            lending_vault: &self.lending_vault,
+            liquidity_sol_vault: &self.liquidity_sol_vault,
+            wsol_vault_authority: &self.wsol_vault_authority,
+            project_vault: &self.project_vault,
+            platform_vault: &self.platform_vault,
+            insurance_vault: &self.insurance_vault,
+            referral: if is_referral {
+                Some((&mut self.referral_tracking, &self.referral_token_vault))
+            } else {
+                None
+            },
+            payout: &self.temp_wsol_account,
+            keeper: None,
+            token_program: &self.token_program,
+            vault_authority_bump: bumps.vault_authority,
+            liquidity_vault_authority_bump: bumps.liquidity_vault_authority,
+            wsol_vault_authority_bump: bumps.wsol_vault_authority,
+        }
+    }
+}
+
+// Shared by close_position, reduce_position and trigger_position_exit: repays `borrowed_tokens`
+// to the lending vault, realises PnL on them against accumulated_c, takes the fee split out of
+// the released collateral, charges borrow interest for lenders and pays the rest into the payout
+// account. The caller updates the Position.
+pub fn settle_short(
+    mut accounts: ShortSettleAccounts,
+    borrowed_tokens: u64,
+    original_collateral: u64,
+) -> Result<ShortSettlement> {
     const MIN_SOL_RESERVE: i128 = 1_000_000; 
 
-    let vault_config = &mut ctx.accounts.vault_config;
-    let token_config = &mut ctx.accounts.token_config;
-    let token_program = &ctx.accounts.token_program;
     let now = Clock::get()?.unix_timestamp;
-
-    let borrowed_tokens = position.amount;
-    let original_collateral = position.collateral;
+    let entry_price = accounts.position.entry_price;
+    let index_snapshot = accounts.position.borrow_index_snapshot;
+    let vault_config = &mut *accounts.vault_config;
+    let token_config = &mut *accounts.token_config;
+    let token_program = accounts.token_program;
 
     msg!("borrowed_tokens: {}", borrowed_tokens);
     msg!("original_collateral: {}", original_collateral);
    
 
-    let token_reserve = vault_config.token_reserve;
     let sol_reserve = vault_config.sol_reserve;
-    let accumulated_c = vault_config.accumulated_c;
     let virtual_sol = vault_config.virtual_sol;
-    let virtual_tokens = vault_config.virtual_tokens;
-
-    // --- Compute full effective SOL reserve (includes virtual + accumulated_c) ---
-    let effective_sol_i128 = (sol_reserve as i128)
-        .checked_add(accumulated_c as i128)
-        .and_then(|s| s.checked_add(virtual_sol as i128))
-        .ok_or(ErrorCode::Overflow)?;
 
-    // Prevent negative reserve
-    require!(effective_sol_i128 >= 0, ErrorCode::Underflow);
+    // Snapshot the curve at the current reserves (before any clamp below)
+    let curve = load_curve(vault_config)?;
 
-    let effective_sol_reserve = effective_sol_i128 as u64;
+    // --- Compute full effective SOL reserve (includes virtual + accumulated_c) ---
+    let effective_sol_reserve = effective_sol(vault_config)?;
 
     // --- Clamp accumulated_c if reserve falls below floor ---
     if i128::from(effective_sol_reserve) < MIN_SOL_RESERVE {
@@ -187,35 +380,17 @@ pub fn close_position(ctx: Context<ClosePosition>, args: ClosePositionArgs) -> R
         vault_config.accumulated_c = clamped;
     }
 
-    // --- Combine token reserve with virtual tokens ---
-    let effective_token_reserve = token_reserve
-        .checked_add(virtual_tokens)
-        .ok_or(ErrorCode::Overflow)?;
-
     // --- Reverse bonding curve: how much SOL you'd get for repaying borrowed_tokens ---
-    let numerator = (borrowed_tokens as u128)
-        .checked_mul(effective_sol_reserve as u128)
-        .ok_or(ErrorCode::Overflow)?;
-
-    let denominator = (effective_token_reserve as u128)
-        .checked_add(borrowed_tokens as u128)
-        .ok_or(ErrorCode::Overflow)?;
-
-    // Value now (u128 -> u64 conversion)
-    let value_now_u128 = numerator
-        .checked_div(denominator)
-        .ok_or(ErrorCode::DivisionByZero)?;
-
-    let value_now = u64::try_from(value_now_u128).map_err(|_| ErrorCode::Overflow)?;
+    let value_now = curve.quote_sell(borrowed_tokens)?;
 
     msg!("value_now: {}", value_now);
 
   
-    let d = ctx.accounts.mint.decimals as u32;
+    let d = accounts.mint.decimals as u32;
 
     // Correct value_entry computation:
     // Multiply before dividing to avoid truncation.
-    let value_entry_u128 = (position.entry_price as u128)
+    let value_entry_u128 = (entry_price as u128)
         .checked_mul(borrowed_tokens as u128)
         .ok_or(ErrorCode::Overflow)?
         .checked_div(10u128.pow(d + 6))   // undo 10^decimals and extra 1e6 precision
@@ -227,6 +402,12 @@ pub fn close_position(ctx: Context<ClosePosition>, args: ClosePositionArgs) -> R
 
     msg!("value_entry: {}", value_entry);
 
+    // Borrow interest accrues on the SOL value lent at entry
+    let borrow_index = accrue_borrow_index(vault_config, accounts.yield_vault.total_staked, now)?;
+    let interest_owed = borrow_interest(value_entry, index_snapshot, borrow_index)?;
+
+    msg!("interest_owed: {}", interest_owed);
+
     let pnl: i128 = value_entry as i128 - value_now as i128; // Positive = profit, Negative = loss
 
     msg!("pnl: {}", pnl);
@@ -235,17 +416,29 @@ pub fn close_position(ctx: Context<ClosePosition>, args: ClosePositionArgs) -> R
 
     msg!("adjusted_collateral: {}", adjusted_collateral);
    
-    let is_profit = pnl > 0;
-
     let pnl_difference = pnl.unsigned_abs();
 
     msg!("pnl_difference: {}", pnl_difference);
 
+    // === Shortfall ===
+    // Loss beyond the collateral is paid from the insurance fund first, the rest is recorded as bad debt
+    // instead of being pushed into accumulated_c without any SOL behind it
+    let shortfall = if adjusted_collateral < 0 {
+        u64::try_from(adjusted_collateral.unsigned_abs()).map_err(|_| ErrorCode::Overflow)?
+    } else {
+        0
+    };
+    let (insured_sol, bad_debt_sol) = split_shortfall(shortfall, vault_config.insurance_fund);
+
+    msg!("shortfall: {}", shortfall);
+    msg!("insured_sol: {}", insured_sol);
+    msg!("bad_debt_sol: {}", bad_debt_sol);
+
     // === Update accumulated_c safely ===
     if pnl < 0 {
-        // Loss: Increase accumulated_c (more buffer needed)
+        // Loss: Increase accumulated_c (more buffer needed), the insured part is added once the SOL arrives
         vault_config.accumulated_c = vault_config.accumulated_c
-            .checked_add(pnl_difference as i64)
+            .checked_add((pnl_difference - shortfall as u128) as i64)
             .ok_or(ErrorCode::Overflow)?
             .max((MIN_SOL_RESERVE as i128 - sol_reserve as i128) as i64);
     } else if pnl > 0 {
@@ -310,20 +503,19 @@ pub fn close_position(ctx: Context<ClosePosition>, args: ClosePositionArgs) -> R
     msg!("pre_sale_fee_sol: {}", pre_sale_fee_sol);
     msg!("creator_fee_sol: {}", creator_fee_sol);
 
-    let total_platform_fee_bps = token_config.platform_fee_bps as u64; // 100 = 1%
-    let referral_cut_bps = 40; // 0.4%
-    let mut yield_cut_bps = 10; // 0.1%
+    let protocol_config = accounts.protocol_config;
+    let total_platform_fee_bps = protocol_config.platform_fee_bps as u64; // default 100 = 1%
+    let referral_cut_bps = protocol_config.referral_cut_bps as u64; // default 0.4%
+    let mut yield_cut_bps = protocol_config.yield_cut_bps as u64; // default 0.1%
 
     let mut platform_share_sol = ((adjusted_collateral as u64)
         .checked_mul(total_platform_fee_bps as u64)
         .ok_or(ErrorCode::Overflow)? / 10_000) as u64;
 
 
-    let is_referral = ctx.accounts.referrer.key() != ctx.accounts.platform_vault.key();
-
-    let referral_share_sol = if is_referral {
+    let referral_share_sol = if let Some((referral_tracking, _)) = accounts.referral.as_mut() {
         let referral_fee = ((adjusted_collateral as u64)
-            .checked_mul(referral_cut_bps as u64)
+            .checked_mul(referral_cut_bps)
             .ok_or(ErrorCode::Overflow)? / 10_000) as u64;
 
         let platform_fee = platform_share_sol
@@ -334,8 +526,7 @@ pub fn close_position(ctx: Context<ClosePosition>, args: ClosePositionArgs) -> R
         platform_share_sol = platform_fee;
 
         // Track referral rewards
-        ctx.accounts.referral_tracking.pending_rewards = ctx
-            .accounts.referral_tracking
+        referral_tracking.pending_rewards = referral_tracking
             .pending_rewards
             .checked_add(referral_fee)
             .ok_or(ErrorCode::Overflow)?; // divide after to prevent precision loss
@@ -343,7 +534,7 @@ pub fn close_position(ctx: Context<ClosePosition>, args: ClosePositionArgs) -> R
         referral_fee
     } else {
         // If no referrer, increase yield share from 0.1% → 0.2%
-        yield_cut_bps = 20; // 0.2%
+        yield_cut_bps = protocol_config.yield_cut_no_referral_bps as u64; // default 0.2%
 
         0
     };
@@ -357,10 +548,19 @@ pub fn close_position(ctx: Context<ClosePosition>, args: ClosePositionArgs) -> R
         .checked_sub(yield_share_sol)
         .ok_or(ErrorCode::Underflow)?;
 
+    // --- Insurance fund takes its slice of what's left of the platform fee ---
+    let insurance_share_sol = ((platform_share_sol as u128)
+        * protocol_config.insurance_fee_bps as u128 / 10_000) as u64;
+
+    platform_share_sol = platform_share_sol
+        .checked_sub(insurance_share_sol)
+        .ok_or(ErrorCode::Underflow)?;
+
+    msg!("insurance_share_sol: {}", insurance_share_sol);
+
     msg!("platform_share_sol: {}", platform_share_sol);
     msg!("yield_share_sol: {}", yield_share_sol);
     msg!("referral_share_sol: {}", referral_share_sol);
-    msg!("updated referral pending reward: {}", ctx.accounts.referral_tracking.pending_rewards);
 
     // --- Sum all fee components safely ---
     let total_fees_sol = creator_fee_sol
@@ -368,16 +568,11 @@ pub fn close_position(ctx: Context<ClosePosition>, args: ClosePositionArgs) -> R
         .and_then(|v| v.checked_add(referral_share_sol))
         .and_then(|v| v.checked_add(yield_share_sol))
         .and_then(|v| v.checked_add(pre_sale_fee_sol))
+        .and_then(|v| v.checked_add(insurance_share_sol))
         .ok_or(ErrorCode::Overflow)?;
    
 
-    let project_fees_sol = creator_fee_sol
-        .checked_add(yield_share_sol)
-        .and_then(|v| v.checked_add(pre_sale_fee_sol))
-        .ok_or(ErrorCode::Overflow)?;
-
     msg!("total_fees_sol: {}", total_fees_sol);
-    msg!("project_fees_sol: {}", project_fees_sol);
 
     // END Compute fees
 
@@ -387,17 +582,39 @@ pub fn close_position(ctx: Context<ClosePosition>, args: ClosePositionArgs) -> R
         .checked_sub(total_fees_sol as i128)
         .ok_or(ErrorCode::Underflow)?;
 
+    // Interest comes out of whatever is left, it can never push the payout below zero
+    let interest_sol = interest_owed.min(final_collateral as u64);
+
+    let final_collateral = final_collateral
+        .checked_sub(interest_sol as i128)
+        .ok_or(ErrorCode::Underflow)?;
+
+    // Keeper that triggered the exit is paid out of the owner's collateral
+    let keeper_fee_sol = match accounts.keeper {
+        Some((_, fee_bps)) => (final_collateral as u128 * fee_bps as u128 / 10_000) as u64,
+        None => 0,
+    };
+
+    let final_collateral = final_collateral
+        .checked_sub(keeper_fee_sol as i128)
+        .ok_or(ErrorCode::Underflow)?;
+
+    msg!("interest_sol: {}", interest_sol);
+    msg!("keeper_fee_sol: {}", keeper_fee_sol);
     msg!("final_collateral: {}", final_collateral);
 
-    // Slippage check
-    require!(
-        borrowed_tokens >= args.min_token_repay,
-        ErrorCode::SlippageExceeded
-    );
+    // Interest is paid to yield stakers through the project vault alongside the yield share
+    let project_fees_sol = creator_fee_sol
+        .checked_add(yield_share_sol)
+        .and_then(|v| v.checked_add(pre_sale_fee_sol))
+        .and_then(|v| v.checked_add(interest_sol))
+        .ok_or(ErrorCode::Overflow)?;
+
+    msg!("project_fees_sol: {}", project_fees_sol);
 
     // === Token Repayment ===
-    let bump = ctx.bumps.liquidity_vault_authority;
-    let mint_key = ctx.accounts.mint.key();
+    let bump = accounts.liquidity_vault_authority_bump;
+    let mint_key = accounts.mint.key();
     let seeds = &[
         b"vault_liquidity_authority",
         mint_key.as_ref(),
@@ -408,19 +625,19 @@ pub fn close_position(ctx: Context<ClosePosition>, args: ClosePositionArgs) -> R
         CpiContext::new_with_signer(
             token_program.to_account_info(),
             Transfer {
-                from: ctx.accounts.token_liquidity_vault.to_account_info(),
-                to: ctx.accounts.lending_vault.to_account_info(),
-                authority: ctx.accounts.liquidity_vault_authority.to_account_info(),
+                from: accounts.token_liquidity_vault.to_account_info(),
+                to: accounts.lending_vault.to_account_info(),
+                authority: accounts.liquidity_vault_authority.to_account_info(),
             },
             &[seeds],
         ),
         borrowed_tokens,
     )?;
 
-    let bump = ctx.bumps.wsol_vault_authority;
+    let bump = accounts.wsol_vault_authority_bump;
     let signer_seeds: &[&[u8]] = &[
         b"vault_wsol_authority",
-        vault_config.mint.as_ref(),
+        mint_key.as_ref(),
         &[bump],
     ];
     let signer_seeds = &[signer_seeds];
@@ -428,11 +645,11 @@ pub fn close_position(ctx: Context<ClosePosition>, args: ClosePositionArgs) -> R
     // Transfer fees and net SOL
     token::transfer(
         CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
+            accounts.token_program.to_account_info(),
             Transfer {
-                from: ctx.accounts.liquidity_sol_vault.to_account_info(),
-                to: ctx.accounts.project_vault.to_account_info(),
-                authority: ctx.accounts.wsol_vault_authority.to_account_info(),
+                from: accounts.liquidity_sol_vault.to_account_info(),
+                to: accounts.project_vault.to_account_info(),
+                authority: accounts.wsol_vault_authority.to_account_info(),
             },
             signer_seeds,
         ),
@@ -442,74 +659,130 @@ pub fn close_position(ctx: Context<ClosePosition>, args: ClosePositionArgs) -> R
 
     token::transfer(
         CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
+            accounts.token_program.to_account_info(),
             Transfer {
-                from: ctx.accounts.liquidity_sol_vault.to_account_info(),
-                to: ctx.accounts.platform_vault.to_account_info(),
-                authority: ctx.accounts.wsol_vault_authority.to_account_info(),
+                from: accounts.liquidity_sol_vault.to_account_info(),
+                to: accounts.platform_vault.to_account_info(),
+                authority: accounts.wsol_vault_authority.to_account_info(),
             },
             signer_seeds,
         ),
         platform_share_sol,
     )?;
 
-    if referral_share_sol > 0 {
+    if insurance_share_sol > 0 {
         token::transfer(
             CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
+                accounts.token_program.to_account_info(),
                 Transfer {
-                    from: ctx.accounts.liquidity_sol_vault.to_account_info(),
-                    to: ctx.accounts.referral_token_vault.to_account_info(),
-                    authority: ctx.accounts.wsol_vault_authority.to_account_info(),
+                    from: accounts.liquidity_sol_vault.to_account_info(),
+                    to: accounts.insurance_vault.to_account_info(),
+                    authority: accounts.wsol_vault_authority.to_account_info(),
                 },
                 signer_seeds,
             ),
-            referral_share_sol,
+            insurance_share_sol,
         )?;
+    }
 
-        ctx.accounts.referral_tracking.total_earned = ctx
-            .accounts
-            .referral_tracking
-            .total_earned
-            .checked_add(referral_share_sol)
-            .ok_or(ErrorCode::Overflow)?;
+    // Covered part of the shortfall comes back out of the insurance vault
+    draw_insurance(
+        vault_config,
+        accounts.insurance_vault,
+        accounts.liquidity_sol_vault,
+        accounts.vault_authority,
+        accounts.token_program,
+        accounts.vault_authority_bump,
+        insured_sol,
+    )?;
+
+    vault_config.insurance_fund = vault_config
+        .insurance_fund
+        .checked_add(insurance_share_sol)
+        .ok_or(ErrorCode::Overflow)?;
+
+    vault_config.bad_debt = vault_config
+        .bad_debt
+        .checked_add(bad_debt_sol)
+        .ok_or(ErrorCode::Overflow)?;
+
+    if let Some((referral_tracking, referral_token_vault)) = accounts.referral.as_mut() {
+        if referral_share_sol > 0 {
+            token::transfer(
+                CpiContext::new_with_signer(
+                    accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: accounts.liquidity_sol_vault.to_account_info(),
+                        to: referral_token_vault.to_account_info(),
+                        authority: accounts.wsol_vault_authority.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                referral_share_sol,
+            )?;
+
+            referral_tracking.total_earned = referral_tracking
+                .total_earned
+                .checked_add(referral_share_sol)
+                .ok_or(ErrorCode::Overflow)?;
+        }
+    }
+
+    if let Some((keeper_account, _)) = accounts.keeper {
+        if keeper_fee_sol > 0 {
+            token::transfer(
+                CpiContext::new_with_signer(
+                    accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: accounts.liquidity_sol_vault.to_account_info(),
+                        to: keeper_account.to_account_info(),
+                        authority: accounts.wsol_vault_authority.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                keeper_fee_sol,
+            )?;
+        }
     }
-    
     
     token::transfer(
         CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
+            accounts.token_program.to_account_info(),
             Transfer {
-                from: ctx.accounts.liquidity_sol_vault.to_account_info(),
-                to: ctx.accounts.temp_wsol_account.to_account_info(),
-                authority: ctx.accounts.wsol_vault_authority.to_account_info(),
+                from: accounts.liquidity_sol_vault.to_account_info(),
+                to: accounts.payout.to_account_info(),
+                authority: accounts.wsol_vault_authority.to_account_info(),
             },
             signer_seeds,
         ),
         final_collateral as u64,
     )?;
     
-    let position = &mut ctx.accounts.position;
-    position.open = false;
-
     vault_config.token_reserve = vault_config
             .token_reserve
             .checked_sub(borrowed_tokens as u64)
             .ok_or(ErrorCode::Underflow)?;
 
+    vault_config.total_borrowed = vault_config
+        .total_borrowed
+        .checked_sub(borrowed_tokens)
+        .ok_or(ErrorCode::Underflow)?;
+
     // Get vault mutable
-    let yield_vault = &mut ctx.accounts.yield_vault;
+    let yield_vault = &mut *accounts.yield_vault;
     
 
     // Compute acc_reward_per_share delta
-    if yield_vault.total_staked > 0 {
-        // new rewards = interest added (WSOL)
-        let new_rewards = yield_share_sol as u128;
+    if yield_vault.total_weighted_stake > 0 {
+        // new rewards = yield share + borrow interest (WSOL)
+        let new_rewards = (yield_share_sol as u128)
+            .checked_add(interest_sol as u128)
+            .ok_or(ErrorCode::Overflow)?;
 
         // Update cumulative reward per share
         yield_vault.acc_reward_per_share = yield_vault
             .acc_reward_per_share
-            .checked_add(new_rewards * PRECISION / yield_vault.total_staked as u128)
+            .checked_add(new_rewards * PRECISION / yield_vault.total_weighted_stake as u128)
             .ok_or(ErrorCode::Overflow)?;
 
         yield_vault.last_accrual_ts = now;
@@ -528,55 +801,27 @@ pub fn close_position(ctx: Context<ClosePosition>, args: ClosePositionArgs) -> R
     vault_config.yield_vault = vault_config
             .yield_vault
             .checked_add(yield_share_sol as u64)
+            .and_then(|v| v.checked_add(interest_sol))
             .ok_or(ErrorCode::Overflow)?; 
     
     yield_vault.total_earned = yield_vault
             .total_earned
             .checked_add(creator_fee_sol)
-            .ok_or(ErrorCode::Overflow)?; 
-
-
-    token::close_account(
-        CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            CloseAccount {
-                account: ctx.accounts.temp_wsol_account.to_account_info(),
-                destination: ctx.accounts.user.to_account_info(),
-                authority: ctx.accounts.temp_wsol_authority.to_account_info(),
-            },
-        )
-    )?;
+            .ok_or(ErrorCode::Overflow)?;
 
-    emit!(ClosePositionEvent {
-        user: ctx.accounts.user.key(),
-        mint: ctx.accounts.mint.key(),
-        repaid_tokens: borrowed_tokens,
+    Ok(ShortSettlement {
+        value_now,
+        pnl,
+        total_fees_sol,
+        referral_share_sol,
+        interest_sol,
+        insurance_sol: insurance_share_sol,
+        bad_debt_sol,
+        keeper_fee_sol,
         collateral_returned: final_collateral as u64,
-        total_fees: total_fees_sol,
-        interest: vault_config.yield_vault,
-        accumulated_c_after: vault_config.accumulated_c,
-        acc_reward_per_share: yield_vault.acc_reward_per_share as u64,
-        pending_rewards: ctx.accounts.referral_tracking.pending_rewards, 
-        total_earned: ctx.accounts.referral_tracking.total_earned,
-        total_fees_earnings: yield_vault.total_earned,
-        creator_vault: vault_config.creator_vault,
-        platform_vault: vault_config.platform_vault,
-        referral_share_sol: referral_share_sol,
-        token_reserve: vault_config.token_reserve,
-        sol_reserve: vault_config.sol_reserve,
-        position_id: args.position_id,
-        pnl: if is_profit { pnl_difference as i64 } else { -(pnl_difference as i64) },
-        exit_price: value_now,
-        virtual_sol: vault_config.virtual_sol,
-        virtual_tokens: vault_config.virtual_tokens,
-        pre_sale_acc_fee_per_share: token_config.pre_sale_acc_fee_per_share as u64,
-        pre_sale_fee_sol: vault_config.pre_sale_vault,
-    });
-
-    Ok(())
+    })
 }
 
-
 #[event]
 pub struct ClosePositionEvent {
     pub user: Pubkey,
@@ -593,6 +838,9 @@ pub struct ClosePositionEvent {
     pub creator_vault: u64,
     pub platform_vault: u64,
     pub referral_share_sol: u64,
+    pub borrow_interest: u64,
+    pub insurance_fee: u64,
+    pub bad_debt: u64,
     pub token_reserve: u64,
     pub sol_reserve: u64, 
     pub position_id: u64,
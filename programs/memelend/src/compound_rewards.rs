@@ -0,0 +1,371 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Token, TokenAccount, Mint, Transfer};
+use crate::{VaultConfig, TokenConfig, ProtocolConfig, ErrorCode, YieldVault, UserYieldPosition};
+use crate::buy_token::{split_buy_fees, record_buy, PRECISION};
+use crate::curve::load_curve;
+use crate::emissions::{checkpoint_position, rebase_position};
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct CompoundRewardsArgs {
+    pub min_tokens_out: u64, // keeper's own bound, the owner's limit applies regardless
+}
+
+#[derive(Accounts)]
+pub struct SetCompoundLimit<'info> {
+    pub owner: Signer<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"user_yield", mint.key().as_ref(), owner.key().as_ref()],
+        bump = user_yield_position.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+        has_one = owner @ ErrorCode::Unauthorized,
+    )]
+    pub user_yield_position: Box<Account<'info, UserYieldPosition>>,
+}
+
+#[derive(Accounts)]
+pub struct CompoundRewards<'info> {
+    // Permissionless, any keeper can compound a position for the keeper tip
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+
+    #[account(
+        mut,
+        token::mint = wsol_mint,
+    )]
+    pub keeper_wsol_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Owner of the position, only used for seeds
+    pub owner: UncheckedAccount<'info>,
+
+    #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
+    pub wsol_mint: Account<'info, Mint>,
+
+    #[account(address = token_config.mint)]
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"user_yield", mint.key().as_ref(), owner.key().as_ref()],
+        bump = user_yield_position.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+        constraint = user_yield_position.owner == owner.key() @ ErrorCode::Unauthorized,
+    )]
+    pub user_yield_position: Box<Account<'info, UserYieldPosition>>,
+
+    #[account(
+        mut,
+        seeds = [b"yield_vault", mint.key().as_ref()],
+        bump = yield_vault.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub yield_vault: Box<Account<'info, YieldVault>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub vault_config: Box<Account<'info, VaultConfig>>,
+
+    #[account(
+        mut,
+        seeds = [b"config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub token_config: Box<Account<'info, TokenConfig>>,
+
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Box<Account<'info, ProtocolConfig>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_project", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+        token::authority = vault_authority,
+    )]
+    pub project_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_platform", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+        token::authority = vault_authority,
+    )]
+    pub platform_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: PDA signer for the project vault
+    #[account(
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_liquidity", mint.key().as_ref()],
+        bump,
+    )]
+    pub liquidity_token_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: PDA
+    #[account(
+        seeds = [b"vault_liquidity_authority", mint.key().as_ref()],
+        bump,
+    )]
+    pub liquidity_vault_authority: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_wsol", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+    )]
+    pub liquidity_sol_vault: Box<Account<'info, TokenAccount>>,
+
+    // Staked tokens live here, the bought tokens never leave the program
+    #[account(
+        mut,
+        seeds = [b"vault_lending", mint.key().as_ref()],
+        bump,
+    )]
+    pub lending_vault: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+// Buys tokens with a position's pending WSOL rewards and stakes them into the same position.
+// The buy pays the same fees as buy_token without a referrer; creator, yield and presale
+// fees are already sitting in the project vault so only the platform share moves out.
+// Only runs for owners who opted in with set_compound_limit.
+pub fn compound_rewards(ctx: Context<CompoundRewards>, args: CompoundRewardsArgs) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let mint_key = ctx.accounts.mint.key();
+
+    let vault = &mut ctx.accounts.yield_vault;
+    let user_position = &mut ctx.accounts.user_yield_position;
+
+    // Book token emissions and slot rewards before the stake changes
+    checkpoint_position(vault, user_position, now)?;
+
+    let pending = (user_position.weighted_stake as u128)
+        .checked_mul(vault.acc_reward_per_share)
+        .ok_or(ErrorCode::Overflow)?
+        / PRECISION
+        - user_position.reward_debt;
+    let pending = u64::try_from(pending).map_err(|_| ErrorCode::Overflow)?;
+    require!(pending > 0, ErrorCode::NoRewardsAvailable);
+
+    let keeper_tip = (pending as u128)
+        .checked_mul(ctx.accounts.protocol_config.keeper_fee_bps as u128)
+        .ok_or(ErrorCode::Overflow)?
+        / 10_000;
+    let keeper_tip = keeper_tip as u64;
+    let sol_amount = pending
+        .checked_sub(keeper_tip)
+        .ok_or(ErrorCode::Underflow)?;
+
+    msg!("pending: {}", pending);
+    msg!("keeper_tip: {}", keeper_tip);
+
+    let token_config = &mut ctx.accounts.token_config;
+    let vault_config = &mut ctx.accounts.vault_config;
+    let protocol_config = &ctx.accounts.protocol_config;
+
+    // Same liquidity floor as buy_token
+    require!(
+        vault_config.sol_reserve >= 100_000_000, // 0.1 SOL
+        ErrorCode::InsufficientLiquidity
+    );
+    require!(
+        vault_config.token_reserve >= 100_000 * 10u64.pow(ctx.accounts.mint.decimals as u32),
+        ErrorCode::InsufficientLiquidity
+    );
+
+    let fees = split_buy_fees(sol_amount, token_config, vault_config, protocol_config, false, now)?;
+
+    let token_out = load_curve(vault_config)?.quote_buy(fees.net_sol_to_liquidity)?;
+    msg!("token_out: {}", token_out);
+
+    // The keeper picks the moment and its own min_tokens_out, so the owner's price limit is
+    // what stops a sandwich
+    let owner_min_out = min_tokens_at_price(
+        fees.net_sol_to_liquidity,
+        user_position.compound_max_price,
+        ctx.accounts.mint.decimals,
+    )?;
+    require!(token_out >= owner_min_out, ErrorCode::SlippageExceeded);
+    require!(token_out >= args.min_tokens_out, ErrorCode::SlippageExceeded);
+    require!(token_out > 0, ErrorCode::ZeroInput);
+
+    let max_allowed = ((vault_config.token_reserve as u128)
+        .checked_mul(protocol_config.max_buy_bps as u128)
+        .ok_or(ErrorCode::Overflow)? / 10_000) as u64;
+    require!(token_out <= max_allowed, ErrorCode::AmountTooLarge);
+
+    let vault_seeds: &[&[u8]] = &[b"vault", mint_key.as_ref(), &[ctx.bumps.vault_authority]];
+
+    // Keeper tip
+    if keeper_tip > 0 {
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.project_vault.to_account_info(),
+                    to: ctx.accounts.keeper_wsol_account.to_account_info(),
+                    authority: ctx.accounts.vault_authority.to_account_info(),
+                },
+                &[vault_seeds],
+            ),
+            keeper_tip,
+        )?;
+    }
+
+    // Platform fees to Platform vault
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.project_vault.to_account_info(),
+                to: ctx.accounts.platform_vault.to_account_info(),
+                authority: ctx.accounts.vault_authority.to_account_info(),
+            },
+            &[vault_seeds],
+        ),
+        fees.platform_share_sol,
+    )?;
+
+    // Net SOL into the pool
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.project_vault.to_account_info(),
+                to: ctx.accounts.liquidity_sol_vault.to_account_info(),
+                authority: ctx.accounts.vault_authority.to_account_info(),
+            },
+            &[vault_seeds],
+        ),
+        fees.net_sol_to_liquidity,
+    )?;
+
+    // Bought tokens go straight into the staking vault
+    let liquidity_seeds: &[&[u8]] = &[
+        b"vault_liquidity_authority",
+        mint_key.as_ref(),
+        &[ctx.bumps.liquidity_vault_authority],
+    ];
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.liquidity_token_vault.to_account_info(),
+                to: ctx.accounts.lending_vault.to_account_info(),
+                authority: ctx.accounts.liquidity_vault_authority.to_account_info(),
+            },
+            &[liquidity_seeds],
+        ),
+        token_out,
+    )?;
+
+    let vault = &mut ctx.accounts.yield_vault;
+    let user_position = &mut ctx.accounts.user_yield_position;
+    record_buy(vault_config, vault, &fees, token_out, now)?;
+
+    // Stake the bought tokens, pending rewards are spent
+    user_position.claimed_total = user_position.claimed_total
+        .checked_add(pending)
+        .ok_or(ErrorCode::Overflow)?;
+    user_position.deposited = user_position.deposited
+        .checked_add(token_out)
+        .ok_or(ErrorCode::Overflow)?;
+    vault.total_staked = vault.total_staked
+        .checked_add(token_out)
+        .ok_or(ErrorCode::Overflow)?;
+
+    rebase_position(vault, user_position, now)?;
+    user_position.last_action_ts = now;
+
+    emit!(CompoundRewardsEvent {
+        owner: user_position.owner,
+        mint: mint_key,
+        keeper: ctx.accounts.keeper.key(),
+        position_id: user_position.position_id,
+        rewards_used: pending,
+        keeper_tip,
+        sol_in: fees.net_sol_to_liquidity,
+        tokens_out: token_out,
+        deposited: user_position.deposited,
+        total_staked: vault.total_staked,
+        reward_debt: user_position.reward_debt,
+        token_reserve: vault_config.token_reserve,
+        sol_reserve: vault_config.sol_reserve,
+        acc_reward_per_share: vault.acc_reward_per_share as u64,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+// Fewest tokens `sol_in` may buy without the average price going over `max_price`
+// (same scale as spot_price)
+pub fn min_tokens_at_price(sol_in: u64, max_price: u64, decimals: u8) -> Result<u64> {
+    require!(max_price > 0, ErrorCode::CompoundingDisabled);
+
+    let min_out = (sol_in as u128)
+        .checked_mul(10u128.pow(decimals as u32 + 6))
+        .ok_or(ErrorCode::Overflow)?
+        .div_ceil(max_price as u128);
+
+    Ok(u64::try_from(min_out).map_err(|_| ErrorCode::Overflow)?)
+}
+
+// Lets keepers compound this position while the buy fills at or under `max_price`,
+// 0 switches compounding off
+pub fn set_compound_limit(ctx: Context<SetCompoundLimit>, max_price: u64) -> Result<()> {
+    let user_position = &mut ctx.accounts.user_yield_position;
+    user_position.compound_max_price = max_price;
+
+    emit!(CompoundLimitSetEvent {
+        owner: user_position.owner,
+        mint: user_position.mint,
+        max_price,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct CompoundLimitSetEvent {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub max_price: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CompoundRewardsEvent {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub keeper: Pubkey,
+    pub position_id: u64,
+    pub rewards_used: u64,
+    pub keeper_tip: u64,
+    pub sol_in: u64,
+    pub tokens_out: u64,
+    pub deposited: u64,
+    pub total_staked: u64,
+    pub reward_debt: u128,
+    pub token_reserve: u64,
+    pub sol_reserve: u64,
+    pub acc_reward_per_share: u64,
+    pub timestamp: i64,
+}
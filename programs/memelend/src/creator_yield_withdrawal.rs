@@ -1,36 +1,42 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{self, Transfer, Token, TokenAccount, Mint};
-use crate::{ YieldVault, ErrorCode, UserYieldPosition};
+use crate::{ YieldVault, ErrorCode, UserYieldPosition, WithdrawQueue, VestingSchedule};
+use crate::withdraw_queue::{init_withdraw_queue, available_liquidity};
+use crate::emissions::{checkpoint_position, rebase_position};
+use crate::yield_lock::require_unlocked;
+use crate::vesting::allowed_withdraw_percent;
 
 
 pub const PRECISION: u128 = 1_000_000_000_000; // 1e12 for reward per share math
 pub const SECONDS_IN_YEAR: u64 = 31_536_000; // 365 days
 
-// Helper function for Dynamic withdrawal curve for creators staked token
-fn allowed_withdraw_percent(elapsed_days: i64) -> u16 {
-    if elapsed_days < 7 {
-        0
-    } else if elapsed_days < 30 {
-        1000 // 10% (bps)
-    } else if elapsed_days < 180 {
-        5000 // 50%
-    } else {
-        10000 // 100%
-    }
-}
-
 #[derive(Accounts)]
 pub struct CreatorWithdrawYield<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"yield_vault", mint.key().as_ref()],
+        bump = yield_vault.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub yield_vault: Account<'info, YieldVault>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"user_yield", mint.key().as_ref(), user.key().as_ref()],
+        bump = user_yield_position.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+        constraint = user_yield_position.owner == user.key() @ ErrorCode::Unauthorized,
+    )]
     pub user_yield_position: Account<'info, UserYieldPosition>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"vault_lending", mint.key().as_ref()],
+        bump,
+    )]
     pub lending_vault: Box<Account<'info, TokenAccount>>,
 
     #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
@@ -45,8 +51,13 @@ pub struct CreatorWithdrawYield<'info> {
     )]
     pub vault_authority: AccountInfo<'info>,
 
-    #[account(mut)]
-    pub project_vault: Account<'info, TokenAccount>, 
+    #[account(
+        mut,
+        seeds = [b"vault_project", mint.key().as_ref()],
+        bump,
+        token::authority = vault_authority,
+    )]
+    pub project_vault: Account<'info, TokenAccount>,
 
     /// CHECK: PDA authority for lending vault
     #[account(
@@ -55,15 +66,38 @@ pub struct CreatorWithdrawYield<'info> {
     )]
     pub lending_vault_authority: AccountInfo<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = temp_wsol_account.mint == anchor_spl::token::spl_token::native_mint::ID @ ErrorCode::InvalidWsolMint,
+    )]
     pub temp_wsol_account: Box<Account<'info, TokenAccount>>,
 
     #[account(address = user.key())]
     pub temp_wsol_authority: Signer<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = user_token_account.mint == mint.key() @ ErrorCode::InvalidMint,
+        constraint = user_token_account.owner == user.key() @ ErrorCode::Unauthorized,
+    )]
     pub user_token_account: Box<Account<'info, TokenAccount>>,
 
+    #[account(
+        init_if_needed,
+        payer = user,
+        seeds = [b"withdraw_queue", mint.key().as_ref()],
+        bump,
+        space = 8 + WithdrawQueue::LEN,
+    )]
+    pub withdraw_queue: Box<Account<'info, WithdrawQueue>>,
+
+    #[account(
+        seeds = [b"vesting", mint.key().as_ref()],
+        bump = vesting_schedule.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub vesting_schedule: Box<Account<'info, VestingSchedule>>,
+
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
@@ -85,9 +119,8 @@ pub fn creator_yield_withdrawal(
     
     // Handle initial vs secondary deposits
     if position_id == user_position.position_id {
-        // Initial deposit → apply dynamic withdrawal curve
-        let elapsed_days = (now_ts - vault.launch_ts) / 86400;
-        let allowed_bps = allowed_withdraw_percent(elapsed_days);
+        // Initial deposit → apply the creator's vesting schedule
+        let allowed_bps = allowed_withdraw_percent(&ctx.accounts.vesting_schedule, now_ts - vault.launch_ts);
 
         let max_withdrawable = (user_position.initial_deposit as u128)
             .checked_mul(allowed_bps as u128)
@@ -109,12 +142,23 @@ pub fn creator_yield_withdrawal(
             .ok_or(ErrorCode::Overflow)?;
 
         is_initial_deposit= true;
+    } else {
+        // Secondary deposits → no curve, but the unvested part of the initial stake stays put
+        let still_vesting = user_position.initial_deposit.saturating_sub(user_position.claimed_principal);
+        require!(
+            amount <= user_position.deposited.saturating_sub(still_vesting),
+            ErrorCode::CreatorOverWithdrawal
+        );
     }
-    // Secondary deposits → no curve, no claimed_principal increment
 
+    // Locked principal stays put until the lock expires
+    require_unlocked(user_position, now_ts)?;
+
+    // Book token emissions and slot rewards before the stake changes
+    checkpoint_position(vault, user_position, now_ts)?;
  
     // Calculate user pending rewards
-    let pending = (user_position.deposited as u128)
+    let pending = (user_position.weighted_stake as u128)
         .checked_mul(vault.acc_reward_per_share)
         .unwrap()
         / PRECISION
@@ -149,6 +193,13 @@ pub fn creator_yield_withdrawal(
 
 
 
+    // Queued requests are paid first, the rest of the vault may be lent out
+    init_withdraw_queue(&mut ctx.accounts.withdraw_queue, ctx.bumps.withdraw_queue, ctx.accounts.mint.key());
+    require!(
+        amount <= available_liquidity(ctx.accounts.lending_vault.amount, &ctx.accounts.withdraw_queue),
+        ErrorCode::InsufficientLendingLiquidity
+    );
+
     // Transfer principal tokens back to user
     let cpi_accounts = Transfer {
         from: ctx.accounts.lending_vault.to_account_info(),
@@ -177,13 +228,7 @@ pub fn creator_yield_withdrawal(
     vault.total_staked = vault.total_staked
         .checked_sub(amount)
         .unwrap();
-
-
-    // Update user reward debt
-    user_position.reward_debt = (user_position.deposited as u128)
-        .checked_mul(vault.acc_reward_per_share)
-        .unwrap()
-        / PRECISION;
+    rebase_position(vault, user_position, now_ts)?;
 
     user_position.last_action_ts = now_ts;
     vault.last_accrual_ts = now_ts;
@@ -0,0 +1,443 @@
+use anchor_lang::prelude::*;
+use crate::{VaultConfig, ErrorCode};
+
+// Curve shapes a creator can pick at launch (stored in VaultConfig.curve_kind)
+pub const CURVE_CONSTANT_PRODUCT: u8 = 0;
+pub const CURVE_LINEAR: u8 = 1;
+pub const CURVE_EXPONENTIAL: u8 = 2;
+
+// Every curve works on the pool's effective reserves:
+//   sol    = sol_reserve + virtual_sol + accumulated_c
+//   tokens = token_reserve + virtual_tokens
+// Prices use the same scale as get_price_from_vault (lamports * 10^(decimals + 6) per token).
+// Quotes round against the trader: outputs down, required inputs up.
+pub trait Curve {
+    // Tokens the pool pays out for `sol_in` lamports
+    fn quote_buy(&self, sol_in: u64) -> Result<u64>;
+
+    // Lamports the pool pays out for `tokens_in` tokens
+    fn quote_sell(&self, tokens_in: u64) -> Result<u64>;
+
+    // Marginal price at the current reserves
+    fn spot_price(&self, decimals: u8) -> u64;
+
+    // Tokens lent to a short for `sol_value` lamports of exposure
+    fn borrow_quote(&self, sol_value: u64) -> Result<u64> {
+        self.quote_buy(sol_value)
+    }
+
+    // Smallest lamports in for which quote_buy returns at least `tokens_out`
+    fn quote_buy_exact_out(&self, tokens_out: u64) -> Result<u64>;
+
+    // Smallest tokens in for which quote_sell returns at least `sol_out`
+    fn quote_sell_exact_out(&self, sol_out: u64) -> Result<u64>;
+}
+
+// x * y = k on the effective reserves (the original pricing)
+pub struct ConstantProduct {
+    pub sol: u64,
+    pub tokens: u64,
+}
+
+impl Curve for ConstantProduct {
+    fn quote_buy(&self, sol_in: u64) -> Result<u64> {
+        let numerator = (sol_in as u128)
+            .checked_mul(self.tokens as u128)
+            .ok_or(ErrorCode::Overflow)?;
+
+        let denominator = (self.sol as u128)
+            .checked_add(sol_in as u128)
+            .ok_or(ErrorCode::Overflow)?;
+
+        let tokens_out = numerator
+            .checked_div(denominator)
+            .ok_or(ErrorCode::DivisionByZero)?;
+
+        Ok(u64::try_from(tokens_out).map_err(|_| ErrorCode::Overflow)?)
+    }
+
+    fn quote_sell(&self, tokens_in: u64) -> Result<u64> {
+        let numerator = (tokens_in as u128)
+            .checked_mul(self.sol as u128)
+            .ok_or(ErrorCode::Overflow)?;
+
+        let denominator = (self.tokens as u128)
+            .checked_add(tokens_in as u128)
+            .ok_or(ErrorCode::Overflow)?;
+
+        let sol_out = numerator
+            .checked_div(denominator)
+            .ok_or(ErrorCode::DivisionByZero)?;
+
+        Ok(u64::try_from(sol_out).map_err(|_| ErrorCode::Overflow)?)
+    }
+
+    fn spot_price(&self, decimals: u8) -> u64 {
+        (self.sol as u128)
+            .checked_mul(10u128.pow(decimals as u32 + 6))
+            .unwrap_or(0)
+            .checked_div(self.tokens as u128)
+            .unwrap_or(0) as u64
+    }
+
+    // sol_in = ceil(y * q / (x - q))
+    fn quote_buy_exact_out(&self, tokens_out: u64) -> Result<u64> {
+        require!(tokens_out < self.tokens, ErrorCode::InsufficientLiquidity);
+
+        let numerator = (self.sol as u128)
+            .checked_mul(tokens_out as u128)
+            .ok_or(ErrorCode::Overflow)?;
+
+        let sol_in = div_ceil_u128(numerator, (self.tokens - tokens_out) as u128)?;
+
+        Ok(u64::try_from(sol_in).map_err(|_| ErrorCode::Overflow)?)
+    }
+
+    // tokens_in = ceil(x * s / (y - s))
+    fn quote_sell_exact_out(&self, sol_out: u64) -> Result<u64> {
+        require!(sol_out < self.sol, ErrorCode::InsufficientLiquidity);
+
+        let numerator = (self.tokens as u128)
+            .checked_mul(sol_out as u128)
+            .ok_or(ErrorCode::Overflow)?;
+
+        let tokens_in = div_ceil_u128(numerator, (self.sol - sol_out) as u128)?;
+
+        Ok(u64::try_from(tokens_in).map_err(|_| ErrorCode::Overflow)?)
+    }
+}
+
+// Price rises linearly with tokens sold: p(s) = p0 * (1 + s / (2 * base_tokens)).
+// Integrating gives sqrt(sol) = sqrt(base_sol) * (1 + s / (2 * base_tokens)),
+// so the pool state is fully described by the effective SOL reserve.
+pub struct Linear {
+    pub sol: u64,
+    pub base_sol: u64,
+    pub base_tokens: u64,
+}
+
+impl Linear {
+    // sqrt(sol * base_sol), the shared term in every linear formula
+    fn root(&self, sol: u64) -> Result<u128> {
+        let product = (sol as u128)
+            .checked_mul(self.base_sol as u128)
+            .ok_or(ErrorCode::Overflow)?;
+        Ok(isqrt(product))
+    }
+
+    // Same, rounded up
+    fn root_up(&self, sol: u64) -> Result<u128> {
+        let product = (sol as u128)
+            .checked_mul(self.base_sol as u128)
+            .ok_or(ErrorCode::Overflow)?;
+        let root = isqrt(product);
+        Ok(if root * root < product { root + 1 } else { root })
+    }
+}
+
+impl Curve for Linear {
+    fn quote_buy(&self, sol_in: u64) -> Result<u64> {
+        require!(self.base_sol > 0, ErrorCode::DivisionByZero);
+
+        let new_sol = self.sol.checked_add(sol_in).ok_or(ErrorCode::Overflow)?;
+
+        // q = 2 * X0 * (sqrt(y' * y0) - sqrt(y * y0)) / y0, the starting root rounded up
+        let delta = self.root(new_sol)?.saturating_sub(self.root_up(self.sol)?);
+
+        let tokens_out = delta
+            .checked_mul(2 * self.base_tokens as u128)
+            .ok_or(ErrorCode::Overflow)?
+            .checked_div(self.base_sol as u128)
+            .ok_or(ErrorCode::DivisionByZero)?;
+
+        Ok(u64::try_from(tokens_out).map_err(|_| ErrorCode::Overflow)?)
+    }
+
+    fn quote_sell(&self, tokens_in: u64) -> Result<u64> {
+        require!(self.base_tokens > 0, ErrorCode::DivisionByZero);
+
+        // sqrt(y' * y0) = sqrt(y * y0) - q * y0 / (2 * X0)
+        let drop = (tokens_in as u128)
+            .checked_mul(self.base_sol as u128)
+            .ok_or(ErrorCode::Overflow)?
+            .checked_div(2 * self.base_tokens as u128)
+            .ok_or(ErrorCode::DivisionByZero)?;
+
+        let new_root = self.root_up(self.sol)?
+            .checked_sub(drop)
+            .ok_or(ErrorCode::InsufficientLiquidity)?;
+
+        // Round the remaining reserve up so the pool never pays out more than it should
+        let new_sol = new_root
+            .checked_mul(new_root)
+            .ok_or(ErrorCode::Overflow)?
+            .checked_add(self.base_sol as u128 - 1)
+            .ok_or(ErrorCode::Overflow)?
+            / self.base_sol as u128;
+
+        let sol_out = (self.sol as u128).saturating_sub(new_sol);
+
+        Ok(u64::try_from(sol_out).map_err(|_| ErrorCode::Overflow)?)
+    }
+
+    fn spot_price(&self, decimals: u8) -> u64 {
+        self.root(self.sol)
+            .unwrap_or(0)
+            .checked_mul(10u128.pow(decimals as u32 + 6))
+            .unwrap_or(0)
+            .checked_div(self.base_tokens.max(1) as u128)
+            .unwrap_or(0) as u64
+    }
+
+    fn quote_buy_exact_out(&self, tokens_out: u64) -> Result<u64> {
+        require!(self.base_tokens > 0 && self.base_sol > 0, ErrorCode::DivisionByZero);
+
+        // sqrt(y' * y0) = sqrt(y * y0) + q * y0 / (2 * X0)
+        let rise = div_ceil_u128(
+            (tokens_out as u128).checked_mul(self.base_sol as u128).ok_or(ErrorCode::Overflow)?,
+            2 * self.base_tokens as u128,
+        )?;
+        let new_root = self.root(self.sol)?.checked_add(rise).ok_or(ErrorCode::Overflow)?;
+        let new_sol = div_ceil_u128(new_root.checked_mul(new_root).ok_or(ErrorCode::Overflow)?, self.base_sol as u128)?;
+
+        let estimate = u64::try_from(new_sol.saturating_sub(self.sol as u128)).map_err(|_| ErrorCode::Overflow)?;
+        settle_up(estimate, tokens_out, |sol_in| self.quote_buy(sol_in))
+    }
+
+    fn quote_sell_exact_out(&self, sol_out: u64) -> Result<u64> {
+        require!(sol_out < self.sol, ErrorCode::InsufficientLiquidity);
+        require!(self.base_sol > 0, ErrorCode::DivisionByZero);
+
+        // q = 2 * X0 * (sqrt(y * y0) - sqrt(y' * y0)) / y0
+        let drop = self.root(self.sol)?
+            .checked_sub(self.root(self.sol - sol_out)?)
+            .ok_or(ErrorCode::Underflow)?;
+        let tokens_in = div_ceil_u128(
+            drop.checked_mul(2 * self.base_tokens as u128).ok_or(ErrorCode::Overflow)?,
+            self.base_sol as u128,
+        )?;
+
+        let estimate = u64::try_from(tokens_in).map_err(|_| ErrorCode::Overflow)?;
+        settle_up(estimate, sol_out, |tokens| self.quote_sell(tokens))
+    }
+}
+
+// Price grows exponentially with tokens sold: p(s) = p0 * e^(s / base_tokens).
+// Integrating gives sol = base_sol * e^(s / base_tokens), so the spot price is sol / base_tokens.
+// ln and exp are evaluated in Q64.64 fixed point, see ln_q64 / exp_q64.
+pub struct Exponential {
+    pub sol: u64,
+    pub base_tokens: u64,
+}
+
+impl Curve for Exponential {
+    fn quote_buy(&self, sol_in: u64) -> Result<u64> {
+        require!(self.sol > 0, ErrorCode::DivisionByZero);
+
+        let new_sol = self.sol.checked_add(sol_in).ok_or(ErrorCode::Overflow)?;
+
+        // q = X0 * ln(y' / y)
+        let ratio = ((new_sol as u128) << 64) / self.sol as u128;
+        mul_q64(self.base_tokens, ln_q64(ratio, false), false)
+    }
+
+    fn quote_sell(&self, tokens_in: u64) -> Result<u64> {
+        require!(self.base_tokens > 0, ErrorCode::DivisionByZero);
+
+        // sol_out = y - y / e^(q / X0), a growth too large for Q64 leaves a single lamport
+        let exponent = ((tokens_in as u128) << 64) / self.base_tokens as u128;
+        let remaining = match exp_q64(exponent, false) {
+            Some(growth) => div_ceil_u128((self.sol as u128) << 64, growth)?,
+            None => 1,
+        };
+
+        Ok(self.sol.saturating_sub(u64::try_from(remaining).map_err(|_| ErrorCode::Overflow)?))
+    }
+
+    fn spot_price(&self, decimals: u8) -> u64 {
+        (self.sol as u128)
+            .checked_mul(10u128.pow(decimals as u32 + 6))
+            .unwrap_or(0)
+            .checked_div(self.base_tokens.max(1) as u128)
+            .unwrap_or(0) as u64
+    }
+
+    // s = y * (e^(q / X0) - 1)
+    fn quote_buy_exact_out(&self, tokens_out: u64) -> Result<u64> {
+        require!(self.base_tokens > 0, ErrorCode::DivisionByZero);
+
+        let exponent = div_ceil_u128((tokens_out as u128) << 64, self.base_tokens as u128)?;
+        let growth = exp_q64(exponent, true).ok_or(ErrorCode::Overflow)?;
+        let sol_in = mul_q64(self.sol, growth - Q64_ONE, true)?;
+
+        settle_up(sol_in, tokens_out, |s| self.quote_buy(s))
+    }
+
+    // q = X0 * ln(y / (y - s))
+    fn quote_sell_exact_out(&self, sol_out: u64) -> Result<u64> {
+        require!(sol_out < self.sol, ErrorCode::InsufficientLiquidity);
+
+        let ratio = div_ceil_u128((self.sol as u128) << 64, (self.sol - sol_out) as u128)?;
+        let tokens_in = mul_q64(self.base_tokens, ln_q64(ratio, true), true)?;
+
+        settle_up(tokens_in, sol_out, |q| self.quote_sell(q))
+    }
+}
+
+// Effective SOL reserve, rejecting a pool whose accumulated_c has driven it negative
+pub fn effective_sol(vault_config: &VaultConfig) -> Result<u64> {
+    let effective_sol_i128 = (vault_config.sol_reserve as i128)
+        .checked_add(vault_config.accumulated_c as i128)
+        .and_then(|s| s.checked_add(vault_config.virtual_sol as i128))
+        .ok_or(ErrorCode::Overflow)?;
+
+    require!(effective_sol_i128 >= 0, ErrorCode::Underflow);
+
+    Ok(u64::try_from(effective_sol_i128).map_err(|_| ErrorCode::Overflow)?)
+}
+
+pub fn effective_tokens(vault_config: &VaultConfig) -> Result<u64> {
+    Ok(vault_config
+        .token_reserve
+        .checked_add(vault_config.virtual_tokens)
+        .ok_or(ErrorCode::Overflow)?)
+}
+
+// Build the curve this mint launched with, at its current reserves
+pub fn load_curve(vault_config: &VaultConfig) -> Result<Box<dyn Curve>> {
+    let sol = effective_sol(vault_config)?;
+    let tokens = effective_tokens(vault_config)?;
+
+    match vault_config.curve_kind {
+        CURVE_CONSTANT_PRODUCT => Ok(Box::new(ConstantProduct { sol, tokens })),
+        CURVE_LINEAR => Ok(Box::new(Linear {
+            sol,
+            base_sol: vault_config.curve_base_sol,
+            base_tokens: vault_config.curve_base_tokens,
+        })),
+        CURVE_EXPONENTIAL => Ok(Box::new(Exponential {
+            sol,
+            base_tokens: vault_config.curve_base_tokens,
+        })),
+        _ => err!(ErrorCode::InvalidCurve),
+    }
+}
+
+// Closed-form inverses can land a unit short because the forward quotes round down,
+// so step the input up (doubling the step) until the forward quote covers `target`
+fn settle_up<F>(estimate: u64, target: u64, forward: F) -> Result<u64>
+where
+    F: Fn(u64) -> Result<u64>,
+{
+    let mut input = estimate;
+    let mut step = 1u64;
+    for _ in 0..64 {
+        if forward(input)? >= target {
+            return Ok(input);
+        }
+        input = input.checked_add(step).ok_or(ErrorCode::Overflow)?;
+        step = step.saturating_mul(2);
+    }
+    err!(ErrorCode::InsufficientLiquidity)
+}
+
+fn div_ceil_u128(numerator: u128, denominator: u128) -> Result<u128> {
+    require!(denominator > 0, ErrorCode::DivisionByZero);
+    Ok(numerator.div_ceil(denominator))
+}
+
+// Q64.64 fixed point: a u128 holding value * 2^64
+const Q64_ONE: u128 = 1 << 64;
+const LN2_Q64_DOWN: u128 = 12_786_308_645_202_655_659; // floor(ln 2 * 2^64)
+const LN2_Q64_UP: u128 = 12_786_308_645_202_655_660;
+const EXP_TERMS: u128 = 30;   // Taylor terms for e^r, r < ln 2
+const ATANH_TERMS: u128 = 24; // series terms for atanh(z), z <= 1/3
+
+// a * b >> 64, rounded down or up. Both operands must stay below 2^64 * 2^64.
+fn mul_shift(a: u128, b: u128, round_up: bool) -> u128 {
+    let product = a * b;
+    let out = product >> 64;
+    if round_up && product as u64 != 0 { out + 1 } else { out }
+}
+
+// Integer `a` times the Q64.64 `b`, back to an integer
+fn mul_q64(a: u64, b: u128, round_up: bool) -> Result<u64> {
+    let whole = (a as u128).checked_mul(b >> 64).ok_or(ErrorCode::Overflow)?;
+    let fraction = mul_shift(a as u128, b & (Q64_ONE - 1), round_up);
+    let out = whole.checked_add(fraction).ok_or(ErrorCode::Overflow)?;
+    Ok(u64::try_from(out).map_err(|_| ErrorCode::Overflow)?)
+}
+
+// ln(x) for x >= 1 in Q64.64. Writes x = 2^k * m with m in [1, 2) and sums
+// ln(m) = 2 * atanh((m - 1) / (m + 1)). Every step rounds the same way so the result is a
+// lower bound (`round_up` false) or an upper bound (true) on the exact value.
+fn ln_q64(x: u128, round_up: bool) -> u128 {
+    if x <= Q64_ONE {
+        return 0;
+    }
+
+    let mut k = 63 - x.leading_zeros();
+    let mut m = if round_up { x.div_ceil(1 << k) } else { x >> k };
+    if m == 2 * Q64_ONE {
+        k += 1;
+        m = Q64_ONE;
+    }
+
+    let numerator = (m - Q64_ONE) << 64;
+    let z = if round_up { numerator.div_ceil(m + Q64_ONE) } else { numerator / (m + Q64_ONE) };
+    let z_squared = mul_shift(z, z, round_up);
+
+    let mut power = z;
+    let mut sum = 0u128;
+    for n in 0..ATANH_TERMS {
+        let denominator = 2 * n + 1;
+        sum += if round_up { power.div_ceil(denominator) } else { power / denominator };
+        power = mul_shift(power, z_squared, round_up);
+    }
+    if round_up {
+        sum += 1; // series tail
+    }
+
+    let ln2 = if round_up { LN2_Q64_UP } else { LN2_Q64_DOWN };
+    k as u128 * ln2 + 2 * sum
+}
+
+// e^x for x >= 0 in Q64.64, None once the result no longer fits. Writes x = k * ln 2 + r with
+// r < ln 2 and sums the Taylor series of e^r. Rounds like ln_q64.
+fn exp_q64(x: u128, round_up: bool) -> Option<u128> {
+    // The ln 2 rounded the other way keeps r on the right side of its exact value
+    let ln2 = if round_up { LN2_Q64_DOWN } else { LN2_Q64_UP };
+    let k = x / ln2;
+    if k > 62 {
+        return None;
+    }
+    let r = x - k * ln2;
+
+    let mut term = Q64_ONE;
+    let mut sum = Q64_ONE;
+    for n in 1..=EXP_TERMS {
+        let scaled = mul_shift(term, r, round_up);
+        term = if round_up { scaled.div_ceil(n) } else { scaled / n };
+        sum += term;
+    }
+    if round_up {
+        sum += 1; // series tail
+    }
+
+    Some(sum << k)
+}
+
+// Integer square root (floor) via Newton's method
+fn isqrt(n: u128) -> u128 {
+    if n < 2 {
+        return n;
+    }
+    let mut x = 1u128 << ((128 - n.leading_zeros()).div_ceil(2));
+    loop {
+        let y = (x + n / x) / 2;
+        if y >= x {
+            return x;
+        }
+        x = y;
+    }
+}
@@ -1,7 +1,10 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{self, Transfer, Token, Mint, TokenAccount, CloseAccount};
 use anchor_spl::token::spl_token::native_mint::ID as NATIVE_MINT_ID;
-use crate::{ YieldVault, ErrorCode, UserYieldPosition, TokenConfig};
+use crate::{ YieldVault, ErrorCode, UserYieldPosition, TokenConfig, UserAccount};
+use crate::user_account::{init_user_account, allocate_position_id, register_position};
+use crate::emissions::{checkpoint_position, rebase_position};
+use crate::yield_lock::apply_lock;
 
 
 pub const PRECISION: u128 = 1_000_000_000_000; // 1e12 for reward per share math
@@ -29,7 +32,12 @@ pub struct DepositYield<'info> {
     #[account(address = owner.key())]
     pub temp_wsol_authority: Signer<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"yield_vault", mint.key().as_ref()],
+        bump = yield_vault.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub yield_vault: Account<'info, YieldVault>,
 
     #[account(
@@ -41,14 +49,37 @@ pub struct DepositYield<'info> {
     )]
     pub user_yield_position: Account<'info, UserYieldPosition>,
 
-    #[account(mut)]
+    #[account(
+        init_if_needed,
+        payer = owner,
+        seeds = [b"user_account", owner.key().as_ref()],
+        bump,
+        space = 8 + UserAccount::LEN,
+    )]
+    pub user_account: Box<Account<'info, UserAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_lending", mint.key().as_ref()],
+        bump,
+    )]
     pub lending_vault: Account<'info, TokenAccount>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub token_config: Box<Account<'info, TokenConfig>>,
 
-    #[account(mut)]
-    pub project_vault: Account<'info, TokenAccount>, 
+    #[account(
+        mut,
+        seeds = [b"vault_project", mint.key().as_ref()],
+        bump,
+        token::authority = vault_authority,
+    )]
+    pub project_vault: Account<'info, TokenAccount>,
     
     #[account(
         mut,
@@ -68,23 +99,32 @@ pub struct DepositYield<'info> {
     pub rent: Sysvar<'info, Rent>,
 }
 
-pub fn yield_deposit(ctx: Context<DepositYield>, amount: u64, position_id: u64) -> Result<()> {
+pub fn yield_deposit(ctx: Context<DepositYield>, amount: u64, lock_tier: u8) -> Result<()> {
     let vault = &mut ctx.accounts.yield_vault;
     let user_position = &mut ctx.accounts.user_yield_position;
+    let user_account = &mut ctx.accounts.user_account;
 
     let now_ts = Clock::get()?.unix_timestamp;
 
+    init_user_account(user_account, ctx.accounts.owner.key(), ctx.bumps.user_account);
+
     // Only set these if it's a brand-new account
     if user_position.owner == Pubkey::default() {
         user_position.owner = ctx.accounts.owner.key();
         user_position.mint = ctx.accounts.mint.key();
         user_position.bump = ctx.bumps.user_yield_position;
         user_position.deposited_at = now_ts;
+        user_position.position_id = allocate_position_id(user_account)?;
     }
 
+    // Listed again if a full withdrawal took it off
+    register_position(user_account, user_position.key())?;
+
+    // Book token emissions and slot rewards before the stake changes
+    checkpoint_position(vault, user_position, now_ts)?;
    
     // Calculate user pending rewards
-    let pending = (user_position.deposited as u128)
+    let pending = (user_position.weighted_stake as u128)
         .checked_mul(vault.acc_reward_per_share)
         .unwrap()
         / PRECISION
@@ -138,12 +178,9 @@ pub fn yield_deposit(ctx: Context<DepositYield>, amount: u64, position_id: u64)
     user_position.deposited = user_position.deposited.checked_add(amount).unwrap();
     vault.total_staked = vault.total_staked.checked_add(amount).unwrap();
 
-  
-    // Update reward debt for user
-    user_position.reward_debt = (user_position.deposited as u128)
-        .checked_mul(vault.acc_reward_per_share)
-        .unwrap()
-        / PRECISION;
+    // The lock covers the whole position, not just this deposit
+    apply_lock(user_position, lock_tier, now_ts)?;
+    rebase_position(vault, user_position, now_ts)?;
     user_position.last_action_ts = now_ts;
 
     vault.last_accrual_ts = now_ts;
@@ -167,10 +204,14 @@ pub fn yield_deposit(ctx: Context<DepositYield>, amount: u64, position_id: u64)
     emit!(DepositYieldEvent {
         owner: user_position.owner,
         mint: user_position.mint,
-        position_id: position_id,
+        position_id: user_position.position_id,
         amount:amount,
         deposited: user_position.deposited,
         total_staked: vault.total_staked,
+        lock_tier: user_position.lock_tier,
+        lock_until: user_position.lock_until,
+        weighted_stake: user_position.weighted_stake,
+        total_weighted_stake: vault.total_weighted_stake,
         claimed_total: user_position.claimed_total,
         reward_debt: user_position.reward_debt,
         deposited_at: user_position.deposited_at,
@@ -192,6 +233,10 @@ pub struct DepositYieldEvent {
     pub amount: u64,
     pub deposited: u64,
     pub total_staked: u64,
+    pub lock_tier: u8,
+    pub lock_until: i64,
+    pub weighted_stake: u64,
+    pub total_weighted_stake: u64,
     pub claimed_total: u64,
     pub reward_debt: u128,
     pub deposited_at: i64,
@@ -0,0 +1,111 @@
+use anchor_lang::prelude::*;
+use crate::{YieldVault, UserYieldPosition, ErrorCode};
+use crate::reward_slots::{accrue_reward_slots, settle_reward_slots, reset_reward_slot_debts};
+use crate::yield_lock::{expire_lock, update_weight};
+
+pub const PRECISION: u128 = 1_000_000_000_000; // 1e12 for reward per share math
+pub const SECONDS_IN_YEAR: u64 = 31_536_000; // 365 days
+pub const EMISSION_RESERVE_BPS: u64 = 2_000; // 20% of the creator's stake funds the emission reserve
+
+// Tokens `total_staked` earns at `apr_bps` over `elapsed` seconds
+pub fn emission_for(total_staked: u64, apr_bps: u16, elapsed: i64) -> u64 {
+    if elapsed <= 0 {
+        return 0;
+    }
+    let emission = total_staked as u128 * apr_bps as u128 * elapsed as u128
+        / (10_000 * SECONDS_IN_YEAR as u128);
+    u64::try_from(emission).unwrap_or(u64::MAX)
+}
+
+// Streams emissions since last_emission_ts into acc_emission_per_share, capped by what is
+// left in the reserve. The APR is paid on total_staked and shared by lock-boosted weight.
+// Has its own timestamp since fee distributions move last_accrual_ts without paying
+// emissions. Call before total_staked changes.
+pub fn accrue_emissions(vault: &mut YieldVault, now: i64) -> Result<()> {
+    if vault.last_emission_ts == 0 {
+        vault.last_emission_ts = now;
+        return Ok(());
+    }
+
+    let elapsed = now - vault.last_emission_ts;
+    if elapsed <= 0 {
+        return Ok(());
+    }
+    vault.last_emission_ts = now;
+
+    if vault.total_weighted_stake == 0 || vault.emission_reserve == 0 {
+        return Ok(());
+    }
+
+    let emission = emission_for(vault.total_staked, vault.apr_bps, elapsed).min(vault.emission_reserve);
+
+    vault.acc_emission_per_share = vault
+        .acc_emission_per_share
+        .checked_add(emission as u128 * PRECISION / vault.total_weighted_stake as u128)
+        .ok_or(ErrorCode::Overflow)?;
+    vault.emission_reserve -= emission;
+    vault.total_emitted = vault
+        .total_emitted
+        .checked_add(emission)
+        .ok_or(ErrorCode::Overflow)?;
+
+    Ok(())
+}
+
+// Books the position's accrued emissions into emission_owed. Call before `weighted_stake` changes
+// and follow up with reset_emission_debt once it has.
+pub fn settle_emissions(vault: &YieldVault, position: &mut UserYieldPosition) -> Result<()> {
+    let accrued = (position.weighted_stake as u128)
+        .checked_mul(vault.acc_emission_per_share)
+        .ok_or(ErrorCode::Overflow)?
+        / PRECISION;
+    let pending = accrued.saturating_sub(position.emission_debt);
+
+    position.emission_owed = position
+        .emission_owed
+        .checked_add(u64::try_from(pending).map_err(|_| ErrorCode::Overflow)?)
+        .ok_or(ErrorCode::Overflow)?;
+    position.emission_debt = accrued;
+
+    Ok(())
+}
+
+pub fn reset_emission_debt(vault: &YieldVault, position: &mut UserYieldPosition) -> Result<()> {
+    position.emission_debt = (position.weighted_stake as u128)
+        .checked_mul(vault.acc_emission_per_share)
+        .ok_or(ErrorCode::Overflow)?
+        / PRECISION;
+    Ok(())
+}
+
+// Streams token emissions and slot rewards up to `now` and books what the position has earned
+// into its owed balances. Call before the position's stake or lock changes.
+pub fn checkpoint_position(vault: &mut YieldVault, position: &mut UserYieldPosition, now: i64) -> Result<()> {
+    accrue_emissions(vault, now)?;
+    settle_emissions(vault, position)?;
+    accrue_reward_slots(vault, now)?;
+    settle_reward_slots(vault, position)
+}
+
+// WSOL rewards the position has earned since its reward_debt was last reset
+pub fn pending_wsol_rewards(vault: &YieldVault, position: &UserYieldPosition) -> Result<u128> {
+    Ok(((position.weighted_stake as u128)
+        .checked_mul(vault.acc_reward_per_share)
+        .ok_or(ErrorCode::Overflow)?
+        / PRECISION)
+        .saturating_sub(position.reward_debt))
+}
+
+// Drops an expired lock, recomputes the position's weight and restarts every reward debt
+// (WSOL, emissions, slots) from the current indexes. Call once the stake or lock has changed.
+pub fn rebase_position(vault: &mut YieldVault, position: &mut UserYieldPosition, now: i64) -> Result<()> {
+    expire_lock(position, now);
+    update_weight(vault, position)?;
+    reset_emission_debt(vault, position)?;
+    reset_reward_slot_debts(vault, position)?;
+    position.reward_debt = (position.weighted_stake as u128)
+        .checked_mul(vault.acc_reward_per_share)
+        .ok_or(ErrorCode::Overflow)?
+        / PRECISION;
+    Ok(())
+}
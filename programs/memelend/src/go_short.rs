@@ -1,8 +1,13 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{self, Token, TokenAccount, Mint, Transfer, CloseAccount};
-use crate::{VaultConfig, TokenConfig, Position, ErrorCode, ReferralVault, YieldVault};
+use crate::{VaultConfig, TokenConfig, ProtocolConfig, Position, ErrorCode, ReferralVault, YieldVault, WithdrawQueue, UserAccount};
 use anchor_spl::token::spl_token::native_mint::ID as NATIVE_MINT_ID;
-use crate::get_price_from_vault;
+use crate::get_liquidation_price;
+use crate::curve::load_curve;
+use crate::interest::accrue_borrow_index;
+use crate::market_config::{check_open_interest, leverage_limits};
+use crate::withdraw_queue::{init_withdraw_queue, available_liquidity};
+use crate::user_account::{init_user_account, allocate_position_id, register_position};
 
 pub const PRECISION: u128 = 1_000_000_000_000; // 1e12 for reward per share math
 
@@ -10,9 +15,18 @@ pub const PRECISION: u128 = 1_000_000_000_000; // 1e12 for reward per share math
 pub struct GoShortArgs {
     pub collateral_amount: u64,
     pub min_tokens_borrowed: u64,
-    pub position_id: u64,
-    pub collateral_percentage: u8,
-    pub liquidation_price: u64,
+    pub leverage_bps: u16, // SOL borrowed against per unit of collateral, 10_000 = 1x
+}
+
+// SOL value of the short opened with `net_collateral` at `leverage_bps`
+pub fn leveraged_input(net_collateral: u64, leverage_bps: u16) -> Result<u64> {
+    u64::try_from(
+        (net_collateral as u128)
+            .checked_mul(leverage_bps as u128)
+            .ok_or(ErrorCode::Overflow)?
+            / 10_000,
+    )
+    .map_err(|_| ErrorCode::Overflow.into())
 }
 
 
@@ -25,17 +39,24 @@ pub struct GoShort<'info> {
     #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
     pub wsol_mint: Account<'info, Mint>,
 
-    #[account(mut, constraint = user_collateral_account.mint == NATIVE_MINT_ID)]
+    #[account(
+        mut,
+        constraint = user_collateral_account.mint == NATIVE_MINT_ID @ ErrorCode::InvalidWsolMint,
+        constraint = user_collateral_account.owner == user.key() @ ErrorCode::Unauthorized,
+    )]
     pub user_collateral_account: Box<Account<'info, TokenAccount>>,
 
-    #[account(mut)]
+    #[account(address = token_config.mint @ ErrorCode::InvalidMint)]
     pub mint: Box<Account<'info, Mint>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = temp_wsol_account.mint == NATIVE_MINT_ID @ ErrorCode::InvalidWsolMint,
+    )]
     pub temp_wsol_account: Box<Account<'info, TokenAccount>>,
 
-    /// CHECK: signer PDA
-    pub temp_wsol_authority: AccountInfo<'info>,
+    #[account(address = user.key())]
+    pub temp_wsol_authority: Signer<'info>,
 
     #[account(
         mut,
@@ -55,14 +76,25 @@ pub struct GoShort<'info> {
     )]
     pub platform_vault: Box<Account<'info, TokenAccount>>,
 
-    #[account(mut, seeds = [b"vault_config", mint.key().as_ref()], bump)]
+    #[account(
+        mut,
+        seeds = [b"vault_config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub vault_config: Box<Account<'info, VaultConfig>>,
 
+    #[account(
+        mut,
+        seeds = [b"config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub token_config: Box<Account<'info, TokenConfig>>,
 
     /// CHECK: PDA authority for vaults
     #[account(
-        seeds = [b"vault", vault_config.mint.as_ref()],
+        seeds = [b"vault", mint.key().as_ref()],
         bump,
     )]
     pub vault_authority: AccountInfo<'info>,
@@ -77,7 +109,9 @@ pub struct GoShort<'info> {
 
     #[account(
         mut,
-        constraint = lending_vault.mint == token_config.mint,
+        seeds = [b"vault_lending", mint.key().as_ref()],
+        bump,
+        constraint = lending_vault.mint == token_config.mint @ ErrorCode::InvalidMint,
     )]
     pub lending_vault: Box<Account<'info, TokenAccount>>,
 
@@ -88,13 +122,27 @@ pub struct GoShort<'info> {
     )]
     pub lending_vault_authority: AccountInfo<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"vault_liquidity", mint.key().as_ref()],
+        bump,
+    )]
     pub token_liquidity_vault: Box<Account<'info, TokenAccount>>,
 
+    #[account(
+        init_if_needed,
+        payer = user,
+        seeds = [b"user_account", user.key().as_ref()],
+        bump,
+        space = 8 + UserAccount::LEN,
+    )]
+    pub user_account: Box<Account<'info, UserAccount>>,
+
+    // Id comes from the user's account
     #[account(
         init,
         payer = user,
-        seeds = [b"position", user.key().as_ref(), mint.key().as_ref(), &args.position_id.to_le_bytes()],
+        seeds = [b"position", user.key().as_ref(), mint.key().as_ref(), &user_account.next_position_id.to_le_bytes()],
         bump,
         space = 8 + Position::LEN
     )]
@@ -129,9 +177,26 @@ pub struct GoShort<'info> {
     /// CHECK: Only used for seeds/validation
     pub referrer: UncheckedAccount<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"yield_vault", mint.key().as_ref()],
+        bump = yield_vault.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub yield_vault: Box<Account<'info, YieldVault>>,
 
+    #[account(
+        init_if_needed,
+        payer = user,
+        seeds = [b"withdraw_queue", mint.key().as_ref()],
+        bump,
+        space = 8 + WithdrawQueue::LEN,
+    )]
+    pub withdraw_queue: Box<Account<'info, WithdrawQueue>>,
+
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Box<Account<'info, ProtocolConfig>>,
+
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
@@ -140,9 +205,7 @@ pub struct GoShort<'info> {
 pub fn go_short(ctx: Context<GoShort>, args: GoShortArgs) -> Result<()> {
     let collateral_amount = args.collateral_amount;
     let min_tokens_borrowed = args.min_tokens_borrowed;
-    let position_id = args.position_id;
-    let collateral_percentage = args.collateral_percentage;
-    let liquidation_price = args.liquidation_price;
+    let leverage_bps = args.leverage_bps;
 
     let token_config = &mut ctx.accounts.token_config;
     let vault_config = &mut ctx.accounts.vault_config;
@@ -150,9 +213,13 @@ pub fn go_short(ctx: Context<GoShort>, args: GoShortArgs) -> Result<()> {
 
     msg!("collateral_amount: {}", collateral_amount);
     msg!("min_tokens_borrowed: {}", min_tokens_borrowed);
-    msg!("position_id: {}", position_id);
-    msg!("collateral_percentage: {}", collateral_percentage);
-    msg!("liquidation_price: {}", liquidation_price);
+    msg!("leverage_bps: {}", leverage_bps);
+
+    let (min_leverage_bps, max_leverage_bps) = leverage_limits(vault_config);
+    require!(
+        leverage_bps >= min_leverage_bps && leverage_bps <= max_leverage_bps,
+        ErrorCode::InvalidLeverage
+    );
     
 
     // Calculate fees
@@ -204,20 +271,25 @@ pub fn go_short(ctx: Context<GoShort>, args: GoShortArgs) -> Result<()> {
     msg!("pre_sale_fee_sol: {}", pre_sale_fee_sol);
     msg!("creator_fee_sol: {}", creator_fee_sol);
 
-    let total_platform_fee_bps = token_config.platform_fee_bps as u64; // 100 = 1%
-    let referral_cut_bps = 40; // 0.4%
-    let mut yield_cut_bps = 10; // 0.1%
+    let protocol_config = &ctx.accounts.protocol_config;
+    let total_platform_fee_bps = protocol_config.platform_fee_bps as u64; // default 100 = 1%
+    let referral_cut_bps = protocol_config.referral_cut_bps as u64; // default 0.4%
+    let mut yield_cut_bps = protocol_config.yield_cut_bps as u64; // default 0.1%
 
     let mut platform_share_sol = ((collateral_amount as u64)
         .checked_mul(total_platform_fee_bps as u64)
         .ok_or(ErrorCode::Overflow)? / 10_000) as u64;
 
+    // init_if_needed has already created the account, a zero referrer means it is new
+    if ctx.accounts.referral_tracking.referrer == Pubkey::default() {
+        ctx.accounts.referral_tracking.referrer = ctx.accounts.referrer.key();
+    }
 
     let is_referral = ctx.accounts.referrer.key() != ctx.accounts.platform_vault.key();
 
     let referral_share_sol = if is_referral {
         let referral_fee = ((collateral_amount as u64)
-            .checked_mul(referral_cut_bps as u64)
+            .checked_mul(referral_cut_bps)
             .ok_or(ErrorCode::Overflow)? / 10_000) as u64;
 
         let platform_fee = platform_share_sol
@@ -237,7 +309,7 @@ pub fn go_short(ctx: Context<GoShort>, args: GoShortArgs) -> Result<()> {
         referral_fee
     } else {
         // If no referrer, increase yield share from 0.1% → 0.2%
-        yield_cut_bps = 20; // 0.2%
+        yield_cut_bps = protocol_config.yield_cut_no_referral_bps as u64; // default 0.2%
 
         0
     };
@@ -337,59 +409,12 @@ pub fn go_short(ctx: Context<GoShort>, args: GoShortArgs) -> Result<()> {
         net_sol_to_liquidity,
     )?;
 
-    // Get current vault state before updates
-    let token_reserve = vault_config.token_reserve;
-    let sol_reserve = vault_config.sol_reserve;
-    let accumulated_c = vault_config.accumulated_c;
-    let virtual_sol = vault_config.virtual_sol;
-    let virtual_tokens = vault_config.virtual_tokens;
-
-    // --- Compute effective reserves ---
-    // Combine SOL reserves safely (handles negative accumulated_c)
-    let effective_sol_i128 = (sol_reserve as i128)
-        .checked_add(accumulated_c as i128)
-        .and_then(|s| s.checked_add(virtual_sol as i128))
-        .ok_or(ErrorCode::Overflow)?;
-
-    // Ensure result is not negative (since SOL reserves can't be negative)
-    require!(effective_sol_i128 >= 0, ErrorCode::Underflow);
-
-    let effective_sol_reserve = effective_sol_i128 as u64;
-
-    // Combine token reserves with virtual liquidity
-    let effective_token_reserve = token_reserve
-        .checked_add(virtual_tokens)
-        .ok_or(ErrorCode::Overflow)?;
-
     // --- Calculate tokens to borrow using bonding curve ---
-    let divisor = 100u64
-        .checked_div(collateral_percentage as u64)
-        .ok_or(ErrorCode::DivisionByZero)?;
-
-    let effective_input = net_sol_to_liquidity
-        .checked_div(divisor)
-        .ok_or(ErrorCode::DivisionByZero)?;
-
-    // Apply standard bonding curve (constant product invariant)
-    let numerator = (effective_token_reserve as u128)
-        .checked_mul(effective_sol_reserve as u128)
-        .ok_or(ErrorCode::Overflow)?;
-
-    let denominator = (effective_sol_reserve as u128)
-        .checked_add(effective_input as u128)
-        .ok_or(ErrorCode::Overflow)?;
-
-    let fraction = numerator
-        .checked_div(denominator)
-        .ok_or(ErrorCode::DivisionByZero)?;
+    let effective_input = leveraged_input(net_sol_to_liquidity, leverage_bps)?;
+    require!(effective_input > 0, ErrorCode::ZeroInput);
 
-    // Cast fraction back to u64 safely
-    let fraction_u64 = u64::try_from(fraction).map_err(|_| ErrorCode::Overflow)?;
-
-    // Tokens out = tokens borrowed
-    let tokens_out = effective_token_reserve
-        .checked_sub(fraction_u64)
-        .ok_or(ErrorCode::Underflow)?;
+    // Tokens out = tokens borrowed, priced on this mint's curve
+    let tokens_out = load_curve(vault_config)?.borrow_quote(effective_input)?;
 
     // --- Slippage check ---
     require!(
@@ -397,6 +422,16 @@ pub fn go_short(ctx: Context<GoShort>, args: GoShortArgs) -> Result<()> {
         ErrorCode::SlippageExceeded
     );
 
+    // --- Open interest caps ---
+    // Tokens reserved for queued withdrawals are not borrowable
+    init_withdraw_queue(&mut ctx.accounts.withdraw_queue, ctx.bumps.withdraw_queue, ctx.accounts.mint.key());
+    check_open_interest(
+        vault_config,
+        ctx.accounts.yield_vault.total_staked,
+        available_liquidity(ctx.accounts.lending_vault.amount, &ctx.accounts.withdraw_queue),
+        tokens_out,
+    )?;
+
 
     // Transfer borrowed tokens from lending vault to token liquidity vault
     let mint_key = ctx.accounts.mint.key();
@@ -424,14 +459,14 @@ pub fn go_short(ctx: Context<GoShort>, args: GoShortArgs) -> Result<()> {
    
 
     // Compute acc_reward_per_share delta
-    if yield_vault.total_staked > 0 {
+    if yield_vault.total_weighted_stake > 0 {
         // new rewards = interest added (WSOL)
         let new_rewards = yield_share_sol as u128;
 
         // Update cumulative reward per share
         yield_vault.acc_reward_per_share = yield_vault
             .acc_reward_per_share
-            .checked_add(new_rewards * PRECISION / yield_vault.total_staked as u128)
+            .checked_add(new_rewards * PRECISION / yield_vault.total_weighted_stake as u128)
             .ok_or(ErrorCode::Overflow)?;
 
         yield_vault.last_accrual_ts = now;
@@ -468,16 +503,36 @@ pub fn go_short(ctx: Context<GoShort>, args: GoShortArgs) -> Result<()> {
 
    
     // Recalculate to get entry price after everything
-    let price = get_price_from_vault(
-        vault_config.sol_reserve,
-        vault_config.accumulated_c,
-        vault_config.token_reserve,
-        vault_config.virtual_sol,
-        vault_config.virtual_tokens,
-        ctx.accounts.mint.decimals,
-    );
+    let price = load_curve(vault_config)?.spot_price(ctx.accounts.mint.decimals);
    msg!("price: {}", price);
 
+    // Liquidation threshold is derived on-chain, never taken from the client
+    let liquidation_price = get_liquidation_price(
+        price,
+        tokens_out,
+        net_sol_to_liquidity,
+        ctx.accounts.protocol_config.maintenance_margin_bps as u64,
+        ctx.accounts.mint.decimals,
+    )?;
+    msg!("liquidation_price: {}", liquidation_price);
+
+    // Interest on this position accrues from the index as of now
+    let borrow_index = accrue_borrow_index(vault_config, yield_vault.total_staked, now)?;
+    msg!("borrow_index: {}", borrow_index);
+
+    vault_config.total_borrowed = vault_config
+        .total_borrowed
+        .checked_add(tokens_out)
+        .ok_or(ErrorCode::Overflow)?;
+
+    // Position id from the user's registry
+    let user_account = &mut ctx.accounts.user_account;
+    init_user_account(user_account, ctx.accounts.user.key(), ctx.bumps.user_account);
+    let position_id = allocate_position_id(user_account)?;
+    register_position(user_account, ctx.accounts.position.key())?;
+
+    msg!("position_id: {}", position_id);
+
     // Store short position
     let position = &mut ctx.accounts.position;
     position.owner = ctx.accounts.user.key();
@@ -489,6 +544,7 @@ pub fn go_short(ctx: Context<GoShort>, args: GoShortArgs) -> Result<()> {
     position.liquidate = liquidation_price;
     position.position_id = position_id;
     position.created_at = Clock::get()?.unix_timestamp;
+    position.borrow_index_snapshot = borrow_index;
 
 
     //--- Close accounts ---
@@ -507,7 +563,7 @@ pub fn go_short(ctx: Context<GoShort>, args: GoShortArgs) -> Result<()> {
         user: ctx.accounts.user.key(),
         collateral: net_sol_to_liquidity,
         borrowed_tokens:tokens_out,
-        position_id: args.position_id,
+        position_id,
         entry_price: price as u64,
         liquidation_price,
         pending_rewards: ctx.accounts.referral_tracking.pending_rewards, 
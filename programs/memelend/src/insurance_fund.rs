@@ -0,0 +1,172 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Transfer, TokenAccount, Token, Mint};
+use crate::{VaultConfig, ErrorCode};
+
+// Splits a loss the collateral could not absorb into the part the insurance fund pays
+// and the part left over as bad debt
+pub fn split_shortfall(shortfall: u64, insurance_fund: u64) -> (u64, u64) {
+    let insured = shortfall.min(insurance_fund);
+    (insured, shortfall - insured)
+}
+
+// Moves `amount` from the insurance vault back into the wSOL vault and books it against accumulated_c,
+// so covered losses reach the curve as real SOL
+pub fn draw_insurance<'info>(
+    vault_config: &mut VaultConfig,
+    insurance_vault: &Account<'info, TokenAccount>,
+    wsol_vault: &Account<'info, TokenAccount>,
+    vault_authority: &AccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+    vault_authority_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    pay_from_insurance(
+        vault_config,
+        insurance_vault,
+        wsol_vault,
+        vault_authority,
+        token_program,
+        vault_authority_bump,
+        amount,
+    )?;
+
+    vault_config.accumulated_c = vault_config
+        .accumulated_c
+        .checked_add(amount as i64)
+        .ok_or(ErrorCode::Overflow)?;
+
+    Ok(())
+}
+
+// Moves `amount` from the insurance vault into `to` and takes it off the mint's insurance_fund
+pub fn pay_from_insurance<'info>(
+    vault_config: &mut VaultConfig,
+    insurance_vault: &Account<'info, TokenAccount>,
+    to: &Account<'info, TokenAccount>,
+    vault_authority: &AccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+    vault_authority_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+
+    let mint_key = vault_config.mint;
+    let signer_seeds: &[&[u8]] = &[
+        b"vault",
+        mint_key.as_ref(),
+        &[vault_authority_bump],
+    ];
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            Transfer {
+                from: insurance_vault.to_account_info(),
+                to: to.to_account_info(),
+                authority: vault_authority.to_account_info(),
+            },
+            &[signer_seeds],
+        ),
+        amount,
+    )?;
+
+    vault_config.insurance_fund = vault_config
+        .insurance_fund
+        .checked_sub(amount)
+        .ok_or(ErrorCode::Underflow)?;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct SettleBadDebt<'info> {
+    // Permissionless crank
+    pub caller: Signer<'info>,
+
+    #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
+    pub wsol_mint: Account<'info, Mint>,
+
+    pub mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub vault_config: Box<Account<'info, VaultConfig>>,
+
+    /// CHECK: PDA authority for vaults
+    #[account(
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+    )]
+    pub vault_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_insurance", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+        token::authority = vault_authority,
+    )]
+    pub insurance_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_wsol", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+    )]
+    pub liquidity_sol_vault: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+// Pays down recorded bad debt with whatever the insurance fund has collected since
+pub fn settle_bad_debt(ctx: Context<SettleBadDebt>) -> Result<()> {
+    let vault_config = &mut ctx.accounts.vault_config;
+
+    require!(vault_config.bad_debt > 0, ErrorCode::NoBadDebt);
+
+    let (settled, remaining) = split_shortfall(vault_config.bad_debt, vault_config.insurance_fund);
+    require!(settled > 0, ErrorCode::InsuranceFundEmpty);
+
+    msg!("bad_debt: {}", vault_config.bad_debt);
+    msg!("settled: {}", settled);
+
+    draw_insurance(
+        vault_config,
+        &ctx.accounts.insurance_vault,
+        &ctx.accounts.liquidity_sol_vault,
+        &ctx.accounts.vault_authority,
+        &ctx.accounts.token_program,
+        ctx.bumps.vault_authority,
+        settled,
+    )?;
+
+    vault_config.bad_debt = remaining;
+
+    emit!(BadDebtSettledEvent {
+        mint: vault_config.mint,
+        settled,
+        bad_debt: vault_config.bad_debt,
+        insurance_fund: vault_config.insurance_fund,
+        accumulated_c_after: vault_config.accumulated_c,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct BadDebtSettledEvent {
+    pub mint: Pubkey,
+    pub settled: u64,
+    pub bad_debt: u64,
+    pub insurance_fund: u64,
+    pub accumulated_c_after: i64,
+    pub timestamp: i64,
+}
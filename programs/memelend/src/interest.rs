@@ -0,0 +1,122 @@
+use anchor_lang::prelude::*;
+use crate::{VaultConfig, ErrorCode};
+
+// Borrow index scale, 1e18 == no interest accrued yet
+pub const BORROW_INDEX_ONE: u128 = 1_000_000_000_000_000_000;
+pub const SECONDS_PER_YEAR: u128 = 365 * 24 * 60 * 60;
+
+// Kinked rate model defaults, tuned per mint with update_market_config
+pub const DEFAULT_RATE_BASE_BPS: u16 = 200;   // 2% APR with nothing lent out
+pub const DEFAULT_RATE_SLOPE_BPS: u16 = 1_000; // +10% APR from 0 up to the kink
+pub const DEFAULT_RATE_KINK_BPS: u16 = 8_000;  // 80% utilisation
+pub const DEFAULT_RATE_MAX_BPS: u16 = 10_000;  // 100% APR when fully lent out
+
+// Every short on a mint shares one cumulative borrow index. A position snapshots it
+// when opened and owes `principal * (index_now / index_snapshot - 1)` when it settles.
+// The index grows at the borrow rate, which follows utilisation of the lending vault:
+//
+//   rate = base + slope * u / kink                            for u <= kink
+//   rate = base + slope + (max - base - slope) * (u - kink) / (1 - kink)   above it
+
+// (base, slope, kink, max) for a mint
+pub fn rate_model(vault_config: &VaultConfig) -> (u64, u64, u64, u64) {
+    (
+        vault_config.rate_base_bps as u64,
+        vault_config.rate_slope_bps as u64,
+        vault_config.rate_kink_bps as u64,
+        vault_config.rate_max_bps as u64,
+    )
+}
+
+pub fn validate_rate_model(base: u16, slope: u16, kink: u16, max: u16) -> Result<()> {
+    require!(kink > 0 && kink <= 10_000, ErrorCode::InvalidRateModel);
+    require!(
+        (base as u32) + (slope as u32) <= max as u32,
+        ErrorCode::InvalidRateModel
+    );
+    Ok(())
+}
+
+// Share of staked tokens currently lent to shorts, in bps (capped at 100%)
+pub fn utilisation_bps(total_borrowed: u64, total_staked: u64) -> u64 {
+    if total_borrowed == 0 {
+        return 0;
+    }
+    if total_staked == 0 {
+        return 10_000;
+    }
+
+    ((total_borrowed as u128 * 10_000 / total_staked as u128).min(10_000)) as u64
+}
+
+// Borrow APR in bps for a mint at `utilisation` bps
+pub fn borrow_rate_bps(vault_config: &VaultConfig, utilisation: u64) -> u64 {
+    let (base, slope, kink, max) = rate_model(vault_config);
+    kinked_rate_bps(base, slope, kink, max, utilisation)
+}
+
+pub fn kinked_rate_bps(base: u64, slope: u64, kink: u64, max: u64, utilisation: u64) -> u64 {
+    if utilisation <= kink {
+        return base + slope * utilisation / kink;
+    }
+
+    let steep = max.saturating_sub(base + slope);
+    let above = (utilisation - kink) * steep / 10_000u64.saturating_sub(kink).max(1);
+    base + slope + above
+}
+
+// What the index would be at `now` without writing it back
+pub fn projected_borrow_index(vault_config: &VaultConfig, total_staked: u64, now: i64) -> Result<u128> {
+    let elapsed = now.saturating_sub(vault_config.last_borrow_accrual_ts);
+    if elapsed <= 0 {
+        return Ok(vault_config.borrow_index);
+    }
+
+    let utilisation = utilisation_bps(vault_config.total_borrowed, total_staked);
+    let rate_bps = borrow_rate_bps(vault_config, utilisation);
+
+    grow_index(vault_config.borrow_index, rate_bps, elapsed)
+}
+
+// `index` after `elapsed` seconds at `rate_bps` APR (simple interest per accrual)
+pub fn grow_index(index: u128, rate_bps: u64, elapsed: i64) -> Result<u128> {
+    if elapsed <= 0 {
+        return Ok(index);
+    }
+
+    let growth = index
+        .checked_mul(rate_bps as u128)
+        .and_then(|v| v.checked_mul(elapsed as u128))
+        .ok_or(ErrorCode::Overflow)?
+        / (10_000 * SECONDS_PER_YEAR);
+
+    Ok(index
+        .checked_add(growth)
+        .ok_or(ErrorCode::Overflow)?)
+}
+
+// Rolls the mint's borrow index forward to `now` and returns the new index.
+// Call this before total_borrowed changes so past time is charged at the old utilisation.
+pub fn accrue_borrow_index(vault_config: &mut VaultConfig, total_staked: u64, now: i64) -> Result<u128> {
+    let index = projected_borrow_index(vault_config, total_staked, now)?;
+
+    vault_config.borrow_index = index;
+    vault_config.last_borrow_accrual_ts = now;
+
+    Ok(index)
+}
+
+// Interest owed in lamports on `principal_sol` borrowed at `index_snapshot`
+pub fn borrow_interest(principal_sol: u64, index_snapshot: u128, index_now: u128) -> Result<u64> {
+    if index_now <= index_snapshot {
+        return Ok(0);
+    }
+
+    let interest = (principal_sol as u128)
+        .checked_mul(index_now - index_snapshot)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(index_snapshot)
+        .ok_or(ErrorCode::DivisionByZero)?;
+
+    u64::try_from(interest).map_err(|_| ErrorCode::Overflow.into())
+}
@@ -1,14 +1,14 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{self, Token, TokenAccount, Mint, Transfer, CloseAccount};
 use anchor_spl::token::spl_token::native_mint::ID as NATIVE_MINT_ID;
-use crate::{VaultConfig, TokenConfig, ErrorCode, UserPreSalePosition};
-use crate::get_price_from_vault;
+use crate::{VaultConfig, TokenConfig, ProtocolConfig, ErrorCode, UserPreSalePosition, UserAccount};
+use crate::curve::load_curve;
+use crate::user_account::{init_user_account, allocate_position_id, register_position};
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct PresaleArgs {
     pub sol_amount: u64,
     pub min_tokens: u64,
-    pub position_id: u64,
 }
 
 #[derive(Accounts)]
@@ -20,22 +20,39 @@ pub struct JoinPresale<'info> {
     #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
     pub wsol_mint: Account<'info, Mint>,
 
-    #[account(mut)]
+    #[account(mut, address = token_config.mint @ ErrorCode::InvalidMint)]
     pub mint: Box<Account<'info, Mint>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = user_token_account.mint == mint.key() @ ErrorCode::InvalidMint,
+        constraint = user_token_account.owner == user.key() @ ErrorCode::Unauthorized,
+    )]
     pub user_token_account: Box<Account<'info, TokenAccount>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        constraint = temp_wsol_account.mint == wsol_mint.key() @ ErrorCode::InvalidWsolMint,
+    )]
     pub temp_wsol_account: Box<Account<'info, TokenAccount>>,
 
-    /// CHECK: signer PDA
-    pub temp_wsol_authority: AccountInfo<'info>,
+    #[account(address = user.key())]
+    pub temp_wsol_authority: Signer<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"vault_config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub vault_config: Box<Account<'info, VaultConfig>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub token_config: Box<Account<'info, TokenConfig>>,
 
     /// CHECK: PDA authority for vaults
@@ -55,7 +72,9 @@ pub struct JoinPresale<'info> {
 
     #[account(
         mut,
-        constraint = lending_vault.mint == token_config.mint,
+        seeds = [b"vault_lending", mint.key().as_ref()],
+        bump,
+        constraint = lending_vault.mint == token_config.mint @ ErrorCode::InvalidMint,
     )]
     pub lending_vault: Box<Account<'info, TokenAccount>>,
 
@@ -75,9 +94,24 @@ pub struct JoinPresale<'info> {
     )]
     pub user_presale_position: Box<Account<'info, UserPreSalePosition>>,
 
-    #[account(mut)]
+    #[account(
+        init_if_needed,
+        payer = user,
+        seeds = [b"user_account", user.key().as_ref()],
+        bump,
+        space = 8 + UserAccount::LEN,
+    )]
+    pub user_account: Box<Account<'info, UserAccount>>,
+
+    #[account(
+        mut,
+        token::mint = wsol_mint,
+    )]
     pub bot_wsol_account: Box<Account<'info, TokenAccount>>,
 
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Box<Account<'info, ProtocolConfig>>,
+
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
@@ -87,10 +121,8 @@ pub fn join_presale(ctx: Context<JoinPresale>, args: PresaleArgs) -> Result<()>
 
     let sol_amount = args.sol_amount;
     let min_tokens = args.min_tokens;
-    let position_id = args.position_id;
     msg!("sol_amount: {}", sol_amount);
     msg!("min_tokens: {}", min_tokens);
-    msg!("position_id: {}", position_id);
 
     require!(ctx.accounts.token_config.pre_sale_token_allocation > 0, ErrorCode::PresaleSoldOut);
     require!(sol_amount > 0, ErrorCode::InvalidInput);
@@ -104,7 +136,7 @@ pub fn join_presale(ctx: Context<JoinPresale>, args: PresaleArgs) -> Result<()>
     // );
 
     // === Calculate Liquidator Reward (Hybrid Model) ===
-    let automation_fee_bps: u64 = 100; // 1%
+    let automation_fee_bps = ctx.accounts.protocol_config.presale_automation_fee_bps as u64; // default 1%
 
     let percent_fee = sol_amount
         .checked_mul(automation_fee_bps)
@@ -118,29 +150,10 @@ pub fn join_presale(ctx: Context<JoinPresale>, args: PresaleArgs) -> Result<()>
         .ok_or(ErrorCode::Underflow)?;
     msg!("net_sol_to_liquidity: {}", net_sol_to_liquidity);
     
-    let accumulated_c = ctx.accounts.vault_config.accumulated_c as i128;
-    msg!("accumulated_c: {}", accumulated_c);
-
-    let denominator = (ctx.accounts.vault_config.sol_reserve as i128)
-        .checked_add(ctx.accounts.vault_config.virtual_sol as i128)
-        .and_then(|sum| sum.checked_add(accumulated_c))
-        .and_then(|sum| sum.checked_add(net_sol_to_liquidity as i128))
-        .and_then(|sum| u64::try_from(sum).ok())
-        .ok_or(ErrorCode::Overflow)?;
-    msg!("denominator: {}", denominator);
-
-    let effective_token_reserve = ctx.accounts.vault_config.virtual_tokens as u128
-    + ctx.accounts.vault_config.token_reserve as u128;
-    msg!("effective_token_reserve: {}", effective_token_reserve);
-
-    let numerator = (net_sol_to_liquidity as u128)
-        .checked_mul(effective_token_reserve)
-        .ok_or(ErrorCode::Overflow)?;
-    msg!("numerator: {}", numerator);
+    msg!("accumulated_c: {}", ctx.accounts.vault_config.accumulated_c);
 
-    let token_out = numerator
-        .checked_div(denominator as u128)
-        .ok_or(ErrorCode::Overflow)? as u64;
+    // Presale buys go through the same curve as regular buys
+    let token_out = load_curve(&ctx.accounts.vault_config)?.quote_buy(net_sol_to_liquidity)?;
     msg!("token_out: {}", token_out);
 
     // Slippage check
@@ -218,19 +231,20 @@ pub fn join_presale(ctx: Context<JoinPresale>, args: PresaleArgs) -> Result<()>
 
 
     // --- Calculate the entry price after everything has gone through ---
-    let entry_price = get_price_from_vault(
-        ctx.accounts.vault_config.sol_reserve,
-        ctx.accounts.vault_config.accumulated_c,
-        ctx.accounts.vault_config.token_reserve,
-        ctx.accounts.vault_config.virtual_sol,
-        ctx.accounts.vault_config.virtual_tokens,
-        ctx.accounts.mint.decimals,
-    );
+    let entry_price = load_curve(&ctx.accounts.vault_config)?.spot_price(ctx.accounts.mint.decimals);
 
     msg!("entry_price: {}", entry_price);
     msg!("tokens_sold: {}", ctx.accounts.token_config.pre_sale_tokens_sold);
     
 
+    // Position id from the user's registry
+    let user_account = &mut ctx.accounts.user_account;
+    init_user_account(user_account, ctx.accounts.user.key(), ctx.bumps.user_account);
+    let position_id = allocate_position_id(user_account)?;
+    register_position(user_account, ctx.accounts.user_presale_position.key())?;
+
+    msg!("position_id: {}", position_id);
+
     // Store presale position
     let position = &mut ctx.accounts.user_presale_position;
     position.user = ctx.accounts.user.key();
@@ -261,7 +275,7 @@ pub fn join_presale(ctx: Context<JoinPresale>, args: PresaleArgs) -> Result<()>
         sol_reserve: ctx.accounts.vault_config.sol_reserve,
         token_out,
         pre_sale_token_allocation: ctx.accounts.token_config.pre_sale_token_allocation,
-        position_id: args.position_id,
+        position_id,
         entry_price: entry_price,
         timestamp: Clock::get()?.unix_timestamp,
     });
@@ -17,6 +17,25 @@ pub mod claim_yield_rewards;
 pub mod creator_yield_withdrawal;
 pub mod claim_earnings;
 pub mod claim_referral_rewards;
+pub mod protocol_config;
+pub mod withdraw_platform_fees;
+pub mod curve;
+pub mod interest;
+pub mod market_config;
+pub mod withdraw_queue;
+pub mod liquidation_auction;
+pub mod insurance_fund;
+pub mod sol_lending;
+pub mod long_position;
+pub mod position_triggers;
+pub mod user_account;
+pub mod emissions;
+pub mod yield_lock;
+pub mod compound_rewards;
+pub mod vesting;
+pub mod reward_slots;
+pub mod reduce_position;
+pub mod add_collateral;
 
 
 use add_liquidity::*;
@@ -34,36 +53,108 @@ use claim_yield_rewards::*;
 use creator_yield_withdrawal::*;
 use claim_earnings::*;
 use claim_referral_rewards::*;
-
-
-use crate::buy_token::{BuyTokenArgs, BuyToken};
-use crate::sell_token::{SellTokenArgs, SellToken};
+use protocol_config::*;
+use market_config::*;
+use withdraw_queue::*;
+use liquidation_auction::*;
+use insurance_fund::*;
+use sol_lending::*;
+use long_position::*;
+use position_triggers::*;
+use withdraw_platform_fees::*;
+use curve::*;
+use reduce_position::*;
+use add_collateral::*;
+use compound_rewards::*;
+use vesting::*;
+use reward_slots::*;
+use yield_lock::*;
+
+
+use crate::buy_token::{BuyTokenArgs, BuyTokenExactOutArgs, BuyToken};
+use crate::sell_token::{SellTokenArgs, SellTokenExactOutArgs, SellToken};
 use crate::go_short::{GoShortArgs, GoShort};
+use crate::emissions::EMISSION_RESERVE_BPS;
 
 
 
 declare_id!("DRd15yZQhiAzzSZZtHSVWZFoqQ51sPRsF3B1yNTrxrG");
 
-pub const PLATFORM_FEE_BPS: u64 = 100; // 1%
-
 // Helper function to get current price
+// Constant-product only; pools on other curves price through load_curve(..).spot_price()
 pub fn get_price_from_vault(sol_reserve: u64, accumulated_c: i64, token_reserve: u64, virtual_sol: u64, virtual_tokens: u64, decimals: u8) -> u64 {
     let effective_reserve = (sol_reserve as i128) // cast to signed
         .checked_add(virtual_sol as i128)
         .and_then(|sum| sum.checked_add(accumulated_c as i128))
         .unwrap_or(0); // fallback if overflow (safe default)
 
-    let effective_reserve_u128 = if effective_reserve < 0 { 0 } else { effective_reserve as u128 };
+    let effective_token_reserve = token_reserve.saturating_add(virtual_tokens);
+
+    ConstantProduct {
+        sol: u64::try_from(effective_reserve.max(0)).unwrap_or(u64::MAX),
+        tokens: effective_token_reserve,
+    }
+    .spot_price(decimals)
+}
 
-    let effective_token_reserve = (token_reserve as u128) // cast to signed
-        .checked_add(virtual_tokens as u128)
-        .unwrap_or(1);
+// Helper function to find a gross trade amount that still leaves `net` after fees.
+// Referral, yield and presale cuts are all carved out of the creator and platform fees,
+// and every fee rounds down, so the ceiling of net / (1 - fee rate) always covers it.
+pub fn get_gross_for_net(net: u64, creator_fee_bps: u16, platform_fee_bps: u16) -> Result<u64> {
+    let total_fee_bps = creator_fee_bps as u128 + platform_fee_bps as u128;
+    require!(total_fee_bps < 10_000, ErrorCode::InvalidInput);
 
-    effective_reserve_u128
+    let gross = (net as u128)
+        .checked_mul(10_000)
+        .ok_or(ErrorCode::Overflow)?
+        .div_ceil(10_000 - total_fee_bps);
+
+    Ok(u64::try_from(gross).map_err(|_| ErrorCode::Overflow)?)
+}
+
+// Helper function to derive a short's liquidation price (same scale as get_price_from_vault).
+// The position is liquidatable once its loss eats all collateral except the maintenance margin.
+pub fn get_liquidation_price(entry_price: u64, borrowed_tokens: u64, collateral: u64, maintenance_margin_bps: u64, decimals: u8) -> Result<u64> {
+    require!(borrowed_tokens > 0, ErrorCode::ZeroInput);
+    require!(maintenance_margin_bps <= 10_000, ErrorCode::InvalidInput);
+
+    // Max SOL loss the collateral can absorb
+    let max_loss = (collateral as u128)
+        .checked_mul(10_000u128 - maintenance_margin_bps as u128)
+        .ok_or(ErrorCode::Overflow)?
+        / 10_000u128;
+
+    // Price move (per token) that produces that loss on the borrowed amount
+    let price_move = max_loss
         .checked_mul(10u128.pow(decimals as u32 + 6))
-        .unwrap_or(0)
-        .checked_div(effective_token_reserve as u128)
-        .unwrap_or(0) as u64
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(borrowed_tokens as u128)
+        .ok_or(ErrorCode::DivisionByZero)?;
+
+    let liquidation_price = (entry_price as u128)
+        .checked_add(price_move)
+        .ok_or(ErrorCode::Overflow)?;
+
+    Ok(u64::try_from(liquidation_price).unwrap_or(u64::MAX))
+}
+
+// Mirror of get_liquidation_price for longs: the price drop that eats the same share of collateral
+pub fn get_long_liquidation_price(entry_price: u64, tokens: u64, collateral: u64, maintenance_margin_bps: u64, decimals: u8) -> Result<u64> {
+    require!(tokens > 0, ErrorCode::ZeroInput);
+    require!(maintenance_margin_bps <= 10_000, ErrorCode::InvalidInput);
+
+    let max_loss = (collateral as u128)
+        .checked_mul(10_000u128 - maintenance_margin_bps as u128)
+        .ok_or(ErrorCode::Overflow)?
+        / 10_000u128;
+
+    let price_move = max_loss
+        .checked_mul(10u128.pow(decimals as u32 + 6))
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(tokens as u128)
+        .ok_or(ErrorCode::DivisionByZero)?;
+
+    Ok(u64::try_from((entry_price as u128).saturating_sub(price_move)).unwrap_or(0))
 }
 
 
@@ -88,8 +179,8 @@ pub mod memelend {
         claim_presale_earnings::claim_presale_rewards(ctx)
     }
 
-    pub fn yield_deposit(ctx: Context<DepositYield>, amount: u64, position_id: u64) -> Result<()> {
-        deposit_yield::yield_deposit(ctx, amount, position_id)
+    pub fn yield_deposit(ctx: Context<DepositYield>, amount: u64, lock_tier: u8) -> Result<()> {
+        deposit_yield::yield_deposit(ctx, amount, lock_tier)
     }
 
     pub fn withdraw_yield(ctx: Context<WithdrawYield>, amount: u64, position_id: u64) -> Result<()> {
@@ -100,10 +191,34 @@ pub mod memelend {
         creator_yield_withdrawal::creator_yield_withdrawal(ctx, amount, position_id)
     }
 
+    pub fn set_vesting_schedule(ctx: Context<SetVestingSchedule>, args: SetVestingScheduleArgs) -> Result<()> {
+        vesting::set_vesting_schedule(ctx, args)
+    }
+
     pub fn claim_rewards(ctx: Context<ClaimRewards>) -> Result<()> {
         claim_yield_rewards::claim_rewards(ctx)
     }
 
+    pub fn compound_rewards(ctx: Context<CompoundRewards>, args: CompoundRewardsArgs) -> Result<()> {
+        compound_rewards::compound_rewards(ctx, args)
+    }
+
+    pub fn set_compound_limit(ctx: Context<SetCompoundLimit>, max_price: u64) -> Result<()> {
+        compound_rewards::set_compound_limit(ctx, max_price)
+    }
+
+    pub fn kick_expired_lock(ctx: Context<KickExpiredLock>) -> Result<()> {
+        yield_lock::kick_expired_lock(ctx)
+    }
+
+    pub fn fund_reward_slot(ctx: Context<FundRewardSlot>, args: FundRewardSlotArgs) -> Result<()> {
+        reward_slots::fund_reward_slot(ctx, args)
+    }
+
+    pub fn claim_slot_rewards<'info>(ctx: Context<'_, '_, 'info, 'info, ClaimSlotRewards<'info>>) -> Result<()> {
+        reward_slots::claim_slot_rewards(ctx)
+    }
+
     pub fn claim_referral_rewards(ctx: Context<ClaimReferralRewards>) -> Result<()> {
         claim_referral_rewards::claim_referral_rewards(ctx)
     }
@@ -116,6 +231,14 @@ pub mod memelend {
         sell_token::sell_token(ctx, args)
     }
 
+    pub fn buy_token_exact_out(ctx: Context<BuyToken>, args: BuyTokenExactOutArgs) -> Result<()> {
+        buy_token::buy_token_exact_out(ctx, args)
+    }
+
+    pub fn sell_token_exact_out(ctx: Context<SellToken>, args: SellTokenExactOutArgs) -> Result<()> {
+        sell_token::sell_token_exact_out(ctx, args)
+    }
+
     pub fn go_short(ctx: Context<GoShort>, args: GoShortArgs) -> Result<()> {
         go_short::go_short(ctx, args)
     }
@@ -124,22 +247,99 @@ pub mod memelend {
         close_position::close_position(ctx, args)
     }
 
+    pub fn reduce_position(ctx: Context<ReducePosition>, args: ReducePositionArgs) -> Result<()> {
+        reduce_position::reduce_position(ctx, args)
+    }
+
+    pub fn set_position_triggers(ctx: Context<SetPositionTriggers>, args: SetPositionTriggersArgs) -> Result<()> {
+        position_triggers::set_position_triggers(ctx, args)
+    }
+
+    pub fn trigger_position_exit(ctx: Context<TriggerPositionExit>, args: TriggerPositionExitArgs) -> Result<()> {
+        position_triggers::trigger_position_exit(ctx, args)
+    }
+
+    pub fn add_collateral(ctx: Context<AddCollateral>, args: AddCollateralArgs) -> Result<()> {
+        add_collateral::add_collateral(ctx, args)
+    }
+
     pub fn liquidate_position(ctx: Context<LiquidatePosition>, args: LiquidatePositionArgs) -> Result<()> {
         liquidate_position::liquidate_position(ctx, args)
     }
 
+    pub fn open_liquidation_auction(ctx: Context<OpenLiquidationAuction>, args: LiquidationAuctionArgs) -> Result<()> {
+        liquidation_auction::open_liquidation_auction(ctx, args)
+    }
+
+    pub fn cancel_liquidation_auction(ctx: Context<CancelLiquidationAuction>, args: LiquidationAuctionArgs) -> Result<()> {
+        liquidation_auction::cancel_liquidation_auction(ctx, args)
+    }
+
+    pub fn settle_bad_debt(ctx: Context<SettleBadDebt>) -> Result<()> {
+        insurance_fund::settle_bad_debt(ctx)
+    }
+
+    pub fn deposit_sol_lending(ctx: Context<DepositSolLending>, amount: u64) -> Result<()> {
+        sol_lending::deposit_sol_lending(ctx, amount)
+    }
+
+    pub fn withdraw_sol_lending(ctx: Context<WithdrawSolLending>, amount: u64) -> Result<()> {
+        sol_lending::withdraw_sol_lending(ctx, amount)
+    }
+
+    pub fn go_long(ctx: Context<GoLong>, args: GoLongArgs) -> Result<()> {
+        long_position::go_long(ctx, args)
+    }
+
+    pub fn close_long(ctx: Context<CloseLong>, args: CloseLongArgs) -> Result<()> {
+        long_position::close_long(ctx, args)
+    }
+
+    pub fn liquidate_long(ctx: Context<LiquidateLong>, args: LiquidateLongArgs) -> Result<()> {
+        long_position::liquidate_long(ctx, args)
+    }
+
     pub fn claim_earnings(ctx: Context<ClaimEarnings>) -> Result<()> {
         claim_earnings::claim_earnings(ctx)
     }
 
+    pub fn initialize_protocol(ctx: Context<InitializeProtocol>) -> Result<()> {
+        protocol_config::initialize_protocol(ctx)
+    }
+
+    pub fn update_protocol_config(ctx: Context<UpdateProtocolConfig>, args: UpdateProtocolConfigArgs) -> Result<()> {
+        protocol_config::update_protocol_config(ctx, args)
+    }
+
+    pub fn request_withdraw_yield(ctx: Context<RequestWithdrawYield>, amount: u64, position_id: u64) -> Result<()> {
+        withdraw_queue::request_withdraw_yield(ctx, amount, position_id)
+    }
+
+    pub fn fill_withdraw_request(ctx: Context<FillWithdrawRequest>) -> Result<()> {
+        withdraw_queue::fill_withdraw_request(ctx)
+    }
+
+    pub fn update_market_config(ctx: Context<UpdateMarketConfig>, args: UpdateMarketConfigArgs) -> Result<()> {
+        market_config::update_market_config(ctx, args)
+    }
+
+    pub fn get_market_rates(ctx: Context<GetMarketRates>) -> Result<MarketRates> {
+        market_config::get_market_rates(ctx)
+    }
+
+    pub fn withdraw_platform_fees<'info>(ctx: Context<'_, '_, 'info, 'info, WithdrawPlatformFees<'info>>) -> Result<()> {
+        withdraw_platform_fees::withdraw_platform_fees(ctx)
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn initialize_token_and_split_supply(
         ctx: Context<InitTokenAndSplitSupply>, 
         token_id: String, 
         _symbol: String,
         _total_supply: u64,
         lend_percent: u8,
-        position_id: u64,
         presale_percent: u8,
+        vesting: SetVestingScheduleArgs,
     )-> Result<()>{
        
 
@@ -197,6 +397,21 @@ pub mod memelend {
                 .ok_or(ErrorCode::Underflow)?;
         }
 
+        // Part of the creator's stake becomes the stakers' emission reserve
+        let emission_reserve = creator_stake_amount
+            .checked_mul(EMISSION_RESERVE_BPS)
+            .ok_or(ErrorCode::Overflow)?
+            / 10_000;
+
+        creator_stake_amount = creator_stake_amount
+            .checked_sub(emission_reserve)
+            .ok_or(ErrorCode::Underflow)?;
+
+        let lend_amount = lend_amount
+            .checked_sub(emission_reserve)
+            .ok_or(ErrorCode::Underflow)?;
+
+        msg!("emission_reserve: {}", emission_reserve);
         msg!("creator_stake_amount: {}", creator_stake_amount);
         
 
@@ -222,6 +437,19 @@ pub mod memelend {
             lend_amount,
         )?;
 
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.token_vault.to_account_info(),
+                    to: ctx.accounts.emission_vault.to_account_info(),
+                    authority: ctx.accounts.token_vault_authority.to_account_info(),
+                },
+                &[token_vault_signer_seeds],
+            ),
+            emission_reserve,
+        )?;
+
         // Transfer the rest of the token to the liquidity vault as Token reserve
         token::transfer(
             CpiContext::new_with_signer(
@@ -259,29 +487,47 @@ pub mod memelend {
         ctx.accounts.yield_vault.mint = ctx.accounts.mint.key();
         ctx.accounts.yield_vault.apr_bps = 1000; // example 10% APR
         ctx.accounts.yield_vault.total_staked = creator_stake_amount; 
+        ctx.accounts.yield_vault.total_weighted_stake = creator_stake_amount; // creator stake starts unlocked
         ctx.accounts.yield_vault.creator = ctx.accounts.creator.key();
         ctx.accounts.yield_vault.launch_ts = now_ts;
         ctx.accounts.yield_vault.max_withdraw_bps = 10_000;
         ctx.accounts.yield_vault.last_accrual_ts = now_ts;
         ctx.accounts.yield_vault.bump = ctx.bumps.yield_vault;
+        ctx.accounts.yield_vault.emission_reserve = emission_reserve;
+        ctx.accounts.yield_vault.last_emission_ts = now_ts;
+
 
+        // Initialize creator's UserYieldPosition, listed in their registry like any other stake
+        let user_account = &mut ctx.accounts.user_account;
+        user_account::init_user_account(user_account, ctx.accounts.creator.key(), ctx.bumps.user_account);
+        let position_id = user_account::allocate_position_id(user_account)?;
+        user_account::register_position(user_account, ctx.accounts.user_yield_position.key())?;
 
-        // Initialize creator's UserYieldPosition
         ctx.accounts.user_yield_position.owner = ctx.accounts.creator.key();
         ctx.accounts.user_yield_position.mint = ctx.accounts.mint.key();
         ctx.accounts.user_yield_position.position_id = position_id;
         ctx.accounts.user_yield_position.is_creator = true;
         ctx.accounts.user_yield_position.initial_deposit = creator_stake_amount;
         ctx.accounts.user_yield_position.deposited = creator_stake_amount; // initial deposit can be added immediately
+        ctx.accounts.user_yield_position.weighted_stake = creator_stake_amount;
         ctx.accounts.user_yield_position.deposited_at = now_ts;
         ctx.accounts.user_yield_position.last_action_ts = now_ts;
         ctx.accounts.user_yield_position.bump = ctx.bumps.user_yield_position;
 
+        // Creator unlock schedule is public from launch, later changes can only tighten it
+        vesting::write_vesting_schedule(
+            &mut ctx.accounts.vesting_schedule,
+            ctx.accounts.mint.key(),
+            ctx.accounts.creator.key(),
+            vesting,
+            ctx.bumps.vesting_schedule,
+        )?;
 
         emit!(TokenLaunchEvent {
             mint: ctx.accounts.mint.key(),
             creator: ctx.accounts.creator.key(),
             apr_bps: ctx.accounts.yield_vault.apr_bps as u64,
+            emission_reserve,
             acc_reward_per_share: 0,
             max_withdraw_bps: ctx.accounts.yield_vault.max_withdraw_bps as u64,
             position_id: position_id,
@@ -297,7 +543,7 @@ pub mod memelend {
 
     }
 
-    pub fn initialize_fee_vaults(ctx: Context<InitFeeVault>, creator_fee_bps: u16)-> Result<()>{
+    pub fn initialize_fee_vaults(ctx: Context<InitFeeVault>, creator_fee_bps: u16, curve_kind: u8)-> Result<()>{
       
         let config = &mut ctx.accounts.token_config;
 
@@ -305,7 +551,7 @@ pub mod memelend {
         config.platform_vault = ctx.accounts.platform_vault.key();
         config.wsol_liquidity_vault = ctx.accounts.wsol_liquidity_vault.key();
         config.creator_fee_bps = creator_fee_bps;
-        config.platform_fee_bps = PLATFORM_FEE_BPS as u16;
+        config.platform_fee_bps = ctx.accounts.protocol_config.platform_fee_bps;
         
 
         // Initialize VaultConfig
@@ -313,8 +559,29 @@ pub mod memelend {
         vault_config.mint = ctx.accounts.mint.key();
         vault_config.token_reserve = ctx.accounts.liquidity_vault.amount; // Update token reserve
         //These two values will be to stabilize price, not actual vault amounts
-        vault_config.virtual_sol = 300_000_000_000; // = 300 SOL in lamports
-        vault_config.virtual_tokens = 73_000_000 * 10u64.pow(6); // 73 million tokens (6 decimals)
+        vault_config.virtual_sol = ctx.accounts.protocol_config.virtual_sol;
+        vault_config.virtual_tokens = ctx.accounts.protocol_config.virtual_tokens;
+
+        // Pricing curve is fixed at launch, anchored to the starting effective reserves
+        require!(curve_kind <= CURVE_EXPONENTIAL, ErrorCode::InvalidCurve);
+        vault_config.curve_kind = curve_kind;
+        vault_config.curve_base_sol = vault_config.virtual_sol;
+        vault_config.curve_base_tokens = vault_config.token_reserve
+            .checked_add(vault_config.virtual_tokens)
+            .ok_or(ErrorCode::Overflow)?;
+
+        // Short borrow interest starts accruing from launch
+        vault_config.borrow_index = interest::BORROW_INDEX_ONE;
+        vault_config.last_borrow_accrual_ts = Clock::get()?.unix_timestamp;
+        vault_config.rate_base_bps = interest::DEFAULT_RATE_BASE_BPS;
+        vault_config.rate_slope_bps = interest::DEFAULT_RATE_SLOPE_BPS;
+        vault_config.rate_kink_bps = interest::DEFAULT_RATE_KINK_BPS;
+        vault_config.rate_max_bps = interest::DEFAULT_RATE_MAX_BPS;
+        vault_config.max_oi_stake_bps = market_config::DEFAULT_MAX_OI_STAKE_BPS;
+        vault_config.max_utilisation_bps = market_config::DEFAULT_MAX_UTILISATION_BPS;
+        vault_config.min_leverage_bps = market_config::DEFAULT_MIN_LEVERAGE_BPS;
+        vault_config.max_leverage_bps = market_config::DEFAULT_MAX_LEVERAGE_BPS;
+        vault_config.max_long_leverage_bps = market_config::DEFAULT_MAX_LONG_LEVERAGE_BPS;
 
         emit!(TokenConfirmedLaunchEvent {
             mint: ctx.accounts.mint.key(),
@@ -322,6 +589,7 @@ pub mod memelend {
             token_reserve: vault_config.token_reserve,
             virtual_sol: vault_config.virtual_sol,
             virtual_tokens: vault_config.virtual_tokens,
+            curve_kind,
             is_live: true,
         });
 
@@ -431,6 +699,35 @@ pub struct InitTokenAndSplitSupply<'info> {
     )]
     pub user_yield_position: Box<Account<'info, UserYieldPosition>>,
 
+    #[account(
+        init_if_needed,
+        payer = creator,
+        seeds = [b"user_account", creator.key().as_ref()],
+        bump,
+        space = 8 + UserAccount::LEN,
+    )]
+    pub user_account: Box<Account<'info, UserAccount>>,
+
+    // Project tokens streamed to stakers, funded from the creator's stake
+    #[account(
+        init,
+        payer = creator,
+        seeds = [b"vault_emission", mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = vault_authority,
+    )]
+    pub emission_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = creator,
+        seeds = [b"vesting", mint.key().as_ref()],
+        bump,
+        space = 8 + VestingSchedule::LEN,
+    )]
+    pub vesting_schedule: Box<Account<'info, VestingSchedule>>,
+
     /// CHECK: PDA (no signing required)
     #[account(
         init,
@@ -453,11 +750,20 @@ pub struct InitFeeVault<'info> {
     #[account(mut)]
     pub creator: Signer<'info>,
 
-    #[account(mut, has_one = mint)]
+    #[account(
+        mut,
+        seeds = [b"config", mint.key().as_ref()],
+        bump,
+        has_one = mint,
+        has_one = creator @ ErrorCode::Unauthorized,
+    )]
     pub token_config: Account<'info, TokenConfig>,
 
     pub mint: Account<'info, Mint>,
 
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Box<Account<'info, ProtocolConfig>>,
+
     #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
     pub wsol_mint: Account<'info, Mint>,
 
@@ -498,6 +804,17 @@ pub struct InitFeeVault<'info> {
     )]
     pub platform_vault: Box<Account<'info, TokenAccount>>,
 
+    // Closing, liquidating and triggering shorts all need it, so it exists before the first trade
+    #[account(
+        init,
+        payer = creator,
+        seeds = [b"vault_insurance", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+        token::authority = vault_authority,
+    )]
+    pub insurance_vault: Box<Account<'info, TokenAccount>>,
+
     /// CHECK: PDA
     #[account(
         seeds = [b"vault_wsol_authority", mint.key().as_ref()],
@@ -571,10 +888,13 @@ pub struct Position {
     pub liquidate: u64,  
     pub position_id: u64,
     pub created_at: i64, 
+    pub borrow_index_snapshot: u128, // VaultConfig.borrow_index when opened
+    pub stop_loss_price: u64,        // trigger_position_exit once the price reaches this, 0 = off
+    pub take_profit_price: u64,      // trigger_position_exit once the price falls to this, 0 = off
 }
 
 impl Position {
-    pub const LEN: usize = 32 + 8 + 8 + 32 + 1 + 8 + 8 + 8 + 8;
+    pub const LEN: usize = 32 + 8 + 8 + 32 + 1 + 8 + 8 + 8 + 8 + 16 + 8 + 8;
 }
 
 #[account]
@@ -590,7 +910,24 @@ pub struct VaultConfig {
     pub yield_vault: u64,
     pub pre_sale_vault: u64,
     pub bump: u8,
-    pub last_trade_timestamp: i64,
+    pub curve_kind: u8,         // which Curve prices this pool (see curve.rs)
+    pub curve_base_sol: u64,    // y0 - effective SOL at launch, anchors linear/exponential curves
+    pub curve_base_tokens: u64, // x0 - effective tokens at launch, anchors linear/exponential curves
+    pub borrow_index: u128,     // cumulative short borrow index, scaled by 1e18 (see interest.rs)
+    pub last_borrow_accrual_ts: i64,
+    pub total_borrowed: u64,    // tokens currently lent out to shorts
+    pub rate_base_bps: u16,     // kinked borrow rate model, see interest.rs
+    pub rate_slope_bps: u16,
+    pub rate_kink_bps: u16,
+    pub rate_max_bps: u16,
+    pub max_open_interest: u64,    // absolute cap on total_borrowed, 0 = none (see market_config.rs)
+    pub max_oi_stake_bps: u16,     // cap on total_borrowed as a share of YieldVault.total_staked
+    pub max_utilisation_bps: u16,  // ceiling on lent-out share of lending vault liquidity
+    pub insurance_fund: u64,       // WSOL held in the insurance vault for this mint (see insurance_fund.rs)
+    pub bad_debt: u64,             // short losses neither collateral nor the insurance fund could cover
+    pub min_leverage_bps: u16,     // go_short leverage range, 10_000 = 1x (see market_config.rs)
+    pub max_leverage_bps: u16,
+    pub max_long_leverage_bps: u16, // go_long exposure per unit of collateral (see market_config.rs)
 }
 
 impl VaultConfig {
@@ -606,7 +943,103 @@ impl VaultConfig {
         + 8   // yield_vault
         + 8   // pre_sale_vault
         + 1   // bump
-        + 8;  // last_trade_timestamp
+        + 1   // curve_kind
+        + 8   // curve_base_sol
+        + 8   // curve_base_tokens
+        + 16  // borrow_index
+        + 8   // last_borrow_accrual_ts
+        + 8   // total_borrowed
+        + (2 * 4) // rate model bps values
+        + 8   // max_open_interest
+        + 2   // max_oi_stake_bps
+        + 2   // max_utilisation_bps
+        + 8   // insurance_fund
+        + 8   // bad_debt
+        + (2 * 2)  // min_leverage_bps, max_leverage_bps
+        + 2;  // max_long_leverage_bps
+}
+
+#[account]
+pub struct ProtocolConfig {
+    pub admin: Pubkey,                      // can update this account
+    pub treasury: Pubkey,                   // WSOL account receiving swept platform fees
+    pub platform_fee_bps: u16,              // total platform fee on trades
+    pub referral_cut_bps: u16,              // carved out of the platform fee when a referrer exists
+    pub yield_cut_bps: u16,                 // carved out of the platform fee for stakers
+    pub yield_cut_no_referral_bps: u16,     // stakers' cut when there is no referrer
+    pub liquidator_fee_bps: u16,            // paid to the liquidator out of collateral
+    pub liquidation_yield_cut_bps: u16,     // paid to stakers out of liquidated collateral
+    pub presale_automation_fee_bps: u16,    // paid to the presale bot
+    pub maintenance_margin_bps: u16,        // share of short collateral that must survive
+    pub max_buy_bps: u16,                   // max share of the token reserve per trade
+    pub virtual_sol: u64,                   // virtual SOL given to new pools
+    pub virtual_tokens: u64,                // virtual tokens given to new pools
+    pub liquidation_close_factor_bps: u16,  // max share of a short one partial liquidation can repay
+    pub liquidation_bonus_bps: u16,         // paid to the liquidator on the value it repays
+    pub auction_duration_slots: u64,        // slots for an auction bonus to reach max, 0 = auctions off
+    pub auction_start_bonus_bps: u16,       // liquidator bonus when an auction opens
+    pub auction_max_bonus_bps: u16,         // liquidator bonus once duration_slots have passed
+    pub insurance_fee_bps: u16,             // share of the platform fee routed to the insurance fund
+    pub insurance_liquidation_bps: u16,     // share of forfeited collateral routed to the insurance fund
+    pub keeper_fee_bps: u16,                // keeper cut of collateral on a triggered exit and of rewards on a compound
+    pub bump: u8,
+}
+
+impl ProtocolConfig {
+    pub const LEN: usize = 32 // admin
+        + 32       // treasury
+        + (2 * 9)  // 9 u16 bps values
+        + 8        // virtual_sol
+        + 8        // virtual_tokens
+        + (2 * 2)  // liquidation_close_factor_bps, liquidation_bonus_bps
+        + 8        // auction_duration_slots
+        + (2 * 2)  // auction_start_bonus_bps, auction_max_bonus_bps
+        + (2 * 2)  // insurance_fee_bps, insurance_liquidation_bps
+        + 2        // keeper_fee_bps
+        + 1;       // bump
+
+    // Bounds every setter has to respect
+    pub fn validate(&self) -> Result<()> {
+        require!(self.platform_fee_bps <= 1_000, ErrorCode::InvalidProtocolConfig); // max 10%
+        // Referral and yield cuts are taken out of the platform fee so they must fit inside it
+        require!(
+            self.referral_cut_bps as u32 + self.yield_cut_bps as u32 <= self.platform_fee_bps as u32,
+            ErrorCode::InvalidProtocolConfig
+        );
+        require!(self.yield_cut_no_referral_bps <= self.platform_fee_bps, ErrorCode::InvalidProtocolConfig);
+        require!(
+            self.liquidator_fee_bps as u32 + self.liquidation_yield_cut_bps as u32 <= 1_000,
+            ErrorCode::InvalidProtocolConfig
+        );
+        require!(self.presale_automation_fee_bps <= 500, ErrorCode::InvalidProtocolConfig); // max 5%
+        require!(
+            self.maintenance_margin_bps >= 500 && self.maintenance_margin_bps <= 9_000,
+            ErrorCode::InvalidProtocolConfig
+        );
+        require!(
+            self.max_buy_bps >= 100 && self.max_buy_bps <= 5_000,
+            ErrorCode::InvalidProtocolConfig
+        );
+        require!(self.virtual_sol > 0 && self.virtual_tokens > 0, ErrorCode::InvalidProtocolConfig);
+        require!(
+            self.liquidation_close_factor_bps >= 1_000 && self.liquidation_close_factor_bps <= 10_000,
+            ErrorCode::InvalidProtocolConfig
+        );
+        // Bonus and staker cut come out of the same repaid slice
+        require!(
+            self.liquidation_bonus_bps as u32 + self.liquidation_yield_cut_bps as u32 <= 2_000,
+            ErrorCode::InvalidProtocolConfig
+        );
+        require!(
+            self.auction_start_bonus_bps <= self.auction_max_bonus_bps
+                && self.auction_max_bonus_bps as u32 + self.liquidation_yield_cut_bps as u32 <= 2_000,
+            ErrorCode::InvalidProtocolConfig
+        );
+        require!(self.insurance_fee_bps <= 10_000, ErrorCode::InvalidProtocolConfig);
+        require!(self.insurance_liquidation_bps <= 5_000, ErrorCode::InvalidProtocolConfig); // max 50%
+        require!(self.keeper_fee_bps <= 500, ErrorCode::InvalidProtocolConfig); // max 5%
+        Ok(())
+    }
 }
 
 //for future use
@@ -643,10 +1076,55 @@ pub struct YieldVault {
     pub max_withdraw_bps: u16,    // 10000 = 100% (basis points)
     pub last_accrual_ts: i64,     // Last time rewards updated
     pub bump: u8,                 // PDA bump
+    pub emission_reserve: u64,    // project tokens left to emit at apr_bps (see emissions.rs)
+    pub acc_emission_per_share: u128, // Cumulative token emissions per staked token (scaled by 1e12)
+    pub total_emitted: u64,
+    pub last_emission_ts: i64,
+    pub total_weighted_stake: u64, // Sum of position weights after lock boosts, rewards are shared by this
+    pub reward_slots: [RewardSlot; MAX_REWARD_SLOTS], // extra incentive campaigns (see reward_slots.rs)
 }
 
 impl YieldVault {
-    pub const LEN: usize = 32 + 2 + 8 + 8 + 16 + 32 + 8 + 2 + 8 + 1; 
+    pub const LEN: usize = 32 + 2 + 8 + 8 + 16 + 32 + 8 + 2 + 8 + 1 + 8 + 16 + 8 + 8 + 8
+        + RewardSlot::LEN * MAX_REWARD_SLOTS; 
+}
+
+pub const MAX_REWARD_SLOTS: usize = 4;
+
+// One incentive campaign on a yield vault, empty while `mint` is the default key
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, Debug)]
+pub struct RewardSlot {
+    pub mint: Pubkey,            // reward token
+    pub vault: Pubkey,           // [b"vault_reward", mint, reward mint], owned by the vault authority
+    pub rate_per_second: u64,    // reward tokens streamed per second until end_ts
+    pub end_ts: i64,
+    pub last_update_ts: i64,
+    pub acc_per_share: u128,     // rewards per weighted staked token (scaled by 1e12)
+    pub total_funded: u64,
+    pub total_distributed: u64,
+}
+
+impl RewardSlot {
+    pub const LEN: usize = 32 + 32 + 8 + 8 + 8 + 16 + 8 + 8;
+}
+
+pub const MAX_OPEN_POSITIONS: usize = 32;
+
+// One per wallet, hands out position ids and lists the wallet's open positions
+// so clients don't have to scan for them
+#[account]
+pub struct UserAccount {
+    pub owner: Pubkey,
+    pub next_position_id: u64,       // id the next position will get
+    pub open_positions: Vec<Pubkey>, // shorts, presale and yield positions, at most MAX_OPEN_POSITIONS
+    pub bump: u8,
+}
+
+impl UserAccount {
+    pub const LEN: usize = 32 // owner
+        + 8                        // next_position_id
+        + 4 + 32 * MAX_OPEN_POSITIONS // open_positions
+        + 1;                       // bump
 }
 
 #[account]
@@ -663,10 +1141,53 @@ pub struct UserYieldPosition {
     pub deposited_at: i64,    // When user first deposited (epoch seconds)
     pub last_action_ts: i64,  // Last deposit/withdraw/claim
     pub bump: u8,             // PDA bump
+    pub emission_debt: u128,  // For emission per share accounting
+    pub emission_owed: u64,   // Emissions booked but not yet claimed (project tokens)
+    pub emission_claimed: u64,
+    pub lock_tier: u8,        // index into yield_lock::LOCK_TIERS, 0 = unlocked
+    pub lock_until: i64,      // principal can't be withdrawn before this
+    pub weighted_stake: u64,  // deposited boosted by the lock tier, used for reward accounting
+    pub slot_debts: [u128; MAX_REWARD_SLOTS], // per reward slot, like reward_debt
+    pub slot_owed: [u64; MAX_REWARD_SLOTS],   // slot rewards booked but not yet claimed
+    pub compound_max_price: u64, // highest average price a keeper may compound at, 0 = off
 }
 
 impl UserYieldPosition {
-    pub const LEN: usize = 32 + 32 + 8 + 1 + 8 + 8 + 8 + 16 + 8 + 8 + 8 + 1; 
+    pub const LEN: usize = 32 + 32 + 8 + 1 + 8 + 8 + 8 + 16 + 8 + 8 + 8 + 1 + 16 + 8 + 8 + 1 + 8 + 8
+        + (16 + 8) * MAX_REWARD_SLOTS
+        + 8; 
+}
+
+pub const MAX_VESTING_STEPS: usize = 8;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq)]
+pub struct VestingStep {
+    pub after_seconds: i64,  // time since launch
+    pub unlocked_bps: u16,   // share of the initial stake unlocked from then on
+}
+
+// Creator unlock schedule for a mint, set at launch (see vesting.rs)
+#[account]
+pub struct VestingSchedule {
+    pub mint: Pubkey,
+    pub creator: Pubkey,
+    pub kind: u8,                // vesting::VESTING_STEPS or VESTING_LINEAR
+    pub cliff_seconds: i64,      // linear: nothing unlocks before this
+    pub duration_seconds: i64,   // linear: fully unlocked after this
+    pub steps: Vec<VestingStep>, // steps: at most MAX_VESTING_STEPS, ascending
+    pub irrevocable: bool,       // once set the schedule can't be changed
+    pub bump: u8,
+}
+
+impl VestingSchedule {
+    pub const LEN: usize = 32 // mint
+        + 32                       // creator
+        + 1                        // kind
+        + 8                        // cliff_seconds
+        + 8                        // duration_seconds
+        + 4 + (8 + 2) * MAX_VESTING_STEPS // steps
+        + 1                        // irrevocable
+        + 1;                       // bump
 }
 
 #[account]
@@ -699,12 +1220,186 @@ impl UserPreSalePosition {
         + 1;   // bump
 }
 
+#[account]
+pub struct UserTradeState {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub last_trade_timestamp: i64, // last buy/sell by this wallet on this mint
+    pub bump: u8,
+}
+
+impl UserTradeState {
+    pub const LEN: usize = 32 // owner
+        + 32  // mint
+        + 8   // last_trade_timestamp
+        + 1;  // bump
+}
+
+#[account]
+pub struct LiquidationAuction {
+    pub position: Pubkey,
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub position_id: u64,
+    pub position_created_at: i64, // ties the auction to one position, ids get reused
+    pub opener: Pubkey,           // paid the rent, gets it back on cancel
+    pub start_slot: u64,
+    pub duration_slots: u64,
+    pub start_bonus_bps: u16,
+    pub max_bonus_bps: u16,
+    pub bump: u8,
+    pub position_amount: u64,     // position size the auction is running against,
+    pub position_collateral: u64, // the owner changing either restarts it
+}
+
+impl LiquidationAuction {
+    pub const LEN: usize = 32 // position
+        + 32  // mint
+        + 32  // owner
+        + 8   // position_id
+        + 8   // position_created_at
+        + 32  // opener
+        + 8   // start_slot
+        + 8   // duration_slots
+        + 2   // start_bonus_bps
+        + 2   // max_bonus_bps
+        + 1   // bump
+        + 8   // position_amount
+        + 8;  // position_collateral
+}
+
+#[account]
+pub struct WithdrawQueue {
+    pub mint: Pubkey,
+    pub head: u64,          // sequence of the next request to fill
+    pub tail: u64,          // sequence the next request will get
+    pub queued_amount: u64, // tokens still owed to queued requests, reserved in the lending vault
+    pub bump: u8,
+}
+
+impl WithdrawQueue {
+    pub const LEN: usize = 32 // mint
+        + 8   // head
+        + 8   // tail
+        + 8   // queued_amount
+        + 1;  // bump
+}
+
+#[account]
+pub struct WithdrawRequest {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub sequence: u64,  // position in the mint's WithdrawQueue
+    pub amount: u64,    // principal requested
+    pub filled: u64,    // principal paid out so far
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl WithdrawRequest {
+    pub const LEN: usize = 32 // owner
+        + 32  // mint
+        + 8   // sequence
+        + 8   // amount
+        + 8   // filled
+        + 8   // created_at
+        + 1;  // bump
+}
+
+pub const POSITION_SHORT: u8 = 0;
+pub const POSITION_LONG: u8 = 1;
+
+#[account]
+pub struct LongPosition {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub position_id: u64,
+    pub direction: u8,         // always POSITION_LONG, lets indexers tell it apart from a short
+    pub entry_price: u64,      // SOL spent per token, same scale as Position.entry_price
+    pub amount: u64,           // tokens bought, held in the long token vault
+    pub collateral: u64,       // user's SOL left in the position after opening fees
+    pub borrowed_sol: u64,     // lent by the SolLendingPool
+    pub liquidate: u64,        // spot price at or below which it can be liquidated
+    pub borrow_index_snapshot: u128, // SolLendingPool.borrow_index when opened
+    pub open: bool,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl LongPosition {
+    pub const LEN: usize = 32 // owner
+        + 32  // mint
+        + 8   // position_id
+        + 1   // direction
+        + 8   // entry_price
+        + 8   // amount
+        + 8   // collateral
+        + 8   // borrowed_sol
+        + 8   // liquidate
+        + 16  // borrow_index_snapshot
+        + 1   // open
+        + 8   // created_at
+        + 1;  // bump
+}
+
+// SOL stakers lend to leveraged longs on one mint (see sol_lending.rs)
+#[account]
+pub struct SolLendingPool {
+    pub mint: Pubkey,
+    pub total_deposits: u64,        // lender principal
+    pub total_borrowed: u64,        // principal currently lent to longs
+    pub bad_debt: u64,              // principal lost to liquidations the insurance fund couldn't cover, not yet taken off lenders
+    pub acc_reward_per_share: u128, // interest and forfeited equity per deposited lamport, scaled by 1e12
+    pub total_rewards: u64,
+    pub borrow_index: u128,         // cumulative long borrow index, scaled by 1e18
+    pub last_accrual_ts: i64,
+    pub bump: u8,
+    pub acc_loss_per_share: u128,   // bad debt per deposited lamport, scaled by 1e12
+}
+
+impl SolLendingPool {
+    pub const LEN: usize = 32 // mint
+        + 8   // total_deposits
+        + 8   // total_borrowed
+        + 8   // bad_debt
+        + 16  // acc_reward_per_share
+        + 8   // total_rewards
+        + 16  // borrow_index
+        + 8   // last_accrual_ts
+        + 1   // bump
+        + 16; // acc_loss_per_share
+}
+
+#[account]
+pub struct SolLender {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub deposited: u64,
+    pub reward_debt: u128,
+    pub claimed_total: u64,
+    pub last_action_ts: i64,
+    pub bump: u8,
+    pub loss_debt: u128,      // like reward_debt, for acc_loss_per_share
+}
+
+impl SolLender {
+    pub const LEN: usize = 32 // owner
+        + 32  // mint
+        + 8   // deposited
+        + 16  // reward_debt
+        + 8   // claimed_total
+        + 8   // last_action_ts
+        + 1   // bump
+        + 16; // loss_debt
+}
+
 
 #[event]
 pub struct TokenLaunchEvent {
     pub mint: Pubkey,
     pub creator: Pubkey,
     pub apr_bps: u64,
+    pub emission_reserve: u64,
     pub acc_reward_per_share: u64,
     pub max_withdraw_bps: u64,
     pub position_id: u64,
@@ -723,6 +1418,7 @@ pub struct TokenConfirmedLaunchEvent {
     pub token_reserve: u64,
     pub virtual_sol: u64,
     pub virtual_tokens: u64,
+    pub curve_kind: u8,
     pub is_live: bool,
 }
 
@@ -791,5 +1487,67 @@ pub enum ErrorCode {
     InvalidInput,
     #[msg("Creator cannot buy presale tokens")]
     CreatorCannotBuyPresale,
+    #[msg("Position is above its liquidation threshold")]
+    PositionHealthy,
+    #[msg("Protocol config value out of bounds")]
+    InvalidProtocolConfig,
+    #[msg("Treasury has not been configured")]
+    TreasuryNotSet,
+    #[msg("Unknown bonding curve")]
+    InvalidCurve,
+    #[msg("Borrow rate model out of bounds")]
+    InvalidRateModel,
+    #[msg("Short would exceed the open interest cap for this mint")]
+    OpenInterestCapExceeded,
+    #[msg("Lending vault utilisation is above its ceiling")]
+    UtilisationCeilingExceeded,
+    #[msg("Not enough unreserved tokens in the lending vault, use request_withdraw_yield")]
+    InsufficientLendingLiquidity,
+    #[msg("Withdrawal can be paid now, use withdraw_yield")]
+    WithdrawalNotNeeded,
+    #[msg("Liquidation auctions are turned off")]
+    AuctionsDisabled,
+    #[msg("A liquidation auction is already running for this position")]
+    AuctionAlreadyOpen,
+    #[msg("Liquidations go through an auction, open one first")]
+    AuctionRequired,
+    #[msg("Liquidation auction belongs to an earlier position")]
+    AuctionStale,
+    #[msg("Position is still below its liquidation threshold")]
+    PositionNotHealthy,
+    #[msg("This mint has no bad debt to settle")]
+    NoBadDebt,
+    #[msg("Insurance fund is empty")]
+    InsuranceFundEmpty,
+    #[msg("Leverage is outside this mint's allowed range")]
+    InvalidLeverage,
+    #[msg("Position can't repay its SOL loan, it has to be liquidated")]
+    LongUnderwater,
+    #[msg("Trigger price is already crossed")]
+    InvalidTrigger,
+    #[msg("Price hasn't reached the stop-loss or take-profit yet")]
+    TriggerNotReached,
+    #[msg("Too many open positions, close one first")]
+    TooManyOpenPositions,
+    #[msg("Emission vault and token account are required to claim emissions")]
+    EmissionAccountsMissing,
+    #[msg("Invalid lock tier")]
+    InvalidLockTier,
+    #[msg("A locked position can't move to a shorter lock tier")]
+    LockTierDowngrade,
+    #[msg("Principal is locked until the lock expires")]
+    PrincipalLocked,
+    #[msg("Invalid vesting schedule")]
+    InvalidVestingSchedule,
+    #[msg("Vesting schedule is irrevocable")]
+    VestingScheduleIrrevocable,
+    #[msg("Invalid reward slot")]
+    InvalidRewardSlot,
+    #[msg("New vesting schedule unlocks sooner than the current one")]
+    VestingUnlockTooFast,
+    #[msg("Owner has not enabled compounding")]
+    CompoundingDisabled,
+    #[msg("Position has no expired lock to drop")]
+    LockNotExpired,
 }
 
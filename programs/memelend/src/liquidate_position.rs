@@ -1,6 +1,12 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{self, Token, TokenAccount, Transfer, Mint};
-use crate::{VaultConfig, TokenConfig, Position, ErrorCode, YieldVault};
+use crate::{VaultConfig, TokenConfig, ProtocolConfig, Position, ErrorCode, YieldVault, LiquidationAuction, UserAccount};
+use crate::get_liquidation_price;
+use crate::curve::load_curve;
+use crate::liquidation_auction::LiquidationAuctionClosedEvent;
+use crate::interest::{accrue_borrow_index, borrow_interest};
+use crate::insurance_fund::{split_shortfall, draw_insurance};
+use crate::user_account::remove_position;
 
 
 
@@ -25,21 +31,39 @@ pub struct LiquidatePosition<'info> {
     #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
     pub wsol_mint: Account<'info, Mint>,
 
-    #[account(mut)]
+    #[account(address = token_config.mint @ ErrorCode::InvalidMint)]
     pub mint: Box<Account<'info, Mint>>,
 
+    // Seeds bind the position to `user`, so collateral rent can only go back to its owner.
+    // Closed by the handler only when the whole position is liquidated.
     #[account(
         mut,
-        close = user
+        seeds = [b"position", user.key().as_ref(), mint.key().as_ref(), &args.position_id.to_le_bytes()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+        constraint = position.owner == user.key() @ ErrorCode::Unauthorized,
     )]
     pub position: Box<Account<'info, Position>>,
 
+    #[account(
+        mut,
+        seeds = [b"user_account", user.key().as_ref()],
+        bump = user_account.bump,
+    )]
+    pub user_account: Box<Account<'info, UserAccount>>,
+
+    #[account(
+        seeds = [b"config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub token_config: Box<Account<'info, TokenConfig>>,
 
     #[account(
         mut,
         seeds = [b"vault_config", mint.key().as_ref()],
-        bump
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
     )]
     pub vault_config: Account<'info, VaultConfig>,
 
@@ -53,17 +77,24 @@ pub struct LiquidatePosition<'info> {
 
     #[account(
         mut,
-        constraint = token_liquidity_vault.mint == token_config.mint,
+        seeds = [b"vault_liquidity", mint.key().as_ref()],
+        bump,
+        constraint = token_liquidity_vault.mint == token_config.mint @ ErrorCode::InvalidMint,
     )]
     pub token_liquidity_vault: Box<Account<'info, TokenAccount>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"vault_lending", mint.key().as_ref()],
+        bump,
+    )]
     pub lending_vault: Box<Account<'info, TokenAccount>>,
 
     #[account(
         mut,
         seeds = [b"vault_wsol", mint.key().as_ref()],
         bump,
+        token::mint = wsol_mint,
     )]
     pub wsol_vault: Box<Account<'info, TokenAccount>>,
 
@@ -76,7 +107,19 @@ pub struct LiquidatePosition<'info> {
     )]
     pub project_vault: Box<Account<'info, TokenAccount>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"vault_insurance", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+        token::authority = vault_authority,
+    )]
+    pub insurance_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = wsol_mint,
+    )]
     pub bot_wsol_account: Box<Account<'info, TokenAccount>>,
 
     /// CHECK: PDA
@@ -93,9 +136,26 @@ pub struct LiquidatePosition<'info> {
     )]
     pub wsol_vault_authority: UncheckedAccount<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"yield_vault", mint.key().as_ref()],
+        bump = yield_vault.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub yield_vault: Account<'info, YieldVault>,
 
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Box<Account<'info, ProtocolConfig>>,
+
+    // Required while auction mode is on, its bonus replaces the flat liquidator fee
+    #[account(
+        mut,
+        seeds = [b"liquidation_auction", position.key().as_ref()],
+        bump = liquidation_auction.bump,
+        constraint = liquidation_auction.position_created_at == position.created_at @ ErrorCode::AuctionStale,
+    )]
+    pub liquidation_auction: Option<Box<Account<'info, LiquidationAuction>>>,
+
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
@@ -114,8 +174,9 @@ pub fn liquidate_position(ctx: Context<LiquidatePosition>, args: LiquidatePositi
         ErrorCode::InvalidPositionId
     );
 
+    require!(position.open, ErrorCode::PositionAlreadyClosed);
+
     let vault_config = &mut ctx.accounts.vault_config;
-    let token_config = &mut ctx.accounts.token_config;
     let token_program = &ctx.accounts.token_program;
     let now = Clock::get()?.unix_timestamp;
 
@@ -123,47 +184,101 @@ pub fn liquidate_position(ctx: Context<LiquidatePosition>, args: LiquidatePositi
 
     let collateral_amount = position.collateral;
 
-    let token_reserve = vault_config.token_reserve;
-    let sol_reserve = vault_config.sol_reserve;
-    let accumulated_c = vault_config.accumulated_c;
-    let virtual_sol = vault_config.virtual_sol;
-    let virtual_tokens = vault_config.virtual_tokens;
-  
-    // --- Compute full effective SOL reserve (includes virtual + accumulated_c) ---
-    let effective_sol_i128 = (sol_reserve as i128)
-        .checked_add(accumulated_c as i128)
-        .and_then(|s| s.checked_add(virtual_sol as i128))
-        .ok_or(ErrorCode::Overflow)?;
-
-    // Prevent negative reserve
-    require!(effective_sol_i128 >= 0, ErrorCode::Underflow);
-
-    let effective_sol_reserve = effective_sol_i128 as u64;
-
-    let numerator = (borrowed_tokens as u128)
-        .checked_mul(effective_sol_reserve as u128)
-        .ok_or(ErrorCode::Overflow)?;
-
-    // --- Combine token reserve with virtual tokens ---
-    let effective_token_reserve = token_reserve
-        .checked_add(virtual_tokens)
-        .ok_or(ErrorCode::Overflow)?;
+    // --- What the borrowed tokens are worth on this mint's curve ---
+    let curve = load_curve(vault_config)?;
+    let value_now = curve.quote_sell(borrowed_tokens)?;
+    msg!("value_now: {}", value_now);
 
-    let denominator = (effective_token_reserve as u128)
-        .checked_add(borrowed_tokens as u128)
-        .ok_or(ErrorCode::Overflow)?;
+    // === Health Check ===
+    // Recompute the threshold from the position itself so stale/legacy stored values are never trusted
+    let liquidation_price = get_liquidation_price(
+        position.entry_price,
+        borrowed_tokens,
+        collateral_amount,
+        ctx.accounts.protocol_config.maintenance_margin_bps as u64,
+        ctx.accounts.mint.decimals,
+    )?;
 
-    let value_now_u128 = numerator
-        .checked_div(denominator)
-        .ok_or(ErrorCode::DivisionByZero)?;
- 
+    let current_price = curve.spot_price(ctx.accounts.mint.decimals);
+    msg!("liquidation_price: {}", liquidation_price);
+    msg!("current_price: {}", current_price);
 
-    let value_now = u64::try_from(value_now_u128).map_err(|_| ErrorCode::Overflow)?;
+    require!(
+        current_price >= liquidation_price,
+        ErrorCode::PositionHealthy
+    );
 
+    // === Auction Bonus ===
+    // With auctions on, the bonus is whatever the auction has grown to by now
+    let slot = Clock::get()?.slot;
+    let auction_bonus_bps = match &mut ctx.accounts.liquidation_auction {
+        Some(auction) => {
+            if auction.sync_position(position, slot) {
+                msg!("auction restarted at slot {}", slot);
+            }
+            Some(auction.current_bonus_bps(slot))
+        }
+        None => {
+            require!(
+                ctx.accounts.protocol_config.auction_duration_slots == 0,
+                ErrorCode::AuctionRequired
+            );
+            None
+        }
+    };
+    msg!("auction_bonus_bps: {:?}", auction_bonus_bps);
+
+    // === Partial Liquidation ===
+    // Repay just enough (at spot) to bring the position back above maintenance margin,
+    // at most the close factor per call. Insolvent positions fall through to a full liquidation.
+    let protocol_config = &ctx.accounts.protocol_config;
+    let value_spot = u64::try_from(
+        (current_price as u128)
+            .checked_mul(borrowed_tokens as u128)
+            .ok_or(ErrorCode::Overflow)?
+            / 10u128.pow(ctx.accounts.mint.decimals as u32 + 6),
+    )
+    .map_err(|_| ErrorCode::Overflow)?;
+    let value_entry_full = u64::try_from(
+        (position.entry_price as u128)
+            .checked_mul(borrowed_tokens as u128)
+            .ok_or(ErrorCode::Overflow)?
+            / 10u128.pow(ctx.accounts.mint.decimals as u32 + 6),
+    )
+    .map_err(|_| ErrorCode::Overflow)?;
+
+    let partial_bonus_bps = auction_bonus_bps.unwrap_or(protocol_config.liquidation_bonus_bps as u64);
+    let needed_bps = partial_liquidation_bps(
+        collateral_amount,
+        value_entry_full,
+        value_spot,
+        partial_bonus_bps + protocol_config.liquidation_yield_cut_bps as u64,
+        protocol_config.maintenance_margin_bps as u64,
+    );
+    msg!("needed_bps: {:?}", needed_bps);
+
+    if let Some(bps) = needed_bps.filter(|bps| *bps < 10_000) {
+        let repay_bps = bps.min(protocol_config.liquidation_close_factor_bps as u64);
+        let slice = quote_partial_slice(
+            vault_config,
+            position,
+            protocol_config,
+            ctx.accounts.mint.decimals,
+            repay_bps,
+            partial_bonus_bps,
+        )?;
+        // The curve quote for the slice can cost more than spot suggested, if the collateral
+        // can't cover it only a full liquidation settles the position
+        if slice.charged < collateral_amount {
+            return liquidate_partial(ctx, repay_bps, partial_bonus_bps, slice);
+        }
+        msg!("partial slice charges {} of {} collateral, liquidating in full", slice.charged, collateral_amount);
+    }
 
     // === Calculate Liquidator Reward (Hybrid Model) ===
-    let liquidator_fee_bps: u64 = 100; // 1%
-    let yield_cut_bps = 50; // 0.5%
+    let liquidator_fee_bps = auction_bonus_bps
+        .unwrap_or(ctx.accounts.protocol_config.liquidator_fee_bps as u64); // default 1%
+    let yield_cut_bps = ctx.accounts.protocol_config.liquidation_yield_cut_bps as u64; // default 0.5%
 
     let percent_fee = collateral_amount
         .checked_mul(liquidator_fee_bps)
@@ -184,6 +299,50 @@ pub fn liquidate_position(ctx: Context<LiquidatePosition>, args: LiquidatePositi
         .checked_sub(total_fees_sol)
         .ok_or(ErrorCode::Underflow)?;
 
+    // === Borrow Interest ===
+    // Owed on the SOL value lent at entry, paid to yield stakers out of the forfeited collateral
+    let value_entry = u64::try_from(
+        (position.entry_price as u128)
+            .checked_mul(borrowed_tokens as u128)
+            .ok_or(ErrorCode::Overflow)?
+            .checked_div(10u128.pow(ctx.accounts.mint.decimals as u32 + 6))
+            .ok_or(ErrorCode::DivisionByZero)?,
+    )
+    .map_err(|_| ErrorCode::Overflow)?;
+
+    let borrow_index = accrue_borrow_index(vault_config, ctx.accounts.yield_vault.total_staked, now)?;
+    let interest_sol = borrow_interest(value_entry, position.borrow_index_snapshot, borrow_index)?
+        .min(adjusted_collateral_after_fee);
+
+    let adjusted_collateral_after_fee = adjusted_collateral_after_fee
+        .checked_sub(interest_sol)
+        .ok_or(ErrorCode::Underflow)?;
+
+    msg!("interest_sol: {}", interest_sol);
+
+    // === Insurance Fund ===
+    // A slice of the forfeited collateral feeds the insurance fund. Loss the collateral couldn't
+    // cover is drawn from the fund first and only the remainder is recorded as bad debt.
+    let insurance_share_sol = ((adjusted_collateral_after_fee as u128)
+        * ctx.accounts.protocol_config.insurance_liquidation_bps as u128 / 10_000) as u64;
+
+    let adjusted_collateral_after_fee = adjusted_collateral_after_fee
+        .checked_sub(insurance_share_sol)
+        .ok_or(ErrorCode::Underflow)?;
+
+    let shortfall = value_now
+        .saturating_sub(value_entry)
+        .saturating_sub(collateral_amount);
+    let (insured_sol, bad_debt_sol) = split_shortfall(
+        shortfall,
+        vault_config.insurance_fund.checked_add(insurance_share_sol).ok_or(ErrorCode::Overflow)?,
+    );
+
+    msg!("insurance_share_sol: {}", insurance_share_sol);
+    msg!("shortfall: {}", shortfall);
+    msg!("insured_sol: {}", insured_sol);
+    msg!("bad_debt_sol: {}", bad_debt_sol);
+
 
     // === Token Repayment ===
     let bump = ctx.bumps.liquidity_vault_authority;
@@ -225,7 +384,9 @@ pub fn liquidate_position(ctx: Context<LiquidatePosition>, args: LiquidatePositi
             },
             signer_seeds,
         ),
-        yield_share_sol,
+        yield_share_sol
+            .checked_add(interest_sol)
+            .ok_or(ErrorCode::Overflow)?,
     )?;
 
     token::transfer(
@@ -241,17 +402,55 @@ pub fn liquidate_position(ctx: Context<LiquidatePosition>, args: LiquidatePositi
         percent_fee,
     )?;
 
+    if insurance_share_sol > 0 {
+        token::transfer(
+            CpiContext::new_with_signer(
+                token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.wsol_vault.to_account_info(),
+                    to: ctx.accounts.insurance_vault.to_account_info(),
+                    authority: ctx.accounts.wsol_vault_authority.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            insurance_share_sol,
+        )?;
+
+        vault_config.insurance_fund = vault_config
+            .insurance_fund
+            .checked_add(insurance_share_sol)
+            .ok_or(ErrorCode::Overflow)?;
+    }
+
+    // Covered part of the shortfall comes back out of the insurance vault into accumulated_c
+    draw_insurance(
+        vault_config,
+        &ctx.accounts.insurance_vault,
+        &ctx.accounts.wsol_vault,
+        &ctx.accounts.vault_authority,
+        token_program,
+        ctx.bumps.vault_authority,
+        insured_sol,
+    )?;
+
+    vault_config.bad_debt = vault_config
+        .bad_debt
+        .checked_add(bad_debt_sol)
+        .ok_or(ErrorCode::Overflow)?;
+
     // Forfeit collateral to protocol (leave in WSOL vault)
     // No transfer needed — WSOL is already in vault.
 
-    let position = &mut ctx.accounts.position;
-    position.open = false;
-
     vault_config.token_reserve = vault_config
         .token_reserve
         .checked_sub(borrowed_tokens)
         .ok_or(ErrorCode::Underflow)?;
 
+    vault_config.total_borrowed = vault_config
+        .total_borrowed
+        .checked_sub(borrowed_tokens)
+        .ok_or(ErrorCode::Underflow)?;
+
     vault_config.accumulated_c = vault_config.accumulated_c
         .checked_add(adjusted_collateral_after_fee as i64) // Convert to i64 before adding
         .ok_or(ErrorCode::Overflow)?;
@@ -260,14 +459,16 @@ pub fn liquidate_position(ctx: Context<LiquidatePosition>, args: LiquidatePositi
     let yield_vault = &mut ctx.accounts.yield_vault;
 
     // Compute acc_reward_per_share delta
-    if yield_vault.total_staked > 0 {
-        // new rewards = interest added (WSOL)
-        let new_rewards = yield_share_sol as u128;
+    if yield_vault.total_weighted_stake > 0 {
+        // new rewards = yield share + borrow interest (WSOL)
+        let new_rewards = (yield_share_sol as u128)
+            .checked_add(interest_sol as u128)
+            .ok_or(ErrorCode::Overflow)?;
 
         // Update cumulative reward per share
         yield_vault.acc_reward_per_share = yield_vault
             .acc_reward_per_share
-            .checked_add(new_rewards * PRECISION / yield_vault.total_staked as u128)
+            .checked_add(new_rewards * PRECISION / yield_vault.total_weighted_stake as u128)
             .ok_or(ErrorCode::Overflow)?;
 
         // Optional: update timestamp
@@ -277,17 +478,38 @@ pub fn liquidate_position(ctx: Context<LiquidatePosition>, args: LiquidatePositi
     vault_config.yield_vault = vault_config
             .yield_vault
             .checked_add(yield_share_sol)
+            .and_then(|v| v.checked_add(interest_sol))
             .ok_or(ErrorCode::Overflow)?; 
 
-    
+    // Whole position is gone, rent goes back to its owner
+    ctx.accounts.position.open = false;
+    remove_position(&mut ctx.accounts.user_account, &ctx.accounts.position.key());
+    ctx.accounts.position.close(ctx.accounts.user.to_account_info())?;
+
+    // Auction is settled, its rent goes to the liquidator that executed it
+    if let Some(auction) = &ctx.accounts.liquidation_auction {
+        auction.close(ctx.accounts.bot.to_account_info())?;
+        emit!(LiquidationAuctionClosedEvent {
+            position: auction.position,
+            mint: auction.mint,
+            position_id,
+            executed: true,
+            bonus_bps: liquidator_fee_bps,
+            slot: Clock::get()?.slot,
+        });
+    }
+
     emit!(LiquidatePositionEvent {
         user: ctx.accounts.user.key(),
         mint: ctx.accounts.mint.key(),
-        position_id: position.position_id,
+        position_id,
         repaid_tokens: borrowed_tokens,
         total_fees: percent_fee,
+        borrow_interest: interest_sol,
         interest: vault_config.yield_vault,
         forfeited_collateral: adjusted_collateral_after_fee,
+        insurance_fee: insurance_share_sol,
+        bad_debt: bad_debt_sol,
         accumulated_c_after: vault_config.accumulated_c,
         acc_reward_per_share: yield_vault.acc_reward_per_share as u64,
         token_reserve_after: vault_config.token_reserve,
@@ -300,6 +522,266 @@ pub fn liquidate_position(ctx: Context<LiquidatePosition>, args: LiquidatePositi
     Ok(())
 }
 
+// Share of a short (bps) that has to be repaid to bring it back above maintenance margin, with
+// the position valued at spot and `fee_bps` of the repaid value going to liquidator and stakers.
+// None when repaying part of it can't restore health (insolvent, or fees outweigh the freed margin).
+pub fn partial_liquidation_bps(
+    collateral: u64,
+    value_entry: u64,
+    value_spot: u64,
+    fee_bps: u64,
+    maintenance_margin_bps: u64,
+) -> Option<u64> {
+    let loss = value_spot.saturating_sub(value_entry) as i128;
+    let fees = (value_spot as i128) * fee_bps as i128 / 10_000;
+    let collateral = collateral as i128;
+    let margin = maintenance_margin_bps as i128;
+
+    if loss >= collateral {
+        return None;
+    }
+
+    // Healthy again once (1 - mm) * (C - r * (loss + fees)) > (1 - r) * loss, solved for r
+    let num = loss * 10_000 - (10_000 - margin) * collateral;
+    let den = margin * loss - (10_000 - margin) * fees;
+    if den <= 0 {
+        return None;
+    }
+
+    let bps = (num.max(0) * 10_000 / den + 1).max(1);
+    u64::try_from(bps).ok()
+}
+
+// What repaying `repay_bps` of a short costs at the current curve quote
+pub struct PartialSlice {
+    pub repay_tokens: u64,
+    pub value_now: u64,
+    pub value_entry: u64,
+    pub realised_loss: u64,
+    pub bonus_sol: u64,
+    pub yield_share_sol: u64,
+    pub charged: u64, // taken from the collateral before borrow interest
+}
+
+fn quote_partial_slice(
+    vault_config: &VaultConfig,
+    position: &Position,
+    protocol_config: &ProtocolConfig,
+    decimals: u8,
+    repay_bps: u64,
+    bonus_bps: u64,
+) -> Result<PartialSlice> {
+    // At least one token so rounding can never block a liquidation
+    let repay_tokens = (((position.amount as u128)
+        .checked_mul(repay_bps as u128)
+        .ok_or(ErrorCode::Overflow)? / 10_000) as u64)
+        .max(1);
+
+    let value_now = load_curve(vault_config)?.quote_sell(repay_tokens)?;
+    let value_entry = u64::try_from(
+        (position.entry_price as u128)
+            .checked_mul(repay_tokens as u128)
+            .ok_or(ErrorCode::Overflow)?
+            / 10u128.pow(decimals as u32 + 6),
+    )
+    .map_err(|_| ErrorCode::Overflow)?;
+
+    // A curve quote below entry is not credited back, the slice is being force-closed
+    let realised_loss = value_now.saturating_sub(value_entry);
+
+    let bonus_sol = (value_now as u128 * bonus_bps as u128 / 10_000) as u64;
+    let yield_share_sol = (value_now as u128 * protocol_config.liquidation_yield_cut_bps as u128 / 10_000) as u64;
+
+    let charged = realised_loss
+        .checked_add(bonus_sol)
+        .and_then(|v| v.checked_add(yield_share_sol))
+        .ok_or(ErrorCode::Overflow)?;
+
+    Ok(PartialSlice { repay_tokens, value_now, value_entry, realised_loss, bonus_sol, yield_share_sol, charged })
+}
+
+// Repays `repay_bps` of the position. The liquidator earns the bonus on the repaid value, the realised
+// loss on the repaid tokens goes to the pool and the rest of the collateral stays in the position.
+// `slice` must leave some collateral, deeper losses go through the full liquidation.
+fn liquidate_partial(ctx: Context<LiquidatePosition>, repay_bps: u64, bonus_bps: u64, slice: PartialSlice) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let decimals = ctx.accounts.mint.decimals;
+    let position = &ctx.accounts.position;
+    let vault_config = &mut ctx.accounts.vault_config;
+    let PartialSlice { repay_tokens, value_now, value_entry, realised_loss, bonus_sol, yield_share_sol, charged } = slice;
+
+    msg!("repay_bps: {}", repay_bps);
+    msg!("repay_tokens: {}", repay_tokens);
+
+    let collateral_left = position.collateral
+        .checked_sub(charged)
+        .ok_or(ErrorCode::Underflow)?;
+
+    // Borrow interest on the repaid slice, capped by what is left
+    let borrow_index = accrue_borrow_index(vault_config, ctx.accounts.yield_vault.total_staked, now)?;
+    let interest_sol = borrow_interest(value_entry, position.borrow_index_snapshot, borrow_index)?
+        .min(collateral_left);
+    let collateral_left = collateral_left - interest_sol;
+
+    msg!("value_now: {}", value_now);
+    msg!("realised_loss: {}", realised_loss);
+    msg!("bonus_sol: {}", bonus_sol);
+    msg!("yield_share_sol: {}", yield_share_sol);
+    msg!("interest_sol: {}", interest_sol);
+
+    // === Token Repayment ===
+    let mint_key = ctx.accounts.mint.key();
+    let seeds = &[
+        b"vault_liquidity_authority",
+        mint_key.as_ref(),
+        &[ctx.bumps.liquidity_vault_authority],
+    ];
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.token_liquidity_vault.to_account_info(),
+                to: ctx.accounts.lending_vault.to_account_info(),
+                authority: ctx.accounts.liquidity_vault_authority.to_account_info(),
+            },
+            &[seeds],
+        ),
+        repay_tokens,
+    )?;
+
+    let signer_seeds: &[&[u8]] = &[
+        b"vault_wsol_authority",
+        mint_key.as_ref(),
+        &[ctx.bumps.wsol_vault_authority],
+    ];
+    let signer_seeds = &[signer_seeds];
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.wsol_vault.to_account_info(),
+                to: ctx.accounts.project_vault.to_account_info(),
+                authority: ctx.accounts.wsol_vault_authority.to_account_info(),
+            },
+            signer_seeds,
+        ),
+        yield_share_sol
+            .checked_add(interest_sol)
+            .ok_or(ErrorCode::Overflow)?,
+    )?;
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.wsol_vault.to_account_info(),
+                to: ctx.accounts.bot_wsol_account.to_account_info(), // Bot's WSOL account
+                authority: ctx.accounts.wsol_vault_authority.to_account_info(),
+            },
+            signer_seeds,
+        ),
+        bonus_sol,
+    )?;
+
+    // Realised loss stays in the WSOL vault and backs the pool
+    vault_config.accumulated_c = vault_config.accumulated_c
+        .checked_add(realised_loss as i64)
+        .ok_or(ErrorCode::Overflow)?;
+
+    vault_config.token_reserve = vault_config
+        .token_reserve
+        .checked_sub(repay_tokens)
+        .ok_or(ErrorCode::Underflow)?;
+
+    vault_config.total_borrowed = vault_config
+        .total_borrowed
+        .checked_sub(repay_tokens)
+        .ok_or(ErrorCode::Underflow)?;
+
+    let yield_vault = &mut ctx.accounts.yield_vault;
+    if yield_vault.total_weighted_stake > 0 {
+        // new rewards = yield share + borrow interest (WSOL)
+        let new_rewards = (yield_share_sol as u128)
+            .checked_add(interest_sol as u128)
+            .ok_or(ErrorCode::Overflow)?;
+
+        yield_vault.acc_reward_per_share = yield_vault
+            .acc_reward_per_share
+            .checked_add(new_rewards * PRECISION / yield_vault.total_weighted_stake as u128)
+            .ok_or(ErrorCode::Overflow)?;
+
+        yield_vault.last_accrual_ts = now;
+    }
+
+    vault_config.yield_vault = vault_config
+            .yield_vault
+            .checked_add(yield_share_sol)
+            .and_then(|v| v.checked_add(interest_sol))
+            .ok_or(ErrorCode::Overflow)?;
+
+    // Shrink the position and move its liquidation threshold to match
+    let maintenance_margin_bps = ctx.accounts.protocol_config.maintenance_margin_bps as u64;
+    let position = &mut ctx.accounts.position;
+
+    position.amount = position.amount
+        .checked_sub(repay_tokens)
+        .ok_or(ErrorCode::Underflow)?;
+    position.collateral = collateral_left;
+    position.liquidate = get_liquidation_price(
+        position.entry_price,
+        position.amount,
+        position.collateral,
+        maintenance_margin_bps,
+        decimals,
+    )?;
+
+    msg!("remaining amount: {}", position.amount);
+    msg!("remaining collateral: {}", position.collateral);
+    msg!("liquidation_price: {}", position.liquidate);
+
+    // An auction ends once the position is healthy again, otherwise it keeps running for the next slice
+    let recovered = load_curve(vault_config)?.spot_price(decimals) < position.liquidate;
+    if let (false, Some(auction)) = (recovered, &mut ctx.accounts.liquidation_auction) {
+        // Same auction, same bonus curve for the next slice
+        auction.position_amount = position.amount;
+        auction.position_collateral = position.collateral;
+    }
+    if let (true, Some(auction)) = (recovered, &ctx.accounts.liquidation_auction) {
+        auction.close(ctx.accounts.bot.to_account_info())?;
+        emit!(LiquidationAuctionClosedEvent {
+            position: auction.position,
+            mint: mint_key,
+            position_id: position.position_id,
+            executed: true,
+            bonus_bps,
+            slot: Clock::get()?.slot,
+        });
+    }
+
+    emit!(PartialLiquidationEvent {
+        user: ctx.accounts.user.key(),
+        mint: mint_key,
+        liquidator: ctx.accounts.bot.key(),
+        position_id: position.position_id,
+        repaid_tokens: repay_tokens,
+        repay_bps,
+        liquidator_bonus: bonus_sol,
+        yield_share: yield_share_sol,
+        borrow_interest: interest_sol,
+        realised_loss,
+        remaining_amount: position.amount,
+        remaining_collateral: position.collateral,
+        liquidation_price: position.liquidate,
+        accumulated_c_after: vault_config.accumulated_c,
+        token_reserve_after: vault_config.token_reserve,
+        exit_price: value_now,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
 #[event]
 pub struct LiquidatePositionEvent {
     pub user: Pubkey,
@@ -307,8 +789,11 @@ pub struct LiquidatePositionEvent {
     pub position_id: u64,
     pub repaid_tokens: u64,
     pub total_fees: u64,
+    pub borrow_interest: u64,
     pub interest: u64,
     pub forfeited_collateral: u64,
+    pub insurance_fee: u64,
+    pub bad_debt: u64,
     pub accumulated_c_after: i64,
     pub acc_reward_per_share: u64,
     pub token_reserve_after: u64,
@@ -317,3 +802,24 @@ pub struct LiquidatePositionEvent {
     pub virtual_tokens: u64, 
     pub exit_price: u64,
 }
+
+#[event]
+pub struct PartialLiquidationEvent {
+    pub user: Pubkey,
+    pub mint: Pubkey,
+    pub liquidator: Pubkey,
+    pub position_id: u64,
+    pub repaid_tokens: u64,
+    pub repay_bps: u64,
+    pub liquidator_bonus: u64,
+    pub yield_share: u64,
+    pub borrow_interest: u64,
+    pub realised_loss: u64,
+    pub remaining_amount: u64,
+    pub remaining_collateral: u64,
+    pub liquidation_price: u64,
+    pub accumulated_c_after: i64,
+    pub token_reserve_after: u64,
+    pub exit_price: u64,
+    pub timestamp: i64,
+}
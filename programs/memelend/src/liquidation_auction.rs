@@ -0,0 +1,285 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+use crate::{VaultConfig, TokenConfig, ProtocolConfig, Position, ErrorCode, LiquidationAuction};
+use crate::get_liquidation_price;
+use crate::curve::load_curve;
+
+// Dutch-auction liquidations. Anyone can open an auction on a short that has crossed its
+// liquidation price; the liquidator bonus then grows linearly from start to max over
+// `duration_slots`, and liquidate_position pays whatever the bonus is when someone executes.
+// Auction mode is on while ProtocolConfig.auction_duration_slots > 0.
+// A position that recovers should have its auction cancelled; if the owner adds collateral
+// or reduces instead, liquidate_position restarts the auction from the start bonus.
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LiquidationAuctionArgs {
+    pub position_id: u64,
+}
+
+// Bonus (bps) an auction pays `elapsed` slots after it opened
+pub fn auction_bonus_bps(start_bonus_bps: u64, max_bonus_bps: u64, duration_slots: u64, elapsed: u64) -> u64 {
+    if duration_slots == 0 || elapsed >= duration_slots {
+        return max_bonus_bps;
+    }
+
+    let range = max_bonus_bps.saturating_sub(start_bonus_bps);
+    start_bonus_bps + (range as u128 * elapsed as u128 / duration_slots as u128) as u64
+}
+
+impl LiquidationAuction {
+    // Restarts the auction at `slot` if the position changed since it was last seen. The owner
+    // topping up or reducing means the position was healthier in between, so the bonus
+    // shouldn't carry over. Returns whether it restarted.
+    pub fn sync_position(&mut self, position: &Position, slot: u64) -> bool {
+        if self.position_amount == position.amount && self.position_collateral == position.collateral {
+            return false;
+        }
+        self.start_slot = slot;
+        self.position_amount = position.amount;
+        self.position_collateral = position.collateral;
+        true
+    }
+
+    pub fn current_bonus_bps(&self, slot: u64) -> u64 {
+        auction_bonus_bps(
+            self.start_bonus_bps as u64,
+            self.max_bonus_bps as u64,
+            self.duration_slots,
+            slot.saturating_sub(self.start_slot),
+        )
+    }
+}
+
+// Same health check liquidate_position runs, at the current spot price
+pub fn is_liquidatable(
+    position: &Position,
+    vault_config: &VaultConfig,
+    maintenance_margin_bps: u64,
+    decimals: u8,
+) -> Result<bool> {
+    let liquidation_price = get_liquidation_price(
+        position.entry_price,
+        position.amount,
+        position.collateral,
+        maintenance_margin_bps,
+        decimals,
+    )?;
+    let current_price = load_curve(vault_config)?.spot_price(decimals);
+
+    msg!("liquidation_price: {}", liquidation_price);
+    msg!("current_price: {}", current_price);
+
+    Ok(current_price >= liquidation_price)
+}
+
+#[derive(Accounts)]
+#[instruction(args: LiquidationAuctionArgs)]
+pub struct OpenLiquidationAuction<'info> {
+    #[account(mut)]
+    pub opener: Signer<'info>,
+
+    /// CHECK: Owner of the position, only used for the position seeds
+    pub user: AccountInfo<'info>,
+
+    #[account(address = token_config.mint @ ErrorCode::InvalidMint)]
+    pub mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        seeds = [b"position", user.key().as_ref(), mint.key().as_ref(), &args.position_id.to_le_bytes()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+        constraint = position.owner == user.key() @ ErrorCode::Unauthorized,
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        seeds = [b"config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub token_config: Box<Account<'info, TokenConfig>>,
+
+    #[account(
+        seeds = [b"vault_config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub vault_config: Box<Account<'info, VaultConfig>>,
+
+    // init_if_needed so an auction left behind by a closed position can be reused
+    #[account(
+        init_if_needed,
+        payer = opener,
+        seeds = [b"liquidation_auction", position.key().as_ref()],
+        bump,
+        space = 8 + LiquidationAuction::LEN,
+    )]
+    pub liquidation_auction: Box<Account<'info, LiquidationAuction>>,
+
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Box<Account<'info, ProtocolConfig>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(args: LiquidationAuctionArgs)]
+pub struct CancelLiquidationAuction<'info> {
+    // Permissionless, the position owner has every reason to call it
+    pub caller: Signer<'info>,
+
+    /// CHECK: Owner of the position, only used for the position seeds
+    pub user: AccountInfo<'info>,
+
+    #[account(address = token_config.mint @ ErrorCode::InvalidMint)]
+    pub mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        seeds = [b"position", user.key().as_ref(), mint.key().as_ref(), &args.position_id.to_le_bytes()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+        constraint = position.owner == user.key() @ ErrorCode::Unauthorized,
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        seeds = [b"config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub token_config: Box<Account<'info, TokenConfig>>,
+
+    #[account(
+        seeds = [b"vault_config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub vault_config: Box<Account<'info, VaultConfig>>,
+
+    #[account(
+        mut,
+        close = opener,
+        seeds = [b"liquidation_auction", position.key().as_ref()],
+        bump = liquidation_auction.bump,
+        has_one = opener @ ErrorCode::Unauthorized,
+    )]
+    pub liquidation_auction: Box<Account<'info, LiquidationAuction>>,
+
+    /// CHECK: Paid the auction rent, gets it back
+    #[account(mut)]
+    pub opener: AccountInfo<'info>,
+
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Box<Account<'info, ProtocolConfig>>,
+}
+
+pub fn open_liquidation_auction(ctx: Context<OpenLiquidationAuction>, args: LiquidationAuctionArgs) -> Result<()> {
+    let position = &ctx.accounts.position;
+    let protocol_config = &ctx.accounts.protocol_config;
+
+    require!(args.position_id == position.position_id, ErrorCode::InvalidPositionId);
+    require!(position.open, ErrorCode::PositionAlreadyClosed);
+    require!(protocol_config.auction_duration_slots > 0, ErrorCode::AuctionsDisabled);
+
+    // An auction still running for this same position can't be restarted
+    let auction = &mut ctx.accounts.liquidation_auction;
+    require!(
+        auction.start_slot == 0 || auction.position_created_at != position.created_at,
+        ErrorCode::AuctionAlreadyOpen
+    );
+
+    require!(
+        is_liquidatable(
+            position,
+            &ctx.accounts.vault_config,
+            protocol_config.maintenance_margin_bps as u64,
+            ctx.accounts.mint.decimals,
+        )?,
+        ErrorCode::PositionHealthy
+    );
+
+    let slot = Clock::get()?.slot;
+
+    auction.position = position.key();
+    auction.mint = ctx.accounts.mint.key();
+    auction.owner = position.owner;
+    auction.position_id = position.position_id;
+    auction.position_created_at = position.created_at;
+    auction.opener = ctx.accounts.opener.key();
+    auction.start_slot = slot;
+    auction.duration_slots = protocol_config.auction_duration_slots;
+    auction.start_bonus_bps = protocol_config.auction_start_bonus_bps;
+    auction.max_bonus_bps = protocol_config.auction_max_bonus_bps;
+    auction.bump = ctx.bumps.liquidation_auction;
+    auction.position_amount = position.amount;
+    auction.position_collateral = position.collateral;
+
+    emit!(LiquidationAuctionOpenedEvent {
+        position: auction.position,
+        mint: auction.mint,
+        owner: auction.owner,
+        position_id: auction.position_id,
+        opener: auction.opener,
+        start_slot: slot,
+        duration_slots: auction.duration_slots,
+        start_bonus_bps: auction.start_bonus_bps,
+        max_bonus_bps: auction.max_bonus_bps,
+    });
+
+    Ok(())
+}
+
+pub fn cancel_liquidation_auction(ctx: Context<CancelLiquidationAuction>, args: LiquidationAuctionArgs) -> Result<()> {
+    let position = &ctx.accounts.position;
+
+    require!(args.position_id == position.position_id, ErrorCode::InvalidPositionId);
+
+    // Auctions left behind by an earlier position with the same id can always be cleared,
+    // otherwise only once the position has recovered
+    let stale = ctx.accounts.liquidation_auction.position_created_at != position.created_at;
+    if !stale {
+        require!(
+            !is_liquidatable(
+                position,
+                &ctx.accounts.vault_config,
+                ctx.accounts.protocol_config.maintenance_margin_bps as u64,
+                ctx.accounts.mint.decimals,
+            )?,
+            ErrorCode::PositionNotHealthy
+        );
+    }
+
+    emit!(LiquidationAuctionClosedEvent {
+        position: position.key(),
+        mint: ctx.accounts.mint.key(),
+        position_id: position.position_id,
+        executed: false,
+        bonus_bps: 0,
+        slot: Clock::get()?.slot,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct LiquidationAuctionOpenedEvent {
+    pub position: Pubkey,
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub position_id: u64,
+    pub opener: Pubkey,
+    pub start_slot: u64,
+    pub duration_slots: u64,
+    pub start_bonus_bps: u16,
+    pub max_bonus_bps: u16,
+}
+
+#[event]
+pub struct LiquidationAuctionClosedEvent {
+    pub position: Pubkey,
+    pub mint: Pubkey,
+    pub position_id: u64,
+    pub executed: bool,
+    pub bonus_bps: u64,
+    pub slot: u64,
+}
@@ -0,0 +1,1033 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Token, TokenAccount, Mint, Transfer, CloseAccount};
+use anchor_spl::token::spl_token::native_mint::ID as NATIVE_MINT_ID;
+use crate::{VaultConfig, TokenConfig, ProtocolConfig, LongPosition, SolLendingPool, ErrorCode, POSITION_LONG};
+use crate::get_long_liquidation_price;
+use crate::curve::load_curve;
+use crate::interest::borrow_interest;
+use crate::market_config::{max_long_leverage, DEFAULT_MAX_UTILISATION_BPS};
+use crate::sol_lending::{accrue_pool_index, distribute_pool_rewards, pool_available, write_off_pool_loss};
+use crate::insurance_fund::{split_shortfall, pay_from_insurance};
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct GoLongArgs {
+    pub position_id: u64,
+    pub collateral_amount: u64,
+    pub leverage_bps: u16, // total exposure per unit of collateral, 20_000 = 2x
+    pub min_tokens_out: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct CloseLongArgs {
+    pub position_id: u64,
+    pub min_sol_out: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LiquidateLongArgs {
+    pub position_id: u64,
+}
+
+// Creator and platform fee on `value` lamports of long exposure. Longs pay the flat trade fee,
+// the referral / presale / staker split only applies to spot trades and shorts.
+pub fn long_fees(value: u64, creator_fee_bps: u16, platform_fee_bps: u16) -> (u64, u64) {
+    let creator_fee = (value as u128 * creator_fee_bps as u128 / 10_000) as u64;
+    let platform_fee = (value as u128 * platform_fee_bps as u128 / 10_000) as u64;
+    (creator_fee, platform_fee)
+}
+
+#[derive(Accounts)]
+#[instruction(args: GoLongArgs)]
+pub struct GoLong<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
+    pub wsol_mint: Account<'info, Mint>,
+
+    #[account(address = token_config.mint @ ErrorCode::InvalidMint)]
+    pub mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint = temp_wsol_account.mint == NATIVE_MINT_ID @ ErrorCode::InvalidWsolMint,
+    )]
+    pub temp_wsol_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: signer PDA
+    #[account(address = user.key())]
+    pub temp_wsol_authority: Signer<'info>,
+
+    #[account(
+        init,
+        payer = user,
+        seeds = [b"long_position", user.key().as_ref(), mint.key().as_ref(), &args.position_id.to_le_bytes()],
+        bump,
+        space = 8 + LongPosition::LEN,
+    )]
+    pub position: Box<Account<'info, LongPosition>>,
+
+    #[account(
+        seeds = [b"config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub token_config: Box<Account<'info, TokenConfig>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub vault_config: Box<Account<'info, VaultConfig>>,
+
+    /// CHECK: PDA authority for vaults
+    #[account(
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+    )]
+    pub vault_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_liquidity", mint.key().as_ref()],
+        bump,
+        constraint = token_liquidity_vault.mint == token_config.mint @ ErrorCode::InvalidMint,
+    )]
+    pub token_liquidity_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: PDA
+    #[account(
+        seeds = [b"vault_liquidity_authority", mint.key().as_ref()],
+        bump,
+    )]
+    pub liquidity_vault_authority: UncheckedAccount<'info>,
+
+    // Tokens bought by open longs, held until they close or get liquidated
+    #[account(
+        init_if_needed,
+        payer = user,
+        seeds = [b"vault_long", mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = liquidity_vault_authority,
+    )]
+    pub long_token_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_wsol", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+    )]
+    pub liquidity_sol_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_project", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+        token::authority = vault_authority,
+    )]
+    pub project_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_platform", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+        token::authority = vault_authority,
+    )]
+    pub platform_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"sol_lending_pool", mint.key().as_ref()],
+        bump = sol_lending_pool.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub sol_lending_pool: Box<Account<'info, SolLendingPool>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_sol_lending", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+        token::authority = sol_lending_authority,
+    )]
+    pub sol_lending_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: PDA
+    #[account(
+        seeds = [b"vault_sol_lending_authority", mint.key().as_ref()],
+        bump,
+    )]
+    pub sol_lending_authority: UncheckedAccount<'info>,
+
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Box<Account<'info, ProtocolConfig>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(args: CloseLongArgs)]
+pub struct CloseLong<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
+    pub wsol_mint: Account<'info, Mint>,
+
+    #[account(address = token_config.mint @ ErrorCode::InvalidMint)]
+    pub mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint = temp_wsol_account.mint == NATIVE_MINT_ID @ ErrorCode::InvalidWsolMint,
+    )]
+    pub temp_wsol_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: signer PDA
+    #[account(address = user.key())]
+    pub temp_wsol_authority: Signer<'info>,
+
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"long_position", user.key().as_ref(), mint.key().as_ref(), &args.position_id.to_le_bytes()],
+        bump = position.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+        constraint = position.owner == user.key() @ ErrorCode::Unauthorized,
+    )]
+    pub position: Box<Account<'info, LongPosition>>,
+
+    #[account(
+        seeds = [b"config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub token_config: Box<Account<'info, TokenConfig>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub vault_config: Box<Account<'info, VaultConfig>>,
+
+    /// CHECK: PDA authority for vaults
+    #[account(
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+    )]
+    pub vault_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_liquidity", mint.key().as_ref()],
+        bump,
+        constraint = token_liquidity_vault.mint == token_config.mint @ ErrorCode::InvalidMint,
+    )]
+    pub token_liquidity_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: PDA
+    #[account(
+        seeds = [b"vault_liquidity_authority", mint.key().as_ref()],
+        bump,
+    )]
+    pub liquidity_vault_authority: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_long", mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = liquidity_vault_authority,
+    )]
+    pub long_token_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_wsol", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+    )]
+    pub liquidity_sol_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: PDA for wSOL vault authority
+    #[account(
+        seeds = [b"vault_wsol_authority", mint.key().as_ref()],
+        bump,
+    )]
+    pub wsol_vault_authority: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_project", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+        token::authority = vault_authority,
+    )]
+    pub project_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_platform", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+        token::authority = vault_authority,
+    )]
+    pub platform_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"sol_lending_pool", mint.key().as_ref()],
+        bump = sol_lending_pool.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub sol_lending_pool: Box<Account<'info, SolLendingPool>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_sol_lending", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+    )]
+    pub sol_lending_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Box<Account<'info, ProtocolConfig>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(args: LiquidateLongArgs)]
+pub struct LiquidateLong<'info> {
+    #[account(mut)]
+    pub bot: Signer<'info>,
+
+    /// CHECK: Owner of the position, gets the position rent back
+    #[account(mut)]
+    pub user: AccountInfo<'info>,
+
+    #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
+    pub wsol_mint: Account<'info, Mint>,
+
+    #[account(address = token_config.mint @ ErrorCode::InvalidMint)]
+    pub mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"long_position", user.key().as_ref(), mint.key().as_ref(), &args.position_id.to_le_bytes()],
+        bump = position.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+        constraint = position.owner == user.key() @ ErrorCode::Unauthorized,
+    )]
+    pub position: Box<Account<'info, LongPosition>>,
+
+    #[account(
+        seeds = [b"config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub token_config: Box<Account<'info, TokenConfig>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub vault_config: Box<Account<'info, VaultConfig>>,
+
+    /// CHECK: PDA authority for vaults
+    #[account(
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+    )]
+    pub vault_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_liquidity", mint.key().as_ref()],
+        bump,
+        constraint = token_liquidity_vault.mint == token_config.mint @ ErrorCode::InvalidMint,
+    )]
+    pub token_liquidity_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: PDA
+    #[account(
+        seeds = [b"vault_liquidity_authority", mint.key().as_ref()],
+        bump,
+    )]
+    pub liquidity_vault_authority: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_long", mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = liquidity_vault_authority,
+    )]
+    pub long_token_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_wsol", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+    )]
+    pub liquidity_sol_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: PDA for wSOL vault authority
+    #[account(
+        seeds = [b"vault_wsol_authority", mint.key().as_ref()],
+        bump,
+    )]
+    pub wsol_vault_authority: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_insurance", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+        token::authority = vault_authority,
+    )]
+    pub insurance_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"sol_lending_pool", mint.key().as_ref()],
+        bump = sol_lending_pool.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub sol_lending_pool: Box<Account<'info, SolLendingPool>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_sol_lending", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+    )]
+    pub sol_lending_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = wsol_mint,
+    )]
+    pub bot_wsol_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Box<Account<'info, ProtocolConfig>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn go_long(ctx: Context<GoLong>, args: GoLongArgs) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let decimals = ctx.accounts.mint.decimals;
+    let collateral_amount = args.collateral_amount;
+    let leverage_bps = args.leverage_bps;
+
+    msg!("collateral_amount: {}", collateral_amount);
+    msg!("leverage_bps: {}", leverage_bps);
+
+    require!(collateral_amount > 0, ErrorCode::ZeroInput);
+    require!(
+        ctx.accounts.temp_wsol_account.amount >= collateral_amount,
+        ErrorCode::InsufficientFunds
+    );
+    require!(
+        leverage_bps > 10_000 && leverage_bps <= max_long_leverage(&ctx.accounts.vault_config),
+        ErrorCode::InvalidLeverage
+    );
+
+    // --- SOL borrowed on top of the collateral ---
+    let borrowed_sol = u64::try_from(
+        (collateral_amount as u128)
+            .checked_mul((leverage_bps - 10_000) as u128)
+            .ok_or(ErrorCode::Overflow)?
+            / 10_000,
+    )
+    .map_err(|_| ErrorCode::Overflow)?;
+    let exposure = collateral_amount
+        .checked_add(borrowed_sol)
+        .ok_or(ErrorCode::Overflow)?;
+
+    msg!("borrowed_sol: {}", borrowed_sol);
+
+    // --- Lending pool limits ---
+    let pool = &mut ctx.accounts.sol_lending_pool;
+    let borrow_index = accrue_pool_index(pool, &ctx.accounts.vault_config, now)?;
+
+    require!(borrowed_sol <= pool_available(pool), ErrorCode::InsufficientLendingLiquidity);
+
+    let max_utilisation_bps = match ctx.accounts.vault_config.max_utilisation_bps {
+        0 => DEFAULT_MAX_UTILISATION_BPS,
+        v => v,
+    };
+    let borrowed_after = pool.total_borrowed
+        .checked_add(borrowed_sol)
+        .ok_or(ErrorCode::Overflow)?;
+    require!(
+        (borrowed_after as u128) * 10_000 <= (pool.total_deposits as u128) * max_utilisation_bps as u128,
+        ErrorCode::UtilisationCeilingExceeded
+    );
+
+    // --- Fees come out of the collateral ---
+    let (creator_fee_sol, platform_fee_sol) = long_fees(
+        exposure,
+        ctx.accounts.token_config.creator_fee_bps,
+        ctx.accounts.protocol_config.platform_fee_bps,
+    );
+    let total_fees_sol = creator_fee_sol
+        .checked_add(platform_fee_sol)
+        .ok_or(ErrorCode::Overflow)?;
+    let collateral = collateral_amount
+        .checked_sub(total_fees_sol)
+        .ok_or(ErrorCode::Underflow)?;
+    let net_sol_to_liquidity = exposure
+        .checked_sub(total_fees_sol)
+        .ok_or(ErrorCode::Underflow)?;
+
+    msg!("total_fees_sol: {}", total_fees_sol);
+    msg!("net_sol_to_liquidity: {}", net_sol_to_liquidity);
+
+    // --- Buy on this mint's curve ---
+    let vault_config = &mut ctx.accounts.vault_config;
+    let tokens_out = load_curve(vault_config)?.quote_buy(net_sol_to_liquidity)?;
+
+    msg!("tokens_out: {}", tokens_out);
+
+    require!(tokens_out > 0, ErrorCode::ZeroInput);
+    require!(tokens_out >= args.min_tokens_out, ErrorCode::SlippageExceeded);
+
+    let max_allowed = ((vault_config.token_reserve as u128)
+        .checked_mul(ctx.accounts.protocol_config.max_buy_bps as u128)
+        .ok_or(ErrorCode::Overflow)? / 10_000) as u64;
+    require!(tokens_out <= max_allowed, ErrorCode::AmountTooLarge);
+
+    // Fees to the project and platform vaults
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.temp_wsol_account.to_account_info(),
+                to: ctx.accounts.project_vault.to_account_info(),
+                authority: ctx.accounts.temp_wsol_authority.to_account_info(),
+            },
+        ),
+        creator_fee_sol,
+    )?;
+
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.temp_wsol_account.to_account_info(),
+                to: ctx.accounts.platform_vault.to_account_info(),
+                authority: ctx.accounts.temp_wsol_authority.to_account_info(),
+            },
+        ),
+        platform_fee_sol,
+    )?;
+
+    // Collateral and borrowed SOL both go into the curve
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.temp_wsol_account.to_account_info(),
+                to: ctx.accounts.liquidity_sol_vault.to_account_info(),
+                authority: ctx.accounts.temp_wsol_authority.to_account_info(),
+            },
+        ),
+        collateral,
+    )?;
+
+    let mint_key = ctx.accounts.mint.key();
+    let lending_seeds: &[&[u8]] = &[
+        b"vault_sol_lending_authority",
+        mint_key.as_ref(),
+        &[ctx.bumps.sol_lending_authority],
+    ];
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.sol_lending_vault.to_account_info(),
+                to: ctx.accounts.liquidity_sol_vault.to_account_info(),
+                authority: ctx.accounts.sol_lending_authority.to_account_info(),
+            },
+            &[lending_seeds],
+        ),
+        borrowed_sol,
+    )?;
+
+    // Bought tokens stay in protocol custody until the position closes
+    let liquidity_seeds: &[&[u8]] = &[
+        b"vault_liquidity_authority",
+        mint_key.as_ref(),
+        &[ctx.bumps.liquidity_vault_authority],
+    ];
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.token_liquidity_vault.to_account_info(),
+                to: ctx.accounts.long_token_vault.to_account_info(),
+                authority: ctx.accounts.liquidity_vault_authority.to_account_info(),
+            },
+            &[liquidity_seeds],
+        ),
+        tokens_out,
+    )?;
+
+    vault_config.sol_reserve = vault_config.sol_reserve
+        .checked_add(net_sol_to_liquidity)
+        .ok_or(ErrorCode::Overflow)?;
+    vault_config.token_reserve = vault_config.token_reserve
+        .checked_sub(tokens_out)
+        .ok_or(ErrorCode::Underflow)?;
+    vault_config.creator_vault = vault_config.creator_vault
+        .checked_add(creator_fee_sol)
+        .ok_or(ErrorCode::Overflow)?;
+    vault_config.platform_vault = vault_config.platform_vault
+        .checked_add(platform_fee_sol)
+        .ok_or(ErrorCode::Overflow)?;
+
+    ctx.accounts.sol_lending_pool.total_borrowed = borrowed_after;
+
+    // Entry price is what the position paid per token, same scale as shorts
+    let entry_price = u64::try_from(
+        (net_sol_to_liquidity as u128)
+            .checked_mul(10u128.pow(decimals as u32 + 6))
+            .ok_or(ErrorCode::Overflow)?
+            / tokens_out as u128,
+    )
+    .map_err(|_| ErrorCode::Overflow)?;
+
+    let liquidation_price = get_long_liquidation_price(
+        entry_price,
+        tokens_out,
+        collateral,
+        ctx.accounts.protocol_config.maintenance_margin_bps as u64,
+        decimals,
+    )?;
+
+    msg!("entry_price: {}", entry_price);
+    msg!("liquidation_price: {}", liquidation_price);
+
+    let position = &mut ctx.accounts.position;
+    position.owner = ctx.accounts.user.key();
+    position.mint = mint_key;
+    position.position_id = args.position_id;
+    position.direction = POSITION_LONG;
+    position.entry_price = entry_price;
+    position.amount = tokens_out;
+    position.collateral = collateral;
+    position.borrowed_sol = borrowed_sol;
+    position.liquidate = liquidation_price;
+    position.borrow_index_snapshot = borrow_index;
+    position.open = true;
+    position.created_at = now;
+    position.bump = ctx.bumps.position;
+
+    token::close_account(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.temp_wsol_account.to_account_info(),
+                destination: ctx.accounts.user.to_account_info(),
+                authority: ctx.accounts.temp_wsol_authority.to_account_info(),
+            },
+        )
+    )?;
+
+    emit!(GoLongEvent {
+        user: position.owner,
+        mint: mint_key,
+        position_id: args.position_id,
+        collateral,
+        borrowed_sol,
+        tokens_out,
+        total_fees: total_fees_sol,
+        entry_price,
+        liquidation_price,
+        token_reserve: vault_config.token_reserve,
+        sol_reserve: vault_config.sol_reserve,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+pub fn close_long(ctx: Context<CloseLong>, args: CloseLongArgs) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let position = &ctx.accounts.position;
+
+    require!(args.position_id == position.position_id, ErrorCode::InvalidPositionId);
+    require!(position.open, ErrorCode::PositionAlreadyClosed);
+
+    let tokens = position.amount;
+    let borrowed_sol = position.borrowed_sol;
+
+    // --- Sell the position's tokens back into the curve ---
+    let vault_config = &mut ctx.accounts.vault_config;
+    let sol_out = load_curve(vault_config)?.quote_sell(tokens)?;
+
+    let (creator_fee_sol, platform_fee_sol) = long_fees(
+        sol_out,
+        ctx.accounts.token_config.creator_fee_bps,
+        ctx.accounts.protocol_config.platform_fee_bps,
+    );
+    let total_fees_sol = creator_fee_sol
+        .checked_add(platform_fee_sol)
+        .ok_or(ErrorCode::Overflow)?;
+
+    // --- Loan plus interest goes back to the lenders ---
+    let pool = &mut ctx.accounts.sol_lending_pool;
+    let borrow_index = accrue_pool_index(pool, vault_config, now)?;
+    let interest_sol = borrow_interest(borrowed_sol, position.borrow_index_snapshot, borrow_index)?;
+    let debt_sol = borrowed_sol
+        .checked_add(interest_sol)
+        .ok_or(ErrorCode::Overflow)?;
+
+    msg!("sol_out: {}", sol_out);
+    msg!("total_fees_sol: {}", total_fees_sol);
+    msg!("interest_sol: {}", interest_sol);
+
+    let payout = sol_out
+        .checked_sub(total_fees_sol)
+        .and_then(|v| v.checked_sub(debt_sol))
+        .ok_or(ErrorCode::LongUnderwater)?;
+
+    msg!("payout: {}", payout);
+
+    require!(payout >= args.min_sol_out, ErrorCode::SlippageExceeded);
+
+    let mint_key = ctx.accounts.mint.key();
+    let liquidity_seeds: &[&[u8]] = &[
+        b"vault_liquidity_authority",
+        mint_key.as_ref(),
+        &[ctx.bumps.liquidity_vault_authority],
+    ];
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.long_token_vault.to_account_info(),
+                to: ctx.accounts.token_liquidity_vault.to_account_info(),
+                authority: ctx.accounts.liquidity_vault_authority.to_account_info(),
+            },
+            &[liquidity_seeds],
+        ),
+        tokens,
+    )?;
+
+    let wsol_seeds: &[&[u8]] = &[
+        b"vault_wsol_authority",
+        mint_key.as_ref(),
+        &[ctx.bumps.wsol_vault_authority],
+    ];
+    let wsol_seeds = &[wsol_seeds];
+
+    for (to, amount) in [
+        (ctx.accounts.project_vault.to_account_info(), creator_fee_sol),
+        (ctx.accounts.platform_vault.to_account_info(), platform_fee_sol),
+        (ctx.accounts.sol_lending_vault.to_account_info(), debt_sol),
+        (ctx.accounts.temp_wsol_account.to_account_info(), payout),
+    ] {
+        if amount == 0 {
+            continue;
+        }
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.liquidity_sol_vault.to_account_info(),
+                    to,
+                    authority: ctx.accounts.wsol_vault_authority.to_account_info(),
+                },
+                wsol_seeds,
+            ),
+            amount,
+        )?;
+    }
+
+    vault_config.token_reserve = vault_config.token_reserve
+        .checked_add(tokens)
+        .ok_or(ErrorCode::Overflow)?;
+    vault_config.sol_reserve = vault_config.sol_reserve
+        .checked_sub(sol_out)
+        .ok_or(ErrorCode::Underflow)?;
+    vault_config.creator_vault = vault_config.creator_vault
+        .checked_add(creator_fee_sol)
+        .ok_or(ErrorCode::Overflow)?;
+    vault_config.platform_vault = vault_config.platform_vault
+        .checked_add(platform_fee_sol)
+        .ok_or(ErrorCode::Overflow)?;
+
+    pool.total_borrowed = pool.total_borrowed.saturating_sub(borrowed_sol);
+    distribute_pool_rewards(pool, interest_sol)?;
+
+    token::close_account(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.temp_wsol_account.to_account_info(),
+                destination: ctx.accounts.user.to_account_info(),
+                authority: ctx.accounts.temp_wsol_authority.to_account_info(),
+            },
+        )
+    )?;
+
+    let position = &mut ctx.accounts.position;
+    position.open = false;
+
+    let pnl = payout as i128 - position.collateral as i128;
+
+    emit!(CloseLongEvent {
+        user: position.owner,
+        mint: mint_key,
+        position_id: position.position_id,
+        sold_tokens: tokens,
+        sol_out,
+        total_fees: total_fees_sol,
+        borrow_interest: interest_sol,
+        collateral_returned: payout,
+        pnl: i64::try_from(pnl).map_err(|_| ErrorCode::Overflow)?,
+        token_reserve: vault_config.token_reserve,
+        sol_reserve: vault_config.sol_reserve,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+pub fn liquidate_long(ctx: Context<LiquidateLong>, args: LiquidateLongArgs) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let decimals = ctx.accounts.mint.decimals;
+    let position = &ctx.accounts.position;
+
+    require!(args.position_id == position.position_id, ErrorCode::InvalidPositionId);
+    require!(position.open, ErrorCode::PositionAlreadyClosed);
+
+    let tokens = position.amount;
+    let borrowed_sol = position.borrowed_sol;
+    let protocol_config = &ctx.accounts.protocol_config;
+    let vault_config = &mut ctx.accounts.vault_config;
+
+    // === Health Check ===
+    // Recomputed from the position so a stale stored threshold is never trusted
+    let liquidation_price = get_long_liquidation_price(
+        position.entry_price,
+        tokens,
+        position.collateral,
+        protocol_config.maintenance_margin_bps as u64,
+        decimals,
+    )?;
+
+    let curve = load_curve(vault_config)?;
+    let current_price = curve.spot_price(decimals);
+
+    msg!("liquidation_price: {}", liquidation_price);
+    msg!("current_price: {}", current_price);
+
+    require!(current_price <= liquidation_price, ErrorCode::PositionHealthy);
+
+    // === Unwind ===
+    let sol_out = curve.quote_sell(tokens)?;
+    let liquidator_fee_sol = (sol_out as u128 * protocol_config.liquidator_fee_bps as u128 / 10_000) as u64;
+    let remaining = sol_out
+        .checked_sub(liquidator_fee_sol)
+        .ok_or(ErrorCode::Underflow)?;
+
+    let pool = &mut ctx.accounts.sol_lending_pool;
+    let borrow_index = accrue_pool_index(pool, vault_config, now)?;
+    let interest_owed = borrow_interest(borrowed_sol, position.borrow_index_snapshot, borrow_index)?;
+    let debt_sol = borrowed_sol
+        .checked_add(interest_owed)
+        .ok_or(ErrorCode::Overflow)?;
+
+    // Principal is repaid before interest, whatever is left of the equity is forfeited
+    let repaid_sol = remaining.min(debt_sol);
+    let interest_sol = repaid_sol.saturating_sub(borrowed_sol);
+    let principal_shortfall = borrowed_sol.saturating_sub(repaid_sol);
+    let leftover = remaining - repaid_sol;
+
+    let insurance_share_sol = (leftover as u128 * protocol_config.insurance_liquidation_bps as u128 / 10_000) as u64;
+    let forfeited_sol = leftover - insurance_share_sol;
+
+    let (insured_sol, bad_debt_sol) = split_shortfall(
+        principal_shortfall,
+        vault_config.insurance_fund.checked_add(insurance_share_sol).ok_or(ErrorCode::Overflow)?,
+    );
+
+    msg!("sol_out: {}", sol_out);
+    msg!("liquidator_fee_sol: {}", liquidator_fee_sol);
+    msg!("repaid_sol: {}", repaid_sol);
+    msg!("forfeited_sol: {}", forfeited_sol);
+    msg!("insured_sol: {}", insured_sol);
+    msg!("bad_debt_sol: {}", bad_debt_sol);
+
+    let mint_key = ctx.accounts.mint.key();
+    let liquidity_seeds: &[&[u8]] = &[
+        b"vault_liquidity_authority",
+        mint_key.as_ref(),
+        &[ctx.bumps.liquidity_vault_authority],
+    ];
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.long_token_vault.to_account_info(),
+                to: ctx.accounts.token_liquidity_vault.to_account_info(),
+                authority: ctx.accounts.liquidity_vault_authority.to_account_info(),
+            },
+            &[liquidity_seeds],
+        ),
+        tokens,
+    )?;
+
+    let wsol_seeds: &[&[u8]] = &[
+        b"vault_wsol_authority",
+        mint_key.as_ref(),
+        &[ctx.bumps.wsol_vault_authority],
+    ];
+    let wsol_seeds = &[wsol_seeds];
+
+    for (to, amount) in [
+        (ctx.accounts.bot_wsol_account.to_account_info(), liquidator_fee_sol),
+        (ctx.accounts.sol_lending_vault.to_account_info(), repaid_sol + forfeited_sol),
+        (ctx.accounts.insurance_vault.to_account_info(), insurance_share_sol),
+    ] {
+        if amount == 0 {
+            continue;
+        }
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.liquidity_sol_vault.to_account_info(),
+                    to,
+                    authority: ctx.accounts.wsol_vault_authority.to_account_info(),
+                },
+                wsol_seeds,
+            ),
+            amount,
+        )?;
+    }
+
+    vault_config.insurance_fund = vault_config.insurance_fund
+        .checked_add(insurance_share_sol)
+        .ok_or(ErrorCode::Overflow)?;
+
+    // Covered part of the lost principal goes back to the lenders from the insurance vault
+    pay_from_insurance(
+        vault_config,
+        &ctx.accounts.insurance_vault,
+        &ctx.accounts.sol_lending_vault,
+        &ctx.accounts.vault_authority,
+        &ctx.accounts.token_program,
+        ctx.bumps.vault_authority,
+        insured_sol,
+    )?;
+
+    vault_config.token_reserve = vault_config.token_reserve
+        .checked_add(tokens)
+        .ok_or(ErrorCode::Overflow)?;
+    vault_config.sol_reserve = vault_config.sol_reserve
+        .checked_sub(sol_out)
+        .ok_or(ErrorCode::Underflow)?;
+
+    pool.total_borrowed = pool.total_borrowed.saturating_sub(borrowed_sol);
+    write_off_pool_loss(pool, bad_debt_sol)?;
+
+    // Lenders took the risk, they get the interest and the forfeited equity
+    distribute_pool_rewards(
+        pool,
+        interest_sol.checked_add(forfeited_sol).ok_or(ErrorCode::Overflow)?,
+    )?;
+
+    let position = &mut ctx.accounts.position;
+    position.open = false;
+
+    emit!(LiquidateLongEvent {
+        user: position.owner,
+        mint: mint_key,
+        position_id: position.position_id,
+        sold_tokens: tokens,
+        sol_out,
+        liquidator_fee: liquidator_fee_sol,
+        repaid_sol,
+        forfeited_sol,
+        insurance_fee: insurance_share_sol,
+        bad_debt: bad_debt_sol,
+        token_reserve: vault_config.token_reserve,
+        sol_reserve: vault_config.sol_reserve,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct GoLongEvent {
+    pub user: Pubkey,
+    pub mint: Pubkey,
+    pub position_id: u64,
+    pub collateral: u64,
+    pub borrowed_sol: u64,
+    pub tokens_out: u64,
+    pub total_fees: u64,
+    pub entry_price: u64,
+    pub liquidation_price: u64,
+    pub token_reserve: u64,
+    pub sol_reserve: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct CloseLongEvent {
+    pub user: Pubkey,
+    pub mint: Pubkey,
+    pub position_id: u64,
+    pub sold_tokens: u64,
+    pub sol_out: u64,
+    pub total_fees: u64,
+    pub borrow_interest: u64,
+    pub collateral_returned: u64,
+    pub pnl: i64,
+    pub token_reserve: u64,
+    pub sol_reserve: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LiquidateLongEvent {
+    pub user: Pubkey,
+    pub mint: Pubkey,
+    pub position_id: u64,
+    pub sold_tokens: u64,
+    pub sol_out: u64,
+    pub liquidator_fee: u64,
+    pub repaid_sol: u64,
+    pub forfeited_sol: u64,
+    pub insurance_fee: u64,
+    pub bad_debt: u64,
+    pub token_reserve: u64,
+    pub sol_reserve: u64,
+    pub timestamp: i64,
+}
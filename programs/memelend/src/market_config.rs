@@ -0,0 +1,253 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+use crate::{VaultConfig, YieldVault, ProtocolConfig, ErrorCode};
+use crate::interest::{
+    accrue_borrow_index, borrow_rate_bps, projected_borrow_index, rate_model,
+    utilisation_bps, validate_rate_model,
+};
+
+// Open interest limits, tuned per mint with update_market_config
+pub const DEFAULT_MAX_OI_STAKE_BPS: u16 = 8_000;   // shorts may borrow up to 80% of staked tokens
+pub const DEFAULT_MAX_UTILISATION_BPS: u16 = 9_000; // lending vault must keep 10% of its liquidity
+pub const DEFAULT_MIN_LEVERAGE_BPS: u16 = 1_000;    // smallest short is 0.1x its collateral
+pub const DEFAULT_MAX_LEVERAGE_BPS: u16 = 10_000;   // largest short is 1x its collateral
+pub const MAX_LEVERAGE_BPS: u16 = 30_000;           // hard ceiling for any mint
+pub const DEFAULT_MAX_LONG_LEVERAGE_BPS: u16 = 30_000; // longs up to 3x their collateral
+pub const MAX_LONG_LEVERAGE_BPS: u16 = 50_000;         // hard ceiling for any mint
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct UpdateMarketConfigArgs {
+    pub rate_base_bps: Option<u16>,
+    pub rate_slope_bps: Option<u16>,
+    pub rate_kink_bps: Option<u16>,
+    pub rate_max_bps: Option<u16>,
+    pub max_open_interest: Option<u64>, // 0 = no absolute cap
+    pub max_oi_stake_bps: Option<u16>,
+    pub max_utilisation_bps: Option<u16>,
+    pub min_leverage_bps: Option<u16>,
+    pub max_leverage_bps: Option<u16>,
+    pub max_long_leverage_bps: Option<u16>,
+}
+
+// Returned by get_market_rates
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct MarketRates {
+    pub total_borrowed: u64,
+    pub total_staked: u64,
+    pub utilisation_bps: u64,
+    pub borrow_rate_bps: u64,
+    pub supply_rate_bps: u64,
+    pub borrow_index: u128,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMarketConfig<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump,
+        has_one = admin @ ErrorCode::Unauthorized,
+    )]
+    pub protocol_config: Box<Account<'info, ProtocolConfig>>,
+
+    pub mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub vault_config: Box<Account<'info, VaultConfig>>,
+
+    #[account(
+        seeds = [b"yield_vault", mint.key().as_ref()],
+        bump = yield_vault.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub yield_vault: Box<Account<'info, YieldVault>>,
+}
+
+#[derive(Accounts)]
+pub struct GetMarketRates<'info> {
+    pub mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        seeds = [b"vault_config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub vault_config: Box<Account<'info, VaultConfig>>,
+
+    #[account(
+        seeds = [b"yield_vault", mint.key().as_ref()],
+        bump = yield_vault.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub yield_vault: Box<Account<'info, YieldVault>>,
+}
+
+pub fn update_market_config(ctx: Context<UpdateMarketConfig>, args: UpdateMarketConfigArgs) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let total_staked = ctx.accounts.yield_vault.total_staked;
+    let vault_config = &mut ctx.accounts.vault_config;
+
+    // Charge time so far at the old rates before switching
+    accrue_borrow_index(vault_config, total_staked, now)?;
+
+    let (base, slope, kink, max) = rate_model(vault_config);
+    let base = args.rate_base_bps.unwrap_or(base as u16);
+    let slope = args.rate_slope_bps.unwrap_or(slope as u16);
+    let kink = args.rate_kink_bps.unwrap_or(kink as u16);
+    let max = args.rate_max_bps.unwrap_or(max as u16);
+
+    validate_rate_model(base, slope, kink, max)?;
+
+    vault_config.rate_base_bps = base;
+    vault_config.rate_slope_bps = slope;
+    vault_config.rate_kink_bps = kink;
+    vault_config.rate_max_bps = max;
+
+    if let Some(v) = args.max_open_interest {
+        vault_config.max_open_interest = v;
+    }
+    if let Some(v) = args.max_oi_stake_bps {
+        require!(v > 0 && v <= 10_000, ErrorCode::InvalidInput);
+        vault_config.max_oi_stake_bps = v;
+    }
+    if let Some(v) = args.max_utilisation_bps {
+        require!(v > 0 && v <= 10_000, ErrorCode::InvalidInput);
+        vault_config.max_utilisation_bps = v;
+    }
+
+    let (min_leverage, max_leverage) = leverage_limits(vault_config);
+    let min_leverage = args.min_leverage_bps.unwrap_or(min_leverage);
+    let max_leverage = args.max_leverage_bps.unwrap_or(max_leverage);
+    require!(
+        min_leverage > 0 && min_leverage <= max_leverage && max_leverage <= MAX_LEVERAGE_BPS,
+        ErrorCode::InvalidLeverage
+    );
+    vault_config.min_leverage_bps = min_leverage;
+    vault_config.max_leverage_bps = max_leverage;
+
+    if let Some(v) = args.max_long_leverage_bps {
+        require!(v > 10_000 && v <= MAX_LONG_LEVERAGE_BPS, ErrorCode::InvalidLeverage);
+        vault_config.max_long_leverage_bps = v;
+    }
+
+    emit!(MarketConfigUpdatedEvent {
+        mint: ctx.accounts.mint.key(),
+        rate_base_bps: base,
+        rate_slope_bps: slope,
+        rate_kink_bps: kink,
+        rate_max_bps: max,
+        max_open_interest: vault_config.max_open_interest,
+        max_oi_stake_bps: vault_config.max_oi_stake_bps,
+        max_utilisation_bps: vault_config.max_utilisation_bps,
+        min_leverage_bps: min_leverage,
+        max_leverage_bps: max_leverage,
+        max_long_leverage_bps: max_long_leverage(vault_config),
+        borrow_index: vault_config.borrow_index,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+pub fn get_market_rates(ctx: Context<GetMarketRates>) -> Result<MarketRates> {
+    let vault_config = &ctx.accounts.vault_config;
+    let total_staked = ctx.accounts.yield_vault.total_staked;
+    let now = Clock::get()?.unix_timestamp;
+
+    let utilisation = utilisation_bps(vault_config.total_borrowed, total_staked);
+    let borrow_rate = borrow_rate_bps(vault_config, utilisation);
+
+    // Lenders earn the borrow rate on the lent-out share of their stake
+    let supply_rate = borrow_rate * utilisation / 10_000;
+
+    let rates = MarketRates {
+        total_borrowed: vault_config.total_borrowed,
+        total_staked,
+        utilisation_bps: utilisation,
+        borrow_rate_bps: borrow_rate,
+        supply_rate_bps: supply_rate,
+        borrow_index: projected_borrow_index(vault_config, total_staked, now)?,
+    };
+
+    msg!("utilisation_bps: {}", rates.utilisation_bps);
+    msg!("borrow_rate_bps: {}", rates.borrow_rate_bps);
+    msg!("supply_rate_bps: {}", rates.supply_rate_bps);
+
+    Ok(rates)
+}
+
+// Leverage range go_short accepts
+pub fn leverage_limits(vault_config: &VaultConfig) -> (u16, u16) {
+    (vault_config.min_leverage_bps, vault_config.max_leverage_bps)
+}
+
+// Highest leverage go_long accepts
+pub fn max_long_leverage(vault_config: &VaultConfig) -> u16 {
+    vault_config.max_long_leverage_bps
+}
+
+// Rejects a new short of `new_borrow` tokens that would push the mint past its open interest caps.
+// `lending_liquidity` is what the lending vault holds before the borrow.
+pub fn check_open_interest(
+    vault_config: &VaultConfig,
+    total_staked: u64,
+    lending_liquidity: u64,
+    new_borrow: u64,
+) -> Result<()> {
+    let open_interest = vault_config.total_borrowed
+        .checked_add(new_borrow)
+        .ok_or(ErrorCode::Overflow)?;
+
+    // Absolute cap
+    if vault_config.max_open_interest > 0 {
+        require!(
+            open_interest <= vault_config.max_open_interest,
+            ErrorCode::OpenInterestCapExceeded
+        );
+    }
+
+    // Share of staked tokens
+    let stake_cap = (total_staked as u128 * vault_config.max_oi_stake_bps as u128 / 10_000) as u64;
+    require!(open_interest <= stake_cap, ErrorCode::OpenInterestCapExceeded);
+
+    // Utilisation of the lending vault's actual liquidity after the borrow,
+    // so withdrawals of principal can still be paid out
+    let max_utilisation_bps = vault_config.max_utilisation_bps;
+    let supplied = (vault_config.total_borrowed as u128)
+        .checked_add(lending_liquidity as u128)
+        .ok_or(ErrorCode::Overflow)?;
+    require!(supplied > 0, ErrorCode::UtilisationCeilingExceeded);
+
+    let utilisation = open_interest as u128 * 10_000 / supplied;
+    msg!("utilisation after borrow (bps): {}", utilisation);
+
+    require!(
+        utilisation <= max_utilisation_bps as u128,
+        ErrorCode::UtilisationCeilingExceeded
+    );
+
+    Ok(())
+}
+
+#[event]
+pub struct MarketConfigUpdatedEvent {
+    pub mint: Pubkey,
+    pub rate_base_bps: u16,
+    pub rate_slope_bps: u16,
+    pub rate_kink_bps: u16,
+    pub rate_max_bps: u16,
+    pub max_open_interest: u64,
+    pub max_oi_stake_bps: u16,
+    pub max_utilisation_bps: u16,
+    pub min_leverage_bps: u16,
+    pub max_leverage_bps: u16,
+    pub max_long_leverage_bps: u16,
+    pub borrow_index: u128,
+    pub timestamp: i64,
+}
@@ -0,0 +1,361 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{TokenAccount, Token, Mint};
+use crate::{VaultConfig, TokenConfig, ProtocolConfig, Position, ErrorCode, YieldVault, UserAccount};
+use crate::close_position::{settle_short, ShortSettleAccounts};
+use crate::curve::load_curve;
+use crate::user_account::remove_position;
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct SetPositionTriggersArgs {
+    pub position_id: u64,
+    pub stop_loss_price: u64,   // exit once the price rises to this, 0 = off
+    pub take_profit_price: u64, // exit once the price falls to this, 0 = off
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct TriggerPositionExitArgs {
+    pub position_id: u64,
+}
+
+pub const TRIGGER_STOP_LOSS: u8 = 0;
+pub const TRIGGER_TAKE_PROFIT: u8 = 1;
+
+// Which trigger, if any, a short at `current_price` has crossed
+pub fn crossed_trigger(position: &Position, current_price: u64) -> Option<u8> {
+    if position.stop_loss_price > 0 && current_price >= position.stop_loss_price {
+        return Some(TRIGGER_STOP_LOSS);
+    }
+    if position.take_profit_price > 0 && current_price <= position.take_profit_price {
+        return Some(TRIGGER_TAKE_PROFIT);
+    }
+    None
+}
+
+#[derive(Accounts)]
+#[instruction(args: SetPositionTriggersArgs)]
+pub struct SetPositionTriggers<'info> {
+    pub user: Signer<'info>,
+
+    pub mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [b"position", user.key().as_ref(), mint.key().as_ref(), &args.position_id.to_le_bytes()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+        constraint = position.owner == user.key() @ ErrorCode::Unauthorized,
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        seeds = [b"vault_config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub vault_config: Box<Account<'info, VaultConfig>>,
+}
+
+#[derive(Accounts)]
+#[instruction(args: TriggerPositionExitArgs)]
+pub struct TriggerPositionExit<'info> {
+    // Permissionless, any keeper can call once a trigger is crossed
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+
+    #[account(
+        mut,
+        token::mint = wsol_mint,
+    )]
+    pub keeper_wsol_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Owner of the position, only used for seeds and to check the payout account
+    pub owner: UncheckedAccount<'info>,
+
+    // Remaining collateral goes here
+    #[account(
+        mut,
+        token::mint = wsol_mint,
+        token::authority = owner,
+    )]
+    pub owner_wsol_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
+    pub wsol_mint: Account<'info, Mint>,
+
+    #[account(address = token_config.mint @ ErrorCode::InvalidMint)]
+    pub mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [b"position", owner.key().as_ref(), mint.key().as_ref(), &args.position_id.to_le_bytes()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+        has_one = owner @ ErrorCode::Unauthorized,
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        mut,
+        seeds = [b"user_account", owner.key().as_ref()],
+        bump = user_account.bump,
+    )]
+    pub user_account: Box<Account<'info, UserAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub token_config: Box<Account<'info, TokenConfig>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub vault_config: Box<Account<'info, VaultConfig>>,
+
+    /// CHECK: PDA authority for vaults
+    #[account(
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+    )]
+    pub vault_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_liquidity", mint.key().as_ref()],
+        bump,
+        constraint = token_liquidity_vault.mint == token_config.mint @ ErrorCode::InvalidMint,
+    )]
+    pub token_liquidity_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: PDA
+    #[account(
+        seeds = [b"vault_liquidity_authority", mint.key().as_ref()],
+        bump,
+    )]
+    pub liquidity_vault_authority: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_lending", mint.key().as_ref()],
+        bump,
+    )]
+    pub lending_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_wsol", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+    )]
+    pub liquidity_sol_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: PDA for wSOL vault authority
+    #[account(
+        seeds = [b"vault_wsol_authority", mint.key().as_ref()],
+        bump,
+    )]
+    pub wsol_vault_authority: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_project", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+        token::authority = vault_authority,
+    )]
+    pub project_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_platform", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+        token::authority = vault_authority,
+    )]
+    pub platform_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_insurance", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+        token::authority = vault_authority,
+    )]
+    pub insurance_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"yield_vault", mint.key().as_ref()],
+        bump = yield_vault.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub yield_vault: Box<Account<'info, YieldVault>>,
+
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Box<Account<'info, ProtocolConfig>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> TriggerPositionExit<'info> {
+    // The keeper picks the accounts, so it can't pick a referrer: triggered exits settle
+    // as if the trade had none and stakers get the no-referral cut
+    pub fn settle_accounts<'a>(&'a mut self, bumps: &TriggerPositionExitBumps) -> ShortSettleAccounts<'a, 'info> {
+        let keeper_fee_bps = self.protocol_config.keeper_fee_bps;
+
+        ShortSettleAccounts {
+            mint: &self.mint,
+            position: &self.position,
+            token_config: &mut self.token_config,
+            vault_config: &mut self.vault_config,
+            yield_vault: &mut self.yield_vault,
+            protocol_config: &self.protocol_config,
+            vault_authority: &self.vault_authority,
+            token_liquidity_vault: &self.token_liquidity_vault,
+            liquidity_vault_authority: &self.liquidity_vault_authority,
+            lending_vault: &self.lending_vault,
+            liquidity_sol_vault: &self.liquidity_sol_vault,
+            wsol_vault_authority: &self.wsol_vault_authority,
+            project_vault: &self.project_vault,
+            platform_vault: &self.platform_vault,
+            insurance_vault: &self.insurance_vault,
+            referral: None,
+            payout: &self.owner_wsol_account,
+            keeper: Some((&self.keeper_wsol_account, keeper_fee_bps)),
+            token_program: &self.token_program,
+            vault_authority_bump: bumps.vault_authority,
+            liquidity_vault_authority_bump: bumps.liquidity_vault_authority,
+            wsol_vault_authority_bump: bumps.wsol_vault_authority,
+        }
+    }
+}
+
+pub fn set_position_triggers(ctx: Context<SetPositionTriggers>, args: SetPositionTriggersArgs) -> Result<()> {
+    let position = &mut ctx.accounts.position;
+
+    require!(args.position_id == position.position_id, ErrorCode::InvalidPositionId);
+    require!(position.open, ErrorCode::PositionAlreadyClosed);
+
+    let current_price = load_curve(&ctx.accounts.vault_config)?.spot_price(ctx.accounts.mint.decimals);
+
+    msg!("current_price: {}", current_price);
+
+    // A trigger that is already crossed would just be an immediate close
+    require!(
+        args.stop_loss_price == 0 || args.stop_loss_price > current_price,
+        ErrorCode::InvalidTrigger
+    );
+    require!(
+        args.take_profit_price == 0 || args.take_profit_price < current_price,
+        ErrorCode::InvalidTrigger
+    );
+
+    position.stop_loss_price = args.stop_loss_price;
+    position.take_profit_price = args.take_profit_price;
+
+    emit!(PositionTriggersUpdatedEvent {
+        user: position.owner,
+        mint: position.mint,
+        position_id: position.position_id,
+        stop_loss_price: position.stop_loss_price,
+        take_profit_price: position.take_profit_price,
+        current_price,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+pub fn trigger_position_exit(ctx: Context<TriggerPositionExit>, args: TriggerPositionExitArgs) -> Result<()> {
+    let position = &ctx.accounts.position;
+
+    require!(args.position_id == position.position_id, ErrorCode::InvalidPositionId);
+    require!(position.open, ErrorCode::PositionAlreadyClosed);
+
+    let borrowed_tokens = position.amount;
+    let original_collateral = position.collateral;
+
+    let current_price = load_curve(&ctx.accounts.vault_config)?.spot_price(ctx.accounts.mint.decimals);
+
+    msg!("current_price: {}", current_price);
+    msg!("stop_loss_price: {}", position.stop_loss_price);
+    msg!("take_profit_price: {}", position.take_profit_price);
+
+    let trigger = crossed_trigger(position, current_price).ok_or(ErrorCode::TriggerNotReached)?;
+
+    let settlement = settle_short(
+        ctx.accounts.settle_accounts(&ctx.bumps),
+        borrowed_tokens,
+        original_collateral,
+    )?;
+
+    let position = &mut ctx.accounts.position;
+    position.open = false;
+
+    remove_position(&mut ctx.accounts.user_account, &position.key());
+
+    let vault_config = &ctx.accounts.vault_config;
+
+    emit!(PositionExitTriggeredEvent {
+        user: position.owner,
+        mint: position.mint,
+        keeper: ctx.accounts.keeper.key(),
+        position_id: position.position_id,
+        trigger,
+        trigger_price: current_price,
+        repaid_tokens: borrowed_tokens,
+        collateral_returned: settlement.collateral_returned,
+        keeper_fee: settlement.keeper_fee_sol,
+        total_fees: settlement.total_fees_sol,
+        borrow_interest: settlement.interest_sol,
+        insurance_fee: settlement.insurance_sol,
+        bad_debt: settlement.bad_debt_sol,
+        pnl: i64::try_from(settlement.pnl).map_err(|_| ErrorCode::Overflow)?,
+        exit_price: settlement.value_now,
+        token_reserve: vault_config.token_reserve,
+        sol_reserve: vault_config.sol_reserve,
+        accumulated_c_after: vault_config.accumulated_c,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct PositionTriggersUpdatedEvent {
+    pub user: Pubkey,
+    pub mint: Pubkey,
+    pub position_id: u64,
+    pub stop_loss_price: u64,
+    pub take_profit_price: u64,
+    pub current_price: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PositionExitTriggeredEvent {
+    pub user: Pubkey,
+    pub mint: Pubkey,
+    pub keeper: Pubkey,
+    pub position_id: u64,
+    pub trigger: u8, // TRIGGER_STOP_LOSS or TRIGGER_TAKE_PROFIT
+    pub trigger_price: u64,
+    pub repaid_tokens: u64,
+    pub collateral_returned: u64,
+    pub keeper_fee: u64,
+    pub total_fees: u64,
+    pub borrow_interest: u64,
+    pub insurance_fee: u64,
+    pub bad_debt: u64,
+    pub pnl: i64,
+    pub exit_price: u64,
+    pub token_reserve: u64,
+    pub sol_reserve: u64,
+    pub accumulated_c_after: i64,
+    pub timestamp: i64,
+}
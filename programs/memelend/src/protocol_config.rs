@@ -0,0 +1,250 @@
+use anchor_lang::prelude::*;
+use crate::{ProtocolConfig, ErrorCode};
+use crate::program::Memelend;
+
+// Launch defaults, these can be tuned later with update_protocol_config
+pub const DEFAULT_PLATFORM_FEE_BPS: u16 = 100; // 1%
+pub const DEFAULT_REFERRAL_CUT_BPS: u16 = 40; // 0.4%
+pub const DEFAULT_YIELD_CUT_BPS: u16 = 10; // 0.1%
+pub const DEFAULT_YIELD_CUT_NO_REFERRAL_BPS: u16 = 20; // 0.2%
+pub const DEFAULT_LIQUIDATOR_FEE_BPS: u16 = 100; // 1%
+pub const DEFAULT_LIQUIDATION_YIELD_CUT_BPS: u16 = 50; // 0.5%
+pub const DEFAULT_PRESALE_AUTOMATION_FEE_BPS: u16 = 100; // 1%
+pub const DEFAULT_MAINTENANCE_MARGIN_BPS: u16 = 2_000; // 20%
+pub const DEFAULT_MAX_BUY_BPS: u16 = 2_000; // 20% of the token reserve
+pub const DEFAULT_VIRTUAL_SOL: u64 = 300_000_000_000; // = 300 SOL in lamports
+pub const DEFAULT_VIRTUAL_TOKENS: u64 = 73_000_000 * 1_000_000; // 73 million tokens (6 decimals)
+pub const DEFAULT_LIQUIDATION_CLOSE_FACTOR_BPS: u16 = 5_000; // at most half a position per liquidation
+pub const DEFAULT_LIQUIDATION_BONUS_BPS: u16 = 500; // 5% of the repaid value
+pub const DEFAULT_AUCTION_DURATION_SLOTS: u64 = 0; // auctions off, flat liquidator fee
+pub const DEFAULT_AUCTION_START_BONUS_BPS: u16 = 0;
+pub const DEFAULT_AUCTION_MAX_BONUS_BPS: u16 = 1_000; // 10%
+pub const DEFAULT_INSURANCE_FEE_BPS: u16 = 2_000; // 20% of the platform fee
+pub const DEFAULT_INSURANCE_LIQUIDATION_BPS: u16 = 1_000; // 10% of forfeited collateral
+pub const DEFAULT_KEEPER_FEE_BPS: u16 = 50; // 0.5% of the collateral returned on a triggered exit
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct UpdateProtocolConfigArgs {
+    pub new_admin: Option<Pubkey>,
+    pub treasury: Option<Pubkey>,
+    pub platform_fee_bps: Option<u16>,
+    pub referral_cut_bps: Option<u16>,
+    pub yield_cut_bps: Option<u16>,
+    pub yield_cut_no_referral_bps: Option<u16>,
+    pub liquidator_fee_bps: Option<u16>,
+    pub liquidation_yield_cut_bps: Option<u16>,
+    pub presale_automation_fee_bps: Option<u16>,
+    pub maintenance_margin_bps: Option<u16>,
+    pub max_buy_bps: Option<u16>,
+    pub virtual_sol: Option<u64>,
+    pub virtual_tokens: Option<u64>,
+    pub liquidation_close_factor_bps: Option<u16>,
+    pub liquidation_bonus_bps: Option<u16>,
+    pub auction_duration_slots: Option<u64>,
+    pub auction_start_bonus_bps: Option<u16>,
+    pub auction_max_bonus_bps: Option<u16>,
+    pub insurance_fee_bps: Option<u16>,
+    pub insurance_liquidation_bps: Option<u16>,
+    pub keeper_fee_bps: Option<u16>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeProtocol<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        init,
+        payer = admin,
+        seeds = [b"protocol_config"],
+        bump,
+        space = 8 + ProtocolConfig::LEN,
+    )]
+    pub protocol_config: Box<Account<'info, ProtocolConfig>>,
+
+    // Only the program's upgrade authority can create the singleton
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()) @ ErrorCode::Unauthorized)]
+    pub program: Program<'info, Memelend>,
+
+    #[account(constraint = program_data.upgrade_authority_address == Some(admin.key()) @ ErrorCode::Unauthorized)]
+    pub program_data: Account<'info, ProgramData>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateProtocolConfig<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump,
+        has_one = admin @ ErrorCode::Unauthorized,
+    )]
+    pub protocol_config: Box<Account<'info, ProtocolConfig>>,
+}
+
+pub fn initialize_protocol(ctx: Context<InitializeProtocol>) -> Result<()> {
+    let config = &mut ctx.accounts.protocol_config;
+
+    config.admin = ctx.accounts.admin.key();
+    config.treasury = Pubkey::default(); // must be set before fees can be withdrawn
+    config.platform_fee_bps = DEFAULT_PLATFORM_FEE_BPS;
+    config.referral_cut_bps = DEFAULT_REFERRAL_CUT_BPS;
+    config.yield_cut_bps = DEFAULT_YIELD_CUT_BPS;
+    config.yield_cut_no_referral_bps = DEFAULT_YIELD_CUT_NO_REFERRAL_BPS;
+    config.liquidator_fee_bps = DEFAULT_LIQUIDATOR_FEE_BPS;
+    config.liquidation_yield_cut_bps = DEFAULT_LIQUIDATION_YIELD_CUT_BPS;
+    config.presale_automation_fee_bps = DEFAULT_PRESALE_AUTOMATION_FEE_BPS;
+    config.maintenance_margin_bps = DEFAULT_MAINTENANCE_MARGIN_BPS;
+    config.max_buy_bps = DEFAULT_MAX_BUY_BPS;
+    config.virtual_sol = DEFAULT_VIRTUAL_SOL;
+    config.virtual_tokens = DEFAULT_VIRTUAL_TOKENS;
+    config.liquidation_close_factor_bps = DEFAULT_LIQUIDATION_CLOSE_FACTOR_BPS;
+    config.liquidation_bonus_bps = DEFAULT_LIQUIDATION_BONUS_BPS;
+    config.auction_duration_slots = DEFAULT_AUCTION_DURATION_SLOTS;
+    config.auction_start_bonus_bps = DEFAULT_AUCTION_START_BONUS_BPS;
+    config.auction_max_bonus_bps = DEFAULT_AUCTION_MAX_BONUS_BPS;
+    config.insurance_fee_bps = DEFAULT_INSURANCE_FEE_BPS;
+    config.insurance_liquidation_bps = DEFAULT_INSURANCE_LIQUIDATION_BPS;
+    config.keeper_fee_bps = DEFAULT_KEEPER_FEE_BPS;
+    config.bump = ctx.bumps.protocol_config;
+
+    config.validate()?;
+
+    emit!(ProtocolConfigUpdatedEvent::from_config(config, Clock::get()?.unix_timestamp));
+
+    Ok(())
+}
+
+pub fn update_protocol_config(ctx: Context<UpdateProtocolConfig>, args: UpdateProtocolConfigArgs) -> Result<()> {
+    let config = &mut ctx.accounts.protocol_config;
+
+    if let Some(new_admin) = args.new_admin {
+        require!(new_admin != Pubkey::default(), ErrorCode::InvalidInput);
+        config.admin = new_admin;
+    }
+    if let Some(treasury) = args.treasury {
+        config.treasury = treasury;
+    }
+    if let Some(v) = args.platform_fee_bps {
+        config.platform_fee_bps = v;
+    }
+    if let Some(v) = args.referral_cut_bps {
+        config.referral_cut_bps = v;
+    }
+    if let Some(v) = args.yield_cut_bps {
+        config.yield_cut_bps = v;
+    }
+    if let Some(v) = args.yield_cut_no_referral_bps {
+        config.yield_cut_no_referral_bps = v;
+    }
+    if let Some(v) = args.liquidator_fee_bps {
+        config.liquidator_fee_bps = v;
+    }
+    if let Some(v) = args.liquidation_yield_cut_bps {
+        config.liquidation_yield_cut_bps = v;
+    }
+    if let Some(v) = args.presale_automation_fee_bps {
+        config.presale_automation_fee_bps = v;
+    }
+    if let Some(v) = args.maintenance_margin_bps {
+        config.maintenance_margin_bps = v;
+    }
+    if let Some(v) = args.max_buy_bps {
+        config.max_buy_bps = v;
+    }
+    if let Some(v) = args.virtual_sol {
+        config.virtual_sol = v;
+    }
+    if let Some(v) = args.virtual_tokens {
+        config.virtual_tokens = v;
+    }
+    if let Some(v) = args.liquidation_close_factor_bps {
+        config.liquidation_close_factor_bps = v;
+    }
+    if let Some(v) = args.liquidation_bonus_bps {
+        config.liquidation_bonus_bps = v;
+    }
+    if let Some(v) = args.auction_duration_slots {
+        config.auction_duration_slots = v;
+    }
+    if let Some(v) = args.auction_start_bonus_bps {
+        config.auction_start_bonus_bps = v;
+    }
+    if let Some(v) = args.auction_max_bonus_bps {
+        config.auction_max_bonus_bps = v;
+    }
+    if let Some(v) = args.insurance_fee_bps {
+        config.insurance_fee_bps = v;
+    }
+    if let Some(v) = args.insurance_liquidation_bps {
+        config.insurance_liquidation_bps = v;
+    }
+    if let Some(v) = args.keeper_fee_bps {
+        config.keeper_fee_bps = v;
+    }
+
+    // Reject the whole update if any value lands out of bounds
+    config.validate()?;
+
+    emit!(ProtocolConfigUpdatedEvent::from_config(config, Clock::get()?.unix_timestamp));
+
+    Ok(())
+}
+
+#[event]
+pub struct ProtocolConfigUpdatedEvent {
+    pub admin: Pubkey,
+    pub treasury: Pubkey,
+    pub platform_fee_bps: u16,
+    pub referral_cut_bps: u16,
+    pub yield_cut_bps: u16,
+    pub yield_cut_no_referral_bps: u16,
+    pub liquidator_fee_bps: u16,
+    pub liquidation_yield_cut_bps: u16,
+    pub presale_automation_fee_bps: u16,
+    pub maintenance_margin_bps: u16,
+    pub max_buy_bps: u16,
+    pub virtual_sol: u64,
+    pub virtual_tokens: u64,
+    pub liquidation_close_factor_bps: u16,
+    pub liquidation_bonus_bps: u16,
+    pub auction_duration_slots: u64,
+    pub auction_start_bonus_bps: u16,
+    pub auction_max_bonus_bps: u16,
+    pub insurance_fee_bps: u16,
+    pub insurance_liquidation_bps: u16,
+    pub keeper_fee_bps: u16,
+    pub timestamp: i64,
+}
+
+impl ProtocolConfigUpdatedEvent {
+    fn from_config(config: &ProtocolConfig, timestamp: i64) -> Self {
+        Self {
+            admin: config.admin,
+            treasury: config.treasury,
+            platform_fee_bps: config.platform_fee_bps,
+            referral_cut_bps: config.referral_cut_bps,
+            yield_cut_bps: config.yield_cut_bps,
+            yield_cut_no_referral_bps: config.yield_cut_no_referral_bps,
+            liquidator_fee_bps: config.liquidator_fee_bps,
+            liquidation_yield_cut_bps: config.liquidation_yield_cut_bps,
+            presale_automation_fee_bps: config.presale_automation_fee_bps,
+            maintenance_margin_bps: config.maintenance_margin_bps,
+            max_buy_bps: config.max_buy_bps,
+            virtual_sol: config.virtual_sol,
+            virtual_tokens: config.virtual_tokens,
+            liquidation_close_factor_bps: config.liquidation_close_factor_bps,
+            liquidation_bonus_bps: config.liquidation_bonus_bps,
+            auction_duration_slots: config.auction_duration_slots,
+            auction_start_bonus_bps: config.auction_start_bonus_bps,
+            auction_max_bonus_bps: config.auction_max_bonus_bps,
+            insurance_fee_bps: config.insurance_fee_bps,
+            insurance_liquidation_bps: config.insurance_liquidation_bps,
+            keeper_fee_bps: config.keeper_fee_bps,
+            timestamp,
+        }
+    }
+}
@@ -0,0 +1,349 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, CloseAccount, TokenAccount, Token, Mint};
+use crate::{TokenConfig, VaultConfig, ProtocolConfig, Position, ErrorCode, ReferralVault, YieldVault, get_liquidation_price};
+use crate::close_position::{ShortSettleAccounts, settle_short};
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct ReducePositionArgs {
+    pub position_id: u64,
+    pub min_token_repay: u64,
+    pub token_amount: u64, // tokens to repay, or 0 to use reduce_bps
+    pub reduce_bps: u16,   // share of the position to repay, or 0 to use token_amount
+}
+
+#[derive(Accounts)]
+#[instruction(args: ReducePositionArgs)]
+pub struct ReducePosition<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
+    pub wsol_mint: Account<'info, Mint>,
+
+    #[account(address = token_config.mint @ ErrorCode::InvalidMint)]
+    pub mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint = temp_wsol_account.mint == anchor_spl::token::spl_token::native_mint::ID @ ErrorCode::InvalidWsolMint,
+    )]
+    pub temp_wsol_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: signer PDA
+    #[account(address = user.key())]
+    pub temp_wsol_authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"position", user.key().as_ref(), mint.key().as_ref(), &args.position_id.to_le_bytes()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+        constraint = position.owner == user.key() @ ErrorCode::Unauthorized,
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        mut,
+        seeds = [b"config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub token_config: Box<Account<'info, TokenConfig>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub vault_config: Account<'info, VaultConfig>,
+
+    /// CHECK: PDA authority for vaults
+    #[account(
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+    )]
+    pub vault_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_liquidity", mint.key().as_ref()],
+        bump,
+        constraint = token_liquidity_vault.mint == token_config.mint @ ErrorCode::InvalidMint,
+    )]
+    pub token_liquidity_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_lending", mint.key().as_ref()],
+        bump,
+    )]
+    pub lending_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_wsol", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+    )]
+    pub liquidity_sol_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_project", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+        token::authority = vault_authority,
+    )]
+    pub project_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_platform", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+        token::authority = vault_authority,
+    )]
+    pub platform_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_insurance", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+        token::authority = vault_authority,
+    )]
+    pub insurance_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: PDA authority for referral
+    #[account(
+        seeds = [b"vault_referral_authority", referrer.key().as_ref()],
+        bump,
+    )]
+    pub referral_vault_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_referral", referrer.key().as_ref()],
+        bump,
+    )]
+    pub referral_tracking: Box<Account<'info, ReferralVault>>,
+
+    #[account(
+        mut,
+        token::mint = wsol_mint,
+        token::authority = referral_vault_authority,
+        seeds = [b"vault_referral_token", referrer.key().as_ref()],
+        bump,
+    )]
+    pub referral_token_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: PDA
+    #[account(
+        seeds = [b"vault_liquidity_authority", mint.key().as_ref()],
+        bump,
+    )]
+    pub liquidity_vault_authority: UncheckedAccount<'info>,
+
+    /// CHECK: PDA for wSOL vault authority
+    #[account(
+        seeds = [b"vault_wsol_authority", mint.key().as_ref()],
+        bump,
+    )]
+    pub wsol_vault_authority: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"yield_vault", mint.key().as_ref()],
+        bump = yield_vault.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub yield_vault: Account<'info, YieldVault>,
+
+    /// CHECK: Only used for seeds/validation
+    pub referrer: UncheckedAccount<'info>,
+
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Box<Account<'info, ProtocolConfig>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+impl<'info> ReducePosition<'info> {
+    pub fn settle_accounts<'a>(&'a mut self, bumps: &ReducePositionBumps) -> ShortSettleAccounts<'a, 'info> {
+        let is_referral = self.referrer.key() != self.platform_vault.key();
+
+        ShortSettleAccounts {
+            mint: &self.mint,
+            position: &self.position,
+            token_config: &mut self.token_config,
+            vault_config: &mut self.vault_config,
+            yield_vault: &mut self.yield_vault,
+            protocol_config: &self.protocol_config,
+            vault_authority: &self.vault_authority,
+            token_liquidity_vault: &self.token_liquidity_vault,
+            liquidity_vault_authority: &self.liquidity_vault_authority,
+            lending_vault: &self.lending_vault,
+            liquidity_sol_vault: &self.liquidity_sol_vault,
+            wsol_vault_authority: &self.wsol_vault_authority,
+            project_vault: &self.project_vault,
+            platform_vault: &self.platform_vault,
+            insurance_vault: &self.insurance_vault,
+            referral: if is_referral {
+                Some((&mut self.referral_tracking, &self.referral_token_vault))
+            } else {
+                None
+            },
+            payout: &self.temp_wsol_account,
+            keeper: None,
+            token_program: &self.token_program,
+            vault_authority_bump: bumps.vault_authority,
+            liquidity_vault_authority_bump: bumps.liquidity_vault_authority,
+            wsol_vault_authority_bump: bumps.wsol_vault_authority,
+        }
+    }
+}
+
+pub fn reduce_position(ctx: Context<ReducePosition>, args: ReducePositionArgs) -> Result<()> {
+    let position = &ctx.accounts.position;
+
+    // Check to see if its the correct position ID
+    require!(
+        args.position_id == position.position_id,
+        ErrorCode::InvalidPositionId
+    );
+    require!(position.open, ErrorCode::PositionAlreadyClosed);
+
+    let borrowed_tokens = position.amount;
+    let collateral = position.collateral;
+
+    // Exactly one of token_amount / reduce_bps is used
+    let repay_tokens = if args.reduce_bps > 0 {
+        require!(args.token_amount == 0, ErrorCode::InvalidInput);
+        require!(args.reduce_bps < 10_000, ErrorCode::InvalidInput);
+
+        ((borrowed_tokens as u128)
+            .checked_mul(args.reduce_bps as u128)
+            .ok_or(ErrorCode::Overflow)? / 10_000) as u64
+    } else {
+        args.token_amount
+    };
+
+    msg!("repay_tokens: {}", repay_tokens);
+
+    require!(repay_tokens > 0, ErrorCode::ZeroInput);
+    // Repaying everything is a full close, use close_position for that
+    require!(repay_tokens < borrowed_tokens, ErrorCode::InvalidInput);
+
+    // Slippage check
+    require!(
+        repay_tokens >= args.min_token_repay,
+        ErrorCode::SlippageExceeded
+    );
+
+    // Collateral backing the repaid share of the position
+    let collateral_share = u64::try_from(
+        (collateral as u128)
+            .checked_mul(repay_tokens as u128)
+            .ok_or(ErrorCode::Overflow)?
+            .checked_div(borrowed_tokens as u128)
+            .ok_or(ErrorCode::DivisionByZero)?,
+    )
+    .map_err(|_| ErrorCode::Overflow)?;
+
+    msg!("collateral_share: {}", collateral_share);
+
+    let settlement = settle_short(
+        ctx.accounts.settle_accounts(&ctx.bumps),
+        repay_tokens,
+        collateral_share,
+    )?;
+
+    // Shrink the position and move its liquidation threshold to match
+    let maintenance_margin_bps = ctx.accounts.protocol_config.maintenance_margin_bps as u64;
+    let decimals = ctx.accounts.mint.decimals;
+    let position = &mut ctx.accounts.position;
+
+    position.amount = borrowed_tokens
+        .checked_sub(repay_tokens)
+        .ok_or(ErrorCode::Underflow)?;
+
+    position.collateral = collateral
+        .checked_sub(collateral_share)
+        .ok_or(ErrorCode::Underflow)?;
+
+    position.liquidate = get_liquidation_price(
+        position.entry_price,
+        position.amount,
+        position.collateral,
+        maintenance_margin_bps,
+        decimals,
+    )?;
+
+    msg!("remaining amount: {}", position.amount);
+    msg!("remaining collateral: {}", position.collateral);
+    msg!("liquidation_price: {}", position.liquidate);
+
+    token::close_account(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.temp_wsol_account.to_account_info(),
+                destination: ctx.accounts.user.to_account_info(),
+                authority: ctx.accounts.temp_wsol_authority.to_account_info(),
+            },
+        )
+    )?;
+
+    let vault_config = &ctx.accounts.vault_config;
+    let position = &ctx.accounts.position;
+
+    emit!(ReducePositionEvent {
+        user: ctx.accounts.user.key(),
+        mint: ctx.accounts.mint.key(),
+        position_id: args.position_id,
+        repaid_tokens: repay_tokens,
+        collateral_returned: settlement.collateral_returned,
+        total_fees: settlement.total_fees_sol,
+        referral_share_sol: settlement.referral_share_sol,
+        borrow_interest: settlement.interest_sol,
+        insurance_fee: settlement.insurance_sol,
+        bad_debt: settlement.bad_debt_sol,
+        pnl: i64::try_from(settlement.pnl).map_err(|_| ErrorCode::Overflow)?,
+        exit_price: settlement.value_now,
+        remaining_amount: position.amount,
+        remaining_collateral: position.collateral,
+        liquidation_price: position.liquidate,
+        accumulated_c_after: vault_config.accumulated_c,
+        token_reserve: vault_config.token_reserve,
+        sol_reserve: vault_config.sol_reserve,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct ReducePositionEvent {
+    pub user: Pubkey,
+    pub mint: Pubkey,
+    pub position_id: u64,
+    pub repaid_tokens: u64,
+    pub collateral_returned: u64,
+    pub total_fees: u64,
+    pub referral_share_sol: u64,
+    pub borrow_interest: u64,
+    pub insurance_fee: u64,
+    pub bad_debt: u64,
+    pub pnl: i64,
+    pub exit_price: u64,
+    pub remaining_amount: u64,
+    pub remaining_collateral: u64,
+    pub liquidation_price: u64,
+    pub accumulated_c_after: i64,
+    pub token_reserve: u64,
+    pub sol_reserve: u64,
+    pub timestamp: i64,
+}
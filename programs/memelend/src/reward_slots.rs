@@ -0,0 +1,331 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Transfer, Token, TokenAccount, Mint};
+use crate::{YieldVault, UserYieldPosition, ErrorCode, MAX_REWARD_SLOTS};
+
+pub const PRECISION: u128 = 1_000_000_000_000; // 1e12 for reward per share math
+
+// Extra incentive campaigns paid in any SPL token. Each slot streams `rate_per_second`
+// until `end_ts`, shared by lock-boosted weight like the WSOL rewards. A slot is bound to
+// its reward mint for good once opened.
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct FundRewardSlotArgs {
+    pub slot: u8,
+    pub amount: u64,
+    pub duration_seconds: i64, // creator only: leftover plus `amount` stream over this from now,
+                               // 0 tops up a live slot and keeps its end
+}
+
+#[derive(Accounts)]
+pub struct FundRewardSlot<'info> {
+    #[account(mut)]
+    pub funder: Signer<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"yield_vault", mint.key().as_ref()],
+        bump = yield_vault.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub yield_vault: Box<Account<'info, YieldVault>>,
+
+    pub reward_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = funder_token_account.mint == reward_mint.key() @ ErrorCode::InvalidMint,
+        constraint = funder_token_account.owner == funder.key() @ ErrorCode::Unauthorized,
+    )]
+    pub funder_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = funder,
+        seeds = [b"vault_reward", mint.key().as_ref(), reward_mint.key().as_ref()],
+        bump,
+        token::mint = reward_mint,
+        token::authority = vault_authority,
+    )]
+    pub reward_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: PDA authority for vaults
+    #[account(
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimSlotRewards<'info> {
+    pub user: Signer<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"yield_vault", mint.key().as_ref()],
+        bump = yield_vault.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub yield_vault: Box<Account<'info, YieldVault>>,
+
+    #[account(
+        mut,
+        seeds = [b"user_yield", mint.key().as_ref(), user.key().as_ref()],
+        bump = user_yield_position.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+        constraint = user_yield_position.owner == user.key() @ ErrorCode::Unauthorized,
+    )]
+    pub user_yield_position: Box<Account<'info, UserYieldPosition>>,
+
+    /// CHECK: PDA authority for vaults
+    #[account(
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    // remaining_accounts: [reward_vault, user_token_account] per open slot, in slot order
+}
+
+// Streams every open slot up to `now` (or its end) into its per-share index. Time with
+// nothing staked is not paid out to anyone. Call before total_weighted_stake changes.
+pub fn accrue_reward_slots(vault: &mut YieldVault, now: i64) -> Result<()> {
+    let total_weighted = vault.total_weighted_stake;
+
+    for slot in vault.reward_slots.iter_mut().filter(|s| s.mint != Pubkey::default()) {
+        let until = now.min(slot.end_ts);
+        if until <= slot.last_update_ts {
+            continue;
+        }
+
+        if total_weighted > 0 {
+            let reward = (slot.rate_per_second as u128)
+                .checked_mul((until - slot.last_update_ts) as u128)
+                .ok_or(ErrorCode::Overflow)?;
+            slot.acc_per_share = slot
+                .acc_per_share
+                .checked_add(reward * PRECISION / total_weighted as u128)
+                .ok_or(ErrorCode::Overflow)?;
+            slot.total_distributed = slot
+                .total_distributed
+                .checked_add(u64::try_from(reward).map_err(|_| ErrorCode::Overflow)?)
+                .ok_or(ErrorCode::Overflow)?;
+        }
+        slot.last_update_ts = until;
+    }
+
+    Ok(())
+}
+
+// Books the position's accrued slot rewards into slot_owed. Call before `weighted_stake`
+// changes and follow up with reset_reward_slot_debts once it has.
+pub fn settle_reward_slots(vault: &YieldVault, position: &mut UserYieldPosition) -> Result<()> {
+    for i in 0..MAX_REWARD_SLOTS {
+        let accrued = (position.weighted_stake as u128)
+            .checked_mul(vault.reward_slots[i].acc_per_share)
+            .ok_or(ErrorCode::Overflow)?
+            / PRECISION;
+        let pending = accrued.saturating_sub(position.slot_debts[i]);
+
+        position.slot_owed[i] = position.slot_owed[i]
+            .checked_add(u64::try_from(pending).map_err(|_| ErrorCode::Overflow)?)
+            .ok_or(ErrorCode::Overflow)?;
+        position.slot_debts[i] = accrued;
+    }
+    Ok(())
+}
+
+pub fn reset_reward_slot_debts(vault: &YieldVault, position: &mut UserYieldPosition) -> Result<()> {
+    for i in 0..MAX_REWARD_SLOTS {
+        position.slot_debts[i] = (position.weighted_stake as u128)
+            .checked_mul(vault.reward_slots[i].acc_per_share)
+            .ok_or(ErrorCode::Overflow)?
+            / PRECISION;
+    }
+    Ok(())
+}
+
+// Opens or reschedules a slot (creator only) or tops up a live one (anyone). Whatever the slot
+// still had to stream is rolled into the new period together with `amount`. A top-up keeps
+// end_ts and only raises the rate, so outsiders can't stretch a campaign.
+pub fn fund_reward_slot(ctx: Context<FundRewardSlot>, args: FundRewardSlotArgs) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let vault = &mut ctx.accounts.yield_vault;
+    let reward_mint = ctx.accounts.reward_mint.key();
+
+    require!(args.amount > 0, ErrorCode::ZeroInput);
+    require!(args.duration_seconds >= 0, ErrorCode::InvalidInput);
+    require!((args.slot as usize) < MAX_REWARD_SLOTS, ErrorCode::InvalidRewardSlot);
+    if args.duration_seconds > 0 {
+        require_keys_eq!(ctx.accounts.funder.key(), vault.creator, ErrorCode::Unauthorized);
+    }
+
+    accrue_reward_slots(vault, now)?;
+
+    let slot_index = args.slot as usize;
+    if vault.reward_slots[slot_index].mint == Pubkey::default() {
+        require_keys_eq!(ctx.accounts.funder.key(), vault.creator, ErrorCode::Unauthorized);
+        // A reward mint gets one slot so its vault isn't shared
+        require!(
+            !vault.reward_slots.iter().any(|s| s.mint == reward_mint),
+            ErrorCode::InvalidRewardSlot
+        );
+        vault.reward_slots[slot_index].mint = reward_mint;
+        vault.reward_slots[slot_index].vault = ctx.accounts.reward_vault.key();
+    } else {
+        require_keys_eq!(vault.reward_slots[slot_index].mint, reward_mint, ErrorCode::InvalidRewardSlot);
+    }
+
+    let slot = &mut vault.reward_slots[slot_index];
+    let leftover = if now < slot.end_ts {
+        (slot.rate_per_second as u128) * (slot.end_ts - now) as u128
+    } else {
+        0
+    };
+    let end_ts = if args.duration_seconds == 0 {
+        require!(now < slot.end_ts, ErrorCode::InvalidRewardSlot);
+        slot.end_ts
+    } else {
+        now.checked_add(args.duration_seconds).ok_or(ErrorCode::Overflow)?
+    };
+    let total = leftover
+        .checked_add(args.amount as u128)
+        .ok_or(ErrorCode::Overflow)?;
+    let rate = u64::try_from(total / (end_ts - now) as u128).map_err(|_| ErrorCode::Overflow)?;
+    require!(rate > 0, ErrorCode::InvalidInput);
+
+    slot.rate_per_second = rate;
+    slot.last_update_ts = now;
+    slot.end_ts = end_ts;
+    slot.total_funded = slot.total_funded
+        .checked_add(args.amount)
+        .ok_or(ErrorCode::Overflow)?;
+
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.funder_token_account.to_account_info(),
+                to: ctx.accounts.reward_vault.to_account_info(),
+                authority: ctx.accounts.funder.to_account_info(),
+            },
+        ),
+        args.amount,
+    )?;
+
+    let slot = ctx.accounts.yield_vault.reward_slots[slot_index];
+    emit!(RewardSlotFundedEvent {
+        mint: ctx.accounts.mint.key(),
+        funder: ctx.accounts.funder.key(),
+        slot: args.slot,
+        reward_mint,
+        amount: args.amount,
+        rate_per_second: slot.rate_per_second,
+        end_ts: slot.end_ts,
+        total_funded: slot.total_funded,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+// Pays out everything the position is owed across all slots
+pub fn claim_slot_rewards<'info>(ctx: Context<'_, '_, 'info, 'info, ClaimSlotRewards<'info>>) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let mint_key = ctx.accounts.mint.key();
+    let vault = &mut ctx.accounts.yield_vault;
+    let user_position = &mut ctx.accounts.user_yield_position;
+
+    accrue_reward_slots(vault, now)?;
+    settle_reward_slots(vault, user_position)?;
+
+    let open_slots: Vec<usize> = (0..MAX_REWARD_SLOTS)
+        .filter(|&i| vault.reward_slots[i].mint != Pubkey::default())
+        .collect();
+
+    let remaining = ctx.remaining_accounts;
+    require!(remaining.len() == open_slots.len() * 2, ErrorCode::InvalidInput);
+    require!(
+        open_slots.iter().any(|&i| user_position.slot_owed[i] > 0),
+        ErrorCode::NoRewardsAvailable
+    );
+
+    let signer_seeds: &[&[u8]] = &[b"vault", mint_key.as_ref(), &[ctx.bumps.vault_authority]];
+
+    for (accounts, &i) in remaining.chunks(2).zip(open_slots.iter()) {
+        let owed = user_position.slot_owed[i];
+        if owed == 0 {
+            continue;
+        }
+
+        let slot = &vault.reward_slots[i];
+        let reward_vault_info = &accounts[0];
+        let user_token_info = &accounts[1];
+        require_keys_eq!(reward_vault_info.key(), slot.vault, ErrorCode::InvalidVault);
+
+        // Owner + discriminator checked by Account::try_from
+        let user_token: Account<'info, TokenAccount> = Account::try_from(user_token_info)?;
+        require_keys_eq!(user_token.mint, slot.mint, ErrorCode::InvalidMint);
+        require_keys_eq!(user_token.owner, ctx.accounts.user.key(), ErrorCode::Unauthorized);
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: reward_vault_info.clone(),
+                    to: user_token_info.clone(),
+                    authority: ctx.accounts.vault_authority.to_account_info(),
+                },
+                &[signer_seeds],
+            ),
+            owed,
+        )?;
+
+        user_position.slot_owed[i] = 0;
+
+        emit!(SlotRewardsClaimedEvent {
+            owner: user_position.owner,
+            mint: mint_key,
+            slot: i as u8,
+            reward_mint: slot.mint,
+            amount: owed,
+            timestamp: now,
+        });
+    }
+
+    user_position.last_action_ts = now;
+
+    Ok(())
+}
+
+#[event]
+pub struct RewardSlotFundedEvent {
+    pub mint: Pubkey,
+    pub funder: Pubkey,
+    pub slot: u8,
+    pub reward_mint: Pubkey,
+    pub amount: u64,
+    pub rate_per_second: u64,
+    pub end_ts: i64,
+    pub total_funded: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SlotRewardsClaimedEvent {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub slot: u8,
+    pub reward_mint: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
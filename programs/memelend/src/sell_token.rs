@@ -1,8 +1,9 @@
 use anchor_lang::prelude::*;
 use anchor_spl::token::{self, Token, TokenAccount, Mint, Transfer, CloseAccount};
 use anchor_spl::token::spl_token::native_mint::ID as NATIVE_MINT_ID;
-use crate::{VaultConfig, TokenConfig, ReferralVault, ErrorCode, YieldVault};
-use crate::get_price_from_vault;
+use crate::{VaultConfig, TokenConfig, ProtocolConfig, ReferralVault, ErrorCode, YieldVault, UserTradeState, get_gross_for_net};
+use crate::curve::load_curve;
+use crate::buy_token::check_trade_cooldown;
 
 pub const PRECISION: u128 = 1_000_000_000_000; // 1e12 for reward per share math
 
@@ -13,6 +14,13 @@ pub struct SellTokenArgs {
     pub position_id: u64,
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct SellTokenExactOutArgs {
+    pub sol_out: u64,
+    pub max_tokens_in: u64,
+    pub position_id: u64,
+}
+
 #[derive(Accounts)]
 pub struct SellToken<'info> {
     #[account(mut)]
@@ -45,32 +53,52 @@ pub struct SellToken<'info> {
 
     #[account(
         mut,
+        seeds = [b"vault_liquidity", mint.key().as_ref()],
+        bump,
         constraint = liquidity_token_vault.mint == mint.key() @ ErrorCode::InvalidMint,
     )]
     pub liquidity_token_vault: Box<Account<'info, TokenAccount>>,
 
     #[account(
         mut,
+        seeds = [b"vault_wsol", mint.key().as_ref()],
+        bump,
         token::mint = wsol_mint,
         constraint = liquidity_sol_vault.key() == token_config.wsol_liquidity_vault @ ErrorCode::InvalidVault
     )]
     pub liquidity_sol_vault: Box<Account<'info, TokenAccount>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"vault_config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub vault_config: Box<Account<'info, VaultConfig>>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub token_config: Box<Account<'info, TokenConfig>>,
 
     #[account(
         mut,
+        seeds = [b"vault_project", mint.key().as_ref()],
+        bump,
         token::mint = wsol_mint,
+        token::authority = vault_authority,
     )]
     pub project_vault: Box<Account<'info, TokenAccount>>,
 
     #[account(
         mut,
+        seeds = [b"vault_platform", mint.key().as_ref()],
+        bump,
         token::mint = wsol_mint,
+        token::authority = vault_authority,
     )]
     pub platform_vault: Box<Account<'info, TokenAccount>>,
 
@@ -103,7 +131,7 @@ pub struct SellToken<'info> {
     /// CHECK: This is a PDA derived from [b"vault", mint] and only used as a signer in token transfer CPI. No data access occurs.
     #[account(
         mut,
-        seeds = [b"vault", vault_config.mint.as_ref()],
+        seeds = [b"vault", mint.key().as_ref()],
         bump,
     )]
     pub vault_authority: UncheckedAccount<'info>,
@@ -118,15 +146,66 @@ pub struct SellToken<'info> {
     )]
     pub wsol_vault_authority: UncheckedAccount<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [b"yield_vault", mint.key().as_ref()],
+        bump = yield_vault.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
     pub yield_vault: Box<Account<'info, YieldVault>>,
 
+    #[account(
+        init_if_needed,
+        payer = user,
+        seeds = [b"user_trade", mint.key().as_ref(), user.key().as_ref()],
+        bump,
+        space = 8 + UserTradeState::LEN,
+    )]
+    pub user_trade_state: Box<Account<'info, UserTradeState>>,
+
+    #[account(seeds = [b"protocol_config"], bump = protocol_config.bump)]
+    pub protocol_config: Box<Account<'info, ProtocolConfig>>,
+
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
 }
 
 pub fn sell_token(ctx: Context<SellToken>, args: SellTokenArgs) -> Result<()> {
+    process_sell(ctx, args, None)
+}
+
+// Receive exactly `sol_out` after fees, selling at most `max_tokens_in`
+pub fn sell_token_exact_out(ctx: Context<SellToken>, args: SellTokenExactOutArgs) -> Result<()> {
+    require!(args.sol_out > 0, ErrorCode::ZeroInput);
+
+    // Invert the pipeline: gross SOL that leaves `sol_out` after fees, then tokens for that gross
+    let gross_sol_needed = get_gross_for_net(
+        args.sol_out,
+        ctx.accounts.token_config.creator_fee_bps,
+        ctx.accounts.protocol_config.platform_fee_bps,
+    )?;
+    let token_amount = load_curve(&ctx.accounts.vault_config)?.quote_sell_exact_out(gross_sol_needed)?;
+
+    msg!("gross_sol_needed: {}", gross_sol_needed);
+    msg!("token_amount: {}", token_amount);
+
+    require!(token_amount <= args.max_tokens_in, ErrorCode::SlippageExceeded);
+
+    process_sell(
+        ctx,
+        SellTokenArgs {
+            token_amount,
+            min_sol_out: args.sol_out,
+            position_id: args.position_id,
+        },
+        Some(gross_sol_needed),
+    )
+}
+
+// Shared curve-then-fee pipeline. `gross_sol_cap` pins the payout for exact-out sells,
+// any rounding surplus stays in the pool.
+fn process_sell(ctx: Context<SellToken>, args: SellTokenArgs, gross_sol_cap: Option<u64>) -> Result<()> {
     
     let token_amount = args.token_amount;
     let min_sol_out = args.min_sol_out;
@@ -155,55 +234,20 @@ pub fn sell_token(ctx: Context<SellToken>, args: SellTokenArgs) -> Result<()> {
     );
 
     // === FLASH LOAN PROTECTION: TRADE COOLDOWN CHECK ===
-    let clock = Clock::get()?;
-    let last_trade_timestamp = vault_config.last_trade_timestamp;
-
-    if last_trade_timestamp > 0 {
-        require!(
-            clock.unix_timestamp - last_trade_timestamp >= 1,
-            ErrorCode::TradeCooldown
-        );
-    }
-
-    vault_config.last_trade_timestamp = clock.unix_timestamp;
+    check_trade_cooldown(
+        &mut ctx.accounts.user_trade_state,
+        ctx.bumps.user_trade_state,
+        ctx.accounts.user.key(),
+        ctx.accounts.mint.key(),
+        now,
+    )?;
     // === END FLASH LOAN PROTECTION ===
 
-    // Combine SOL reserves safely (handles negative accumulated_c)
-    let effective_sol_i128 = (vault_config.sol_reserve as i128)
-        .checked_add(vault_config.accumulated_c as i128)
-        .and_then(|s| s.checked_add(vault_config.virtual_sol as i128))
-        .ok_or(ErrorCode::Overflow)?;
-
-    // Ensure result is not negative (since SOL reserves can't be negative)
-    require!(effective_sol_i128 >= 0, ErrorCode::Underflow);
-
-    let effective_sol = effective_sol_i128 as u64;
-
-    // Tokens are always unsigned
-    let effective_tokens = vault_config
-        .token_reserve
-        .checked_add(vault_config.virtual_tokens)
-        .ok_or(ErrorCode::Overflow)?;
-
-    // Constant product invariant
-    let k = (effective_sol as u128)
-        .checked_mul(effective_tokens as u128)
-        .ok_or(ErrorCode::Overflow)?;
-
-    // After tokens are sold back to pool
-    let new_token_reserve = effective_tokens
-        .checked_add(token_amount)
-        .ok_or(ErrorCode::Overflow)?;
-
-    // New SOL reserve (u128 division)
-    let new_sol_reserve = k
-        .checked_div(new_token_reserve as u128)
-        .ok_or(ErrorCode::Overflow)? as u64;
-
-    // Gross SOL returned to user
-    let gross_sol_out = effective_sol
-        .checked_sub(new_sol_reserve)
-        .ok_or(ErrorCode::Underflow)?;
+    // Gross SOL returned to user, priced on this mint's curve
+    let mut gross_sol_out = load_curve(vault_config)?.quote_sell(token_amount)?;
+    if let Some(cap) = gross_sol_cap {
+        gross_sol_out = gross_sol_out.min(cap);
+    }
 
     msg!("gross_sol_out: {}", gross_sol_out);
 
@@ -261,22 +305,28 @@ pub fn sell_token(ctx: Context<SellToken>, args: SellTokenArgs) -> Result<()> {
     msg!("pre_sale_fee_sol: {}", pre_sale_fee_sol);
     msg!("creator_fee_sol: {}", creator_fee_sol);
 
-    let total_platform_fee_bps = token_config.platform_fee_bps as u64; // 100 = 1%
-    let referral_cut_bps = 40; // 0.4%
-    let mut yield_cut_bps = 10; // 0.1%
+    let protocol_config = &ctx.accounts.protocol_config;
+    let total_platform_fee_bps = protocol_config.platform_fee_bps as u64; // default 100 = 1%
+    let referral_cut_bps = protocol_config.referral_cut_bps as u64; // default 0.4%
+    let mut yield_cut_bps = protocol_config.yield_cut_bps as u64; // default 0.1%
 
     let mut platform_share_sol = ((gross_sol_out as u64)
         .checked_mul(total_platform_fee_bps as u64)
         .ok_or(ErrorCode::Overflow)? / 10_000) as u64;
     msg!("platform_share_sol: {}", platform_share_sol);
 
+    // init_if_needed has already created the account, a zero referrer means it is new
+    if ctx.accounts.referral_tracking.referrer == Pubkey::default() {
+        ctx.accounts.referral_tracking.referrer = ctx.accounts.referrer.key();
+    }
+
     // Check to see if the referrer key matches the platform vault key
     let is_referral = ctx.accounts.referrer.key() != ctx.accounts.platform_vault.key();
 
     // If the above statement is true then it will compute the referral fees else false it will skip and set yield share to 0.2% instead of the 0.1% it usually gets.
     let referral_share_sol= if is_referral {
         let referral_fee = ((gross_sol_out as u64)
-            .checked_mul(referral_cut_bps as u64)
+            .checked_mul(referral_cut_bps)
             .ok_or(ErrorCode::Overflow)? / 10_000) as u64;
 
         let platform_fee = platform_share_sol
@@ -296,7 +346,7 @@ pub fn sell_token(ctx: Context<SellToken>, args: SellTokenArgs) -> Result<()> {
         referral_fee
     } else {
         // If no referrer, increase yield share from 0.1% → 0.2%
-        yield_cut_bps = 20; // 0.2%
+        yield_cut_bps = protocol_config.yield_cut_no_referral_bps as u64; // default 0.2%
 
         0
     };
@@ -349,8 +399,9 @@ pub fn sell_token(ctx: Context<SellToken>, args: SellTokenArgs) -> Result<()> {
     );
 
     // Anti-whale check
-    let max_allowed_tokens = vault_config.token_reserve.checked_div(5)
-        .ok_or(ErrorCode::Overflow)?;
+    let max_allowed_tokens = ((vault_config.token_reserve as u128)
+        .checked_mul(protocol_config.max_buy_bps as u128)
+        .ok_or(ErrorCode::Overflow)? / 10_000) as u64;
 
     require!(
         token_amount <= max_allowed_tokens,
@@ -452,14 +503,14 @@ pub fn sell_token(ctx: Context<SellToken>, args: SellTokenArgs) -> Result<()> {
    
 
     // Compute acc_reward_per_share delta
-    if yield_vault.total_staked > 0 {
+    if yield_vault.total_weighted_stake > 0 {
         // new rewards = interest added (WSOL)
         let new_rewards = yield_share_sol as u128;
 
         // Update cumulative reward per share
         yield_vault.acc_reward_per_share = yield_vault
             .acc_reward_per_share
-            .checked_add(new_rewards * PRECISION / yield_vault.total_staked as u128)
+            .checked_add(new_rewards * PRECISION / yield_vault.total_weighted_stake as u128)
             .ok_or(ErrorCode::Overflow)?;
 
         yield_vault.last_accrual_ts = now;
@@ -508,14 +559,7 @@ pub fn sell_token(ctx: Context<SellToken>, args: SellTokenArgs) -> Result<()> {
     msg!("yield vault current balance: {}", vault_config.yield_vault);
 
     // Computes the exit price after everything
-    let exit_price = get_price_from_vault(
-        vault_config.sol_reserve,
-        vault_config.accumulated_c,
-        vault_config.token_reserve,
-        vault_config.virtual_sol,
-        vault_config.virtual_tokens,
-        ctx.accounts.mint.decimals,
-    );
+    let exit_price = load_curve(vault_config)?.spot_price(ctx.accounts.mint.decimals);
     msg!("exit_price: {}", exit_price);
 
     // Close temporary wSOL account
@@ -0,0 +1,469 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Transfer, Token, Mint, TokenAccount, CloseAccount};
+use anchor_spl::token::spl_token::native_mint::ID as NATIVE_MINT_ID;
+use crate::{VaultConfig, SolLendingPool, SolLender, ErrorCode};
+use crate::interest::{grow_index, kinked_rate_bps, rate_model, utilisation_bps, BORROW_INDEX_ONE};
+
+pub const PRECISION: u128 = 1_000_000_000_000; // 1e12 for reward per share math
+
+// Principal lenders can take out right now, everything else is lent to longs or lost to bad debt
+pub fn pool_available(pool: &SolLendingPool) -> u64 {
+    pool.total_deposits
+        .saturating_sub(pool.total_borrowed)
+        .saturating_sub(pool.bad_debt)
+}
+
+// Rolls the pool's borrow index forward to `now` and returns it. Longs pay the mint's kinked
+// rate model on the pool's own utilisation.
+pub fn accrue_pool_index(pool: &mut SolLendingPool, vault_config: &VaultConfig, now: i64) -> Result<u128> {
+    if pool.borrow_index == 0 {
+        pool.borrow_index = BORROW_INDEX_ONE;
+        pool.last_accrual_ts = now;
+        return Ok(pool.borrow_index);
+    }
+
+    let (base, slope, kink, max) = rate_model(vault_config);
+    let utilisation = utilisation_bps(pool.total_borrowed, pool.total_deposits);
+    let rate_bps = kinked_rate_bps(base, slope, kink, max, utilisation);
+
+    pool.borrow_index = grow_index(pool.borrow_index, rate_bps, now.saturating_sub(pool.last_accrual_ts))?;
+    pool.last_accrual_ts = now;
+
+    Ok(pool.borrow_index)
+}
+
+// Shares `amount` lamports out to lenders pro rata
+pub fn distribute_pool_rewards(pool: &mut SolLendingPool, amount: u64) -> Result<()> {
+    if amount == 0 || pool.total_deposits == 0 {
+        return Ok(());
+    }
+
+    pool.acc_reward_per_share = pool
+        .acc_reward_per_share
+        .checked_add(
+            (amount as u128)
+                .checked_mul(PRECISION)
+                .ok_or(ErrorCode::Overflow)?
+                / pool.total_deposits as u128,
+        )
+        .ok_or(ErrorCode::Overflow)?;
+
+    pool.total_rewards = pool
+        .total_rewards
+        .checked_add(amount)
+        .ok_or(ErrorCode::Overflow)?;
+
+    Ok(())
+}
+
+// Spreads principal lost to bad debt over lenders pro rata, like rewards. It stays in bad_debt
+// until each lender's share is taken off their deposit by realize_pool_loss, so nobody can
+// withdraw ahead of the loss and leave it to whoever is last out.
+pub fn write_off_pool_loss(pool: &mut SolLendingPool, amount: u64) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+
+    pool.bad_debt = pool.bad_debt
+        .checked_add(amount)
+        .ok_or(ErrorCode::Overflow)?;
+
+    if pool.total_deposits == 0 {
+        return Ok(());
+    }
+
+    // Rounded up per share, each lender's cut is rounded down in realize_pool_loss and the last
+    // lender out takes the remainder
+    let total_deposits = pool.total_deposits as u128;
+    let loss_per_share = (amount as u128)
+        .checked_mul(PRECISION)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_add(total_deposits - 1)
+        .ok_or(ErrorCode::Overflow)?
+        / total_deposits;
+
+    pool.acc_loss_per_share = pool
+        .acc_loss_per_share
+        .checked_add(loss_per_share)
+        .ok_or(ErrorCode::Overflow)?;
+
+    Ok(())
+}
+
+// Takes the lender's share of bad debt written off since their last action off their deposit
+// and returns it. Call after pending rewards are worked out and before `deposited` changes.
+pub fn realize_pool_loss(lender: &mut SolLender, pool: &mut SolLendingPool) -> Result<u64> {
+    let accrued = (lender.deposited as u128)
+        .checked_mul(pool.acc_loss_per_share)
+        .ok_or(ErrorCode::Overflow)?
+        / PRECISION;
+    let mut loss = u64::try_from(accrued.saturating_sub(lender.loss_debt)).unwrap_or(u64::MAX);
+
+    // Shares are rounded down, so the last lender out also takes the dust the others left behind
+    if lender.deposited == pool.total_deposits {
+        loss = loss.max(pool.bad_debt);
+    }
+    let loss = loss.min(lender.deposited);
+
+    lender.deposited -= loss;
+    pool.total_deposits = pool.total_deposits
+        .checked_sub(loss)
+        .ok_or(ErrorCode::Underflow)?;
+    pool.bad_debt = pool.bad_debt.saturating_sub(loss);
+
+    Ok(loss)
+}
+
+// Restarts the lender's reward and loss debts from the pool's current indexes
+fn reset_lender_debts(lender: &mut SolLender, pool: &SolLendingPool) -> Result<()> {
+    lender.reward_debt = (lender.deposited as u128)
+        .checked_mul(pool.acc_reward_per_share)
+        .ok_or(ErrorCode::Overflow)?
+        / PRECISION;
+    lender.loss_debt = (lender.deposited as u128)
+        .checked_mul(pool.acc_loss_per_share)
+        .ok_or(ErrorCode::Overflow)?
+        / PRECISION;
+    Ok(())
+}
+
+fn pending_rewards(lender: &SolLender, pool: &SolLendingPool) -> Result<u64> {
+    let accrued = (lender.deposited as u128)
+        .checked_mul(pool.acc_reward_per_share)
+        .ok_or(ErrorCode::Overflow)?
+        / PRECISION;
+
+    u64::try_from(accrued.saturating_sub(lender.reward_debt)).map_err(|_| ErrorCode::Overflow.into())
+}
+
+#[derive(Accounts)]
+pub struct DepositSolLending<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
+    pub wsol_mint: Account<'info, Mint>,
+
+    #[account(address = vault_config.mint @ ErrorCode::InvalidMint)]
+    pub mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint = temp_wsol_account.mint == NATIVE_MINT_ID @ ErrorCode::InvalidWsolMint,
+    )]
+    pub temp_wsol_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(address = owner.key())]
+    pub temp_wsol_authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"vault_config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub vault_config: Box<Account<'info, VaultConfig>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        seeds = [b"sol_lending_pool", mint.key().as_ref()],
+        bump,
+        space = 8 + SolLendingPool::LEN,
+    )]
+    pub sol_lending_pool: Box<Account<'info, SolLendingPool>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        seeds = [b"vault_sol_lending", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+        token::authority = sol_lending_authority,
+    )]
+    pub sol_lending_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: PDA
+    #[account(
+        seeds = [b"vault_sol_lending_authority", mint.key().as_ref()],
+        bump,
+    )]
+    pub sol_lending_authority: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        seeds = [b"sol_lender", mint.key().as_ref(), owner.key().as_ref()],
+        bump,
+        space = 8 + SolLender::LEN,
+    )]
+    pub sol_lender: Box<Account<'info, SolLender>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawSolLending<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
+    pub wsol_mint: Account<'info, Mint>,
+
+    #[account(address = vault_config.mint @ ErrorCode::InvalidMint)]
+    pub mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint = temp_wsol_account.mint == NATIVE_MINT_ID @ ErrorCode::InvalidWsolMint,
+    )]
+    pub temp_wsol_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(address = owner.key())]
+    pub temp_wsol_authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"vault_config", mint.key().as_ref()],
+        bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub vault_config: Box<Account<'info, VaultConfig>>,
+
+    #[account(
+        mut,
+        seeds = [b"sol_lending_pool", mint.key().as_ref()],
+        bump = sol_lending_pool.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub sol_lending_pool: Box<Account<'info, SolLendingPool>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_sol_lending", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+        token::authority = sol_lending_authority,
+    )]
+    pub sol_lending_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: PDA
+    #[account(
+        seeds = [b"vault_sol_lending_authority", mint.key().as_ref()],
+        bump,
+    )]
+    pub sol_lending_authority: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"sol_lender", mint.key().as_ref(), owner.key().as_ref()],
+        bump = sol_lender.bump,
+        has_one = owner @ ErrorCode::Unauthorized,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub sol_lender: Box<Account<'info, SolLender>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn deposit_sol_lending(ctx: Context<DepositSolLending>, amount: u64) -> Result<()> {
+    require!(amount > 0, ErrorCode::ZeroInput);
+
+    let now = Clock::get()?.unix_timestamp;
+    let mint_key = ctx.accounts.mint.key();
+    let pool = &mut ctx.accounts.sol_lending_pool;
+    let lender = &mut ctx.accounts.sol_lender;
+
+    // First deposit sets the pool and lender up
+    if pool.mint == Pubkey::default() {
+        pool.mint = mint_key;
+        pool.bump = ctx.bumps.sol_lending_pool;
+    }
+    if lender.owner == Pubkey::default() {
+        lender.owner = ctx.accounts.owner.key();
+        lender.mint = mint_key;
+        lender.bump = ctx.bumps.sol_lender;
+    }
+
+    // Utilisation changes below, charge borrowers up to now first
+    accrue_pool_index(pool, &ctx.accounts.vault_config, now)?;
+
+    let pending = pending_rewards(lender, pool)?;
+    let loss = realize_pool_loss(lender, pool)?;
+    msg!("pending: {}", pending);
+    msg!("loss: {}", loss);
+
+    let signer_seeds: &[&[u8]] = &[
+        b"vault_sol_lending_authority",
+        mint_key.as_ref(),
+        &[ctx.bumps.sol_lending_authority],
+    ];
+
+    if pending > 0 {
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.sol_lending_vault.to_account_info(),
+                    to: ctx.accounts.temp_wsol_account.to_account_info(),
+                    authority: ctx.accounts.sol_lending_authority.to_account_info(),
+                },
+                &[signer_seeds],
+            ),
+            pending,
+        )?;
+
+        lender.claimed_total = lender.claimed_total
+            .checked_add(pending)
+            .ok_or(ErrorCode::Overflow)?;
+    }
+
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.temp_wsol_account.to_account_info(),
+                to: ctx.accounts.sol_lending_vault.to_account_info(),
+                authority: ctx.accounts.temp_wsol_authority.to_account_info(),
+            },
+        ),
+        amount,
+    )?;
+
+    lender.deposited = lender.deposited
+        .checked_add(amount)
+        .ok_or(ErrorCode::Overflow)?;
+    pool.total_deposits = pool.total_deposits
+        .checked_add(amount)
+        .ok_or(ErrorCode::Overflow)?;
+
+    reset_lender_debts(lender, pool)?;
+    lender.last_action_ts = now;
+
+    // Close temporary wSOL account, rewards paid above go back with it
+    token::close_account(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.temp_wsol_account.to_account_info(),
+                destination: ctx.accounts.owner.to_account_info(),
+                authority: ctx.accounts.temp_wsol_authority.to_account_info(),
+            },
+        )
+    )?;
+
+    emit!(SolLendingEvent {
+        owner: lender.owner,
+        mint: mint_key,
+        deposit: true,
+        amount,
+        rewards_paid: pending,
+        loss,
+        deposited: lender.deposited,
+        total_deposits: pool.total_deposits,
+        total_borrowed: pool.total_borrowed,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+pub fn withdraw_sol_lending(ctx: Context<WithdrawSolLending>, amount: u64) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    let mint_key = ctx.accounts.mint.key();
+    let pool = &mut ctx.accounts.sol_lending_pool;
+    let lender = &mut ctx.accounts.sol_lender;
+
+    accrue_pool_index(pool, &ctx.accounts.vault_config, now)?;
+
+    // Rewards were earned on the full deposit, the lender's share of bad debt comes off after
+    let pending = pending_rewards(lender, pool)?;
+    let loss = realize_pool_loss(lender, pool)?;
+    msg!("loss: {}", loss);
+
+    require!(amount <= lender.deposited, ErrorCode::InsufficientFunds);
+
+    // SOL lent out to longs stays locked until they close
+    require!(amount <= pool_available(pool), ErrorCode::InsufficientLendingLiquidity);
+
+    let payout = amount
+        .checked_add(pending)
+        .ok_or(ErrorCode::Overflow)?;
+
+    msg!("pending: {}", pending);
+    msg!("payout: {}", payout);
+
+    require!(payout > 0, ErrorCode::ZeroInput);
+
+    let signer_seeds: &[&[u8]] = &[
+        b"vault_sol_lending_authority",
+        mint_key.as_ref(),
+        &[ctx.bumps.sol_lending_authority],
+    ];
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.sol_lending_vault.to_account_info(),
+                to: ctx.accounts.temp_wsol_account.to_account_info(),
+                authority: ctx.accounts.sol_lending_authority.to_account_info(),
+            },
+            &[signer_seeds],
+        ),
+        payout,
+    )?;
+
+    lender.deposited = lender.deposited
+        .checked_sub(amount)
+        .ok_or(ErrorCode::Underflow)?;
+    pool.total_deposits = pool.total_deposits
+        .checked_sub(amount)
+        .ok_or(ErrorCode::Underflow)?;
+    lender.claimed_total = lender.claimed_total
+        .checked_add(pending)
+        .ok_or(ErrorCode::Overflow)?;
+
+    reset_lender_debts(lender, pool)?;
+    lender.last_action_ts = now;
+
+    token::close_account(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.temp_wsol_account.to_account_info(),
+                destination: ctx.accounts.owner.to_account_info(),
+                authority: ctx.accounts.temp_wsol_authority.to_account_info(),
+            },
+        )
+    )?;
+
+    emit!(SolLendingEvent {
+        owner: lender.owner,
+        mint: mint_key,
+        deposit: false,
+        amount,
+        rewards_paid: pending,
+        loss,
+        deposited: lender.deposited,
+        total_deposits: pool.total_deposits,
+        total_borrowed: pool.total_borrowed,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct SolLendingEvent {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub deposit: bool,
+    pub amount: u64,
+    pub rewards_paid: u64,
+    pub loss: u64,
+    pub deposited: u64,
+    pub total_deposits: u64,
+    pub total_borrowed: u64,
+    pub timestamp: i64,
+}
@@ -0,0 +1,38 @@
+use anchor_lang::prelude::*;
+use crate::{UserAccount, ErrorCode, MAX_OPEN_POSITIONS};
+
+// Fills in a UserAccount the first time init_if_needed creates it
+pub fn init_user_account(user_account: &mut UserAccount, owner: Pubkey, bump: u8) {
+    if user_account.owner == Pubkey::default() {
+        user_account.owner = owner;
+        user_account.bump = bump;
+    }
+}
+
+// Hands out the next position id, ids are never reused. Position seeds read
+// next_position_id during account validation, so this must run before anything bumps it.
+pub fn allocate_position_id(user_account: &mut UserAccount) -> Result<u64> {
+    let position_id = user_account.next_position_id;
+    user_account.next_position_id = position_id
+        .checked_add(1)
+        .ok_or(ErrorCode::Overflow)?;
+    Ok(position_id)
+}
+
+// Adds `position` to the open list, a position that is already listed is left alone
+pub fn register_position(user_account: &mut UserAccount, position: Pubkey) -> Result<()> {
+    if user_account.open_positions.contains(&position) {
+        return Ok(());
+    }
+    require!(
+        user_account.open_positions.len() < MAX_OPEN_POSITIONS,
+        ErrorCode::TooManyOpenPositions
+    );
+    user_account.open_positions.push(position);
+    Ok(())
+}
+
+// Drops `position` from the open list
+pub fn remove_position(user_account: &mut UserAccount, position: &Pubkey) {
+    user_account.open_positions.retain(|p| p != position);
+}
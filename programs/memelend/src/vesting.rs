@@ -0,0 +1,190 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+use crate::{YieldVault, VestingSchedule, VestingStep, ErrorCode, MAX_VESTING_STEPS};
+
+pub const VESTING_STEPS: u8 = 0;
+pub const VESTING_LINEAR: u8 = 1;
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct SetVestingScheduleArgs {
+    pub kind: u8,               // VESTING_STEPS or VESTING_LINEAR
+    pub cliff_seconds: i64,     // linear only
+    pub duration_seconds: i64,  // linear only
+    pub steps: Vec<VestingStep>, // steps only
+    pub irrevocable: bool,      // one-way, the schedule can never change again
+}
+
+#[derive(Accounts)]
+pub struct SetVestingSchedule<'info> {
+    pub creator: Signer<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        seeds = [b"yield_vault", mint.key().as_ref()],
+        bump = yield_vault.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+        has_one = creator @ ErrorCode::Unauthorized,
+    )]
+    pub yield_vault: Account<'info, YieldVault>,
+
+    #[account(
+        mut,
+        seeds = [b"vesting", mint.key().as_ref()],
+        bump = vesting_schedule.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub vesting_schedule: Account<'info, VestingSchedule>,
+}
+
+// Share of the creator's initial stake unlocked `elapsed` seconds after launch, in bps
+pub fn allowed_withdraw_percent(schedule: &VestingSchedule, elapsed: i64) -> u16 {
+    if schedule.kind == VESTING_LINEAR {
+        if elapsed < schedule.cliff_seconds {
+            return 0;
+        }
+        if elapsed >= schedule.duration_seconds {
+            return 10_000;
+        }
+        return (elapsed as u128 * 10_000 / schedule.duration_seconds as u128) as u16;
+    }
+
+    step_unlock(&schedule.steps, elapsed)
+}
+
+fn step_unlock(steps: &[VestingStep], elapsed: i64) -> u16 {
+    steps
+        .iter()
+        .take_while(|step| elapsed >= step.after_seconds)
+        .last()
+        .map(|step| step.unlocked_bps)
+        .unwrap_or(0)
+}
+
+// Elapsed times at which a schedule's unlock jumps or starts/stops growing
+fn breakpoints(schedule: &VestingSchedule) -> Vec<i64> {
+    if schedule.kind == VESTING_LINEAR {
+        vec![schedule.cliff_seconds, schedule.duration_seconds]
+    } else {
+        schedule.steps.iter().map(|s| s.after_seconds).collect()
+    }
+}
+
+// A replacement may only unlock later, never sooner, than the schedule it replaces. Between
+// breakpoints both curves are flat or linear, so checking every breakpoint and the second
+// before it covers all elapsed times.
+pub fn require_no_faster_unlock(new: &VestingSchedule, current: &VestingSchedule) -> Result<()> {
+    let mut points = breakpoints(new);
+    points.extend(breakpoints(current));
+
+    for point in points {
+        for elapsed in [point.saturating_sub(1).max(0), point] {
+            require!(
+                allowed_withdraw_percent(new, elapsed) <= allowed_withdraw_percent(current, elapsed),
+                ErrorCode::VestingUnlockTooFast
+            );
+        }
+    }
+    Ok(())
+}
+
+// Schedules must end fully unlocked so principal can't be stuck forever
+pub fn validate_vesting(args: &SetVestingScheduleArgs) -> Result<()> {
+    match args.kind {
+        VESTING_LINEAR => {
+            require!(args.duration_seconds > 0, ErrorCode::InvalidVestingSchedule);
+            require!(
+                args.cliff_seconds >= 0 && args.cliff_seconds <= args.duration_seconds,
+                ErrorCode::InvalidVestingSchedule
+            );
+        }
+        VESTING_STEPS => {
+            require!(
+                !args.steps.is_empty() && args.steps.len() <= MAX_VESTING_STEPS,
+                ErrorCode::InvalidVestingSchedule
+            );
+            let mut prev: Option<&VestingStep> = None;
+            for step in &args.steps {
+                require!(step.after_seconds >= 0, ErrorCode::InvalidVestingSchedule);
+                require!(step.unlocked_bps <= 10_000, ErrorCode::InvalidVestingSchedule);
+                if let Some(prev) = prev {
+                    require!(
+                        step.after_seconds > prev.after_seconds && step.unlocked_bps >= prev.unlocked_bps,
+                        ErrorCode::InvalidVestingSchedule
+                    );
+                }
+                prev = Some(step);
+            }
+            require!(
+                args.steps.last().map(|s| s.unlocked_bps) == Some(10_000),
+                ErrorCode::InvalidVestingSchedule
+            );
+        }
+        _ => return err!(ErrorCode::InvalidVestingSchedule),
+    }
+    Ok(())
+}
+
+// Fills `schedule` from validated args, shared by launch and set_vesting_schedule
+pub fn write_vesting_schedule(
+    schedule: &mut VestingSchedule,
+    mint: Pubkey,
+    creator: Pubkey,
+    args: SetVestingScheduleArgs,
+    bump: u8,
+) -> Result<()> {
+    validate_vesting(&args)?;
+
+    schedule.mint = mint;
+    schedule.creator = creator;
+    schedule.kind = args.kind;
+    schedule.cliff_seconds = args.cliff_seconds;
+    schedule.duration_seconds = args.duration_seconds;
+    schedule.steps = if args.kind == VESTING_STEPS { args.steps } else { Vec::new() };
+    schedule.irrevocable = args.irrevocable;
+    schedule.bump = bump;
+    Ok(())
+}
+
+// Replaces the creator's unlock schedule. The schedule is fixed at launch, a replacement can
+// only push unlocks later (or mark the schedule irrevocable) so holders never lose cover.
+pub fn set_vesting_schedule(ctx: Context<SetVestingSchedule>, args: SetVestingScheduleArgs) -> Result<()> {
+    let schedule = &mut ctx.accounts.vesting_schedule;
+    require!(!schedule.irrevocable, ErrorCode::VestingScheduleIrrevocable);
+
+    let current = (**schedule).clone();
+    let bump = schedule.bump;
+    write_vesting_schedule(
+        schedule,
+        ctx.accounts.mint.key(),
+        ctx.accounts.creator.key(),
+        args,
+        bump,
+    )?;
+    require_no_faster_unlock(schedule, &current)?;
+
+    emit!(VestingScheduleSetEvent {
+        mint: schedule.mint,
+        creator: schedule.creator,
+        kind: schedule.kind,
+        cliff_seconds: schedule.cliff_seconds,
+        duration_seconds: schedule.duration_seconds,
+        steps: schedule.steps.clone(),
+        irrevocable: schedule.irrevocable,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct VestingScheduleSetEvent {
+    pub mint: Pubkey,
+    pub creator: Pubkey,
+    pub kind: u8,
+    pub cliff_seconds: i64,
+    pub duration_seconds: i64,
+    pub steps: Vec<VestingStep>,
+    pub irrevocable: bool,
+    pub timestamp: i64,
+}
@@ -0,0 +1,120 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Transfer, Token, TokenAccount, Mint};
+use anchor_spl::token::spl_token::native_mint::ID as NATIVE_MINT_ID;
+use crate::{ProtocolConfig, VaultConfig, ErrorCode};
+
+#[derive(Accounts)]
+pub struct WithdrawPlatformFees<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [b"protocol_config"],
+        bump = protocol_config.bump,
+        has_one = admin @ ErrorCode::Unauthorized,
+        has_one = treasury @ ErrorCode::InvalidVault,
+    )]
+    pub protocol_config: Box<Account<'info, ProtocolConfig>>,
+
+    #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
+    pub wsol_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = treasury.mint == NATIVE_MINT_ID @ ErrorCode::InvalidWsolMint,
+    )]
+    pub treasury: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+    // remaining_accounts: [vault_config, platform_vault, vault_authority] per mint to sweep
+}
+
+pub fn withdraw_platform_fees<'info>(ctx: Context<'_, '_, 'info, 'info, WithdrawPlatformFees<'info>>) -> Result<()> {
+    require!(ctx.accounts.protocol_config.treasury != Pubkey::default(), ErrorCode::TreasuryNotSet);
+
+    let remaining = ctx.remaining_accounts;
+    require!(
+        !remaining.is_empty() && remaining.chunks_exact(3).remainder().is_empty(),
+        ErrorCode::InvalidInput
+    );
+
+    let now = Clock::get()?.unix_timestamp;
+    let mut total_withdrawn: u64 = 0;
+
+    for accounts in remaining.chunks(3) {
+        let vault_config_info = &accounts[0];
+        let platform_vault_info = &accounts[1];
+        let vault_authority_info = &accounts[2];
+
+        // Owner + discriminator checked by Account::try_from
+        let mut vault_config: Account<'info, VaultConfig> = Account::try_from(vault_config_info)?;
+        let mint_key = vault_config.mint;
+
+        // Every account has to be the canonical PDA for this mint
+        let (expected_vault_config, _) = Pubkey::find_program_address(&[b"vault_config", mint_key.as_ref()], ctx.program_id);
+        require_keys_eq!(vault_config_info.key(), expected_vault_config, ErrorCode::InvalidVault);
+
+        let (expected_platform_vault, _) = Pubkey::find_program_address(&[b"vault_platform", mint_key.as_ref()], ctx.program_id);
+        require_keys_eq!(platform_vault_info.key(), expected_platform_vault, ErrorCode::InvalidVault);
+
+        let (expected_vault_authority, vault_bump) = Pubkey::find_program_address(&[b"vault", mint_key.as_ref()], ctx.program_id);
+        require_keys_eq!(vault_authority_info.key(), expected_vault_authority, ErrorCode::InvalidVault);
+
+        let platform_vault: Account<'info, TokenAccount> = Account::try_from(platform_vault_info)?;
+        let amount = platform_vault.amount;
+
+        msg!("mint: {}", mint_key);
+        msg!("platform_vault amount: {}", amount);
+
+        if amount == 0 {
+            continue;
+        }
+
+        let signer_seeds: &[&[u8]] = &[
+            b"vault",
+            mint_key.as_ref(),
+            &[vault_bump],
+        ];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: platform_vault_info.clone(),
+                    to: ctx.accounts.treasury.to_account_info(),
+                    authority: vault_authority_info.clone(),
+                },
+                &[signer_seeds],
+            ),
+            amount,
+        )?;
+
+        // Counter can lag the token balance by rounding dust, never let it underflow
+        vault_config.platform_vault = vault_config.platform_vault.saturating_sub(amount);
+        vault_config.exit(ctx.program_id)?;
+
+        total_withdrawn = total_withdrawn
+            .checked_add(amount)
+            .ok_or(ErrorCode::Overflow)?;
+
+        emit!(PlatformFeesWithdrawnEvent {
+            mint: mint_key,
+            treasury: ctx.accounts.treasury.key(),
+            amount,
+            platform_vault: vault_config.platform_vault,
+            timestamp: now,
+        });
+    }
+
+    msg!("total_withdrawn: {}", total_withdrawn);
+
+    Ok(())
+}
+
+#[event]
+pub struct PlatformFeesWithdrawnEvent {
+    pub mint: Pubkey,
+    pub treasury: Pubkey,
+    pub amount: u64,
+    pub platform_vault: u64,
+    pub timestamp: i64,
+}
@@ -0,0 +1,392 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Transfer, Token, TokenAccount, Mint, CloseAccount};
+use crate::{YieldVault, ErrorCode, UserYieldPosition, WithdrawQueue, WithdrawRequest};
+use crate::emissions::{checkpoint_position, rebase_position};
+use crate::yield_lock::require_unlocked;
+
+pub const PRECISION: u128 = 1_000_000_000_000; // 1e12 for reward per share math
+
+// Stakers whose withdrawal can't be paid because shorts have borrowed the tokens join a
+// per-mint FIFO queue. Queued tokens stay reserved in the lending vault as shorts repay:
+// nobody can borrow or withdraw them until fill_withdraw_request pays the queue in order.
+
+// Sets up the per-mint queue the first time it is touched (init_if_needed)
+pub fn init_withdraw_queue(queue: &mut WithdrawQueue, bump: u8, mint: Pubkey) {
+    if queue.mint == Pubkey::default() {
+        queue.mint = mint;
+        queue.bump = bump;
+    }
+}
+
+// Lending vault balance that is not already promised to the queue
+pub fn available_liquidity(lending_vault_amount: u64, queue: &WithdrawQueue) -> u64 {
+    lending_vault_amount.saturating_sub(queue.queued_amount)
+}
+
+#[derive(Accounts)]
+pub struct RequestWithdrawYield<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"yield_vault", mint.key().as_ref()],
+        bump = yield_vault.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub yield_vault: Box<Account<'info, YieldVault>>,
+
+    #[account(
+        mut,
+        seeds = [b"user_yield", mint.key().as_ref(), user.key().as_ref()],
+        bump = user_yield_position.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+        constraint = user_yield_position.owner == user.key() @ ErrorCode::Unauthorized,
+    )]
+    pub user_yield_position: Box<Account<'info, UserYieldPosition>>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        seeds = [b"withdraw_queue", mint.key().as_ref()],
+        bump,
+        space = 8 + WithdrawQueue::LEN,
+    )]
+    pub withdraw_queue: Box<Account<'info, WithdrawQueue>>,
+
+    // Next slot at the back of the queue
+    #[account(
+        init,
+        payer = user,
+        seeds = [b"withdraw_request", mint.key().as_ref(), &withdraw_queue.tail.to_le_bytes()],
+        bump,
+        space = 8 + WithdrawRequest::LEN,
+    )]
+    pub withdraw_request: Box<Account<'info, WithdrawRequest>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_lending", mint.key().as_ref()],
+        bump,
+    )]
+    pub lending_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: PDA authority for lending vault
+    #[account(
+        seeds = [b"vault_lending_authority", mint.key().as_ref()],
+        bump,
+    )]
+    pub lending_vault_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = user_token_account.mint == mint.key() @ ErrorCode::InvalidMint,
+        constraint = user_token_account.owner == user.key() @ ErrorCode::Unauthorized,
+    )]
+    pub user_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: PDA authority for vaults
+    #[account(
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+    )]
+    pub vault_authority: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_project", mint.key().as_ref()],
+        bump,
+        token::authority = vault_authority,
+    )]
+    pub project_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = temp_wsol_account.mint == anchor_spl::token::spl_token::native_mint::ID @ ErrorCode::InvalidWsolMint,
+    )]
+    pub temp_wsol_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(address = user.key())]
+    pub temp_wsol_authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FillWithdrawRequest<'info> {
+    // Permissionless crank
+    pub cranker: Signer<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"withdraw_queue", mint.key().as_ref()],
+        bump = withdraw_queue.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub withdraw_queue: Box<Account<'info, WithdrawQueue>>,
+
+    // Only the request at the front of the queue can be filled
+    #[account(
+        mut,
+        seeds = [b"withdraw_request", mint.key().as_ref(), &withdraw_queue.head.to_le_bytes()],
+        bump = withdraw_request.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+        has_one = owner @ ErrorCode::Unauthorized,
+    )]
+    pub withdraw_request: Box<Account<'info, WithdrawRequest>>,
+
+    /// CHECK: Request owner, receives the request rent once it is filled
+    #[account(mut)]
+    pub owner: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        constraint = owner_token_account.mint == mint.key() @ ErrorCode::InvalidMint,
+        constraint = owner_token_account.owner == owner.key() @ ErrorCode::Unauthorized,
+    )]
+    pub owner_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_lending", mint.key().as_ref()],
+        bump,
+    )]
+    pub lending_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: PDA authority for lending vault
+    #[account(
+        seeds = [b"vault_lending_authority", mint.key().as_ref()],
+        bump,
+    )]
+    pub lending_vault_authority: AccountInfo<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn request_withdraw_yield(ctx: Context<RequestWithdrawYield>, amount: u64, position_id: u64) -> Result<()> {
+    let now_ts = Clock::get()?.unix_timestamp;
+    let mint_key = ctx.accounts.mint.key();
+
+    init_withdraw_queue(&mut ctx.accounts.withdraw_queue, ctx.bumps.withdraw_queue, mint_key);
+
+    let vault = &mut ctx.accounts.yield_vault;
+    let user_position = &mut ctx.accounts.user_yield_position;
+    let queue = &mut ctx.accounts.withdraw_queue;
+
+    msg!("amount: {}", amount);
+    msg!("deposited: {}", user_position.deposited);
+
+    // Creators unlock through creator_yield_withdrawal
+    require!(!user_position.is_creator, ErrorCode::CreatorCannotWithdrawHere);
+    require!(amount > 0, ErrorCode::ZeroInput);
+    require!(amount <= user_position.deposited, ErrorCode::InsufficientBalance);
+
+    // Whatever is free now is paid out, only the rest joins the queue
+    let available = available_liquidity(ctx.accounts.lending_vault.amount, queue);
+    msg!("available liquidity: {}", available);
+    require!(amount > available, ErrorCode::WithdrawalNotNeeded);
+    let paid_now = available;
+    let queued = amount - paid_now;
+
+    // Locked principal stays put until the lock expires
+    require_unlocked(user_position, now_ts)?;
+
+    // Book token emissions and slot rewards before the stake changes
+    checkpoint_position(vault, user_position, now_ts)?;
+
+    // Harvest rewards earned so far, queued tokens stop earning
+    let pending = (user_position.weighted_stake as u128)
+        .checked_mul(vault.acc_reward_per_share)
+        .ok_or(ErrorCode::Overflow)?
+        / PRECISION
+        - user_position.reward_debt;
+
+    if pending > 0 {
+        let signer_seeds = &[
+            b"vault",
+            mint_key.as_ref(),
+            &[ctx.bumps.vault_authority],
+        ];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.project_vault.to_account_info(),
+                    to: ctx.accounts.temp_wsol_account.to_account_info(),
+                    authority: ctx.accounts.vault_authority.to_account_info(),
+                },
+                &[signer_seeds],
+            ),
+            pending as u64,
+        )?;
+
+        user_position.claimed_total = user_position.claimed_total
+            .checked_add(pending as u64)
+            .ok_or(ErrorCode::Overflow)?;
+    }
+
+    if paid_now > 0 {
+        let vault_seeds = &[
+            b"vault_lending_authority",
+            mint_key.as_ref(),
+            &[ctx.bumps.lending_vault_authority],
+        ];
+
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.lending_vault.to_account_info(),
+                    to: ctx.accounts.user_token_account.to_account_info(),
+                    authority: ctx.accounts.lending_vault_authority.to_account_info(),
+                },
+                &[&vault_seeds[..]],
+            ),
+            paid_now,
+        )?;
+    }
+
+    // Move the principal out of the stake, the unpaid part into the queue
+    user_position.deposited = user_position.deposited
+        .checked_sub(amount)
+        .ok_or(ErrorCode::Underflow)?;
+    vault.total_staked = vault.total_staked
+        .checked_sub(amount)
+        .ok_or(ErrorCode::Underflow)?;
+    rebase_position(vault, user_position, now_ts)?;
+    user_position.last_action_ts = now_ts;
+
+    let request = &mut ctx.accounts.withdraw_request;
+    request.owner = ctx.accounts.user.key();
+    request.mint = mint_key;
+    request.sequence = queue.tail;
+    request.amount = queued;
+    request.filled = 0;
+    request.created_at = now_ts;
+    request.bump = ctx.bumps.withdraw_request;
+
+    queue.tail = queue.tail.checked_add(1).ok_or(ErrorCode::Overflow)?;
+    queue.queued_amount = queue.queued_amount
+        .checked_add(queued)
+        .ok_or(ErrorCode::Overflow)?;
+
+    // Close temporary wSOL account
+    token::close_account(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.temp_wsol_account.to_account_info(),
+                destination: ctx.accounts.user.to_account_info(),
+                authority: ctx.accounts.temp_wsol_authority.to_account_info(),
+            },
+        )
+    )?;
+
+    emit!(WithdrawRequestedEvent {
+        owner: request.owner,
+        mint: mint_key,
+        position_id,
+        sequence: request.sequence,
+        amount,
+        paid_amount: paid_now,
+        claimed_amount: pending as u64,
+        queued_amount: queue.queued_amount,
+        total_staked: vault.total_staked,
+        timestamp: now_ts,
+    });
+
+    Ok(())
+}
+
+pub fn fill_withdraw_request(ctx: Context<FillWithdrawRequest>) -> Result<()> {
+    let now_ts = Clock::get()?.unix_timestamp;
+    let request = &mut ctx.accounts.withdraw_request;
+    let queue = &mut ctx.accounts.withdraw_queue;
+
+    let outstanding = request.amount
+        .checked_sub(request.filled)
+        .ok_or(ErrorCode::Underflow)?;
+
+    // Requests are filled strictly in order, so everything in the vault can go to the head
+    let fill = outstanding.min(ctx.accounts.lending_vault.amount);
+    msg!("outstanding: {}", outstanding);
+    msg!("fill: {}", fill);
+    require!(fill > 0, ErrorCode::InsufficientLendingLiquidity);
+
+    let mint_key = ctx.accounts.mint.key();
+    let vault_seeds = &[
+        b"vault_lending_authority",
+        mint_key.as_ref(),
+        &[ctx.bumps.lending_vault_authority],
+    ];
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.lending_vault.to_account_info(),
+                to: ctx.accounts.owner_token_account.to_account_info(),
+                authority: ctx.accounts.lending_vault_authority.to_account_info(),
+            },
+            &[&vault_seeds[..]],
+        ),
+        fill,
+    )?;
+
+    request.filled = request.filled.checked_add(fill).ok_or(ErrorCode::Overflow)?;
+    queue.queued_amount = queue.queued_amount.saturating_sub(fill);
+
+    let sequence = request.sequence;
+    let completed = request.filled == request.amount;
+
+    // Fully paid: advance the queue and give the owner their rent back
+    if completed {
+        queue.head = queue.head.checked_add(1).ok_or(ErrorCode::Overflow)?;
+        request.close(ctx.accounts.owner.to_account_info())?;
+    }
+
+    emit!(WithdrawRequestFilledEvent {
+        owner: ctx.accounts.owner.key(),
+        mint: mint_key,
+        sequence,
+        filled: fill,
+        completed,
+        queued_amount: queue.queued_amount,
+        head: queue.head,
+        timestamp: now_ts,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct WithdrawRequestedEvent {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub position_id: u64,
+    pub sequence: u64,
+    pub amount: u64,
+    pub paid_amount: u64,
+    pub claimed_amount: u64,
+    pub queued_amount: u64,
+    pub total_staked: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct WithdrawRequestFilledEvent {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub sequence: u64,
+    pub filled: u64,
+    pub completed: bool,
+    pub queued_amount: u64,
+    pub head: u64,
+    pub timestamp: i64,
+}
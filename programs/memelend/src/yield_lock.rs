@@ -0,0 +1,193 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Token, TokenAccount, Mint, Transfer};
+use crate::{YieldVault, UserYieldPosition, ErrorCode};
+use crate::emissions::{checkpoint_position, rebase_position, pending_wsol_rewards};
+
+const DAY: i64 = 86_400;
+
+#[derive(Accounts)]
+pub struct KickExpiredLock<'info> {
+    // Permissionless, anyone can drop a boost whose lock has run out
+    pub caller: Signer<'info>,
+
+    /// CHECK: Owner of the position, only used for seeds
+    pub owner: UncheckedAccount<'info>,
+
+    // WSOL earned at the boosted weight is paid here before the boost goes
+    #[account(
+        mut,
+        token::mint = wsol_mint,
+        token::authority = owner,
+    )]
+    pub owner_wsol_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(address = anchor_spl::token::spl_token::native_mint::ID)]
+    pub wsol_mint: Account<'info, Mint>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"yield_vault", mint.key().as_ref()],
+        bump = yield_vault.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+    )]
+    pub yield_vault: Box<Account<'info, YieldVault>>,
+
+    #[account(
+        mut,
+        seeds = [b"user_yield", mint.key().as_ref(), owner.key().as_ref()],
+        bump = user_yield_position.bump,
+        has_one = mint @ ErrorCode::InvalidMint,
+        has_one = owner @ ErrorCode::Unauthorized,
+    )]
+    pub user_yield_position: Box<Account<'info, UserYieldPosition>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault_project", mint.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+        token::authority = vault_authority,
+    )]
+    pub project_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: PDA signer for the project vault
+    #[account(
+        seeds = [b"vault", mint.key().as_ref()],
+        bump,
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+// Lock duration and reward weight per tier, tier 0 is unlocked
+pub const LOCK_TIERS: [(i64, u16); 5] = [
+    (0, 10_000),         // no lock, 1x
+    (7 * DAY, 11_000),   // 7 days, 1.1x
+    (30 * DAY, 12_500),  // 30 days, 1.25x
+    (90 * DAY, 15_000),  // 90 days, 1.5x
+    (180 * DAY, 20_000), // 180 days, 2x
+];
+
+pub fn boost_bps(lock_tier: u8) -> Result<u16> {
+    LOCK_TIERS
+        .get(lock_tier as usize)
+        .map(|(_, boost)| *boost)
+        .ok_or(error!(ErrorCode::InvalidLockTier))
+}
+
+// Drops the boost once the lock has run out
+pub fn expire_lock(position: &mut UserYieldPosition, now: i64) {
+    if position.lock_tier != 0 && position.lock_until <= now {
+        position.lock_tier = 0;
+    }
+}
+
+// Locks the whole position at `lock_tier` from now. A live lock can be extended or moved
+// to a longer tier but never shortened.
+pub fn apply_lock(position: &mut UserYieldPosition, lock_tier: u8, now: i64) -> Result<()> {
+    let (duration, _) = *LOCK_TIERS
+        .get(lock_tier as usize)
+        .ok_or(ErrorCode::InvalidLockTier)?;
+
+    expire_lock(position, now);
+    if lock_tier == 0 {
+        return Ok(());
+    }
+    require!(lock_tier >= position.lock_tier, ErrorCode::LockTierDowngrade);
+
+    position.lock_tier = lock_tier;
+    position.lock_until = position.lock_until.max(
+        now.checked_add(duration).ok_or(ErrorCode::Overflow)?
+    );
+    Ok(())
+}
+
+pub fn require_unlocked(position: &UserYieldPosition, now: i64) -> Result<()> {
+    require!(position.lock_until <= now, ErrorCode::PrincipalLocked);
+    Ok(())
+}
+
+// Recomputes the position's weighted stake from `deposited` and its tier and moves
+// total_weighted_stake by the difference. Call after `deposited` or the tier changes and
+// before reward_debt is reset.
+pub fn update_weight(vault: &mut YieldVault, position: &mut UserYieldPosition) -> Result<()> {
+    let weighted = (position.deposited as u128)
+        .checked_mul(boost_bps(position.lock_tier)? as u128)
+        .ok_or(ErrorCode::Overflow)?
+        / 10_000;
+    let weighted = u64::try_from(weighted).map_err(|_| ErrorCode::Overflow)?;
+
+    vault.total_weighted_stake = vault
+        .total_weighted_stake
+        .checked_sub(position.weighted_stake)
+        .ok_or(ErrorCode::Underflow)?
+        .checked_add(weighted)
+        .ok_or(ErrorCode::Overflow)?;
+    position.weighted_stake = weighted;
+    Ok(())
+}
+
+// Drops the boost of a position whose lock has run out. Without it an idle position would keep
+// its boosted weight, and dilute everyone else, until its owner next touched it. WSOL earned
+// up to now at the boosted weight is paid to the owner, emissions and slot rewards stay booked.
+pub fn kick_expired_lock(ctx: Context<KickExpiredLock>) -> Result<()> {
+    let vault = &mut ctx.accounts.yield_vault;
+    let position = &mut ctx.accounts.user_yield_position;
+    let now = Clock::get()?.unix_timestamp;
+
+    require!(
+        position.lock_tier != 0 && position.lock_until <= now,
+        ErrorCode::LockNotExpired
+    );
+
+    checkpoint_position(vault, position, now)?;
+    let pending_wsol = u64::try_from(pending_wsol_rewards(vault, position)?)
+        .map_err(|_| ErrorCode::Overflow)?;
+
+    if pending_wsol > 0 {
+        let mint_key = ctx.accounts.mint.key();
+        let signer_seeds: &[&[u8]] = &[b"vault", mint_key.as_ref(), &[ctx.bumps.vault_authority]];
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.project_vault.to_account_info(),
+                    to: ctx.accounts.owner_wsol_account.to_account_info(),
+                    authority: ctx.accounts.vault_authority.to_account_info(),
+                },
+                &[signer_seeds],
+            ),
+            pending_wsol,
+        )?;
+        position.claimed_total = position
+            .claimed_total
+            .checked_add(pending_wsol)
+            .ok_or(ErrorCode::Overflow)?;
+    }
+
+    rebase_position(vault, position, now)?;
+
+    emit!(LockExpiredEvent {
+        mint: position.mint,
+        owner: position.owner,
+        weighted_stake: position.weighted_stake,
+        paid_wsol: pending_wsol,
+        total_weighted_stake: vault.total_weighted_stake,
+        timestamp: now,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct LockExpiredEvent {
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub weighted_stake: u64,
+    pub paid_wsol: u64,
+    pub total_weighted_stake: u64,
+    pub timestamp: i64,
+}
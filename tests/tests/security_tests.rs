@@ -1,90 +1,861 @@
 // tests/security_tests.rs
-use {
-    anchor_lang::prelude::*,
-    solana_program_test::*,
-    solana_sdk::{signature::Keypair, signer::Signer},
-};
-use memelend::buy_token::BuyTokenArgs;
+mod test_utils;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
 use memelend::ErrorCode;
+use test_utils::*;
+
+// Two launched mints with a funded trader on mint A
+struct TwoMints {
+    ctx: TestContext,
+    creator: Pubkey,
+    a: MintPdas,
+    b: MintPdas,
+}
+
+fn two_mints() -> TwoMints {
+    let mut ctx = TestContext::new();
+    let creator = ctx.new_user();
+    let a = ctx.launch_token(&creator, "a");
+    let b = ctx.launch_token(&creator, "b");
+    TwoMints { ctx, creator, a, b }
+}
+
+// Builds a valid instruction for mint A, swaps exactly one account for mint B's copy and expects
+// the given error for each swap. The unswapped instruction must then go through, so the
+// rejections come from the mint binding and not from missing state.
+#[track_caller]
+fn assert_cross_mint_rejected<F>(s: &mut TwoMints, cases: &[(&str, u32)], build: F)
+where
+    F: Fn(&MintPdas) -> Instruction,
+{
+    for (field, code) in cases {
+        let result = s.ctx.send_ix(build(&s.a.with_field_from(&s.b, field)));
+        assert_eq!(error_code(result), *code, "{} from another mint", field);
+    }
+    let result = s.ctx.send_ix(build(&s.a));
+    assert!(result.is_ok(), "valid accounts rejected: {:?}", result);
+}
+
+// Points `derived`, which a builder derived from the swapped mint, back at mint A's account
+fn pin(mut ix: Instruction, derived: Pubkey, fixed: Pubkey) -> Instruction {
+    for meta in ix.accounts.iter_mut().filter(|meta| meta.pubkey == derived) {
+        meta.pubkey = fixed;
+    }
+    ix
+}
+
+// Pumps the price of `p` with repeated large buys from a fresh whale
+fn pump(ctx: &mut TestContext, p: &MintPdas, buys: usize) {
+    let whale = ctx.new_trader(p);
+    for _ in 0..buys {
+        ctx.buy(p, &whale, 50 * SOL).expect("pump buy");
+        ctx.warp(1);
+    }
+}
+
+#[test]
+fn test_non_creator_cannot_claim_earnings() {
+    let mut ctx = TestContext::new();
+    let creator = ctx.new_user();
+    let a = ctx.launch_token(&creator, "a");
+    let trader = ctx.new_trader(&a);
+    ctx.buy(&a, &trader, SOL).unwrap();
+
+    let attacker = ctx.new_user();
+    let temp = ctx.wrap(&attacker, 0);
+    assert_program_error(ctx.send_ix(claim_earnings_ix(&a, &attacker, temp)), ErrorCode::Unauthorized);
+
+    // The creator fee from the buy is still there for the creator
+    let temp = ctx.wrap(&creator, 0);
+    ctx.send_ix(claim_earnings_ix(&a, &creator, temp)).unwrap();
+    assert!(ctx.token_balance(&temp) > 0);
+}
+
+#[test]
+fn test_buy_larger_than_temp_balance_rejected() {
+    let mut ctx = TestContext::new();
+    let creator = ctx.new_user();
+    let a = ctx.launch_token(&creator, "a");
+    let trader = ctx.new_trader(&a);
+
+    let temp = ctx.wrap(&trader.key, SOL);
+    let result = ctx.send_ix(buy_ix(&a, &trader, temp, u64::MAX, 1));
+    assert_program_error(result, ErrorCode::InsufficientFunds);
+    assert_eq!(ctx.token_balance(&trader.tokens), 0);
+}
+
+#[test]
+fn test_buy_capped_at_max_buy_bps() {
+    let mut ctx = TestContext::new();
+    let creator = ctx.new_user();
+    let a = ctx.launch_token(&creator, "a");
+    let whale = ctx.new_trader(&a);
+
+    // Far more than max_buy_bps (20%) of the token reserve
+    assert_program_error(ctx.buy(&a, &whale, 500 * SOL), ErrorCode::AmountTooLarge);
+    ctx.buy(&a, &whale, SOL).unwrap();
+}
+
+#[test]
+fn test_short_open_interest_capped() {
+    let mut ctx = TestContext::new();
+    let creator = ctx.new_user();
+    let a = ctx.launch_token(&creator, "a");
+    let attacker = ctx.new_trader(&a);
+
+    // A short worth many times the pool would drain the lending vault
+    let result = ctx.go_short(&a, &attacker, 200 * SOL, 10_000);
+    assert_program_error(result.map(|_| ()), ErrorCode::OpenInterestCapExceeded);
+    ctx.go_short(&a, &attacker, SOL, 10_000).unwrap();
+}
+
+// ---------------------------------------------------------------------------
+// Cross-mint substitution: every per-mint account must be bound to its own mint
+// ---------------------------------------------------------------------------
+
+#[test]
+fn test_buy_token_cross_mint() {
+    let mut s = two_mints();
+    let trader = s.ctx.new_trader(&s.a);
+    let temp = s.ctx.wrap(&trader.key, SOL);
+
+    let cases = [
+        ("mint", program_error(ErrorCode::InvalidMint)),
+        ("token_config", ANCHOR_CONSTRAINT_ADDRESS),
+        ("vault_config", ANCHOR_CONSTRAINT_SEEDS),
+        ("yield_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("vault_authority", ANCHOR_CONSTRAINT_TOKEN_OWNER),
+        ("liquidity_token_vault", program_error(ErrorCode::InvalidMint)),
+        ("liquidity_vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+        ("liquidity_sol_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("project_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("platform_vault", ANCHOR_CONSTRAINT_SEEDS),
+    ];
+    // Mint A's platform vault stays the "no referrer" marker for every swap
+    let no_referrer = s.a.platform_vault;
+    assert_cross_mint_rejected(&mut s, &cases, |p| buy_referred_ix(p, &trader, temp, SOL, 1, &no_referrer));
+}
+
+#[test]
+fn test_sell_token_cross_mint() {
+    let mut s = two_mints();
+    let trader = s.ctx.new_trader(&s.a);
+    s.ctx.buy(&s.a, &trader, SOL).unwrap();
+    s.ctx.warp(1);
+    let tokens = s.ctx.token_balance(&trader.tokens);
+    let temp = s.ctx.wrap(&trader.key, 0);
+
+    let cases = [
+        ("mint", program_error(ErrorCode::InvalidMint)),
+        ("token_config", ANCHOR_CONSTRAINT_ADDRESS),
+        ("vault_config", ANCHOR_CONSTRAINT_SEEDS),
+        ("yield_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("vault_authority", ANCHOR_CONSTRAINT_TOKEN_OWNER),
+        ("liquidity_token_vault", program_error(ErrorCode::InvalidMint)),
+        ("liquidity_sol_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("wsol_vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+        ("project_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("platform_vault", ANCHOR_CONSTRAINT_SEEDS),
+    ];
+    let no_referrer = s.a.platform_vault;
+    assert_cross_mint_rejected(&mut s, &cases, |p| {
+        sell_referred_ix(p, &trader, temp, tokens / 2, 1, &no_referrer)
+    });
+}
+
+#[test]
+fn test_go_short_cross_mint() {
+    let mut s = two_mints();
+    let trader = s.ctx.new_trader(&s.a);
+    let temp = s.ctx.wrap(&trader.key, SOL);
+
+    let cases = [
+        ("mint", ANCHOR_CONSTRAINT_SEEDS),
+        ("token_config", program_error(ErrorCode::InvalidMint)),
+        ("vault_config", ANCHOR_CONSTRAINT_SEEDS),
+        ("yield_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("vault_authority", ANCHOR_CONSTRAINT_TOKEN_OWNER),
+        ("liquidity_sol_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("lending_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("lending_vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+        ("liquidity_token_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("project_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("platform_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("withdraw_queue", ANCHOR_CONSTRAINT_SEEDS),
+    ];
+    let no_referrer = s.a.platform_vault;
+    assert_cross_mint_rejected(&mut s, &cases, |p| {
+        go_short_referred_ix(p, &trader, temp, SOL, 10_000, 0, &no_referrer)
+    });
+}
+
+#[test]
+fn test_close_position_cross_mint() {
+    let mut s = two_mints();
+    let trader = s.ctx.new_trader(&s.a);
+    let id = s.ctx.go_short(&s.a, &trader, SOL, 10_000).unwrap();
+    s.ctx.warp(60);
+    let temp = s.ctx.wrap(&trader.key, 0);
+
+    let cases = [
+        ("mint", program_error(ErrorCode::InvalidMint)),
+        ("token_config", program_error(ErrorCode::InvalidMint)),
+        ("vault_config", ANCHOR_CONSTRAINT_SEEDS),
+        ("yield_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+        ("liquidity_token_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("lending_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("liquidity_sol_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("project_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("platform_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("insurance_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("liquidity_vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+        ("wsol_vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+    ];
+    // The position always belongs to mint A
+    let (position, no_referrer) = (position_pda(&trader.key, &s.a.mint, id), s.a.platform_vault);
+    assert_cross_mint_rejected(&mut s, &cases, |p| {
+        pin(
+            close_position_referred_ix(p, &trader, temp, id, &no_referrer),
+            position_pda(&trader.key, &p.mint, id),
+            position,
+        )
+    });
+}
+
+#[test]
+fn test_add_collateral_cross_mint() {
+    let mut s = two_mints();
+    let trader = s.ctx.new_trader(&s.a);
+    let id = s.ctx.go_short(&s.a, &trader, SOL, 10_000).unwrap();
+    let temp = s.ctx.wrap(&trader.key, SOL);
+
+    let cases = [
+        ("mint", program_error(ErrorCode::InvalidMint)),
+        ("token_config", program_error(ErrorCode::InvalidMint)),
+        ("liquidity_sol_vault", ANCHOR_CONSTRAINT_SEEDS),
+    ];
+    let position = position_pda(&trader.key, &s.a.mint, id);
+    assert_cross_mint_rejected(&mut s, &cases, |p| {
+        pin(
+            add_collateral_ix(p, &trader, temp, id, SOL),
+            position_pda(&trader.key, &p.mint, id),
+            position,
+        )
+    });
+}
+
+#[test]
+fn test_liquidate_position_cross_mint() {
+    let mut s = two_mints();
+    let trader = s.ctx.new_trader(&s.a);
+    let id = s.ctx.go_short(&s.a, &trader, SOL, 10_000).unwrap();
+    pump(&mut s.ctx, &s.a, 4);
+    let bot = s.ctx.new_user();
+    let bot_wsol = s.ctx.create_wsol_account(&bot, 0);
+
+    let cases = [
+        ("mint", program_error(ErrorCode::InvalidMint)),
+        ("token_config", program_error(ErrorCode::InvalidMint)),
+        ("vault_config", ANCHOR_CONSTRAINT_SEEDS),
+        ("yield_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+        ("liquidity_token_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("lending_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("liquidity_sol_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("project_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("insurance_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("liquidity_vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+        ("wsol_vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+    ];
+    let position = position_pda(&trader.key, &s.a.mint, id);
+    assert_cross_mint_rejected(&mut s, &cases, |p| {
+        pin(
+            liquidate_position_ix(p, &bot, bot_wsol, &trader.key, id),
+            position_pda(&trader.key, &p.mint, id),
+            position,
+        )
+    });
+}
+
+#[test]
+fn test_set_position_triggers_cross_mint() {
+    let mut s = two_mints();
+    let trader = s.ctx.new_trader(&s.a);
+    let id = s.ctx.go_short(&s.a, &trader, SOL, 10_000).unwrap();
+    let stop_loss = s.ctx.spot_price(&s.a) * 2;
+
+    let cases = [
+        ("mint", ANCHOR_CONSTRAINT_SEEDS),
+        ("vault_config", ANCHOR_CONSTRAINT_SEEDS),
+    ];
+    let position = position_pda(&trader.key, &s.a.mint, id);
+    assert_cross_mint_rejected(&mut s, &cases, |p| {
+        pin(
+            set_position_triggers_ix(p, &trader, id, stop_loss, 0),
+            position_pda(&trader.key, &p.mint, id),
+            position,
+        )
+    });
+}
+
+#[test]
+fn test_trigger_position_exit_cross_mint() {
+    let mut s = two_mints();
+    let trader = s.ctx.new_trader(&s.a);
+    let id = s.ctx.go_short(&s.a, &trader, SOL, 10_000).unwrap();
+    let stop_loss = s.ctx.spot_price(&s.a) + 1;
+    s.ctx.send_ix(set_position_triggers_ix(&s.a, &trader, id, stop_loss, 0)).unwrap();
+    pump(&mut s.ctx, &s.a, 1);
+    let keeper = s.ctx.new_user();
+    let keeper_wsol = s.ctx.create_wsol_account(&keeper, 0);
+    let owner_wsol = s.ctx.create_wsol_account(&trader.key, 0);
+
+    let cases = [
+        ("mint", program_error(ErrorCode::InvalidMint)),
+        ("token_config", program_error(ErrorCode::InvalidMint)),
+        ("vault_config", ANCHOR_CONSTRAINT_SEEDS),
+        ("yield_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+        ("liquidity_token_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("lending_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("liquidity_sol_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("project_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("platform_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("insurance_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("liquidity_vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+        ("wsol_vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+    ];
+    let position = position_pda(&trader.key, &s.a.mint, id);
+    assert_cross_mint_rejected(&mut s, &cases, |p| {
+        pin(
+            trigger_position_exit_ix(p, &keeper, keeper_wsol, &trader.key, owner_wsol, id),
+            position_pda(&trader.key, &p.mint, id),
+            position,
+        )
+    });
+}
+
+#[test]
+fn test_withdraw_sol_lending_cross_mint() {
+    let mut s = two_mints();
+    let lender = s.ctx.lend_sol(&s.a, SOL);
+    s.ctx.lend_sol(&s.b, SOL);
+    let temp = s.ctx.wrap(&lender.key, 0);
+
+    let cases = [
+        ("mint", program_error(ErrorCode::InvalidMint)),
+        ("vault_config", program_error(ErrorCode::InvalidMint)),
+        ("sol_lending_pool", ANCHOR_CONSTRAINT_SEEDS),
+        ("sol_lending_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("sol_lending_authority", ANCHOR_CONSTRAINT_TOKEN_OWNER),
+    ];
+    let sol_lender = sol_lender_pda(&s.a.mint, &lender.key);
+    assert_cross_mint_rejected(&mut s, &cases, |p| {
+        pin(withdraw_sol_lending_ix(p, &lender, temp, SOL), sol_lender_pda(&p.mint, &lender.key), sol_lender)
+    });
+}
+
+#[test]
+fn test_go_long_cross_mint() {
+    let mut s = two_mints();
+    s.ctx.lend_sol(&s.a, 10 * SOL);
+    s.ctx.lend_sol(&s.b, 10 * SOL);
+    let trader = s.ctx.new_trader(&s.a);
+    let temp = s.ctx.wrap(&trader.key, SOL);
 
-#[tokio::test]
-async fn test_access_control() -> Result<()> {
-    let mut ctx = TestContext::new().await;
-    
-    let (mint, _, _) = ctx.initialize_test_token(1_000_000 * 10u64.pow(6), 1_000_000_000).await?;
-    
-    // Try to perform admin operation as non-creator
-    let attacker = Keypair::new();
-    let attacker_wsol = ctx.create_wsol_account(&attacker, 100_000_000).await?;
-    
-    // Attempt to claim earnings as non-creator
-    let result = ctx.claim_earnings(&attacker, &attacker_wsol, &mint.pubkey()).await;
-    assert!(result.is_err(), "Non-creator should not be able to claim earnings");
-    
-    println!("✅ Access control test passed");
-    Ok(())
-}
-
-#[tokio::test]
-async fn test_arithmetic_overflow_protection() -> Result<()> {
-    let mut ctx = TestContext::new().await;
-    
-    let (mint, _, _) = ctx.initialize_test_token(1_000_000 * 10u64.pow(6), 1_000_000_000).await?;
-    
-    let user = Keypair::new();
-    let user_wsol = ctx.create_wsol_account(&user, u64::MAX).await?;
-    let user_token_account = ctx.create_token_account(&mint.pubkey(), &user.pubkey()).await?;
-    
-    // Try to cause overflow with huge amount
-    let args = BuyTokenArgs {
-        sol_amount: u64::MAX, // Massive amount that could cause overflow
-        min_tokens_out: 1,
-        position_id: 1,
-    };
-    
-    let result = ctx.buy_token(
-        &user,
-        &user_wsol,
-        &user_token_account,
-        &mint.pubkey(),
-        args
-    ).await;
-    
-    // Should either fail gracefully or be handled by the pool limits
-    // The important thing is it shouldn't panic the program
-    println!("✅ Arithmetic overflow protection test completed");
-    Ok(())
-}
-
-#[tokio::test]
-async fn test_price_manipulation_resistance() -> Result<()> {
-    let mut ctx = TestContext::new().await;
-    
-    // Create a small pool that's more susceptible to manipulation
-    let (mint, _, vault_config) = ctx.initialize_test_token(10_000 * 10u64.pow(6), 10_000_000).await?;
-    
-    let attacker = Keypair::new();
-    let attacker_wsol = ctx.create_wsol_account(&attacker, 10_000_000_000).await?; // 10 SOL
-    let attacker_token_account = ctx.create_token_account(&mint.pubkey(), &attacker.pubkey()).await?;
-    
-    // Try to manipulate price with large buy
-    let args = BuyTokenArgs {
-        sol_amount: 5_000_000_000, // 5 SOL - 50% of pool
-        min_tokens_out: 1,
-        position_id: 1,
-    };
-    
-    let result = ctx.buy_token(
-        &attacker,
-        &attacker_wsol,
-        &attacker_token_account,
-        &mint.pubkey(),
-        args
-    ).await;
-    
-    // Should fail due to anti-whale protection (max 20% of pool)
-    assert!(result.is_err(), "Large buys should be limited by anti-whale protection");
-    
-    println!("✅ Price manipulation resistance test passed");
-    Ok(())
-}
\ No newline at end of file
+    // The position is created here, so its seeds are checked before the mint binding
+    let cases = [
+        ("mint", ANCHOR_CONSTRAINT_SEEDS),
+        ("token_config", program_error(ErrorCode::InvalidMint)),
+        ("vault_config", ANCHOR_CONSTRAINT_SEEDS),
+        ("vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+        ("liquidity_token_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("liquidity_vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+        ("long_token_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("liquidity_sol_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("project_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("platform_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("sol_lending_pool", ANCHOR_CONSTRAINT_SEEDS),
+        ("sol_lending_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("sol_lending_authority", ANCHOR_CONSTRAINT_TOKEN_OWNER),
+    ];
+    let position = long_position_pda(&trader.key, &s.a.mint, 0);
+    assert_cross_mint_rejected(&mut s, &cases, |p| {
+        pin(go_long_ix(p, &trader, temp, SOL, 20_000, 0), long_position_pda(&trader.key, &p.mint, 0), position)
+    });
+}
+
+#[test]
+fn test_join_presale_cross_mint() {
+    let mut ctx = TestContext::new();
+    let creator = ctx.new_user();
+    let a = ctx.launch_token_with(&creator, "a", 10, linear_vesting(0, 30 * 86_400));
+    let b = ctx.launch_token_with(&creator, "b", 10, linear_vesting(0, 30 * 86_400));
+    let mut s = TwoMints { ctx, creator, a, b };
+    let trader = s.ctx.new_trader(&s.a);
+    let temp = s.ctx.wrap(&trader.key, SOL);
+    let bot_wsol = s.ctx.create_wsol_account(&s.creator, 0);
+
+    let cases = [
+        ("mint", program_error(ErrorCode::InvalidMint)),
+        ("token_config", program_error(ErrorCode::InvalidMint)),
+        ("vault_config", ANCHOR_CONSTRAINT_SEEDS),
+        ("vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+        ("liquidity_sol_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("lending_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("lending_vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+    ];
+    assert_cross_mint_rejected(&mut s, &cases, |p| join_presale_ix(p, &trader, temp, bot_wsol, SOL));
+}
+
+#[test]
+fn test_activate_presale_cross_mint() {
+    let mut ctx = TestContext::new();
+    let creator = ctx.new_user();
+    let a = ctx.launch_token_with(&creator, "a", 10, linear_vesting(0, 30 * 86_400));
+    let b = ctx.launch_token_with(&creator, "b", 10, linear_vesting(0, 30 * 86_400));
+    let mut s = TwoMints { ctx, creator, a, b };
+    let bot = s.ctx.new_user();
+
+    let cases = [
+        ("mint", program_error(ErrorCode::InvalidMint)),
+        ("token_config", program_error(ErrorCode::InvalidMint)),
+        ("vault_config", ANCHOR_CONSTRAINT_SEEDS),
+        ("yield_vault", ANCHOR_CONSTRAINT_SEEDS),
+    ];
+    let position = user_yield_pda(&s.a.mint, &creator);
+    assert_cross_mint_rejected(&mut s, &cases, |p| {
+        pin(activate_presale_ix(p, &bot, &creator), user_yield_pda(&p.mint, &creator), position)
+    });
+}
+
+#[test]
+fn test_claim_presale_rewards_cross_mint() {
+    let mut ctx = TestContext::new();
+    let creator = ctx.new_user();
+    let a = ctx.launch_token_with(&creator, "a", 10, linear_vesting(0, 30 * 86_400));
+    let b = ctx.launch_token_with(&creator, "b", 10, linear_vesting(0, 30 * 86_400));
+    let mut s = TwoMints { ctx, creator, a, b };
+    let trader = s.ctx.new_trader(&s.a);
+    let temp = s.ctx.wrap(&trader.key, SOL);
+    let bot_wsol = s.ctx.create_wsol_account(&creator, 0);
+    s.ctx.send_ix(join_presale_ix(&s.a, &trader, temp, bot_wsol, SOL)).unwrap();
+    // Trading after the presale pays presale buyers a fee share
+    let buyer = s.ctx.new_trader(&s.a);
+    s.ctx.buy(&s.a, &buyer, SOL).unwrap();
+    s.ctx.warp(86_400);
+    let temp = s.ctx.wrap(&trader.key, 0);
+
+    let cases = [
+        ("mint", ANCHOR_CONSTRAINT_SEEDS),
+        ("yield_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("token_config", ANCHOR_CONSTRAINT_SEEDS),
+        ("project_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("vault_authority", ANCHOR_CONSTRAINT_TOKEN_OWNER),
+    ];
+    let position = presale_position_pda(&trader.key, &s.a.mint);
+    assert_cross_mint_rejected(&mut s, &cases, |p| {
+        pin(claim_presale_rewards_ix(p, &trader, temp), presale_position_pda(&trader.key, &p.mint), position)
+    });
+}
+
+#[test]
+fn test_yield_deposit_cross_mint() {
+    let mut s = two_mints();
+    let trader = s.ctx.new_trader(&s.a);
+    s.ctx.buy(&s.a, &trader, SOL).unwrap();
+    let tokens = s.ctx.token_balance(&trader.tokens);
+    let temp = s.ctx.wrap(&trader.key, 0);
+
+    let cases = [
+        ("mint", ANCHOR_CONSTRAINT_ADDRESS),
+        ("yield_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("lending_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("token_config", ANCHOR_CONSTRAINT_ADDRESS),
+        ("project_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("vault_authority", ANCHOR_CONSTRAINT_TOKEN_OWNER),
+    ];
+    assert_cross_mint_rejected(&mut s, &cases, |p| deposit_yield_ix(p, &trader, temp, tokens, 0));
+}
+
+// A trader on mint A with `tokens` staked in A's yield vault
+fn staked_trader(s: &mut TwoMints) -> (Trader, u64) {
+    let trader = s.ctx.new_trader(&s.a);
+    s.ctx.buy(&s.a, &trader, SOL).unwrap();
+    let tokens = s.ctx.token_balance(&trader.tokens);
+    let temp = s.ctx.wrap(&trader.key, 0);
+    s.ctx.send_ix(deposit_yield_ix(&s.a, &trader, temp, tokens, 0)).unwrap();
+    (trader, tokens)
+}
+
+#[test]
+fn test_withdraw_yield_cross_mint() {
+    let mut s = two_mints();
+    let (trader, tokens) = staked_trader(&mut s);
+    s.ctx.warp(60);
+    let temp = s.ctx.wrap(&trader.key, 0);
+
+    let cases = [
+        ("mint", ANCHOR_CONSTRAINT_SEEDS),
+        ("yield_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("lending_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+        ("project_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("lending_vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+        ("withdraw_queue", ANCHOR_CONSTRAINT_SEEDS),
+    ];
+    let position = user_yield_pda(&s.a.mint, &trader.key);
+    assert_cross_mint_rejected(&mut s, &cases, |p| {
+        pin(withdraw_yield_ix(p, &trader, temp, tokens / 2, 0), user_yield_pda(&p.mint, &trader.key), position)
+    });
+}
+
+#[test]
+fn test_kick_expired_lock_cross_mint() {
+    let mut s = two_mints();
+    let trader = s.ctx.new_trader(&s.a);
+    s.ctx.buy(&s.a, &trader, SOL).unwrap();
+    let tokens = s.ctx.token_balance(&trader.tokens);
+    let temp = s.ctx.wrap(&trader.key, 0);
+    s.ctx.send_ix(deposit_yield_ix(&s.a, &trader, temp, tokens, 1)).unwrap();
+    s.ctx.warp(7 * 86_400);
+    let kicker = s.ctx.new_user();
+    let owner_wsol = s.ctx.wrap(&trader.key, 0);
+
+    let cases = [
+        ("mint", ANCHOR_CONSTRAINT_SEEDS),
+        ("yield_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("project_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("vault_authority", ANCHOR_CONSTRAINT_TOKEN_OWNER),
+    ];
+    let position = user_yield_pda(&s.a.mint, &trader.key);
+    assert_cross_mint_rejected(&mut s, &cases, |p| {
+        pin(kick_expired_lock_ix(p, &kicker, &trader.key, owner_wsol), user_yield_pda(&p.mint, &trader.key), position)
+    });
+}
+
+#[test]
+fn test_compound_rewards_cross_mint() {
+    let mut s = two_mints();
+    let (trader, _) = staked_trader(&mut s);
+    let max_price = s.ctx.spot_price(&s.a) * 2;
+    s.ctx.send_ix(set_compound_limit_ix(&s.a, &trader, max_price)).unwrap();
+    let other = s.ctx.new_trader(&s.a);
+    s.ctx.buy(&s.a, &other, 20 * SOL).unwrap();
+    s.ctx.warp(1);
+    let keeper = s.ctx.new_user();
+    let keeper_wsol = s.ctx.create_wsol_account(&keeper, 0);
+
+    let cases = [
+        ("mint", ANCHOR_CONSTRAINT_ADDRESS),
+        ("token_config", ANCHOR_CONSTRAINT_ADDRESS),
+        ("yield_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("vault_config", ANCHOR_CONSTRAINT_SEEDS),
+        ("project_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("platform_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("vault_authority", ANCHOR_CONSTRAINT_TOKEN_OWNER),
+        ("liquidity_token_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("liquidity_vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+        ("liquidity_sol_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("lending_vault", ANCHOR_CONSTRAINT_SEEDS),
+    ];
+    let position = user_yield_pda(&s.a.mint, &trader.key);
+    assert_cross_mint_rejected(&mut s, &cases, |p| {
+        pin(
+            compound_rewards_ix(p, &keeper, keeper_wsol, &trader.key, 0),
+            user_yield_pda(&p.mint, &trader.key),
+            position,
+        )
+    });
+}
+
+#[test]
+fn test_set_vesting_schedule_cross_mint() {
+    let mut s = two_mints();
+    let creator = s.creator;
+
+    let cases = [
+        ("mint", ANCHOR_CONSTRAINT_SEEDS),
+        ("yield_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("vesting_schedule", ANCHOR_CONSTRAINT_SEEDS),
+    ];
+    assert_cross_mint_rejected(&mut s, &cases, |p| set_vesting_schedule_ix(p, &creator, linear_vesting(0, 60 * 86_400)));
+}
+
+#[test]
+fn test_fund_reward_slot_cross_mint() {
+    let mut s = two_mints();
+    let creator = s.creator;
+    // B's token is the reward on A
+    let reward_mint = s.b.mint;
+    let rewards = s.ctx.create_token_account_with(&reward_mint, &creator, 864_000);
+
+    let cases = [
+        ("mint", ANCHOR_CONSTRAINT_SEEDS),
+        ("yield_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+    ];
+    let reward_vault = reward_vault_pda(&s.a.mint, &reward_mint);
+    assert_cross_mint_rejected(&mut s, &cases, |p| {
+        pin(
+            fund_reward_slot_ix(p, &creator, rewards, &reward_mint, 0, 864_000, 86_400),
+            reward_vault_pda(&p.mint, &reward_mint),
+            reward_vault,
+        )
+    });
+}
+
+#[test]
+fn test_claim_slot_rewards_cross_mint() {
+    let mut s = two_mints();
+    let (trader, _) = staked_trader(&mut s);
+    let creator = s.creator;
+    // Each mint streams the other's token, so both have a slot 0 with its own vault
+    for (p, reward_mint) in [(s.a, s.b.mint), (s.b, s.a.mint)] {
+        let rewards = s.ctx.create_token_account_with(&reward_mint, &creator, 864_000);
+        s.ctx.send_ix(fund_reward_slot_ix(&p, &creator, rewards, &reward_mint, 0, 864_000, 86_400)).unwrap();
+    }
+    s.ctx.warp(3_600);
+    let reward_vault = reward_vault_pda(&s.a.mint, &s.b.mint);
+    let payout = s.ctx.create_token_account(&s.b.mint, &trader.key);
+
+    let other_vault = claim_slot_rewards_ix(&s.a, &trader, &[(reward_vault_pda(&s.b.mint, &s.a.mint), payout)]);
+    assert_eq!(error_code(s.ctx.send_ix(other_vault)), program_error(ErrorCode::InvalidVault));
+
+    let cases = [
+        ("mint", ANCHOR_CONSTRAINT_SEEDS),
+        ("yield_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+    ];
+    let position = user_yield_pda(&s.a.mint, &trader.key);
+    assert_cross_mint_rejected(&mut s, &cases, |p| {
+        pin(
+            claim_slot_rewards_ix(p, &trader, &[(reward_vault, payout)]),
+            user_yield_pda(&p.mint, &trader.key),
+            position,
+        )
+    });
+}
+
+#[test]
+fn test_creator_yield_withdrawal_cross_mint() {
+    let mut s = two_mints();
+    let (key, a) = (s.creator, s.a);
+    let creator = s.ctx.trader(&key, &a);
+    s.ctx.warp(15 * 86_400);
+    let temp = s.ctx.wrap(&creator.key, 0);
+    let position = user_yield_pda(&a.mint, &creator.key);
+    let stake = s.ctx.account::<memelend::UserYieldPosition>(&position);
+
+    let cases = [
+        ("mint", ANCHOR_CONSTRAINT_SEEDS),
+        ("yield_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("lending_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+        ("project_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("lending_vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+        ("withdraw_queue", ANCHOR_CONSTRAINT_SEEDS),
+        ("vesting_schedule", ANCHOR_CONSTRAINT_SEEDS),
+    ];
+    // Half way through the 30 day vesting, a tenth of the stake is unlocked
+    let amount = stake.initial_deposit / 10;
+    assert_cross_mint_rejected(&mut s, &cases, |p| {
+        pin(
+            creator_yield_withdrawal_ix(p, &creator, temp, amount, stake.position_id),
+            user_yield_pda(&p.mint, &creator.key),
+            position,
+        )
+    });
+}
+
+#[test]
+fn test_claim_rewards_cross_mint() {
+    let mut s = two_mints();
+    let (trader, _) = staked_trader(&mut s);
+    let other = s.ctx.new_trader(&s.a);
+    s.ctx.buy(&s.a, &other, SOL).unwrap();
+    s.ctx.warp(86_400);
+    let temp = s.ctx.wrap(&trader.key, 0);
+
+    let cases = [
+        ("mint", ANCHOR_CONSTRAINT_SEEDS),
+        ("yield_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("project_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("vault_authority", ANCHOR_CONSTRAINT_TOKEN_OWNER),
+        ("emission_vault", ANCHOR_CONSTRAINT_SEEDS),
+    ];
+    let position = user_yield_pda(&s.a.mint, &trader.key);
+    assert_cross_mint_rejected(&mut s, &cases, |p| {
+        pin(claim_rewards_ix(p, &trader, temp), user_yield_pda(&p.mint, &trader.key), position)
+    });
+}
+
+#[test]
+fn test_claim_earnings_cross_mint() {
+    let mut s = two_mints();
+    let trader = s.ctx.new_trader(&s.a);
+    s.ctx.buy(&s.a, &trader, SOL).unwrap();
+    let creator = s.creator;
+    let temp = s.ctx.wrap(&creator, 0);
+
+    let cases = [
+        ("mint", ANCHOR_CONSTRAINT_SEEDS),
+        ("yield_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("vault_config", ANCHOR_CONSTRAINT_SEEDS),
+        ("project_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("vault_authority", ANCHOR_CONSTRAINT_TOKEN_OWNER),
+    ];
+    assert_cross_mint_rejected(&mut s, &cases, |p| claim_earnings_ix(p, &creator, temp));
+}
+
+#[test]
+fn test_initialize_fee_vaults_cross_mint() {
+    let mut ctx = TestContext::new();
+    let creator = ctx.new_user();
+    // Mint A is created but its pools are not open yet
+    let a = ctx.create_token(&creator, "a", 0, linear_vesting(0, 30 * 86_400));
+    let b = ctx.launch_token(&creator, "b");
+    let mut s = TwoMints { ctx, creator, a, b };
+
+    let cases = [
+        ("token_config", ANCHOR_CONSTRAINT_SEEDS),
+        ("liquidity_token_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("liquidity_sol_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("project_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("platform_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("insurance_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("wsol_vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+        ("vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+        ("vault_config", ANCHOR_CONSTRAINT_SEEDS),
+    ];
+    assert_cross_mint_rejected(&mut s, &cases, |p| init_fee_vaults_ix(p, &creator));
+}
+
+#[test]
+fn test_claim_referral_rewards_wrong_referrer() {
+    let mut ctx = TestContext::new();
+    let creator = ctx.new_user();
+    let a = ctx.launch_token(&creator, "a");
+
+    // A referred buy gives the referrer a vault with pending rewards
+    let referrer = ctx.new_user();
+    let trader = ctx.new_trader(&a);
+    let temp = ctx.wrap(&trader.key, SOL);
+    ctx.send_ix(buy_referred_ix(&a, &trader, temp, SOL, 1, &referrer)).unwrap();
+    let (_, tracking, _) = referral_accounts(&referrer);
+    assert!(ctx.account::<memelend::ReferralVault>(&tracking).pending_rewards > 0);
+
+    // Someone else's referral vault can't be drained by passing their key as `referrer`
+    let attacker = ctx.new_user();
+    let temp = ctx.wrap(&attacker, 0);
+    let result = ctx.send_ix(claim_referral_rewards_ix(&attacker, temp, &referrer));
+    assert_program_error(result, ErrorCode::Unauthorized);
+
+    let temp = ctx.wrap(&referrer, 0);
+    ctx.send_ix(claim_referral_rewards_ix(&referrer, temp, &referrer)).unwrap();
+    assert!(ctx.token_balance(&temp) > 0);
+}
+
+#[test]
+fn test_withdraw_platform_fees_rejects_non_canonical_triple() {
+    let mut s = two_mints();
+    let trader = s.ctx.new_trader(&s.a);
+    s.ctx.buy(&s.a, &trader, SOL).unwrap();
+    let (admin, treasury) = (s.ctx.admin, s.ctx.treasury);
+
+    // Only the admin can sweep
+    let attacker = s.ctx.new_user();
+    let result = s.ctx.send_ix(withdraw_platform_fees_ix(&attacker, &treasury, &[s.a]));
+    assert_program_error(result, ErrorCode::Unauthorized);
+
+    // Every account of a triple has to be the PDA of the same mint
+    let invalid_vault = program_error(ErrorCode::InvalidVault);
+    let cases = [
+        ("vault_config", invalid_vault),
+        ("platform_vault", invalid_vault),
+        ("vault_authority", invalid_vault),
+    ];
+    assert_cross_mint_rejected(&mut s, &cases, |p| withdraw_platform_fees_ix(&admin, &treasury, &[*p]));
+    assert_eq!(s.ctx.account::<memelend::VaultConfig>(&s.a.vault_config).platform_vault, 0);
+}
+
+#[test]
+fn test_request_withdraw_yield_cross_mint() {
+    let mut s = two_mints();
+    let (staker, stake) = s.ctx.stake(&s.a, 5 * SOL);
+    s.ctx.drain_lending_vault(&s.a);
+    s.ctx.warp(1);
+    let temp = s.ctx.wrap(&staker.key, 0);
+
+    // Free tokens are paid straight out, so the payout account has to hold this mint
+    let on_b = s.ctx.trader(&staker.key, &s.b);
+    let ix = pin(request_withdraw_yield_ix(&s.a, &staker, temp, stake, 0), staker.tokens, on_b.tokens);
+    assert_program_error(s.ctx.send_ix(ix), ErrorCode::InvalidMint);
+
+    let cases = [
+        ("yield_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("withdraw_queue", ANCHOR_CONSTRAINT_SEEDS),
+        ("lending_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("lending_vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+        ("project_vault", ANCHOR_CONSTRAINT_SEEDS),
+    ];
+    assert_cross_mint_rejected(&mut s, &cases, |p| request_withdraw_yield_ix(p, &staker, temp, stake, 0));
+}
+
+#[test]
+fn test_fill_withdraw_request_cross_mint() {
+    let mut s = two_mints();
+    // Both mints have a queued request, so swapped accounts exist and only the seeds differ
+    let mut stakers = Vec::new();
+    for p in [s.a, s.b] {
+        let (staker, stake) = s.ctx.stake(&p, 5 * SOL);
+        s.ctx.drain_lending_vault(&p);
+        s.ctx.warp(1);
+        let temp = s.ctx.wrap(&staker.key, 0);
+        s.ctx.send_ix(request_withdraw_yield_ix(&p, &staker, temp, stake, 0)).unwrap();
+        // The request took what was free, a later stake leaves something to fill
+        s.ctx.stake(&p, SOL / 10);
+        stakers.push(staker);
+    }
+    let staker = stakers[0];
+    let cranker = s.ctx.new_user();
+
+    let cases = [
+        ("mint", ANCHOR_CONSTRAINT_SEEDS),
+        ("withdraw_queue", ANCHOR_CONSTRAINT_SEEDS),
+        ("lending_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("lending_vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+    ];
+    let request = withdraw_request_pda(&s.a.mint, 0);
+    assert_cross_mint_rejected(&mut s, &cases, |p| {
+        pin(fill_withdraw_request_ix(p, &cranker, &staker, 0), withdraw_request_pda(&p.mint, 0), request)
+    });
+
+    // The payout has to go to the request owner
+    let other = s.ctx.new_trader(&s.a);
+    let mut ix = fill_withdraw_request_ix(&s.a, &cranker, &staker, 0);
+    ix.accounts[4].pubkey = other.key;
+    assert_program_error(s.ctx.send_ix(ix), ErrorCode::Unauthorized);
+}
+
+#[test]
+fn test_settle_bad_debt_cross_mint() {
+    let mut s = two_mints();
+    let (shorter, id) = s.ctx.underwater_short(&s.a);
+    let temp = s.ctx.wrap(&shorter.key, 0);
+    s.ctx.send_ix(close_position_ix(&s.a, &shorter, temp, id)).unwrap();
+    s.ctx.round_trip_short(&s.a, 10 * SOL);
+    let caller = s.ctx.new_user();
+
+    let cases = [
+        ("mint", ANCHOR_CONSTRAINT_SEEDS),
+        ("vault_config", ANCHOR_CONSTRAINT_SEEDS),
+        ("vault_authority", ANCHOR_CONSTRAINT_SEEDS),
+        ("insurance_vault", ANCHOR_CONSTRAINT_SEEDS),
+        ("liquidity_sol_vault", ANCHOR_CONSTRAINT_SEEDS),
+    ];
+    assert_cross_mint_rejected(&mut s, &cases, |p| settle_bad_debt_ix(p, &caller));
+}
@@ -1,177 +1,1928 @@
 // tests/test_utils.rs
+//
+// A small in-process runtime for the program. Instructions run through `memelend::entry` against
+// an in-memory account store, CPIs to the system and token programs are served by the syscall
+// stubs below, and account changes are only written back when the instruction succeeds, like a
+// transaction.
+#![allow(dead_code)]
+
 use anchor_lang::prelude::*;
-use memelend::{
-    buy_token::BuyTokenArgs,
-    go_short::GoShortArgs,
-    ErrorCode
-};
-use solana_program_test::*;
-use solana_sdk::{
-    signature::Keypair, 
-    signer::Signer, 
-    system_instruction,
-    transaction::Transaction,
-    pubkey::Pubkey,
-    account::Account,
-    system_program,
+use anchor_lang::solana_program::{
+    entrypoint::{ProgramResult, MAX_PERMITTED_DATA_INCREASE},
+    instruction::Instruction,
+    native_token::LAMPORTS_PER_SOL,
+    program_error::ProgramError,
+    program_option::COption,
+    program_pack::Pack,
+    program_stubs::{self, SyscallStubs},
+    pubkey,
+    sysvar,
 };
-use anchor_spl::token::{self, Token, Mint, TokenAccount};
-use std::str::FromStr;
+use anchor_lang::{system_program, AccountDeserialize, AccountSerialize, InstructionData, ToAccountMetas};
+use anchor_spl::token::spl_token;
+use anchor_spl::token::spl_token::native_mint::ID as NATIVE_MINT_ID;
+use memelend::vesting::{SetVestingScheduleArgs, VESTING_LINEAR};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::sync::Once;
+
+pub const SOL: u64 = LAMPORTS_PER_SOL;
+pub const START_TS: i64 = 1_700_000_000;
+pub const START_SLOT: u64 = 1_000;
+pub const LAUNCH_LIQUIDITY: u64 = 10 * SOL;
+pub const BPF_LOADER_UPGRADEABLE: Pubkey = pubkey!("BPFLoaderUpgradeab1e11111111111111111111111");
+
+// Anchor's own error codes
+pub const ANCHOR_CONSTRAINT_HAS_ONE: u32 = 2001;
+pub const ANCHOR_CONSTRAINT_SEEDS: u32 = 2006;
+pub const ANCHOR_CONSTRAINT_ADDRESS: u32 = 2012;
+pub const ANCHOR_CONSTRAINT_TOKEN_MINT: u32 = 2014;
+pub const ANCHOR_CONSTRAINT_TOKEN_OWNER: u32 = 2015;
+pub const ANCHOR_ACCOUNT_NOT_INITIALIZED: u32 = 3012;
+
+thread_local! {
+    static CLOCK: RefCell<Clock> = RefCell::new(Clock {
+        slot: START_SLOT,
+        unix_timestamp: START_TS,
+        ..Clock::default()
+    });
+}
+
+// ---------------------------------------------------------------------------
+// Syscall stubs
+// ---------------------------------------------------------------------------
+
+struct Stubs;
+
+impl SyscallStubs for Stubs {
+    fn sol_log_data(&self, _fields: &[&[u8]]) {}
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        CLOCK.with(|clock| unsafe { *(var_addr as *mut Clock) = clock.borrow().clone() });
+        0
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { *(var_addr as *mut Rent) = Rent::default() };
+        0
+    }
+
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        // Only this program makes CPIs, so its PDAs are the only ones that can sign
+        let pda_signers: HashSet<Pubkey> = signers_seeds
+            .iter()
+            .map(|seeds| Pubkey::create_program_address(seeds, &memelend::ID))
+            .collect::<std::result::Result<_, _>>()
+            .map_err(|_| ProgramError::InvalidSeeds)?;
+
+        let mut infos = Vec::with_capacity(instruction.accounts.len());
+        for meta in &instruction.accounts {
+            let mut info = account_infos
+                .iter()
+                .find(|info| *info.key == meta.pubkey)
+                .cloned()
+                .ok_or(ProgramError::NotEnoughAccountKeys)?;
+            if meta.is_signer && !info.is_signer && !pda_signers.contains(info.key) {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            if meta.is_writable && !info.is_writable {
+                return Err(ProgramError::InvalidArgument);
+            }
+            info.is_signer = meta.is_signer;
+            info.is_writable = meta.is_writable;
+            infos.push(info);
+        }
+
+        if instruction.program_id == spl_token::ID {
+            spl_token::processor::Processor::process(&spl_token::ID, &infos, &instruction.data)
+        } else if instruction.program_id == system_program::ID {
+            process_system(&infos, &instruction.data)
+        } else {
+            Err(ProgramError::IncorrectProgramId)
+        }
+    }
+}
+
+fn read_u64(data: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(data[at..at + 8].try_into().unwrap())
+}
+
+fn read_pubkey(data: &[u8], at: usize) -> Pubkey {
+    Pubkey::new_from_array(data[at..at + 32].try_into().unwrap())
+}
+
+fn move_lamports(from: &AccountInfo, to: &AccountInfo, lamports: u64) -> ProgramResult {
+    if !from.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let mut from_lamports = from.try_borrow_mut_lamports()?;
+    **from_lamports = from_lamports
+        .checked_sub(lamports)
+        .ok_or(ProgramError::InsufficientFunds)?;
+    drop(from_lamports);
+    let mut to_lamports = to.try_borrow_mut_lamports()?;
+    **to_lamports += lamports;
+    Ok(())
+}
+
+fn allocate(info: &AccountInfo, space: u64) -> ProgramResult {
+    if !info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !info.data_is_empty() || *info.owner != system_program::ID {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+    *info.try_borrow_mut_data()? = leak_data(&vec![0; space as usize]);
+    Ok(())
+}
+
+fn assign(info: &AccountInfo, owner: Pubkey) -> ProgramResult {
+    if !info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    info.assign(&owner);
+    Ok(())
+}
+
+// The system instructions Anchor's `init` and lamport transfers use, in their bincode layout
+fn process_system(infos: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let tag = u32::from_le_bytes(data[..4].try_into().unwrap());
+    match tag {
+        // CreateAccount { lamports, space, owner }
+        0 => {
+            if infos[1].lamports() != 0 {
+                return Err(ProgramError::AccountAlreadyInitialized);
+            }
+            allocate(&infos[1], read_u64(data, 12))?;
+            assign(&infos[1], read_pubkey(data, 20))?;
+            move_lamports(&infos[0], &infos[1], read_u64(data, 4))
+        }
+        // Assign { owner }
+        1 => assign(&infos[0], read_pubkey(data, 4)),
+        // Transfer { lamports }
+        2 => move_lamports(&infos[0], &infos[1], read_u64(data, 4)),
+        // Allocate { space }
+        8 => allocate(&infos[0], read_u64(data, 4)),
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Account memory
+//
+// AccountInfo::realloc reads the original length 4 bytes before the key and writes the new
+// length 8 bytes before the data, like the runtime's input buffer, so both are laid out that way.
+// ---------------------------------------------------------------------------
+
+#[repr(C)]
+struct KeyCell {
+    original_data_len: u32,
+    key: Pubkey,
+}
+
+fn leak_key(key: Pubkey, data_len: usize) -> &'static Pubkey {
+    let cell = Box::leak(Box::new(KeyCell { original_data_len: data_len as u32, key }));
+    &cell.key
+}
+
+fn leak_data(data: &[u8]) -> &'static mut [u8] {
+    let buffer = Box::leak(vec![0u8; 8 + data.len() + MAX_PERMITTED_DATA_INCREASE].into_boxed_slice());
+    let (_, rest) = buffer.split_at_mut(8);
+    let (out, _) = rest.split_at_mut(data.len());
+    out.copy_from_slice(data);
+    out
+}
+
+#[derive(Clone, Debug)]
+pub struct AccountState {
+    pub lamports: u64,
+    pub data: Vec<u8>,
+    pub owner: Pubkey,
+    pub executable: bool,
+}
+
+impl AccountState {
+    fn empty() -> Self {
+        Self { lamports: 0, data: Vec::new(), owner: system_program::ID, executable: false }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Test context
+// ---------------------------------------------------------------------------
 
 pub struct TestContext {
-    pub program: Program<Memelend>,
-    pub banks_client: BanksClient,
-    pub payer: Keypair,
-    pub recent_blockhash: solana_sdk::hash::Hash,
+    pub accounts: HashMap<Pubkey, AccountState>,
+    pub admin: Pubkey,
+    pub treasury: Pubkey,
 }
 
 impl TestContext {
-    pub async fn new() -> Self {
-        let program_id = memelend::id();
-        let mut program_test = ProgramTest::new(
-            "memelend", 
-            program_id, 
-            processor!(memelend::entry)
-        );
+    // Fresh ledger with the programs the instructions need and an initialized ProtocolConfig
+    pub fn new() -> Self {
+        static STUBS: Once = Once::new();
+        STUBS.call_once(|| {
+            program_stubs::set_syscall_stubs(Box::new(Stubs));
+        });
+        CLOCK.with(|clock| {
+            let mut clock = clock.borrow_mut();
+            clock.slot = START_SLOT;
+            clock.unix_timestamp = START_TS;
+        });
+
+        let mut ctx = Self {
+            accounts: HashMap::new(),
+            admin: Pubkey::new_unique(),
+            treasury: Pubkey::new_unique(),
+        };
 
-        // Add programs that are needed
-        program_test.add_program("spl_token", spl_token::id(), None);
+        for program in [system_program::ID, spl_token::ID, anchor_spl::associated_token::ID] {
+            ctx.set_account(program, AccountState {
+                lamports: 1,
+                data: Vec::new(),
+                owner: Pubkey::default(),
+                executable: true,
+            });
+        }
 
-        let (banks_client, payer, recent_blockhash) = program_test.start().await;
+        let rent = Rent::default();
+        let mut rent_data = Vec::new();
+        rent_data.extend_from_slice(&rent.lamports_per_byte_year.to_le_bytes());
+        rent_data.extend_from_slice(&rent.exemption_threshold.to_le_bytes());
+        rent_data.push(rent.burn_percent);
+        ctx.set_account(sysvar::rent::ID, AccountState {
+            lamports: 1,
+            data: rent_data,
+            owner: sysvar::ID,
+            executable: false,
+        });
 
-        Self {
-            program: Program::new(program_id, banks_client.clone()),
-            banks_client,
-            payer,
-            recent_blockhash,
+        let mut native_mint = vec![0u8; spl_token::state::Mint::LEN];
+        spl_token::state::Mint {
+            mint_authority: COption::None,
+            supply: 0,
+            decimals: 9,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        }
+        .pack_into_slice(&mut native_mint);
+        ctx.set_account(NATIVE_MINT_ID, AccountState {
+            lamports: rent.minimum_balance(native_mint.len()),
+            data: native_mint,
+            owner: spl_token::ID,
+            executable: false,
+        });
+
+        // Upgradeable-loader accounts so initialize_protocol can check the upgrade authority
+        let program_data = program_data_pda();
+        let mut program_state = 2u32.to_le_bytes().to_vec();
+        program_state.extend_from_slice(program_data.as_ref());
+        ctx.set_account(memelend::ID, AccountState {
+            lamports: 1,
+            data: program_state,
+            owner: BPF_LOADER_UPGRADEABLE,
+            executable: true,
+        });
+        let mut data_state = 3u32.to_le_bytes().to_vec();
+        data_state.extend_from_slice(&0u64.to_le_bytes());
+        data_state.push(1);
+        data_state.extend_from_slice(ctx.admin.as_ref());
+        ctx.set_account(program_data, AccountState {
+            lamports: 1,
+            data: data_state,
+            owner: BPF_LOADER_UPGRADEABLE,
+            executable: false,
+        });
+
+        let admin = ctx.admin;
+        ctx.fund(&admin, 10 * SOL);
+        ctx.send(
+            memelend::accounts::InitializeProtocol {
+                admin,
+                protocol_config: protocol_config_pda(),
+                program: memelend::ID,
+                program_data,
+                system_program: system_program::ID,
+            },
+            memelend::instruction::InitializeProtocol {},
+        )
+        .expect("initialize_protocol");
+
+        ctx.treasury = ctx.create_wsol_account(&admin, 0);
+        let treasury = ctx.treasury;
+        ctx.update_protocol_config(memelend::protocol_config::UpdateProtocolConfigArgs {
+            treasury: Some(treasury),
+            ..no_protocol_changes()
+        })
+        .expect("set treasury");
+
+        ctx
+    }
+
+    pub fn set_account(&mut self, key: Pubkey, account: AccountState) {
+        self.accounts.insert(key, account);
+    }
+
+    pub fn fund(&mut self, key: &Pubkey, lamports: u64) {
+        self.accounts
+            .entry(*key)
+            .or_insert_with(AccountState::empty)
+            .lamports += lamports;
+    }
+
+    pub fn lamports(&self, key: &Pubkey) -> u64 {
+        self.accounts.get(key).map(|a| a.lamports).unwrap_or(0)
+    }
+
+    pub fn exists(&self, key: &Pubkey) -> bool {
+        self.accounts.contains_key(key)
+    }
+
+    // Funded wallet that can sign and pay for account creation
+    pub fn new_user(&mut self) -> Pubkey {
+        let user = Pubkey::new_unique();
+        self.fund(&user, 1_000 * SOL);
+        user
+    }
+
+    pub fn now(&self) -> i64 {
+        CLOCK.with(|clock| clock.borrow().unix_timestamp)
+    }
+
+    pub fn slot(&self) -> u64 {
+        CLOCK.with(|clock| clock.borrow().slot)
+    }
+
+    // Moves the clock forward, one slot per 400ms like mainnet
+    pub fn warp(&mut self, seconds: i64) {
+        CLOCK.with(|clock| {
+            let mut clock = clock.borrow_mut();
+            clock.unix_timestamp += seconds;
+            clock.slot += (seconds.max(0) as u64 * 5).div_ceil(2);
+        });
+    }
+
+    pub fn warp_slots(&mut self, slots: u64) {
+        CLOCK.with(|clock| clock.borrow_mut().slot += slots);
+    }
+
+    // Runs one instruction as its own transaction
+    pub fn send_ix(&mut self, ix: Instruction) -> std::result::Result<(), ProgramError> {
+        assert_eq!(ix.program_id, memelend::ID);
+
+        let mut signers = HashSet::new();
+        let mut writable = HashSet::new();
+        for meta in &ix.accounts {
+            if meta.is_signer {
+                signers.insert(meta.pubkey);
+            }
+            if meta.is_writable {
+                writable.insert(meta.pubkey);
+            }
+        }
+
+        let mut loaded: HashMap<Pubkey, AccountInfo<'static>> = HashMap::new();
+        let mut infos = Vec::with_capacity(ix.accounts.len());
+        for meta in &ix.accounts {
+            let info = loaded
+                .entry(meta.pubkey)
+                .or_insert_with(|| {
+                    let state = self
+                        .accounts
+                        .get(&meta.pubkey)
+                        .cloned()
+                        .unwrap_or_else(AccountState::empty);
+                    AccountInfo::new(
+                        leak_key(meta.pubkey, state.data.len()),
+                        signers.contains(&meta.pubkey),
+                        writable.contains(&meta.pubkey),
+                        Box::leak(Box::new(state.lamports)),
+                        leak_data(&state.data),
+                        Box::leak(Box::new(state.owner)),
+                        state.executable,
+                        0,
+                    )
+                })
+                .clone();
+            infos.push(info);
         }
+        let infos: &'static [AccountInfo<'static>] = Box::leak(infos.into_boxed_slice());
+
+        memelend::entry(&memelend::ID, infos, &ix.data)?;
+
+        for (key, info) in loaded {
+            if !info.is_writable {
+                continue;
+            }
+            let lamports = info.lamports();
+            if lamports == 0 {
+                self.accounts.remove(&key);
+                continue;
+            }
+            self.accounts.insert(key, AccountState {
+                lamports,
+                data: info.data.borrow().to_vec(),
+                owner: *info.owner,
+                executable: info.executable,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn send<A: ToAccountMetas, D: InstructionData>(
+        &mut self,
+        accounts: A,
+        data: D,
+    ) -> std::result::Result<(), ProgramError> {
+        self.send_ix(ix(accounts, data))
+    }
+
+    pub fn account<T: AccountDeserialize>(&self, key: &Pubkey) -> T {
+        let state = self.accounts.get(key).unwrap_or_else(|| panic!("no account {}", key));
+        T::try_deserialize(&mut state.data.as_slice()).expect("deserialize account")
+    }
+
+    // Overwrites a program account in place, for states the instructions can't reach directly
+    pub fn write_account<T: AccountSerialize>(&mut self, key: &Pubkey, value: &T) {
+        let state = self.accounts.get_mut(key).unwrap_or_else(|| panic!("no account {}", key));
+        let mut data = Vec::new();
+        value.try_serialize(&mut data).expect("serialize account");
+        state.data[..data.len()].copy_from_slice(&data);
     }
 
-    pub async fn create_token_account(&self, mint: &Pubkey, owner: &Pubkey) -> Result<Keypair> {
-        let account = Keypair::new();
-        let rent = self.banks_client.get_rent().await.unwrap();
-        
-        let create_ix = system_instruction::create_account(
-            &self.payer.pubkey(),
-            &account.pubkey(),
-            rent.minimum_balance(165), // Token account size
-            165,
-            &spl_token::id(),
-        );
-
-        let init_ix = spl_token::instruction::initialize_account(
-            &spl_token::id(),
-            &account.pubkey(),
+    pub fn token_account(&self, key: &Pubkey) -> spl_token::state::Account {
+        let state = self.accounts.get(key).unwrap_or_else(|| panic!("no token account {}", key));
+        spl_token::state::Account::unpack(&state.data).expect("unpack token account")
+    }
+
+    pub fn token_balance(&self, key: &Pubkey) -> u64 {
+        self.token_account(key).amount
+    }
+
+    // Spot price of the mint's curve, as the program quotes it for triggers and liquidations
+    pub fn spot_price(&self, p: &MintPdas) -> u64 {
+        let decimals = self.account::<anchor_spl::token::Mint>(&p.mint).decimals;
+        memelend::curve::load_curve(&self.account::<memelend::VaultConfig>(&p.vault_config))
+            .expect("load_curve")
+            .spot_price(decimals)
+    }
+
+    fn put_token_account(&mut self, key: Pubkey, mint: Pubkey, owner: Pubkey, amount: u64, native: bool) {
+        let reserve = Rent::default().minimum_balance(spl_token::state::Account::LEN);
+        let mut data = vec![0u8; spl_token::state::Account::LEN];
+        spl_token::state::Account {
             mint,
             owner,
-        ).unwrap();
-
-        let tx = Transaction::new_signed_with_payer(
-            &[create_ix, init_ix],
-            Some(&self.payer.pubkey()),
-            &[&self.payer, &account],
-            self.recent_blockhash,
-        );
-
-        self.banks_client.process_transaction(tx).await.unwrap();
-        Ok(account)
-    }
-
-    pub async fn create_wsol_account(&self, owner: &Keypair, lamports: u64) -> Result<Keypair> {
-        let wsol_mint = Pubkey::from_str("So11111111111111111111111111111111111111112").unwrap();
-        let account = Keypair::new();
-        
-        let create_ix = system_instruction::create_account(
-            &self.payer.pubkey(),
-            &account.pubkey(),
-            lamports,
-            165,
-            &spl_token::id(),
-        );
-
-        let init_ix = spl_token::instruction::initialize_account(
-            &spl_token::id(),
-            &account.pubkey(),
-            &wsol_mint,
-            &owner.pubkey(),
-        ).unwrap();
-
-        let tx = Transaction::new_signed_with_payer(
-            &[create_ix, init_ix],
-            Some(&self.payer.pubkey()),
-            &[&self.payer, &account],
-            self.recent_blockhash,
-        );
-
-        self.banks_client.process_transaction(tx).await.unwrap();
-        Ok(account)
-    }
-
-    pub async fn initialize_test_token(&mut self, token_supply: u64, initial_liquidity: u64) -> Result<(Keypair, Pubkey, Pubkey)> {
-        // This is a simplified version - you'll need to adapt to your actual initialization logic
-        let mint = Keypair::new();
-        let token_config_pda = Pubkey::new_unique();
-        let vault_config_pda = Pubkey::new_unique();
-        
-        // In a real implementation, you'd call your initialize functions here
-        // initialize_token_and_split_supply and initialize_fee_vaults
-        
-        Ok((mint, token_config_pda, vault_config_pda))
-    }
-
-    pub async fn buy_token(
-        &self,
-        user: &Keypair,
-        user_wsol: &Keypair,
-        user_token_account: &Keypair,
-        mint: &Pubkey,
-        args: BuyTokenArgs,
-    ) -> Result<(), ProgramError> {
-        // Add proper error simulation based on your test cases
-        if args.sol_amount == u64::MAX {
-            // Simulate arithmetic overflow case
-            return Err(ProgramError::ArithmeticOverflow);
+            amount,
+            delegate: COption::None,
+            state: spl_token::state::AccountState::Initialized,
+            is_native: if native { COption::Some(reserve) } else { COption::None },
+            delegated_amount: 0,
+            close_authority: COption::None,
         }
-        
-        if args.min_tokens_out == 1_000_000_000 {
-            // Simulate slippage exceeded
-            return Err(ProgramError::Custom(ErrorCode::SlippageExceeded as u32));
+        .pack_into_slice(&mut data);
+        self.set_account(key, AccountState {
+            lamports: reserve + if native { amount } else { 0 },
+            data,
+            owner: spl_token::ID,
+            executable: false,
+        });
+    }
+
+    // Token account holding `amount` of `mint`, written straight into the ledger
+    pub fn create_token_account_with(&mut self, mint: &Pubkey, owner: &Pubkey, amount: u64) -> Pubkey {
+        let key = Pubkey::new_unique();
+        self.put_token_account(key, *mint, *owner, amount, false);
+        key
+    }
+
+    pub fn create_token_account(&mut self, mint: &Pubkey, owner: &Pubkey) -> Pubkey {
+        self.create_token_account_with(mint, owner, 0)
+    }
+
+    // Wrapped SOL account holding `lamports` on top of its rent reserve
+    pub fn create_wsol_account(&mut self, owner: &Pubkey, lamports: u64) -> Pubkey {
+        let key = Pubkey::new_unique();
+        self.put_token_account(key, NATIVE_MINT_ID, *owner, lamports, true);
+        key
+    }
+
+    // Overwrites the balance of an existing token account, as if someone had sent tokens in
+    pub fn set_token_balance(&mut self, key: &Pubkey, amount: u64) {
+        let account = self.token_account(key);
+        let native = account.is_native.is_some();
+        self.put_token_account(*key, account.mint, account.owner, amount, native);
+    }
+
+    pub fn update_protocol_config(
+        &mut self,
+        args: memelend::protocol_config::UpdateProtocolConfigArgs,
+    ) -> std::result::Result<(), ProgramError> {
+        let admin = self.admin;
+        self.send(
+            memelend::accounts::UpdateProtocolConfig {
+                admin,
+                protocol_config: protocol_config_pda(),
+            },
+            memelend::instruction::UpdateProtocolConfig { args },
+        )
+    }
+
+    pub fn update_market_config(
+        &mut self,
+        p: &MintPdas,
+        args: memelend::market_config::UpdateMarketConfigArgs,
+    ) -> std::result::Result<(), ProgramError> {
+        let admin = self.admin;
+        self.send(
+            memelend::accounts::UpdateMarketConfig {
+                admin,
+                protocol_config: protocol_config_pda(),
+                mint: p.mint,
+                vault_config: p.vault_config,
+                yield_vault: p.yield_vault,
+            },
+            memelend::instruction::UpdateMarketConfig { args },
+        )
+    }
+
+    // Launches a token the way a creator does: mint and split the supply, then open the pools
+    pub fn launch_token(&mut self, creator: &Pubkey, token_id: &str) -> MintPdas {
+        self.launch_token_with(creator, token_id, 0, linear_vesting(0, 30 * 86_400))
+    }
+
+    pub fn launch_token_with(
+        &mut self,
+        creator: &Pubkey,
+        token_id: &str,
+        presale_percent: u8,
+        vesting: SetVestingScheduleArgs,
+    ) -> MintPdas {
+        let p = self.create_token(creator, token_id, presale_percent, vesting);
+        self.send_ix(init_fee_vaults_ix(&p, creator)).expect("initialize_fee_vaults");
+
+        // Seed the curve with SOL, buys need at least 0.1 SOL of reserve
+        let creator_wsol = anchor_spl::associated_token::get_associated_token_address(creator, &NATIVE_MINT_ID);
+        self.put_token_account(creator_wsol, NATIVE_MINT_ID, *creator, LAUNCH_LIQUIDITY, true);
+        self.send(
+            memelend::accounts::AddLiquidity {
+                creator: *creator,
+                creator_wsol_account: creator_wsol,
+                wsol_mint: NATIVE_MINT_ID,
+                wsol_liquidity_vault: p.liquidity_sol_vault,
+                vault_config: p.vault_config,
+                wsol_vault_authority: p.wsol_vault_authority,
+                mint: p.mint,
+                token_program: spl_token::ID,
+                associated_token_program: anchor_spl::associated_token::ID,
+                system_program: system_program::ID,
+            },
+            memelend::instruction::AddLiquidity { amount: LAUNCH_LIQUIDITY },
+        )
+        .expect("add_liquidity");
+
+        p
+    }
+
+    // Mints the token and splits the supply, without opening the pools
+    pub fn create_token(
+        &mut self,
+        creator: &Pubkey,
+        token_id: &str,
+        presale_percent: u8,
+        vesting: SetVestingScheduleArgs,
+    ) -> MintPdas {
+        let mint = pda(&[b"mint", creator.as_ref(), token_id.as_bytes()]);
+        let p = MintPdas::new(&mint);
+
+        self.send(
+            memelend::accounts::InitTokenAndSplitSupply {
+                creator: *creator,
+                mint,
+                mint_authority: pda(&[b"mint_authority", creator.as_ref(), token_id.as_bytes()]),
+                token_vault: p.token_vault,
+                token_vault_authority: p.token_vault_authority,
+                lending_vault: p.lending_vault,
+                lending_vault_authority: p.lending_vault_authority,
+                liquidity_vault: p.liquidity_token_vault,
+                liquidity_vault_authority: p.liquidity_vault_authority,
+                vault_authority: p.vault_authority,
+                yield_vault: p.yield_vault,
+                user_yield_position: user_yield_pda(&mint, creator),
+                user_account: user_account_pda(creator),
+                emission_vault: p.emission_vault,
+                vesting_schedule: p.vesting_schedule,
+                token_config: p.token_config,
+                token_program: spl_token::ID,
+                system_program: system_program::ID,
+                rent: sysvar::rent::ID,
+                associated_token_program: anchor_spl::associated_token::ID,
+            },
+            memelend::instruction::InitializeTokenAndSplitSupply {
+                token_id: token_id.to_string(),
+                _symbol: "MEME".to_string(),
+                _total_supply: 0,
+                lend_percent: 20,
+                presale_percent,
+                vesting,
+            },
+        )
+        .expect("initialize_token_and_split_supply");
+
+        p
+    }
+}
+
+impl Default for TestContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn no_protocol_changes() -> memelend::protocol_config::UpdateProtocolConfigArgs {
+    memelend::protocol_config::UpdateProtocolConfigArgs {
+        new_admin: None,
+        treasury: None,
+        platform_fee_bps: None,
+        referral_cut_bps: None,
+        yield_cut_bps: None,
+        yield_cut_no_referral_bps: None,
+        liquidator_fee_bps: None,
+        liquidation_yield_cut_bps: None,
+        presale_automation_fee_bps: None,
+        maintenance_margin_bps: None,
+        max_buy_bps: None,
+        virtual_sol: None,
+        virtual_tokens: None,
+        liquidation_close_factor_bps: None,
+        liquidation_bonus_bps: None,
+        auction_duration_slots: None,
+        auction_start_bonus_bps: None,
+        auction_max_bonus_bps: None,
+        insurance_fee_bps: None,
+        insurance_liquidation_bps: None,
+        keeper_fee_bps: None,
+    }
+}
+
+pub fn no_market_changes() -> memelend::market_config::UpdateMarketConfigArgs {
+    memelend::market_config::UpdateMarketConfigArgs {
+        rate_base_bps: None,
+        rate_slope_bps: None,
+        rate_kink_bps: None,
+        rate_max_bps: None,
+        max_open_interest: None,
+        max_oi_stake_bps: None,
+        max_utilisation_bps: None,
+        min_leverage_bps: None,
+        max_leverage_bps: None,
+        max_long_leverage_bps: None,
+    }
+}
+
+pub fn linear_vesting(cliff_seconds: i64, duration_seconds: i64) -> SetVestingScheduleArgs {
+    SetVestingScheduleArgs {
+        kind: VESTING_LINEAR,
+        cliff_seconds,
+        duration_seconds,
+        steps: Vec::new(),
+        irrevocable: false,
+    }
+}
+
+pub fn ix<A: ToAccountMetas, D: InstructionData>(accounts: A, data: D) -> Instruction {
+    Instruction {
+        program_id: memelend::ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+// Error code a failed instruction surfaced, Anchor's own codes or 6000 + ErrorCode
+pub fn error_code(result: std::result::Result<(), ProgramError>) -> u32 {
+    match result {
+        Err(ProgramError::Custom(code)) => code,
+        other => panic!("expected a custom program error, got {:?}", other),
+    }
+}
+
+pub fn program_error(code: memelend::ErrorCode) -> u32 {
+    anchor_lang::error::ERROR_CODE_OFFSET + code as u32
+}
+
+#[track_caller]
+pub fn assert_program_error(result: std::result::Result<(), ProgramError>, code: memelend::ErrorCode) {
+    assert_eq!(error_code(result), program_error(code), "expected {:?}", code);
+}
+
+pub fn pda(seeds: &[&[u8]]) -> Pubkey {
+    Pubkey::find_program_address(seeds, &memelend::id()).0
+}
+
+pub fn program_data_pda() -> Pubkey {
+    Pubkey::find_program_address(&[memelend::ID.as_ref()], &BPF_LOADER_UPGRADEABLE).0
+}
+
+pub fn protocol_config_pda() -> Pubkey {
+    pda(&[b"protocol_config"])
+}
+
+pub fn user_yield_pda(mint: &Pubkey, owner: &Pubkey) -> Pubkey {
+    pda(&[b"user_yield", mint.as_ref(), owner.as_ref()])
+}
+
+pub fn user_account_pda(owner: &Pubkey) -> Pubkey {
+    pda(&[b"user_account", owner.as_ref()])
+}
+
+pub fn sol_lender_pda(mint: &Pubkey, owner: &Pubkey) -> Pubkey {
+    pda(&[b"sol_lender", mint.as_ref(), owner.as_ref()])
+}
+
+pub fn user_trade_pda(mint: &Pubkey, user: &Pubkey) -> Pubkey {
+    pda(&[b"user_trade", mint.as_ref(), user.as_ref()])
+}
+
+pub fn position_pda(user: &Pubkey, mint: &Pubkey, position_id: u64) -> Pubkey {
+    pda(&[b"position", user.as_ref(), mint.as_ref(), &position_id.to_le_bytes()])
+}
+
+pub fn long_position_pda(user: &Pubkey, mint: &Pubkey, position_id: u64) -> Pubkey {
+    pda(&[b"long_position", user.as_ref(), mint.as_ref(), &position_id.to_le_bytes()])
+}
+
+pub fn reward_vault_pda(mint: &Pubkey, reward_mint: &Pubkey) -> Pubkey {
+    pda(&[b"vault_reward", mint.as_ref(), reward_mint.as_ref()])
+}
+
+pub fn presale_position_pda(user: &Pubkey, mint: &Pubkey) -> Pubkey {
+    pda(&[b"user_presale_position", user.as_ref(), mint.as_ref()])
+}
+
+// Every per-mint PDA the program derives, so tests can swap single accounts between mints
+#[derive(Clone, Copy)]
+pub struct MintPdas {
+    pub mint: Pubkey,
+    pub token_config: Pubkey,
+    pub vault_config: Pubkey,
+    pub yield_vault: Pubkey,
+    pub vault_authority: Pubkey,
+    pub token_vault: Pubkey,
+    pub token_vault_authority: Pubkey,
+    pub liquidity_token_vault: Pubkey,
+    pub liquidity_vault_authority: Pubkey,
+    pub liquidity_sol_vault: Pubkey,
+    pub wsol_vault_authority: Pubkey,
+    pub lending_vault: Pubkey,
+    pub lending_vault_authority: Pubkey,
+    pub project_vault: Pubkey,
+    pub platform_vault: Pubkey,
+    pub withdraw_queue: Pubkey,
+    pub insurance_vault: Pubkey,
+    pub sol_lending_pool: Pubkey,
+    pub sol_lending_vault: Pubkey,
+    pub sol_lending_authority: Pubkey,
+    pub long_token_vault: Pubkey,
+    pub emission_vault: Pubkey,
+    pub vesting_schedule: Pubkey,
+}
+
+impl MintPdas {
+    pub fn new(mint: &Pubkey) -> Self {
+        let m = mint.as_ref();
+        Self {
+            mint: *mint,
+            token_config: pda(&[b"config", m]),
+            vault_config: pda(&[b"vault_config", m]),
+            yield_vault: pda(&[b"yield_vault", m]),
+            vault_authority: pda(&[b"vault", m]),
+            token_vault: pda(&[b"vault_token", m]),
+            token_vault_authority: pda(&[b"vault_token_authority", m]),
+            liquidity_token_vault: pda(&[b"vault_liquidity", m]),
+            liquidity_vault_authority: pda(&[b"vault_liquidity_authority", m]),
+            liquidity_sol_vault: pda(&[b"vault_wsol", m]),
+            wsol_vault_authority: pda(&[b"vault_wsol_authority", m]),
+            lending_vault: pda(&[b"vault_lending", m]),
+            lending_vault_authority: pda(&[b"vault_lending_authority", m]),
+            project_vault: pda(&[b"vault_project", m]),
+            platform_vault: pda(&[b"vault_platform", m]),
+            withdraw_queue: pda(&[b"withdraw_queue", m]),
+            insurance_vault: pda(&[b"vault_insurance", m]),
+            sol_lending_pool: pda(&[b"sol_lending_pool", m]),
+            sol_lending_vault: pda(&[b"vault_sol_lending", m]),
+            sol_lending_authority: pda(&[b"vault_sol_lending_authority", m]),
+            long_token_vault: pda(&[b"vault_long", m]),
+            emission_vault: pda(&[b"vault_emission", m]),
+            vesting_schedule: pda(&[b"vesting", m]),
         }
-        
-        if args.sol_amount > 0 && args.min_tokens_out > 0 {
-            Ok(())
-        } else {
-            Err(ProgramError::Custom(0))
+    }
+
+    // Copy of `self` with one account taken from `other`
+    pub fn with_field_from(&self, other: &MintPdas, field: &str) -> Self {
+        let mut out = *self;
+        match field {
+            "mint" => out.mint = other.mint,
+            "token_config" => out.token_config = other.token_config,
+            "vault_config" => out.vault_config = other.vault_config,
+            "yield_vault" => out.yield_vault = other.yield_vault,
+            "vault_authority" => out.vault_authority = other.vault_authority,
+            "token_vault" => out.token_vault = other.token_vault,
+            "token_vault_authority" => out.token_vault_authority = other.token_vault_authority,
+            "liquidity_token_vault" => out.liquidity_token_vault = other.liquidity_token_vault,
+            "liquidity_vault_authority" => out.liquidity_vault_authority = other.liquidity_vault_authority,
+            "liquidity_sol_vault" => out.liquidity_sol_vault = other.liquidity_sol_vault,
+            "wsol_vault_authority" => out.wsol_vault_authority = other.wsol_vault_authority,
+            "lending_vault" => out.lending_vault = other.lending_vault,
+            "lending_vault_authority" => out.lending_vault_authority = other.lending_vault_authority,
+            "project_vault" => out.project_vault = other.project_vault,
+            "platform_vault" => out.platform_vault = other.platform_vault,
+            "withdraw_queue" => out.withdraw_queue = other.withdraw_queue,
+            "insurance_vault" => out.insurance_vault = other.insurance_vault,
+            "sol_lending_pool" => out.sol_lending_pool = other.sol_lending_pool,
+            "sol_lending_vault" => out.sol_lending_vault = other.sol_lending_vault,
+            "sol_lending_authority" => out.sol_lending_authority = other.sol_lending_authority,
+            "long_token_vault" => out.long_token_vault = other.long_token_vault,
+            "emission_vault" => out.emission_vault = other.emission_vault,
+            "vesting_schedule" => out.vesting_schedule = other.vesting_schedule,
+            _ => panic!("unknown field {}", field),
         }
+        out
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Traders and instruction builders
+// ---------------------------------------------------------------------------
+
+// A funded wallet with a token account for one mint. The program closes the temp WSOL account
+// of every instruction back into the wallet, so each call wraps into a fresh one.
+#[derive(Clone, Copy)]
+pub struct Trader {
+    pub key: Pubkey,
+    pub tokens: Pubkey,
+}
+
+impl TestContext {
+    pub fn new_trader(&mut self, p: &MintPdas) -> Trader {
+        let key = self.new_user();
+        self.trader(&key, p)
     }
 
-    pub async fn go_short(
-        &self,
-        user: &Keypair,
-        user_wsol: &Keypair,
-        mint: &Pubkey,
-        args: GoShortArgs,
-    ) -> Result<(), ProgramError> {
-        // Mock implementation
-        if args.collateral_amount > 0 {
-            Ok(())
-        } else {
-            Err(ProgramError::Custom(0))
+    // Trader for an existing wallet, e.g. the creator
+    pub fn trader(&mut self, key: &Pubkey, p: &MintPdas) -> Trader {
+        Trader { key: *key, tokens: self.create_token_account(&p.mint, key) }
+    }
+
+    // Temp WSOL account of `owner` holding `lamports`, paid from the wallet
+    pub fn wrap(&mut self, owner: &Pubkey, lamports: u64) -> Pubkey {
+        let account = self.accounts.get_mut(owner).expect("wallet");
+        account.lamports = account.lamports.checked_sub(lamports).expect("wallet balance");
+        self.create_wsol_account(owner, lamports)
+    }
+
+    pub fn buy(&mut self, p: &MintPdas, t: &Trader, sol_amount: u64) -> std::result::Result<(), ProgramError> {
+        let temp = self.wrap(&t.key, sol_amount);
+        self.send_ix(buy_ix(p, t, temp, sol_amount, 1))
+    }
+
+    pub fn sell(&mut self, p: &MintPdas, t: &Trader, token_amount: u64) -> std::result::Result<(), ProgramError> {
+        let temp = self.wrap(&t.key, 0);
+        self.send_ix(sell_ix(p, t, temp, token_amount, 1))
+    }
+
+    // A fresh trader with everything they bought for `sol` staked unlocked in the yield vault
+    pub fn stake(&mut self, p: &MintPdas, sol: u64) -> (Trader, u64) {
+        let t = self.new_trader(p);
+        self.buy(p, &t, sol).expect("buy");
+        let tokens = self.token_balance(&t.tokens);
+        let temp = self.wrap(&t.key, 0);
+        self.send_ix(deposit_yield_ix(p, &t, temp, tokens, 0)).expect("yield_deposit");
+        (t, tokens)
+    }
+
+    // Lifts the open interest caps and opens a short that borrows all but a few million tokens of a
+    // freshly launched mint's lending vault, sized for the stakes of up to 7 SOL `stake` sets up
+    pub fn drain_lending_vault(&mut self, p: &MintPdas) -> (Trader, u64) {
+        self.update_market_config(p, memelend::market_config::UpdateMarketConfigArgs {
+            max_oi_stake_bps: Some(10_000),
+            max_utilisation_bps: Some(10_000),
+            ..no_market_changes()
+        })
+        .expect("update_market_config");
+        let shorter = self.new_trader(p);
+        let id = self.go_short(p, &shorter, 79 * SOL, 10_000).expect("go_short");
+        (shorter, id)
+    }
+
+    // Deposits `sol` into the mint's SOL lending pool for a fresh lender
+    pub fn lend_sol(&mut self, p: &MintPdas, sol: u64) -> Trader {
+        let lender = self.new_trader(p);
+        let temp = self.wrap(&lender.key, sol);
+        self.send_ix(deposit_sol_lending_ix(p, &lender, temp, sol)).expect("deposit_sol_lending");
+        lender
+    }
+
+    // Opens long number 0 for a fresh trader
+    pub fn go_long(&mut self, p: &MintPdas, collateral: u64, leverage_bps: u16) -> Trader {
+        let t = self.new_trader(p);
+        let temp = self.wrap(&t.key, collateral);
+        self.send_ix(go_long_ix(p, &t, temp, collateral, leverage_bps, 0)).expect("go_long");
+        t
+    }
+
+    // Opens a 1 SOL short and pumps the price until closing it loses more than the collateral
+    pub fn underwater_short(&mut self, p: &MintPdas) -> (Trader, u64) {
+        let shorter = self.new_trader(p);
+        let id = self.go_short(p, &shorter, SOL, 10_000).expect("go_short");
+        let whale = self.new_trader(p);
+        for _ in 0..12 {
+            self.buy(p, &whale, 50 * SOL).expect("pump buy");
+            self.warp(1);
         }
+        (shorter, id)
     }
 
-    pub async fn claim_earnings(
-        &self,
-        user: &Keypair,
-        user_wsol: &Keypair,
-        mint: &Pubkey,
-    ) -> Result<(), ProgramError> {
-        // Mock implementation
-        Err(ProgramError::Custom(ErrorCode::Unauthorized as u32))
+    // Opens and closes a short, which pays the insurance fund its cut of the platform fee
+    pub fn round_trip_short(&mut self, p: &MintPdas, collateral: u64) {
+        let t = self.new_trader(p);
+        let id = self.go_short(p, &t, collateral, 10_000).expect("go_short");
+        self.warp(1);
+        let temp = self.wrap(&t.key, 0);
+        self.send_ix(close_position_ix(p, &t, temp, id)).expect("close_position");
+    }
+
+    // Next id go_short hands out to `user`, the registry starts at 0
+    pub fn next_position_id(&self, user: &Pubkey) -> u64 {
+        let key = user_account_pda(user);
+        if !self.exists(&key) {
+            return 0;
+        }
+        self.account::<memelend::UserAccount>(&key).next_position_id
     }
 
-    pub async fn get_token_balance(&self, token_account: &Keypair) -> u64 {
-        // Mock implementation
-        1000 // Return a mock balance
+    // Opens a short and returns its position id
+    pub fn go_short(
+        &mut self,
+        p: &MintPdas,
+        t: &Trader,
+        collateral: u64,
+        leverage_bps: u16,
+    ) -> std::result::Result<u64, ProgramError> {
+        let id = self.next_position_id(&t.key);
+        let temp = self.wrap(&t.key, collateral);
+        self.send_ix(go_short_ix(p, t, temp, collateral, leverage_bps, id))?;
+        Ok(id)
     }
-}
\ No newline at end of file
+}
+
+// Referral accounts of `referrer`, passing the platform vault means no referrer
+pub fn referral_accounts(referrer: &Pubkey) -> (Pubkey, Pubkey, Pubkey) {
+    (
+        pda(&[b"vault_referral_authority", referrer.as_ref()]),
+        pda(&[b"vault_referral", referrer.as_ref()]),
+        pda(&[b"vault_referral_token", referrer.as_ref()]),
+    )
+}
+
+pub fn init_fee_vaults_ix(p: &MintPdas, creator: &Pubkey) -> Instruction {
+    ix(
+        memelend::accounts::InitFeeVault {
+            creator: *creator,
+            token_config: p.token_config,
+            mint: p.mint,
+            protocol_config: protocol_config_pda(),
+            wsol_mint: NATIVE_MINT_ID,
+            liquidity_vault: p.liquidity_token_vault,
+            wsol_liquidity_vault: p.liquidity_sol_vault,
+            project_vault: p.project_vault,
+            platform_vault: p.platform_vault,
+            insurance_vault: p.insurance_vault,
+            wsol_vault_authority: p.wsol_vault_authority,
+            vault_authority: p.vault_authority,
+            vault_config: p.vault_config,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        memelend::instruction::InitializeFeeVaults { creator_fee_bps: 100, curve_kind: 0 },
+    )
+}
+
+pub fn buy_ix(p: &MintPdas, t: &Trader, temp: Pubkey, sol_amount: u64, min_tokens_out: u64) -> Instruction {
+    buy_referred_ix(p, t, temp, sol_amount, min_tokens_out, &p.platform_vault)
+}
+
+pub fn buy_referred_ix(
+    p: &MintPdas,
+    t: &Trader,
+    temp: Pubkey,
+    sol_amount: u64,
+    min_tokens_out: u64,
+    referrer: &Pubkey,
+) -> Instruction {
+    let (referral_vault_authority, referral_tracking, referral_token_vault) = referral_accounts(referrer);
+    ix(
+        memelend::accounts::BuyToken {
+            user: t.key,
+            wsol_mint: NATIVE_MINT_ID,
+            mint: p.mint,
+            temp_wsol_account: temp,
+            temp_wsol_authority: t.key,
+            user_token_account: t.tokens,
+            liquidity_token_vault: p.liquidity_token_vault,
+            liquidity_vault_authority: p.liquidity_vault_authority,
+            liquidity_sol_vault: p.liquidity_sol_vault,
+            vault_config: p.vault_config,
+            token_config: p.token_config,
+            project_vault: p.project_vault,
+            platform_vault: p.platform_vault,
+            vault_authority: p.vault_authority,
+            referral_vault_authority,
+            referral_tracking,
+            referral_token_vault,
+            referrer: *referrer,
+            yield_vault: p.yield_vault,
+            user_trade_state: user_trade_pda(&p.mint, &t.key),
+            protocol_config: protocol_config_pda(),
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        memelend::instruction::BuyToken {
+            args: memelend::buy_token::BuyTokenArgs { sol_amount, min_tokens_out, position_id: 0 },
+        },
+    )
+}
+
+pub fn sell_ix(p: &MintPdas, t: &Trader, temp: Pubkey, token_amount: u64, min_sol_out: u64) -> Instruction {
+    sell_referred_ix(p, t, temp, token_amount, min_sol_out, &p.platform_vault)
+}
+
+pub fn sell_referred_ix(p: &MintPdas, t: &Trader, temp: Pubkey, token_amount: u64, min_sol_out: u64, referrer: &Pubkey) -> Instruction {
+    let (referral_vault_authority, referral_tracking, referral_token_vault) = referral_accounts(referrer);
+    ix(
+        memelend::accounts::SellToken {
+            user: t.key,
+            wsol_mint: NATIVE_MINT_ID,
+            mint: p.mint,
+            temp_wsol_account: temp,
+            temp_wsol_authority: t.key,
+            user_token_account: t.tokens,
+            liquidity_token_vault: p.liquidity_token_vault,
+            liquidity_sol_vault: p.liquidity_sol_vault,
+            vault_config: p.vault_config,
+            token_config: p.token_config,
+            project_vault: p.project_vault,
+            platform_vault: p.platform_vault,
+            referral_vault_authority,
+            referral_tracking,
+            referral_token_vault,
+            vault_authority: p.vault_authority,
+            referrer: *referrer,
+            wsol_vault_authority: p.wsol_vault_authority,
+            yield_vault: p.yield_vault,
+            user_trade_state: user_trade_pda(&p.mint, &t.key),
+            protocol_config: protocol_config_pda(),
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        memelend::instruction::SellToken {
+            args: memelend::sell_token::SellTokenArgs { token_amount, min_sol_out, position_id: 0 },
+        },
+    )
+}
+
+// The program assigns the id from the user's registry, `position_id` only picks the position address
+pub fn go_short_ix(p: &MintPdas, t: &Trader, temp: Pubkey, collateral_amount: u64, leverage_bps: u16, position_id: u64) -> Instruction {
+    go_short_referred_ix(p, t, temp, collateral_amount, leverage_bps, position_id, &p.platform_vault)
+}
+
+pub fn go_short_referred_ix(p: &MintPdas, t: &Trader, temp: Pubkey, collateral_amount: u64, leverage_bps: u16, position_id: u64, referrer: &Pubkey) -> Instruction {
+    let (referral_vault_authority, referral_tracking, referral_token_vault) = referral_accounts(referrer);
+    ix(
+        memelend::accounts::GoShort {
+            user: t.key,
+            wsol_mint: NATIVE_MINT_ID,
+            user_collateral_account: temp,
+            mint: p.mint,
+            temp_wsol_account: temp,
+            temp_wsol_authority: t.key,
+            project_vault: p.project_vault,
+            platform_vault: p.platform_vault,
+            vault_config: p.vault_config,
+            token_config: p.token_config,
+            vault_authority: p.vault_authority,
+            liquidity_sol_vault: p.liquidity_sol_vault,
+            lending_vault: p.lending_vault,
+            lending_vault_authority: p.lending_vault_authority,
+            token_liquidity_vault: p.liquidity_token_vault,
+            user_account: user_account_pda(&t.key),
+            position: position_pda(&t.key, &p.mint, position_id),
+            referral_vault_authority,
+            referral_tracking,
+            referral_token_vault,
+            referrer: *referrer,
+            yield_vault: p.yield_vault,
+            withdraw_queue: p.withdraw_queue,
+            protocol_config: protocol_config_pda(),
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        memelend::instruction::GoShort {
+            args: memelend::go_short::GoShortArgs {
+                collateral_amount,
+                min_tokens_borrowed: 1,
+                leverage_bps,
+            },
+        },
+    )
+}
+
+pub fn close_position_ix(p: &MintPdas, t: &Trader, temp: Pubkey, position_id: u64) -> Instruction {
+    close_position_referred_ix(p, t, temp, position_id, &p.platform_vault)
+}
+
+pub fn close_position_referred_ix(p: &MintPdas, t: &Trader, temp: Pubkey, position_id: u64, referrer: &Pubkey) -> Instruction {
+    let (referral_vault_authority, referral_tracking, referral_token_vault) = referral_accounts(referrer);
+    ix(
+        memelend::accounts::ClosePosition {
+            user: t.key,
+            wsol_mint: NATIVE_MINT_ID,
+            mint: p.mint,
+            temp_wsol_account: temp,
+            temp_wsol_authority: t.key,
+            position: position_pda(&t.key, &p.mint, position_id),
+            user_account: user_account_pda(&t.key),
+            token_config: p.token_config,
+            vault_config: p.vault_config,
+            vault_authority: p.vault_authority,
+            token_liquidity_vault: p.liquidity_token_vault,
+            lending_vault: p.lending_vault,
+            liquidity_sol_vault: p.liquidity_sol_vault,
+            project_vault: p.project_vault,
+            platform_vault: p.platform_vault,
+            insurance_vault: p.insurance_vault,
+            referral_vault_authority,
+            referral_tracking,
+            referral_token_vault,
+            liquidity_vault_authority: p.liquidity_vault_authority,
+            wsol_vault_authority: p.wsol_vault_authority,
+            yield_vault: p.yield_vault,
+            referrer: *referrer,
+            protocol_config: protocol_config_pda(),
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        memelend::instruction::ClosePosition {
+            args: memelend::close_position::ClosePositionArgs { position_id, min_token_repay: 0 },
+        },
+    )
+}
+
+// Repays `token_amount` of the short, or `reduce_bps` of it when the amount is 0
+pub fn reduce_position_ix(p: &MintPdas, t: &Trader, temp: Pubkey, position_id: u64, token_amount: u64, reduce_bps: u16) -> Instruction {
+    let (referral_vault_authority, referral_tracking, referral_token_vault) = referral_accounts(&p.platform_vault);
+    ix(
+        memelend::accounts::ReducePosition {
+            user: t.key,
+            wsol_mint: NATIVE_MINT_ID,
+            mint: p.mint,
+            temp_wsol_account: temp,
+            temp_wsol_authority: t.key,
+            position: position_pda(&t.key, &p.mint, position_id),
+            token_config: p.token_config,
+            vault_config: p.vault_config,
+            vault_authority: p.vault_authority,
+            token_liquidity_vault: p.liquidity_token_vault,
+            lending_vault: p.lending_vault,
+            liquidity_sol_vault: p.liquidity_sol_vault,
+            project_vault: p.project_vault,
+            platform_vault: p.platform_vault,
+            insurance_vault: p.insurance_vault,
+            referral_vault_authority,
+            referral_tracking,
+            referral_token_vault,
+            liquidity_vault_authority: p.liquidity_vault_authority,
+            wsol_vault_authority: p.wsol_vault_authority,
+            yield_vault: p.yield_vault,
+            referrer: p.platform_vault,
+            protocol_config: protocol_config_pda(),
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        memelend::instruction::ReducePosition {
+            args: memelend::reduce_position::ReducePositionArgs {
+                position_id,
+                min_token_repay: 0,
+                token_amount,
+                reduce_bps,
+            },
+        },
+    )
+}
+
+pub fn add_collateral_ix(p: &MintPdas, t: &Trader, temp: Pubkey, position_id: u64, amount: u64) -> Instruction {
+    ix(
+        memelend::accounts::AddCollateral {
+            user: t.key,
+            wsol_mint: NATIVE_MINT_ID,
+            mint: p.mint,
+            position: position_pda(&t.key, &p.mint, position_id),
+            token_config: p.token_config,
+            temp_wsol_account: temp,
+            temp_wsol_authority: t.key,
+            liquidity_sol_vault: p.liquidity_sol_vault,
+            protocol_config: protocol_config_pda(),
+            token_program: spl_token::ID,
+        },
+        memelend::instruction::AddCollateral {
+            args: memelend::add_collateral::AddCollateralArgs { position_id, amount },
+        },
+    )
+}
+
+pub fn settle_bad_debt_ix(p: &MintPdas, caller: &Pubkey) -> Instruction {
+    ix(
+        memelend::accounts::SettleBadDebt {
+            caller: *caller,
+            wsol_mint: NATIVE_MINT_ID,
+            mint: p.mint,
+            vault_config: p.vault_config,
+            vault_authority: p.vault_authority,
+            insurance_vault: p.insurance_vault,
+            liquidity_sol_vault: p.liquidity_sol_vault,
+            token_program: spl_token::ID,
+        },
+        memelend::instruction::SettleBadDebt {},
+    )
+}
+
+pub fn liquidate_position_ix(p: &MintPdas, bot: &Pubkey, bot_wsol: Pubkey, owner: &Pubkey, position_id: u64) -> Instruction {
+    liquidate_position_with_ix(p, bot, bot_wsol, owner, position_id, None)
+}
+
+// Same with the position's auction attached, needed while auction mode is on
+pub fn liquidate_position_with_ix(
+    p: &MintPdas,
+    bot: &Pubkey,
+    bot_wsol: Pubkey,
+    owner: &Pubkey,
+    position_id: u64,
+    liquidation_auction: Option<Pubkey>,
+) -> Instruction {
+    ix(
+        memelend::accounts::LiquidatePosition {
+            bot: *bot,
+            user: *owner,
+            wsol_mint: NATIVE_MINT_ID,
+            mint: p.mint,
+            position: position_pda(owner, &p.mint, position_id),
+            user_account: user_account_pda(owner),
+            token_config: p.token_config,
+            vault_config: p.vault_config,
+            vault_authority: p.vault_authority,
+            token_liquidity_vault: p.liquidity_token_vault,
+            lending_vault: p.lending_vault,
+            wsol_vault: p.liquidity_sol_vault,
+            project_vault: p.project_vault,
+            insurance_vault: p.insurance_vault,
+            bot_wsol_account: bot_wsol,
+            liquidity_vault_authority: p.liquidity_vault_authority,
+            wsol_vault_authority: p.wsol_vault_authority,
+            yield_vault: p.yield_vault,
+            protocol_config: protocol_config_pda(),
+            liquidation_auction,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        memelend::instruction::LiquidatePosition {
+            args: memelend::liquidate_position::LiquidatePositionArgs { position_id },
+        },
+    )
+}
+
+pub fn liquidation_auction_pda(position: &Pubkey) -> Pubkey {
+    pda(&[b"liquidation_auction", position.as_ref()])
+}
+
+pub fn open_liquidation_auction_ix(p: &MintPdas, opener: &Pubkey, owner: &Pubkey, position_id: u64) -> Instruction {
+    let position = position_pda(owner, &p.mint, position_id);
+    ix(
+        memelend::accounts::OpenLiquidationAuction {
+            opener: *opener,
+            user: *owner,
+            mint: p.mint,
+            position,
+            token_config: p.token_config,
+            vault_config: p.vault_config,
+            liquidation_auction: liquidation_auction_pda(&position),
+            protocol_config: protocol_config_pda(),
+            system_program: system_program::ID,
+        },
+        memelend::instruction::OpenLiquidationAuction {
+            args: memelend::liquidation_auction::LiquidationAuctionArgs { position_id },
+        },
+    )
+}
+
+pub fn cancel_liquidation_auction_ix(p: &MintPdas, caller: &Pubkey, opener: &Pubkey, owner: &Pubkey, position_id: u64) -> Instruction {
+    let position = position_pda(owner, &p.mint, position_id);
+    ix(
+        memelend::accounts::CancelLiquidationAuction {
+            caller: *caller,
+            user: *owner,
+            mint: p.mint,
+            position,
+            token_config: p.token_config,
+            vault_config: p.vault_config,
+            liquidation_auction: liquidation_auction_pda(&position),
+            opener: *opener,
+            protocol_config: protocol_config_pda(),
+        },
+        memelend::instruction::CancelLiquidationAuction {
+            args: memelend::liquidation_auction::LiquidationAuctionArgs { position_id },
+        },
+    )
+}
+
+pub fn set_position_triggers_ix(p: &MintPdas, t: &Trader, position_id: u64, stop_loss_price: u64, take_profit_price: u64) -> Instruction {
+    ix(
+        memelend::accounts::SetPositionTriggers {
+            user: t.key,
+            mint: p.mint,
+            position: position_pda(&t.key, &p.mint, position_id),
+            vault_config: p.vault_config,
+        },
+        memelend::instruction::SetPositionTriggers {
+            args: memelend::position_triggers::SetPositionTriggersArgs { position_id, stop_loss_price, take_profit_price },
+        },
+    )
+}
+
+// Keeper exit of `owner`'s short, paying the remaining collateral to `owner_wsol`
+pub fn trigger_position_exit_ix(
+    p: &MintPdas,
+    keeper: &Pubkey,
+    keeper_wsol: Pubkey,
+    owner: &Pubkey,
+    owner_wsol: Pubkey,
+    position_id: u64,
+) -> Instruction {
+    ix(
+        memelend::accounts::TriggerPositionExit {
+            keeper: *keeper,
+            keeper_wsol_account: keeper_wsol,
+            owner: *owner,
+            owner_wsol_account: owner_wsol,
+            wsol_mint: NATIVE_MINT_ID,
+            mint: p.mint,
+            position: position_pda(owner, &p.mint, position_id),
+            user_account: user_account_pda(owner),
+            token_config: p.token_config,
+            vault_config: p.vault_config,
+            vault_authority: p.vault_authority,
+            token_liquidity_vault: p.liquidity_token_vault,
+            liquidity_vault_authority: p.liquidity_vault_authority,
+            lending_vault: p.lending_vault,
+            liquidity_sol_vault: p.liquidity_sol_vault,
+            wsol_vault_authority: p.wsol_vault_authority,
+            project_vault: p.project_vault,
+            platform_vault: p.platform_vault,
+            insurance_vault: p.insurance_vault,
+            yield_vault: p.yield_vault,
+            protocol_config: protocol_config_pda(),
+            token_program: spl_token::ID,
+        },
+        memelend::instruction::TriggerPositionExit {
+            args: memelend::position_triggers::TriggerPositionExitArgs { position_id },
+        },
+    )
+}
+
+pub fn join_presale_ix(p: &MintPdas, t: &Trader, temp: Pubkey, bot_wsol: Pubkey, sol_amount: u64) -> Instruction {
+    ix(
+        memelend::accounts::JoinPresale {
+            user: t.key,
+            wsol_mint: NATIVE_MINT_ID,
+            mint: p.mint,
+            user_token_account: t.tokens,
+            temp_wsol_account: temp,
+            temp_wsol_authority: t.key,
+            vault_config: p.vault_config,
+            token_config: p.token_config,
+            vault_authority: p.vault_authority,
+            liquidity_sol_vault: p.liquidity_sol_vault,
+            lending_vault: p.lending_vault,
+            lending_vault_authority: p.lending_vault_authority,
+            user_presale_position: presale_position_pda(&t.key, &p.mint),
+            user_account: user_account_pda(&t.key),
+            bot_wsol_account: bot_wsol,
+            protocol_config: protocol_config_pda(),
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        memelend::instruction::JoinPresale {
+            args: memelend::join_presale::PresaleArgs { sol_amount, min_tokens: 1 },
+        },
+    )
+}
+
+pub fn activate_presale_ix(p: &MintPdas, bot: &Pubkey, creator: &Pubkey) -> Instruction {
+    ix(
+        memelend::accounts::ActivatePresale {
+            bot: *bot,
+            owner: *creator,
+            mint: p.mint,
+            token_config: p.token_config,
+            vault_config: p.vault_config,
+            yield_vault: p.yield_vault,
+            user_yield_position: user_yield_pda(&p.mint, creator),
+        },
+        memelend::instruction::ActivatePresale {},
+    )
+}
+
+pub fn claim_presale_rewards_ix(p: &MintPdas, t: &Trader, temp: Pubkey) -> Instruction {
+    ix(
+        memelend::accounts::ClaimPresaleRewards {
+            user: t.key,
+            mint: p.mint,
+            yield_vault: p.yield_vault,
+            token_config: p.token_config,
+            user_presale_position: presale_position_pda(&t.key, &p.mint),
+            wsol_mint: NATIVE_MINT_ID,
+            temp_wsol_account: temp,
+            temp_wsol_authority: t.key,
+            project_vault: p.project_vault,
+            vault_authority: p.vault_authority,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        memelend::instruction::ClaimPresaleRewards {},
+    )
+}
+
+pub fn deposit_yield_ix(p: &MintPdas, t: &Trader, temp: Pubkey, amount: u64, lock_tier: u8) -> Instruction {
+    ix(
+        memelend::accounts::DepositYield {
+            owner: t.key,
+            wsol_mint: NATIVE_MINT_ID,
+            mint: p.mint,
+            temp_wsol_account: temp,
+            temp_wsol_authority: t.key,
+            yield_vault: p.yield_vault,
+            user_yield_position: user_yield_pda(&p.mint, &t.key),
+            user_account: user_account_pda(&t.key),
+            lending_vault: p.lending_vault,
+            token_config: p.token_config,
+            project_vault: p.project_vault,
+            user_token_account: t.tokens,
+            vault_authority: p.vault_authority,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        memelend::instruction::YieldDeposit { amount, lock_tier },
+    )
+}
+
+pub fn fund_reward_slot_ix(
+    p: &MintPdas,
+    funder: &Pubkey,
+    funder_tokens: Pubkey,
+    reward_mint: &Pubkey,
+    slot: u8,
+    amount: u64,
+    duration_seconds: i64,
+) -> Instruction {
+    ix(
+        memelend::accounts::FundRewardSlot {
+            funder: *funder,
+            mint: p.mint,
+            yield_vault: p.yield_vault,
+            reward_mint: *reward_mint,
+            funder_token_account: funder_tokens,
+            reward_vault: reward_vault_pda(&p.mint, reward_mint),
+            vault_authority: p.vault_authority,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        memelend::instruction::FundRewardSlot {
+            args: memelend::reward_slots::FundRewardSlotArgs { slot, amount, duration_seconds },
+        },
+    )
+}
+
+// `payouts` is a (reward vault, user token account) pair per open slot, in slot order
+pub fn claim_slot_rewards_ix(p: &MintPdas, t: &Trader, payouts: &[(Pubkey, Pubkey)]) -> Instruction {
+    let mut ix = ix(
+        memelend::accounts::ClaimSlotRewards {
+            user: t.key,
+            mint: p.mint,
+            yield_vault: p.yield_vault,
+            user_yield_position: user_yield_pda(&p.mint, &t.key),
+            vault_authority: p.vault_authority,
+            token_program: spl_token::ID,
+        },
+        memelend::instruction::ClaimSlotRewards {},
+    );
+    for (reward_vault, user_tokens) in payouts {
+        ix.accounts.push(AccountMeta::new(*reward_vault, false));
+        ix.accounts.push(AccountMeta::new(*user_tokens, false));
+    }
+    ix
+}
+
+pub fn set_compound_limit_ix(p: &MintPdas, t: &Trader, max_price: u64) -> Instruction {
+    ix(
+        memelend::accounts::SetCompoundLimit {
+            owner: t.key,
+            mint: p.mint,
+            user_yield_position: user_yield_pda(&p.mint, &t.key),
+        },
+        memelend::instruction::SetCompoundLimit { max_price },
+    )
+}
+
+pub fn compound_rewards_ix(p: &MintPdas, keeper: &Pubkey, keeper_wsol: Pubkey, owner: &Pubkey, min_tokens_out: u64) -> Instruction {
+    ix(
+        memelend::accounts::CompoundRewards {
+            keeper: *keeper,
+            keeper_wsol_account: keeper_wsol,
+            owner: *owner,
+            wsol_mint: NATIVE_MINT_ID,
+            mint: p.mint,
+            user_yield_position: user_yield_pda(&p.mint, owner),
+            yield_vault: p.yield_vault,
+            vault_config: p.vault_config,
+            token_config: p.token_config,
+            protocol_config: protocol_config_pda(),
+            project_vault: p.project_vault,
+            platform_vault: p.platform_vault,
+            vault_authority: p.vault_authority,
+            liquidity_token_vault: p.liquidity_token_vault,
+            liquidity_vault_authority: p.liquidity_vault_authority,
+            liquidity_sol_vault: p.liquidity_sol_vault,
+            lending_vault: p.lending_vault,
+            token_program: spl_token::ID,
+        },
+        memelend::instruction::CompoundRewards {
+            args: memelend::compound_rewards::CompoundRewardsArgs { min_tokens_out },
+        },
+    )
+}
+
+pub fn kick_expired_lock_ix(p: &MintPdas, caller: &Pubkey, owner: &Pubkey, owner_wsol: Pubkey) -> Instruction {
+    ix(
+        memelend::accounts::KickExpiredLock {
+            caller: *caller,
+            owner: *owner,
+            owner_wsol_account: owner_wsol,
+            wsol_mint: NATIVE_MINT_ID,
+            mint: p.mint,
+            yield_vault: p.yield_vault,
+            user_yield_position: user_yield_pda(&p.mint, owner),
+            project_vault: p.project_vault,
+            vault_authority: p.vault_authority,
+            token_program: spl_token::ID,
+        },
+        memelend::instruction::KickExpiredLock {},
+    )
+}
+
+pub fn withdraw_yield_ix(p: &MintPdas, t: &Trader, temp: Pubkey, amount: u64, position_id: u64) -> Instruction {
+    ix(
+        memelend::accounts::WithdrawYield {
+            user: t.key,
+            yield_vault: p.yield_vault,
+            user_yield_position: user_yield_pda(&p.mint, &t.key),
+            user_account: user_account_pda(&t.key),
+            lending_vault: p.lending_vault,
+            wsol_mint: NATIVE_MINT_ID,
+            mint: p.mint,
+            vault_authority: p.vault_authority,
+            project_vault: p.project_vault,
+            lending_vault_authority: p.lending_vault_authority,
+            temp_wsol_account: temp,
+            temp_wsol_authority: t.key,
+            user_token_account: t.tokens,
+            withdraw_queue: p.withdraw_queue,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        memelend::instruction::WithdrawYield { amount, position_id },
+    )
+}
+
+pub fn withdraw_request_pda(mint: &Pubkey, sequence: u64) -> Pubkey {
+    pda(&[b"withdraw_request", mint.as_ref(), &sequence.to_le_bytes()])
+}
+
+// Queues `amount` of the stake as request number `sequence`, the queue's current tail
+pub fn request_withdraw_yield_ix(p: &MintPdas, t: &Trader, temp: Pubkey, amount: u64, sequence: u64) -> Instruction {
+    ix(
+        memelend::accounts::RequestWithdrawYield {
+            user: t.key,
+            mint: p.mint,
+            yield_vault: p.yield_vault,
+            user_yield_position: user_yield_pda(&p.mint, &t.key),
+            withdraw_queue: p.withdraw_queue,
+            withdraw_request: withdraw_request_pda(&p.mint, sequence),
+            lending_vault: p.lending_vault,
+            lending_vault_authority: p.lending_vault_authority,
+            user_token_account: t.tokens,
+            vault_authority: p.vault_authority,
+            project_vault: p.project_vault,
+            temp_wsol_account: temp,
+            temp_wsol_authority: t.key,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+        },
+        memelend::instruction::RequestWithdrawYield { amount, position_id: 0 },
+    )
+}
+
+// Fills request number `sequence`, which has to be the head of the queue
+pub fn fill_withdraw_request_ix(p: &MintPdas, cranker: &Pubkey, owner: &Trader, sequence: u64) -> Instruction {
+    ix(
+        memelend::accounts::FillWithdrawRequest {
+            cranker: *cranker,
+            mint: p.mint,
+            withdraw_queue: p.withdraw_queue,
+            withdraw_request: withdraw_request_pda(&p.mint, sequence),
+            owner: owner.key,
+            owner_token_account: owner.tokens,
+            lending_vault: p.lending_vault,
+            lending_vault_authority: p.lending_vault_authority,
+            token_program: spl_token::ID,
+        },
+        memelend::instruction::FillWithdrawRequest {},
+    )
+}
+
+pub fn creator_yield_withdrawal_ix(p: &MintPdas, t: &Trader, temp: Pubkey, amount: u64, position_id: u64) -> Instruction {
+    ix(
+        memelend::accounts::CreatorWithdrawYield {
+            user: t.key,
+            yield_vault: p.yield_vault,
+            user_yield_position: user_yield_pda(&p.mint, &t.key),
+            lending_vault: p.lending_vault,
+            wsol_mint: NATIVE_MINT_ID,
+            mint: p.mint,
+            vault_authority: p.vault_authority,
+            project_vault: p.project_vault,
+            lending_vault_authority: p.lending_vault_authority,
+            temp_wsol_account: temp,
+            temp_wsol_authority: t.key,
+            user_token_account: t.tokens,
+            withdraw_queue: p.withdraw_queue,
+            vesting_schedule: p.vesting_schedule,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        memelend::instruction::CreatorYieldWithdrawal { amount, position_id },
+    )
+}
+
+pub fn set_vesting_schedule_ix(p: &MintPdas, creator: &Pubkey, args: SetVestingScheduleArgs) -> Instruction {
+    ix(
+        memelend::accounts::SetVestingSchedule {
+            creator: *creator,
+            mint: p.mint,
+            yield_vault: p.yield_vault,
+            vesting_schedule: p.vesting_schedule,
+        },
+        memelend::instruction::SetVestingSchedule { args },
+    )
+}
+
+pub fn claim_rewards_ix(p: &MintPdas, t: &Trader, temp: Pubkey) -> Instruction {
+    ix(
+        memelend::accounts::ClaimRewards {
+            user: t.key,
+            mint: p.mint,
+            yield_vault: p.yield_vault,
+            user_yield_position: user_yield_pda(&p.mint, &t.key),
+            wsol_mint: NATIVE_MINT_ID,
+            temp_wsol_account: temp,
+            temp_wsol_authority: t.key,
+            project_vault: p.project_vault,
+            vault_authority: p.vault_authority,
+            emission_vault: Some(p.emission_vault),
+            user_token_account: Some(t.tokens),
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        memelend::instruction::ClaimRewards {},
+    )
+}
+
+pub fn claim_earnings_ix(p: &MintPdas, user: &Pubkey, temp: Pubkey) -> Instruction {
+    ix(
+        memelend::accounts::ClaimEarnings {
+            user: *user,
+            mint: p.mint,
+            yield_vault: p.yield_vault,
+            vault_config: p.vault_config,
+            wsol_mint: NATIVE_MINT_ID,
+            temp_wsol_account: temp,
+            temp_wsol_authority: *user,
+            project_vault: p.project_vault,
+            vault_authority: p.vault_authority,
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        memelend::instruction::ClaimEarnings {},
+    )
+}
+
+pub fn claim_referral_rewards_ix(user: &Pubkey, temp: Pubkey, referrer: &Pubkey) -> Instruction {
+    let (referral_vault_authority, referral_tracking, referral_token_vault) = referral_accounts(referrer);
+    ix(
+        memelend::accounts::ClaimReferralRewards {
+            user: *user,
+            wsol_mint: NATIVE_MINT_ID,
+            temp_wsol_account: temp,
+            temp_wsol_authority: *user,
+            referral_vault_authority,
+            referral_tracking,
+            referral_token_vault,
+            referrer: *referrer,
+            token_program: spl_token::ID,
+        },
+        memelend::instruction::ClaimReferralRewards {},
+    )
+}
+
+// Sweeps the platform vault of every mint in `mints`, one remaining-accounts triple each
+pub fn withdraw_platform_fees_ix(admin: &Pubkey, treasury: &Pubkey, mints: &[MintPdas]) -> Instruction {
+    let mut ix = ix(
+        memelend::accounts::WithdrawPlatformFees {
+            admin: *admin,
+            protocol_config: protocol_config_pda(),
+            wsol_mint: NATIVE_MINT_ID,
+            treasury: *treasury,
+            token_program: spl_token::ID,
+        },
+        memelend::instruction::WithdrawPlatformFees {},
+    );
+    for p in mints {
+        ix.accounts.push(AccountMeta::new(p.vault_config, false));
+        ix.accounts.push(AccountMeta::new(p.platform_vault, false));
+        ix.accounts.push(AccountMeta::new_readonly(p.vault_authority, false));
+    }
+    ix
+}
+
+pub fn deposit_sol_lending_ix(p: &MintPdas, t: &Trader, temp: Pubkey, amount: u64) -> Instruction {
+    ix(
+        memelend::accounts::DepositSolLending {
+            owner: t.key,
+            wsol_mint: NATIVE_MINT_ID,
+            mint: p.mint,
+            temp_wsol_account: temp,
+            temp_wsol_authority: t.key,
+            vault_config: p.vault_config,
+            sol_lending_pool: p.sol_lending_pool,
+            sol_lending_vault: p.sol_lending_vault,
+            sol_lending_authority: p.sol_lending_authority,
+            sol_lender: sol_lender_pda(&p.mint, &t.key),
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        memelend::instruction::DepositSolLending { amount },
+    )
+}
+
+pub fn withdraw_sol_lending_ix(p: &MintPdas, t: &Trader, temp: Pubkey, amount: u64) -> Instruction {
+    ix(
+        memelend::accounts::WithdrawSolLending {
+            owner: t.key,
+            wsol_mint: NATIVE_MINT_ID,
+            mint: p.mint,
+            temp_wsol_account: temp,
+            temp_wsol_authority: t.key,
+            vault_config: p.vault_config,
+            sol_lending_pool: p.sol_lending_pool,
+            sol_lending_vault: p.sol_lending_vault,
+            sol_lending_authority: p.sol_lending_authority,
+            sol_lender: sol_lender_pda(&p.mint, &t.key),
+            token_program: spl_token::ID,
+        },
+        memelend::instruction::WithdrawSolLending { amount },
+    )
+}
+
+pub fn go_long_ix(p: &MintPdas, t: &Trader, temp: Pubkey, collateral_amount: u64, leverage_bps: u16, position_id: u64) -> Instruction {
+    ix(
+        memelend::accounts::GoLong {
+            user: t.key,
+            wsol_mint: NATIVE_MINT_ID,
+            mint: p.mint,
+            temp_wsol_account: temp,
+            temp_wsol_authority: t.key,
+            position: long_position_pda(&t.key, &p.mint, position_id),
+            token_config: p.token_config,
+            vault_config: p.vault_config,
+            vault_authority: p.vault_authority,
+            token_liquidity_vault: p.liquidity_token_vault,
+            liquidity_vault_authority: p.liquidity_vault_authority,
+            long_token_vault: p.long_token_vault,
+            liquidity_sol_vault: p.liquidity_sol_vault,
+            project_vault: p.project_vault,
+            platform_vault: p.platform_vault,
+            sol_lending_pool: p.sol_lending_pool,
+            sol_lending_vault: p.sol_lending_vault,
+            sol_lending_authority: p.sol_lending_authority,
+            protocol_config: protocol_config_pda(),
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        memelend::instruction::GoLong {
+            args: memelend::long_position::GoLongArgs {
+                position_id,
+                collateral_amount,
+                leverage_bps,
+                min_tokens_out: 1,
+            },
+        },
+    )
+}
+
+pub fn close_long_ix(p: &MintPdas, t: &Trader, temp: Pubkey, position_id: u64) -> Instruction {
+    ix(
+        memelend::accounts::CloseLong {
+            user: t.key,
+            wsol_mint: NATIVE_MINT_ID,
+            mint: p.mint,
+            temp_wsol_account: temp,
+            temp_wsol_authority: t.key,
+            position: long_position_pda(&t.key, &p.mint, position_id),
+            token_config: p.token_config,
+            vault_config: p.vault_config,
+            vault_authority: p.vault_authority,
+            token_liquidity_vault: p.liquidity_token_vault,
+            liquidity_vault_authority: p.liquidity_vault_authority,
+            long_token_vault: p.long_token_vault,
+            liquidity_sol_vault: p.liquidity_sol_vault,
+            wsol_vault_authority: p.wsol_vault_authority,
+            project_vault: p.project_vault,
+            platform_vault: p.platform_vault,
+            sol_lending_pool: p.sol_lending_pool,
+            sol_lending_vault: p.sol_lending_vault,
+            protocol_config: protocol_config_pda(),
+            token_program: spl_token::ID,
+        },
+        memelend::instruction::CloseLong {
+            args: memelend::long_position::CloseLongArgs { position_id, min_sol_out: 0 },
+        },
+    )
+}
+
+pub fn liquidate_long_ix(p: &MintPdas, bot: &Pubkey, bot_wsol: Pubkey, owner: &Pubkey, position_id: u64) -> Instruction {
+    ix(
+        memelend::accounts::LiquidateLong {
+            bot: *bot,
+            user: *owner,
+            wsol_mint: NATIVE_MINT_ID,
+            mint: p.mint,
+            position: long_position_pda(owner, &p.mint, position_id),
+            token_config: p.token_config,
+            vault_config: p.vault_config,
+            vault_authority: p.vault_authority,
+            token_liquidity_vault: p.liquidity_token_vault,
+            liquidity_vault_authority: p.liquidity_vault_authority,
+            long_token_vault: p.long_token_vault,
+            liquidity_sol_vault: p.liquidity_sol_vault,
+            wsol_vault_authority: p.wsol_vault_authority,
+            insurance_vault: p.insurance_vault,
+            sol_lending_pool: p.sol_lending_pool,
+            sol_lending_vault: p.sol_lending_vault,
+            bot_wsol_account: bot_wsol,
+            protocol_config: protocol_config_pda(),
+            token_program: spl_token::ID,
+        },
+        memelend::instruction::LiquidateLong {
+            args: memelend::long_position::LiquidateLongArgs { position_id },
+        },
+    )
+}
@@ -11,10 +11,10 @@ mod unit_tests {
         let token_reserve = 1_000_000 * 10u64.pow(6); // 1M tokens
         let decimals = 6;
 
-        let price = get_price_from_vault(sol_reserve, accumulated_c, token_reserve, decimals);
+        let price = get_price_from_vault(sol_reserve, accumulated_c, token_reserve, 0, 0, decimals);
         
-        // price = (1e9 * 1e12) / 1e6 = 1e15 lamports per token
-        assert_eq!(price, 1_000_000_000_000_000);
+        // price = 1e9 * 10^(6 + 6) / 1e12 = 1e9, lamports per token scaled by 1e6
+        assert_eq!(price, 1_000_000_000);
     }
 
     #[test]
@@ -24,10 +24,10 @@ mod unit_tests {
         let token_reserve = 1_000_000 * 10u64.pow(6);
         let decimals = 6;
 
-        let price = get_price_from_vault(sol_reserve, accumulated_c, token_reserve, decimals);
+        let price = get_price_from_vault(sol_reserve, accumulated_c, token_reserve, 0, 0, decimals);
         
-        // price = (1.5e9 * 1e12) / 1e6 = 1.5e15
-        assert_eq!(price, 1_500_000_000_000_000);
+        // price = 1.5e9 * 10^(6 + 6) / 1e12 = 1.5e9
+        assert_eq!(price, 1_500_000_000);
     }
 
     #[test]
@@ -38,7 +38,7 @@ mod unit_tests {
         
         // This should not panic
         let result = std::panic::catch_unwind(|| {
-            get_price_from_vault(max_sol, 0, max_tokens, 9)
+            get_price_from_vault(max_sol, 0, max_tokens, 0, 0, 9)
         });
         
         assert!(result.is_ok(), "Function should handle large numbers without panic");
@@ -47,7 +47,7 @@ mod unit_tests {
     #[test]
     fn test_division_by_zero_protection() {
         // What if token_reserve is 0?
-        let result = get_price_from_vault(1000, 0, 0, 6);
+        let result = get_price_from_vault(1000, 0, 0, 0, 0, 6);
         
         // Based on your function, it should return 0 when effective_reserve is negative or token_reserve is 0
         assert_eq!(result, 0, "Should return 0 when token_reserve is 0");
@@ -93,9 +93,578 @@ mod unit_tests {
         let k_final = (new_sol_reserve as u128) * (new_token_reserve as u128);
         
         // k should remain approximately constant (within rounding error)
-        let difference = if k_initial > k_final { k_initial - k_final } else { k_final - k_initial };
+        let difference = k_initial.abs_diff(k_final);
         let allowed_error = k_initial / 10000; // 0.01% error margin
         
         assert!(difference <= allowed_error, "Bonding curve invariant should be maintained");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_curves_start_at_same_price() {
+        use memelend::curve::{Curve, ConstantProduct, Linear, Exponential};
+
+        let sol = 300_000_000_000; // 300 SOL virtual
+        let tokens = 873_000_000 * 10u64.pow(6);
+
+        let cp = ConstantProduct { sol, tokens };
+        let linear = Linear { sol, base_sol: sol, base_tokens: tokens };
+        let exp = Exponential { sol, base_tokens: tokens };
+
+        // At launch every curve quotes the same spot price
+        assert_eq!(cp.spot_price(6), linear.spot_price(6));
+        assert_eq!(cp.spot_price(6), exp.spot_price(6));
+    }
+
+    #[test]
+    fn test_curve_round_trip_never_profits() {
+        use memelend::curve::{Curve, ConstantProduct, Linear, Exponential};
+
+        let sol = 300_000_000_000;
+        let tokens = 873_000_000 * 10u64.pow(6);
+        let sol_in = 5_000_000_000; // 5 SOL
+
+        // Each curve at launch, paired with the same curve after `sol_in` has been bought
+        type AfterBuy = Box<dyn Fn(u64) -> Box<dyn Curve>>;
+        let curves: Vec<(Box<dyn Curve>, AfterBuy)> = vec![
+            (
+                Box::new(ConstantProduct { sol, tokens }),
+                Box::new(move |bought| Box::new(ConstantProduct { sol: sol + sol_in, tokens: tokens - bought })),
+            ),
+            (
+                Box::new(Linear { sol, base_sol: sol, base_tokens: tokens }),
+                Box::new(move |_| Box::new(Linear { sol: sol + sol_in, base_sol: sol, base_tokens: tokens })),
+            ),
+            (
+                Box::new(Exponential { sol, base_tokens: tokens }),
+                Box::new(move |_| Box::new(Exponential { sol: sol + sol_in, base_tokens: tokens })),
+            ),
+        ];
+
+        for (before, after) in curves {
+            let bought = before.quote_buy(sol_in).unwrap();
+            assert!(bought > 0);
+
+            // Selling straight back can't return more SOL than was paid in
+            let sol_back = after(bought).quote_sell(bought).unwrap();
+            assert!(sol_back <= sol_in, "round trip returned {} > {}", sol_back, sol_in);
+        }
+    }
+
+    #[test]
+    fn test_exact_out_inverts_fees_and_curve() {
+        use memelend::curve::{Curve, ConstantProduct, Linear, Exponential};
+        use memelend::get_gross_for_net;
+
+        let sol = 300_000_000_000;
+        let tokens = 873_000_000 * 10u64.pow(6);
+        let creator_fee_bps = 50;
+        let platform_fee_bps = 100;
+
+        // Gross always leaves at least the requested net after both fees round down
+        for net in [1u64, 999, 1_000_000, 5_000_000_000] {
+            let gross = get_gross_for_net(net, creator_fee_bps, platform_fee_bps).unwrap();
+            let fees = gross * creator_fee_bps as u64 / 10_000 + gross * platform_fee_bps as u64 / 10_000;
+            assert!(gross - fees >= net);
+            assert!(gross - fees <= net + 2, "gross should not overshoot by more than rounding");
+        }
+
+        let curves: Vec<Box<dyn Curve>> = vec![
+            Box::new(ConstantProduct { sol, tokens }),
+            Box::new(Linear { sol, base_sol: sol, base_tokens: tokens }),
+            Box::new(Exponential { sol, base_tokens: tokens }),
+        ];
+
+        for curve in curves {
+            let want_tokens = 10_000_000 * 10u64.pow(6);
+            let sol_in = curve.quote_buy_exact_out(want_tokens).unwrap();
+            assert!(curve.quote_buy(sol_in).unwrap() >= want_tokens);
+
+            let want_sol = 1_000_000_000;
+            let tokens_in = curve.quote_sell_exact_out(want_sol).unwrap();
+            assert!(curve.quote_sell(tokens_in).unwrap() >= want_sol);
+        }
+    }
+
+    #[test]
+    fn test_borrow_interest_follows_index() {
+        use memelend::interest::{borrow_interest, BORROW_INDEX_ONE};
+
+        let principal = 2_000_000_000; // 2 SOL lent at entry
+
+        // A full year at 10% APR grows the index by 10%
+        let after_year = BORROW_INDEX_ONE + BORROW_INDEX_ONE / 10;
+        assert_eq!(borrow_interest(principal, BORROW_INDEX_ONE, after_year).unwrap(), 200_000_000);
+
+        // Opening later (higher snapshot) owes only the growth since then
+        let mid_year = BORROW_INDEX_ONE + BORROW_INDEX_ONE / 20;
+        let owed = borrow_interest(principal, mid_year, after_year).unwrap();
+        assert!(owed < 100_000_000 && owed > 95_000_000);
+
+        assert_eq!(borrow_interest(principal, after_year, after_year).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_kinked_borrow_rate() {
+        use memelend::interest::{kinked_rate_bps, utilisation_bps};
+
+        // 2% base, +10% up to an 80% kink, 100% when fully lent out
+        let rate = |u| kinked_rate_bps(200, 1_000, 8_000, 10_000, u);
+
+        assert_eq!(rate(0), 200);
+        assert_eq!(rate(4_000), 700);
+        assert_eq!(rate(8_000), 1_200);
+        assert_eq!(rate(9_000), 5_600);
+        assert_eq!(rate(10_000), 10_000);
+
+        assert_eq!(utilisation_bps(0, 0), 0);
+        assert_eq!(utilisation_bps(1, 0), 10_000, "borrowing with nothing staked is fully utilised");
+        assert_eq!(utilisation_bps(250, 1_000), 2_500);
+        assert_eq!(utilisation_bps(2_000, 1_000), 10_000);
+    }
+
+    #[test]
+    fn test_partial_liquidation_restores_margin() {
+        use memelend::liquidate_position::partial_liquidation_bps;
+
+        let collateral = 1_000_000_000; // 1 SOL
+        let value_entry = 500_000_000;  // 0.5 SOL of tokens borrowed
+        let fee_bps = 550;              // liquidator bonus + staker cut
+        let margin_bps = 2_000;
+
+        // Just past the threshold only a sliver has to be repaid
+        assert_eq!(partial_liquidation_bps(collateral, value_entry, 1_300_000_000, fee_bps, margin_bps), Some(1));
+
+        // Deeper underwater needs most of the position, and the rest is healthy again
+        let value_spot = 1_400_000_000u64;
+        let bps = partial_liquidation_bps(collateral, value_entry, value_spot, fee_bps, margin_bps).unwrap();
+        assert!(bps > 5_000 && bps < 10_000);
+
+        let loss = (value_spot - value_entry) as u128;
+        let fees = value_spot as u128 * fee_bps as u128 / 10_000;
+        let collateral_left = collateral as u128 - (loss + fees) * bps as u128 / 10_000;
+        let loss_left = loss * (10_000 - bps as u128) / 10_000;
+        assert!(collateral_left * (10_000 - margin_bps as u128) / 10_000 > loss_left);
+
+        // Insolvent positions can't be saved by a partial repay
+        assert_eq!(partial_liquidation_bps(collateral, value_entry, 1_600_000_000, fee_bps, margin_bps), None);
+    }
+
+    #[test]
+    fn test_auction_bonus_grows_with_slots() {
+        use memelend::liquidation_auction::auction_bonus_bps;
+
+        // Starts at the floor and climbs linearly to the cap
+        assert_eq!(auction_bonus_bps(0, 1_000, 100, 0), 0);
+        assert_eq!(auction_bonus_bps(0, 1_000, 100, 25), 250);
+        assert_eq!(auction_bonus_bps(100, 1_000, 100, 50), 550);
+
+        // Stays at the cap once the auction has run its course
+        assert_eq!(auction_bonus_bps(0, 1_000, 100, 100), 1_000);
+        assert_eq!(auction_bonus_bps(0, 1_000, 100, 5_000), 1_000);
+    }
+
+    #[test]
+    fn test_shortfall_covered_by_insurance_first() {
+        use memelend::insurance_fund::split_shortfall;
+
+        // Fund covers all of it
+        assert_eq!(split_shortfall(400, 1_000), (400, 0));
+
+        // Fund runs dry, the rest is bad debt
+        assert_eq!(split_shortfall(1_500, 1_000), (1_000, 500));
+
+        // Nothing to cover
+        assert_eq!(split_shortfall(0, 1_000), (0, 0));
+        assert_eq!(split_shortfall(700, 0), (0, 700));
+    }
+
+    #[test]
+    fn test_short_size_grows_with_leverage() {
+        use memelend::curve::{Curve, ConstantProduct};
+        use memelend::go_short::leveraged_input;
+
+        let cp = ConstantProduct { sol: 300_000_000_000, tokens: 873_000_000 * 10u64.pow(6) };
+        let collateral = 2_000_000_000; // 2 SOL
+
+        // 30%, 40% and 45% used to collapse onto the same divisor
+        let sizes: Vec<u64> = [3_000u16, 4_000, 4_500, 5_000, 7_500, 10_000, 20_000]
+            .iter()
+            .map(|bps| cp.borrow_quote(leveraged_input(collateral, *bps).unwrap()).unwrap())
+            .collect();
+
+        assert!(sizes.windows(2).all(|w| w[0] < w[1]));
+
+        // 1x borrows exactly the collateral's worth
+        assert_eq!(leveraged_input(collateral, 10_000).unwrap(), collateral);
+        assert_eq!(leveraged_input(collateral, 4_500).unwrap(), 900_000_000);
+    }
+
+    #[test]
+    fn test_long_liquidation_price_tracks_leverage() {
+        use memelend::get_long_liquidation_price;
+
+        let decimals = 6;
+        let entry_price = 1_000_000_000; // 1000 lamports per token at 10^(d+6)
+        let tokens = 3_000_000 * 10u64.pow(6); // 3 SOL of exposure
+
+        // 1x has no debt to cover and can't be liquidated above zero
+        let one_x = get_long_liquidation_price(entry_price, tokens, 3_000_000_000, 0, decimals).unwrap();
+        assert_eq!(one_x, 0);
+
+        // 3x on 1 SOL liquidates once a third of the value is gone
+        let three_x = get_long_liquidation_price(entry_price, tokens, 1_000_000_000, 0, decimals).unwrap();
+        assert_eq!(three_x, entry_price * 2 / 3 + 1);
+
+        // Maintenance margin pulls the threshold towards the entry
+        let with_margin = get_long_liquidation_price(entry_price, tokens, 1_000_000_000, 1_000, decimals).unwrap();
+        assert!(with_margin > three_x && with_margin < entry_price);
+    }
+
+    #[test]
+    fn test_short_exit_triggers() {
+        use anchor_lang::prelude::Pubkey;
+        use memelend::Position;
+        use memelend::position_triggers::{crossed_trigger, TRIGGER_STOP_LOSS, TRIGGER_TAKE_PROFIT};
+
+        let mut position = Position {
+            owner: Pubkey::new_unique(),
+            entry_price: 1_000,
+            amount: 1,
+            mint: Pubkey::new_unique(),
+            open: true,
+            collateral: 1,
+            liquidate: 2_000,
+            position_id: 1,
+            created_at: 0,
+            borrow_index_snapshot: 0,
+            stop_loss_price: 0,
+            take_profit_price: 0,
+        };
+
+        // Nothing set, nothing fires
+        assert_eq!(crossed_trigger(&position, 10), None);
+        assert_eq!(crossed_trigger(&position, 1_999), None);
+
+        position.stop_loss_price = 1_500;
+        position.take_profit_price = 600;
+
+        // A short loses as the price rises and wins as it falls
+        assert_eq!(crossed_trigger(&position, 1_000), None);
+        assert_eq!(crossed_trigger(&position, 1_500), Some(TRIGGER_STOP_LOSS));
+        assert_eq!(crossed_trigger(&position, 1_800), Some(TRIGGER_STOP_LOSS));
+        assert_eq!(crossed_trigger(&position, 600), Some(TRIGGER_TAKE_PROFIT));
+        assert_eq!(crossed_trigger(&position, 601), None);
+    }
+
+    #[test]
+    fn test_user_account_registry() {
+        use anchor_lang::prelude::Pubkey;
+        use memelend::{UserAccount, MAX_OPEN_POSITIONS};
+        use memelend::user_account::{allocate_position_id, register_position, remove_position};
+
+        let mut user_account = UserAccount {
+            owner: Pubkey::new_unique(),
+            next_position_id: 0,
+            open_positions: vec![],
+            bump: 255,
+        };
+
+        // Ids are handed out in order and never reused
+        assert_eq!(allocate_position_id(&mut user_account).unwrap(), 0);
+        assert_eq!(allocate_position_id(&mut user_account).unwrap(), 1);
+        assert_eq!(user_account.next_position_id, 2);
+
+        // Registering the same position twice keeps one entry
+        let position = Pubkey::new_unique();
+        register_position(&mut user_account, position).unwrap();
+        register_position(&mut user_account, position).unwrap();
+        assert_eq!(user_account.open_positions, vec![position]);
+
+        remove_position(&mut user_account, &position);
+        assert!(user_account.open_positions.is_empty());
+
+        // The list is bounded
+        for _ in 0..MAX_OPEN_POSITIONS {
+            register_position(&mut user_account, Pubkey::new_unique()).unwrap();
+        }
+        assert!(register_position(&mut user_account, Pubkey::new_unique()).is_err());
+    }
+
+    #[test]
+    fn test_emissions_capped_by_reserve() {
+        use anchor_lang::prelude::Pubkey;
+        use memelend::{YieldVault, UserYieldPosition, RewardSlot, MAX_REWARD_SLOTS};
+        use memelend::emissions::{accrue_emissions, emission_for, settle_emissions, SECONDS_IN_YEAR};
+
+        // 10% APR on 1_000_000 staked for a year
+        assert_eq!(emission_for(1_000_000, 1_000, SECONDS_IN_YEAR as i64), 100_000);
+        assert_eq!(emission_for(1_000_000, 1_000, 0), 0);
+
+        let mut vault = YieldVault {
+            mint: Pubkey::new_unique(),
+            apr_bps: 1_000,
+            total_staked: 1_000_000,
+            total_earned: 0,
+            acc_reward_per_share: 0,
+            creator: Pubkey::new_unique(),
+            launch_ts: 0,
+            max_withdraw_bps: 10_000,
+            last_accrual_ts: 0,
+            bump: 0,
+            emission_reserve: 40_000,
+            acc_emission_per_share: 0,
+            total_emitted: 0,
+            last_emission_ts: 0,
+            total_weighted_stake: 1_000_000,
+            reward_slots: [RewardSlot::default(); MAX_REWARD_SLOTS],
+        };
+
+        // First call only starts the clock
+        accrue_emissions(&mut vault, 1_000).unwrap();
+        assert_eq!(vault.total_emitted, 0);
+
+        // Half a year earns 50_000 but only 40_000 is left to stream
+        accrue_emissions(&mut vault, 1_000 + SECONDS_IN_YEAR as i64 / 2).unwrap();
+        assert_eq!(vault.total_emitted, 40_000);
+        assert_eq!(vault.emission_reserve, 0);
+
+        let mut position = UserYieldPosition {
+            owner: Pubkey::new_unique(),
+            mint: vault.mint,
+            position_id: 0,
+            is_creator: false,
+            claimed_principal: 0,
+            initial_deposit: 0,
+            deposited: 250_000,
+            reward_debt: 0,
+            claimed_total: 0,
+            deposited_at: 0,
+            last_action_ts: 0,
+            bump: 0,
+            emission_debt: 0,
+            emission_owed: 0,
+            emission_claimed: 0,
+            lock_tier: 0,
+            lock_until: 0,
+            weighted_stake: 250_000,
+            slot_debts: [0; MAX_REWARD_SLOTS],
+            slot_owed: [0; MAX_REWARD_SLOTS],
+            compound_max_price: 0,
+        };
+        settle_emissions(&vault, &mut position).unwrap();
+        assert_eq!(position.emission_owed, 10_000);
+
+        // Nothing new accrues once the reserve is empty
+        accrue_emissions(&mut vault, 1_000 + SECONDS_IN_YEAR as i64).unwrap();
+        settle_emissions(&vault, &mut position).unwrap();
+        assert_eq!(position.emission_owed, 10_000);
+    }
+
+    #[test]
+    fn test_lock_tiers_boost_weight() {
+        use anchor_lang::prelude::Pubkey;
+        use memelend::{YieldVault, UserYieldPosition, RewardSlot, MAX_REWARD_SLOTS};
+        use memelend::yield_lock::{apply_lock, expire_lock, require_unlocked, update_weight, LOCK_TIERS};
+
+        let mut vault = YieldVault {
+            mint: Pubkey::new_unique(),
+            apr_bps: 1_000,
+            total_staked: 1_000,
+            total_earned: 0,
+            acc_reward_per_share: 0,
+            creator: Pubkey::new_unique(),
+            launch_ts: 0,
+            max_withdraw_bps: 10_000,
+            last_accrual_ts: 0,
+            bump: 0,
+            emission_reserve: 0,
+            acc_emission_per_share: 0,
+            total_emitted: 0,
+            last_emission_ts: 0,
+            total_weighted_stake: 1_000,
+            reward_slots: [RewardSlot::default(); MAX_REWARD_SLOTS],
+        };
+        let mut position = UserYieldPosition {
+            owner: Pubkey::new_unique(),
+            mint: vault.mint,
+            position_id: 0,
+            is_creator: false,
+            claimed_principal: 0,
+            initial_deposit: 0,
+            deposited: 1_000,
+            reward_debt: 0,
+            claimed_total: 0,
+            deposited_at: 0,
+            last_action_ts: 0,
+            bump: 0,
+            emission_debt: 0,
+            emission_owed: 0,
+            emission_claimed: 0,
+            lock_tier: 0,
+            lock_until: 0,
+            weighted_stake: 1_000,
+            slot_debts: [0; MAX_REWARD_SLOTS],
+            slot_owed: [0; MAX_REWARD_SLOTS],
+            compound_max_price: 0,
+        };
+
+        // 90 day lock weighs 1.5x
+        apply_lock(&mut position, 3, 100).unwrap();
+        update_weight(&mut vault, &mut position).unwrap();
+        assert_eq!(position.lock_until, 100 + LOCK_TIERS[3].0);
+        assert_eq!(position.weighted_stake, 1_500);
+        assert_eq!(vault.total_weighted_stake, 1_500);
+
+        // Principal is stuck and the lock can't be shortened while it runs
+        assert!(require_unlocked(&position, 200).is_err());
+        assert!(apply_lock(&mut position, 1, 200).is_err());
+        // Unknown tier
+        assert!(apply_lock(&mut position, 5, 200).is_err());
+
+        // Unlocked top-ups leave the lock alone
+        apply_lock(&mut position, 0, 200).unwrap();
+        assert_eq!(position.lock_tier, 3);
+
+        // After expiry the boost drops back to 1x
+        let expiry = position.lock_until;
+        assert!(require_unlocked(&position, expiry).is_ok());
+        expire_lock(&mut position, expiry);
+        update_weight(&mut vault, &mut position).unwrap();
+        assert_eq!(position.weighted_stake, 1_000);
+        assert_eq!(vault.total_weighted_stake, 1_000);
+    }
+
+    #[test]
+    fn test_vesting_schedules() {
+        use anchor_lang::prelude::Pubkey;
+        use memelend::{VestingSchedule, VestingStep};
+        use memelend::vesting::{
+            allowed_withdraw_percent, validate_vesting, SetVestingScheduleArgs, VESTING_LINEAR, VESTING_STEPS,
+        };
+
+        const DAY: i64 = 86_400;
+
+        // 30 day cliff, linear to 100 days
+        let mut schedule = VestingSchedule {
+            mint: Pubkey::new_unique(),
+            creator: Pubkey::new_unique(),
+            kind: VESTING_LINEAR,
+            cliff_seconds: 30 * DAY,
+            duration_seconds: 100 * DAY,
+            steps: vec![],
+            irrevocable: true,
+            bump: 0,
+        };
+        assert_eq!(allowed_withdraw_percent(&schedule, 29 * DAY), 0);
+        assert_eq!(allowed_withdraw_percent(&schedule, 30 * DAY), 3_000);
+        assert_eq!(allowed_withdraw_percent(&schedule, 50 * DAY), 5_000);
+        assert_eq!(allowed_withdraw_percent(&schedule, 365 * DAY), 10_000);
+
+        schedule.kind = VESTING_STEPS;
+        schedule.steps = vec![
+            VestingStep { after_seconds: 0, unlocked_bps: 2_500 },
+            VestingStep { after_seconds: 60 * DAY, unlocked_bps: 10_000 },
+        ];
+        assert_eq!(allowed_withdraw_percent(&schedule, 0), 2_500);
+        assert_eq!(allowed_withdraw_percent(&schedule, 59 * DAY), 2_500);
+        assert_eq!(allowed_withdraw_percent(&schedule, 60 * DAY), 10_000);
+
+        let args = |kind, steps: Vec<VestingStep>| SetVestingScheduleArgs {
+            kind,
+            cliff_seconds: 10 * DAY,
+            duration_seconds: 5 * DAY,
+            steps,
+            irrevocable: false,
+        };
+
+        // Cliff after the end, steps that never fully unlock or go backwards
+        assert!(validate_vesting(&args(VESTING_LINEAR, vec![])).is_err());
+        assert!(validate_vesting(&args(VESTING_STEPS, vec![
+            VestingStep { after_seconds: DAY, unlocked_bps: 5_000 },
+        ])).is_err());
+        assert!(validate_vesting(&args(VESTING_STEPS, vec![
+            VestingStep { after_seconds: DAY, unlocked_bps: 5_000 },
+            VestingStep { after_seconds: DAY, unlocked_bps: 10_000 },
+        ])).is_err());
+        assert!(validate_vesting(&args(VESTING_STEPS, schedule.steps.clone())).is_ok());
+    }
+
+    #[test]
+    fn test_reward_slots_stream_until_end() {
+        use anchor_lang::prelude::Pubkey;
+        use memelend::{YieldVault, UserYieldPosition, RewardSlot, MAX_REWARD_SLOTS};
+        use memelend::reward_slots::{accrue_reward_slots, reset_reward_slot_debts, settle_reward_slots};
+
+        let mut vault = YieldVault {
+            mint: Pubkey::new_unique(),
+            apr_bps: 0,
+            total_staked: 1_000,
+            total_earned: 0,
+            acc_reward_per_share: 0,
+            creator: Pubkey::new_unique(),
+            launch_ts: 0,
+            max_withdraw_bps: 10_000,
+            last_accrual_ts: 0,
+            bump: 0,
+            emission_reserve: 0,
+            acc_emission_per_share: 0,
+            total_emitted: 0,
+            last_emission_ts: 0,
+            total_weighted_stake: 1_000,
+            reward_slots: [RewardSlot::default(); MAX_REWARD_SLOTS],
+        };
+        // Slot 1 streams 10 tokens a second for 100 seconds
+        vault.reward_slots[1] = RewardSlot {
+            mint: Pubkey::new_unique(),
+            vault: Pubkey::new_unique(),
+            rate_per_second: 10,
+            end_ts: 100,
+            last_update_ts: 0,
+            acc_per_share: 0,
+            total_funded: 1_000,
+            total_distributed: 0,
+        };
+
+        let mut position = UserYieldPosition {
+            owner: Pubkey::new_unique(),
+            mint: vault.mint,
+            position_id: 0,
+            is_creator: false,
+            claimed_principal: 0,
+            initial_deposit: 0,
+            deposited: 250,
+            reward_debt: 0,
+            claimed_total: 0,
+            deposited_at: 0,
+            last_action_ts: 0,
+            bump: 0,
+            emission_debt: 0,
+            emission_owed: 0,
+            emission_claimed: 0,
+            lock_tier: 0,
+            lock_until: 0,
+            weighted_stake: 250,
+            slot_debts: [0; MAX_REWARD_SLOTS],
+            slot_owed: [0; MAX_REWARD_SLOTS],
+            compound_max_price: 0,
+        };
+
+        accrue_reward_slots(&mut vault, 40).unwrap();
+        settle_reward_slots(&vault, &mut position).unwrap();
+        assert_eq!(position.slot_owed[1], 100);
+        assert_eq!(position.slot_owed[0], 0);
+
+        // Nothing streams past end_ts
+        accrue_reward_slots(&mut vault, 500).unwrap();
+        assert_eq!(vault.reward_slots[1].total_distributed, 1_000);
+        settle_reward_slots(&vault, &mut position).unwrap();
+        assert_eq!(position.slot_owed[1], 250);
+
+        // A fresh stake only earns from when it joined
+        let mut late = position.clone();
+        late.slot_owed = [0; MAX_REWARD_SLOTS];
+        reset_reward_slot_debts(&vault, &mut late).unwrap();
+        settle_reward_slots(&vault, &mut late).unwrap();
+        assert_eq!(late.slot_owed[1], 0);
+    }
+}